@@ -47,7 +47,7 @@ use libafl::{
 use libafl_qemu::{
     //asan::{init_with_asan, QemuAsanHelper},
     cmplog,
-    cmplog::{CmpLogObserver, QemuCmpLogHelper},
+    cmplog::{CmpLogObserver, QemuCmpLogHelper, QemuCmpLogRoutinesHelper},
     edges,
     edges::QemuEdgeCoverageHelper,
     elf::EasyElf,
@@ -57,6 +57,7 @@ use libafl_qemu::{
     //snapshot::QemuSnapshotHelper,
     MmapPerms,
     QemuExecutor,
+    QemuInstrumentationFilter,
     Regs,
 };
 #[cfg(unix)]
@@ -171,6 +172,8 @@ fn fuzz(
     timeout: Duration,
 ) -> Result<(), Error> {
     env::remove_var("LD_LIBRARY_PATH");
+    // Bind the imports early, for the routines cmplog to find them in the GOT
+    env::set_var("LD_BIND_NOW", "1");
 
     let args: Vec<String> = env::args().collect();
     let env: Vec<(String, String)> = env::vars().collect();
@@ -342,6 +345,7 @@ fn fuzz(
         tuple_list!(
             QemuEdgeCoverageHelper::default(),
             QemuCmpLogHelper::default(),
+            QemuCmpLogRoutinesHelper::new(&emu, QemuInstrumentationFilter::None),
             //QemuAsanHelper::default(),
             //QemuSnapshotHelper::new()
         ),
@@ -11,7 +11,6 @@ use libafl::{
         rands::StdRand,
         shmem::{ShMemProvider, StdShMemProvider},
         tuples::tuple_list,
-        AsSlice,
     },
     corpus::{Corpus, InMemoryCorpus, OnDiskCorpus},
     events::EventConfig,
@@ -19,7 +18,7 @@ use libafl::{
     feedback_or, feedback_or_fast,
     feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback, TimeoutFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
-    inputs::{BytesInput, HasTargetBytes},
+    inputs::BytesInput,
     monitors::MultiMonitor,
     mutators::scheduled::{havoc_mutations, StdScheduledMutator},
     observers::{HitcountsMapObserver, TimeObserver, VariableMapObserver},
@@ -38,9 +37,10 @@ use libafl_qemu::{
     emu::Emulator,
     filter_qemu_args,
     //snapshot::QemuSnapshotHelper,
-    MmapPerms,
     QemuExecutor,
     QemuHooks,
+    QemuPersistentHelper,
+    QemuPersistentInput,
     Regs,
 };
 
@@ -68,49 +68,10 @@ pub fn fuzz() {
         .expect("Symbol LLVMFuzzerTestOneInput not found");
     println!("LLVMFuzzerTestOneInput @ {:#x}", test_one_input_ptr);
 
-    emu.set_breakpoint(test_one_input_ptr); // LLVMFuzzerTestOneInput
-    unsafe { emu.run() };
-
-    println!("Break at {:#x}", emu.read_reg::<_, u64>(Regs::Rip).unwrap());
-
-    // Get the return address
-    let stack_ptr: u64 = emu.read_reg(Regs::Rsp).unwrap();
-    let mut ret_addr = [0; 8];
-    unsafe { emu.read_mem(stack_ptr, &mut ret_addr) };
-    let ret_addr = u64::from_le_bytes(ret_addr);
-
-    println!("Stack pointer = {:#x}", stack_ptr);
-    println!("Return address = {:#x}", ret_addr);
-
-    emu.remove_breakpoint(test_one_input_ptr); // LLVMFuzzerTestOneInput
-    emu.set_breakpoint(ret_addr); // LLVMFuzzerTestOneInput ret addr
-
-    let input_addr = emu
-        .map_private(0, MAX_INPUT_SIZE, MmapPerms::ReadWrite)
-        .unwrap();
-    println!("Placing input at {:#x}", input_addr);
-
-    // The wrapped harness function, calling out to the LLVM-style harness
-    let mut harness = |input: &BytesInput| {
-        let target = input.target_bytes();
-        let mut buf = target.as_slice();
-        let mut len = buf.len();
-        if len > MAX_INPUT_SIZE {
-            buf = &buf[0..MAX_INPUT_SIZE];
-            len = MAX_INPUT_SIZE;
-        }
-
-        unsafe {
-            emu.write_mem(input_addr, buf);
-
-            emu.write_reg(Regs::Rdi, input_addr).unwrap();
-            emu.write_reg(Regs::Rsi, len).unwrap();
-            emu.write_reg(Regs::Rip, test_one_input_ptr).unwrap();
-            emu.write_reg(Regs::Rsp, stack_ptr).unwrap();
-
-            emu.run();
-        }
-
+    // The wrapped harness function, the persistent helper places the input and restores the
+    // registers, so here we only run LLVMFuzzerTestOneInput until it returns
+    let mut harness = |_input: &BytesInput| {
+        unsafe { emu.run() };
         ExitKind::Ok
     };
 
@@ -161,7 +122,25 @@ pub fn fuzz() {
         // A fuzzer with feedbacks and a corpus scheduler
         let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
-        let mut hooks = QemuHooks::new(&emu, tuple_list!(QemuEdgeCoverageHelper::default()));
+        // Loop in LLVMFuzzerTestOneInput, passing the input in the (data, size) arguments.
+        // Without a snapshot helper, only the registers are restored and the target is never
+        // restarted, like libFuzzer does.
+        let persistent = QemuPersistentHelper::new(
+            &emu,
+            test_one_input_ptr,
+            None,
+            QemuPersistentInput::Registers {
+                ptr: Regs::Rdi,
+                len: Some(Regs::Rsi),
+                max_size: MAX_INPUT_SIZE,
+            },
+        )?
+        .with_max_iterations(0);
+
+        let mut hooks = QemuHooks::new(
+            &emu,
+            tuple_list!(QemuEdgeCoverageHelper::default(), persistent),
+        );
 
         // Create a QEMU in-process executor
         let executor = QemuExecutor::new(
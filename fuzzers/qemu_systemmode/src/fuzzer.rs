@@ -15,7 +15,7 @@ use libafl::{
     },
     corpus::{Corpus, InMemoryCorpus, OnDiskCorpus},
     events::EventConfig,
-    executors::{ExitKind, TimeoutExecutor},
+    executors::TimeoutExecutor,
     feedback_or, feedback_or_fast,
     feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback, TimeoutFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
@@ -29,11 +29,11 @@ use libafl::{
     Error,
 };
 use libafl_qemu::{
-    edges, edges::QemuEdgeCoverageHelper, elf::EasyElf, emu::Emulator, GuestPhysAddr, QemuExecutor,
-    QemuHooks, Regs,
+    edges, edges::QemuEdgeCoverageHelper, elf::EasyElf, emu::Emulator, QemuExecutor, QemuHooks,
+    QemuSystemHarnessConfig,
 };
 
-pub static mut MAX_INPUT_SIZE: usize = 50;
+pub const MAX_INPUT_SIZE: usize = 50;
 
 pub fn fuzz() {
     if let Ok(s) = env::var("FUZZ_SIZE") {
@@ -53,26 +53,15 @@ pub fn fuzz() {
     )
     .unwrap();
 
-    let input_addr = elf
-        .resolve_symbol(
-            &env::var("FUZZ_INPUT").unwrap_or_else(|_| "FUZZ_INPUT".to_owned()),
-            0,
-        )
-        .expect("Symbol or env FUZZ_INPUT not found") as GuestPhysAddr;
-    println!("FUZZ_INPUT @ {:#x}", input_addr);
-
-    let main_addr = elf
-        .resolve_symbol("main", 0)
-        .expect("Symbol main not found");
-    println!("main address = {:#x}", main_addr);
-
-    let breakpoint = elf
-        .resolve_symbol(
-            &env::var("BREAKPOINT").unwrap_or_else(|_| "BREAKPOINT".to_owned()),
-            0,
-        )
-        .expect("Symbol or env BREAKPOINT not found");
-    println!("Breakpoint address = {:#x}", breakpoint);
+    let config = QemuSystemHarnessConfig::new(
+        "main",
+        env::var("FUZZ_INPUT").unwrap_or_else(|_| "FUZZ_INPUT".to_owned()),
+        MAX_INPUT_SIZE,
+    )
+    // If the execution stops at any point other then the designated breakpoint (e.g. a breakpoint on a panic method) we consider it a crash
+    .with_exit(env::var("BREAKPOINT").unwrap_or_else(|_| "BREAKPOINT".to_owned()))
+    // The input is in the flash, write it with its physical address
+    .with_physical_input(true);
 
     let mut run_client = |state: Option<_>, mut mgr, _core_id| {
         // Initialize QEMU
@@ -80,61 +69,14 @@ pub fn fuzz() {
         let env: Vec<(String, String)> = env::vars().collect();
         let emu = Emulator::new(&args, &env);
 
-        emu.set_breakpoint(main_addr);
-        unsafe {
-            emu.run();
-        }
-        emu.remove_breakpoint(main_addr);
-
-        emu.set_breakpoint(breakpoint); // BREAKPOINT
-
-        // let saved_cpu_states: Vec<_> = (0..emu.num_cpus())
-        //     .map(|i| emu.cpu_from_index(i).save_state())
-        //     .collect();
-
-        // emu.save_snapshot("start", true);
-
-        let snap = emu.create_fast_snapshot(true);
+        let qemu_harness = config
+            .build(&emu, Some(&elf))
+            .expect("Failed to build the systemmode harness");
 
-        // The wrapped harness function, calling out to the LLVM-style harness
+        // The wrapped harness function, restoring a fast devices+mem snapshot after each run
         let mut harness = |input: &BytesInput| {
             let target = input.target_bytes();
-            let mut buf = target.as_slice();
-            let len = buf.len();
-            unsafe {
-                if len > MAX_INPUT_SIZE {
-                    buf = &buf[0..MAX_INPUT_SIZE];
-                    // len = MAX_INPUT_SIZE;
-                }
-
-                emu.write_phys_mem(input_addr, buf);
-
-                emu.run();
-
-                // If the execution stops at any point other then the designated breakpoint (e.g. a breakpoint on a panic method) we consider it a crash
-                let mut pcs = (0..emu.num_cpus())
-                    .map(|i| emu.cpu_from_index(i))
-                    .map(|cpu| -> Result<u32, String> { cpu.read_reg(Regs::Pc) });
-                let ret = match pcs
-                    .find(|pc| (breakpoint..breakpoint + 5).contains(pc.as_ref().unwrap_or(&0)))
-                {
-                    Some(_) => ExitKind::Ok,
-                    None => ExitKind::Crash,
-                };
-
-                // OPTION 1: restore only the CPU state (registers et. al)
-                // for (i, s) in saved_cpu_states.iter().enumerate() {
-                //     emu.cpu_from_index(i).restore_state(s);
-                // }
-
-                // OPTION 2: restore a slow vanilla QEMU snapshot
-                // emu.load_snapshot("start", true);
-
-                // OPTION 3: restore a fast devices+mem snapshot
-                emu.restore_fast_snapshot(snap);
-
-                ret
-            }
+            qemu_harness.run(target.as_slice())
         };
 
         // Create an observation channel using the coverage map
@@ -64,8 +64,12 @@
 //!```
 
 #[cfg(feature = "frida_cli")]
-use alloc::{boxed::Box, string::ToString};
-use alloc::{string::String, vec::Vec};
+use alloc::boxed::Box;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Write, str::FromStr};
 #[cfg(feature = "frida_cli")]
 use std::error;
 use std::{net::SocketAddr, path::PathBuf, time::Duration};
@@ -104,6 +108,32 @@ fn parse_instrumentation_location(
     ))
 }
 
+/// helper function to go from MODULE@symbol or MODULE@0x12345 to (String, String); aka the function
+/// hooked by the persistent mode, or run on fake stacks by ASAN
+#[cfg(feature = "frida_cli")]
+fn parse_module_function(
+    function: &str,
+) -> Result<(String, String), Box<dyn error::Error + Send + Sync + 'static>> {
+    let (module, target) = function
+        .split_once('@')
+        .ok_or("Expected an '@' in function specifier")?;
+    if module.is_empty() || target.is_empty() {
+        return Err("Expected a module and a symbol or an offset in function specifier".into());
+    }
+    Ok((module.to_string(), target.to_string()))
+}
+
+/// helper function to go from 0:1 to (usize, usize); aka the indices of a pointer and a length argument
+#[cfg(feature = "frida_cli")]
+fn parse_argument_pair(
+    pair: &str,
+) -> Result<(usize, usize), Box<dyn error::Error + Send + Sync + 'static>> {
+    let (pointer, length) = pair
+        .split_once(':')
+        .ok_or("Expected a ':' between the pointer and the length argument indices")?;
+    Ok((pointer.parse()?, length.parse()?))
+}
+
 /// Top-level container for cli options/arguments/subcommands
 #[derive(Parser, Clone, Debug, Serialize, Deserialize)]
 #[command(
@@ -226,6 +256,25 @@ pub struct FuzzerOptions {
     #[arg(long, help_heading = "ASAN Options")]
     pub max_allocation_panics: bool,
 
+    /// instruct ASAN to poison the padding between the writable globals found in the symbol tables of the instrumented modules
+    #[cfg(feature = "frida_cli")]
+    #[arg(long, help_heading = "ASAN Options")]
+    pub asan_globals: bool,
+
+    /// functions that ASAN runs on fake stacks, to detect the uses of their stack after they return (ex: mod_name@symbol or mod_name@0x12345); only functions taking up to 8 integer or pointer arguments, all in registers, and returning an integer or a pointer are supported
+    #[cfg(feature = "frida_cli")]
+    #[arg(long, help_heading = "ASAN Options", value_parser = parse_module_function)]
+    pub asan_fake_stack: Vec<(String, String)>,
+
+    /// the size in bytes of each frame of the fake stacks, for a hooked call and its callees; a call overflowing its frame hits a guard page
+    #[cfg(feature = "frida_cli")]
+    #[arg(
+        long,
+        default_value = "1048576",  // 1_usize << 20
+        help_heading = "ASAN Options"
+    )]
+    pub asan_fake_stack_size: usize,
+
     /// disable coverage
     #[cfg(feature = "frida_cli")]
     #[arg(long, help_heading = "Frida Options")]
@@ -241,6 +290,21 @@ pub struct FuzzerOptions {
     #[arg(short = 'D', long, help_heading = "Frida Options", value_parser = parse_instrumentation_location)]
     pub dont_instrument: Vec<(String, usize)>,
 
+    /// function hooked by the persistent mode, which injects the input into its arguments (ex: mod_name@symbol or mod_name@0x12345); only functions taking up to 8 integer or pointer arguments, all in registers, are supported
+    #[cfg(feature = "frida_cli")]
+    #[arg(long, help_heading = "Frida Options", value_parser = parse_module_function)]
+    pub persistent_function: Option<(String, String)>,
+
+    /// arguments of the persistent function that receive the input, as pointer:length argument indices (ex: 0:1)
+    #[cfg(feature = "frida_cli")]
+    #[arg(long, help_heading = "Frida Options", value_parser = parse_argument_pair)]
+    pub persistent_args: Vec<(usize, usize)>,
+
+    /// number of times the persistent function is run on each input
+    #[cfg(feature = "frida_cli")]
+    #[arg(long, default_value = "1", help_heading = "Frida Options")]
+    pub persistent_iterations: usize,
+
     /// trailing arguments (after "--"); can be passed directly to QEMU
     #[cfg(feature = "qemu_cli")]
     #[arg(last = true)]
@@ -353,6 +417,278 @@ pub fn parse_args() -> FuzzerOptions {
     FuzzerOptions::parse()
 }
 
+/// The flags understood by [`LibfuzzerOptions`], with their description, as printed by `-help=1`
+pub const LIBFUZZER_FLAGS: &[(&str, &str)] = &[
+    (
+        "runs",
+        "Number of individual test runs (-1 for infinite runs).",
+    ),
+    (
+        "max_len",
+        "Maximum length of the test input. If 0, the fuzzer picks a default.",
+    ),
+    (
+        "timeout",
+        "Timeout in seconds (if positive). If one unit runs more than this number of seconds the process will abort.",
+    ),
+    (
+        "max_total_time",
+        "If positive, indicates the maximal total time in seconds to run the fuzzer.",
+    ),
+    (
+        "dict",
+        "Use the dictionary file.",
+    ),
+    (
+        "jobs",
+        "Number of jobs to run. If jobs >= 1 we spawn this number of jobs in separate worker processes.",
+    ),
+    (
+        "workers",
+        "Number of simultaneous worker processes to run the jobs. If zero, \"min(jobs,NumberOfCpuCores()/2)\" is used.",
+    ),
+    (
+        "merge",
+        "If 1, the 2-nd, 3-rd, etc corpora will be merged into the 1-st corpus. Only interesting units will be taken.",
+    ),
+    (
+        "minimize_crash",
+        "If 1, minimizes the provided crash input. Use with -runs=N or -max_total_time=N to limit the number attempts.",
+    ),
+    (
+        "artifact_prefix",
+        "Write fuzzing artifacts (crash, timeout, or slow inputs) as $(artifact_prefix)file",
+    ),
+    (
+        "seed",
+        "Random seed. If 0, seed is generated.",
+    ),
+    (
+        "ignore_remaining_args",
+        "If 1, ignore all arguments passed after this one.",
+    ),
+    ("help", "Print help."),
+];
+
+/// The `libFuzzer` flags that are accepted but have no effect, as they make no sense for `LibAFL`
+pub const LIBFUZZER_IGNORED_FLAGS: &[&str] = &[
+    "rss_limit_mb",
+    "malloc_limit_mb",
+    "close_fd_mask",
+    "print_final_stats",
+    "print_pcs",
+    "print_coverage",
+    "use_value_profile",
+    "detect_leaks",
+    "reload",
+    "report_slow_units",
+    "len_control",
+    "entropic",
+    "verbosity",
+];
+
+/// The default timeout of `libFuzzer`, in seconds
+pub const LIBFUZZER_DEFAULT_TIMEOUT_SECS: u64 = 1200;
+
+/// Runtime options for `libFuzzer`-style harnesses, parsed from `libFuzzer` flags (`-runs=100`),
+/// so that scripts written for `libFuzzer` can be reused as they are.
+///
+/// Positional arguments are the corpus directories or, when only files are passed, the inputs to run.
+/// The `libFuzzer` flags in [`LIBFUZZER_IGNORED_FLAGS`], and the unknown or malformed flags, are
+/// collected into `ignored`, to be warned about, like `libFuzzer` does. Only invalid values of the
+/// supported flags are errors.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct LibfuzzerOptions {
+    /// Number of runs, `None` to run indefinitely (`-runs`)
+    pub runs: Option<usize>,
+    /// Maximum length of the inputs, `None` for the default (`-max_len`)
+    pub max_len: Option<usize>,
+    /// Timeout for each run, a zero duration disables it (`-timeout`, in seconds)
+    pub timeout: Duration,
+    /// Maximal total time of the fuzzing, `None` to run indefinitely (`-max_total_time`, in seconds)
+    pub max_total_time: Option<Duration>,
+    /// Dictionary file (`-dict`)
+    pub dict: Option<PathBuf>,
+    /// Number of jobs to run (`-jobs`)
+    pub jobs: usize,
+    /// Number of simultaneous workers, `0` to pick it from the number of cores (`-workers`)
+    pub workers: usize,
+    /// Merge the corpus directories after the first one into the first one (`-merge=1`)
+    pub merge: bool,
+    /// Minimize the crashing input given as positional argument (`-minimize_crash=1`)
+    pub minimize_crash: bool,
+    /// Prefix of the paths where the crashes are written (`-artifact_prefix`)
+    pub artifact_prefix: String,
+    /// Random seed, `0` to generate one (`-seed`)
+    pub seed: u64,
+    /// Print the supported flags and exit (`-help=1`)
+    pub help: bool,
+    /// Corpus directories or input files, the positional arguments
+    pub inputs: Vec<PathBuf>,
+    /// Flags in [`LIBFUZZER_IGNORED_FLAGS`] or unknown flags that were passed, and ignored
+    pub ignored: Vec<String>,
+}
+
+impl Default for LibfuzzerOptions {
+    fn default() -> Self {
+        Self {
+            runs: None,
+            max_len: None,
+            timeout: Duration::from_secs(LIBFUZZER_DEFAULT_TIMEOUT_SECS),
+            max_total_time: None,
+            dict: None,
+            jobs: 0,
+            workers: 0,
+            merge: false,
+            minimize_crash: false,
+            artifact_prefix: String::new(),
+            seed: 0,
+            help: false,
+            inputs: Vec::new(),
+            ignored: Vec::new(),
+        }
+    }
+}
+
+impl LibfuzzerOptions {
+    /// Parses `libFuzzer` flags; the first argument is the program name, and is skipped
+    pub fn parse_from<I, T>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let mut options = Self::default();
+        for arg in args.into_iter().skip(1) {
+            let arg: String = arg.into();
+            if !arg.starts_with('-') || arg == "-" {
+                options.inputs.push(PathBuf::from(arg));
+                continue;
+            }
+
+            // libFuzzer ignores the flags starting with `--` and the ones without a value
+            let (name, value) = match arg[1..].split_once('=') {
+                Some(name_value) if !arg.starts_with("--") => name_value,
+                _ => {
+                    options.ignored.push(arg);
+                    continue;
+                }
+            };
+
+            match name {
+                // libFuzzer takes -1 for infinite runs
+                "runs" => {
+                    options.runs = usize::try_from(parse_flag::<i64>(name, value)?).ok();
+                }
+                "max_len" => {
+                    options.max_len = Some(parse_flag::<usize>(name, value)?).filter(|l| *l > 0);
+                }
+                "timeout" => {
+                    // Non-positive timeouts disable it
+                    let secs = parse_flag::<i64>(name, value)?;
+                    options.timeout = Duration::from_secs(u64::try_from(secs).unwrap_or(0));
+                }
+                "max_total_time" => {
+                    let secs = parse_flag::<i64>(name, value)?;
+                    options.max_total_time = u64::try_from(secs)
+                        .ok()
+                        .filter(|secs| *secs > 0)
+                        .map(Duration::from_secs);
+                }
+                "dict" => options.dict = Some(PathBuf::from(value)),
+                "jobs" => options.jobs = parse_flag(name, value)?,
+                "workers" => options.workers = parse_flag(name, value)?,
+                "merge" => options.merge = parse_flag::<i64>(name, value)? != 0,
+                "minimize_crash" => options.minimize_crash = parse_flag::<i64>(name, value)? != 0,
+                "artifact_prefix" => options.artifact_prefix = value.to_string(),
+                "seed" => options.seed = parse_flag(name, value)?,
+                "help" => options.help = parse_flag::<i64>(name, value)? != 0,
+                "ignore_remaining_args" => {
+                    if parse_flag::<i64>(name, value)? != 0 {
+                        break;
+                    }
+                }
+                // The flags in LIBFUZZER_IGNORED_FLAGS, and the unknown ones
+                _ => options.ignored.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    /// The usage message listing the supported flags
+    #[must_use]
+    pub fn usage(program: &str) -> String {
+        let mut usage = format!("Usage:\n\nTo run fuzzing pass 0 or more directories.\n{program} [-flag1=val1 [-flag2=val2 ...] ] [dir1 [dir2 ...] ]\n\nTo run individual tests without fuzzing pass 1 or more files:\n{program} [-flag1=val1 [-flag2=val2 ...] ] file1 [file2 ...]\n\nFlags: (strictly in form -flag=value)\n");
+        for (name, description) in LIBFUZZER_FLAGS {
+            writeln!(usage, " {name:<24}{description}").unwrap();
+        }
+        usage
+    }
+
+    /// The number of fuzzing processes to spawn for the given `-jobs` and `-workers`,
+    /// given the number of available cores
+    #[must_use]
+    pub fn worker_count(&self, num_cores: usize) -> usize {
+        if self.jobs <= 1 {
+            1
+        } else if self.workers > 0 {
+            self.workers.min(self.jobs)
+        } else {
+            (num_cores / 2).clamp(1, self.jobs)
+        }
+    }
+
+    /// The directory in which the crashes are written, derived from the `-artifact_prefix`
+    #[must_use]
+    pub fn artifact_dir(&self) -> PathBuf {
+        if self.artifact_prefix.is_empty() {
+            PathBuf::from(".")
+        } else if self.artifact_prefix.ends_with('/') {
+            PathBuf::from(&self.artifact_prefix)
+        } else {
+            PathBuf::from(&self.artifact_prefix)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), PathBuf::from)
+        }
+    }
+
+    /// The path of an artifact, prefixed with the `-artifact_prefix`
+    #[must_use]
+    pub fn artifact_path(&self, name: &str) -> PathBuf {
+        PathBuf::from(format!("{}{name}", self.artifact_prefix))
+    }
+}
+
+/// helper function to parse the value of a `libFuzzer` flag
+fn parse_flag<T>(name: &str, value: &str) -> Result<T, Error>
+where
+    T: FromStr,
+{
+    value
+        .parse()
+        .map_err(|_| Error::illegal_argument(format!("Invalid value for -{name}: {value}")))
+}
+
+/// Parse `libFuzzer` flags from `std::env::args()`, print the usage and exit on error or `-help=1`
+///
+/// for more information, see the [`LibfuzzerOptions`] documentation
+#[must_use]
+pub fn parse_libfuzzer_args() -> LibfuzzerOptions {
+    let program = std::env::args().next().unwrap_or_default();
+    match LibfuzzerOptions::parse_from(std::env::args()) {
+        Ok(options) if options.help => {
+            println!("{}", LibfuzzerOptions::usage(&program));
+            std::process::exit(0);
+        }
+        Ok(options) => options,
+        Err(err) => {
+            println!("ERROR: {err}\n\n{}", LibfuzzerOptions::usage(&program));
+            std::process::exit(1);
+        }
+    }
+}
+
 #[cfg(all(
     test,
     any(feature = "cli", feature = "qemu_cli", feature = "frida_cli")
@@ -420,6 +756,131 @@ mod tests {
         );
     }
 
+    /// pass a symbol and an offset to `parse_module_function`, expect both to be kept as strings
+    #[test]
+    #[cfg(feature = "frida_cli")]
+    fn parse_module_function_succeeds() {
+        assert_eq!(
+            parse_module_function("libz.so@inflate").unwrap(),
+            (String::from("libz.so"), String::from("inflate"))
+        );
+        assert_eq!(
+            parse_module_function("libz.so@0x1234").unwrap(),
+            (String::from("libz.so"), String::from("0x1234"))
+        );
+        parse_module_function("libz.so@").unwrap_err();
+        parse_module_function("inflate").unwrap_err();
+    }
+
+    /// pass the persistent options, expect the argument pairs to be parsed
+    #[test]
+    #[cfg(feature = "frida_cli")]
+    fn persistent_options_are_parsed() {
+        let parsed = FuzzerOptions::parse_from([
+            "some-command",
+            "--persistent-function",
+            "libz.so@inflate",
+            "--persistent-args",
+            "0:1",
+            "--persistent-args",
+            "2:3",
+        ]);
+        assert_eq!(parsed.persistent_args, [(0, 1), (2, 3)]);
+        assert_eq!(parsed.persistent_iterations, 1);
+        parse_argument_pair("0").unwrap_err();
+    }
+
+    /// pass the flags of a typical `libFuzzer` invocation, expect them to be parsed with the
+    /// positional arguments collected into `inputs`
+    #[test]
+    #[cfg(feature = "cli")]
+    fn libfuzzer_flags_are_parsed() {
+        let parsed = LibfuzzerOptions::parse_from([
+            "fuzz-target",
+            "-runs=100",
+            "-max_len=4096",
+            "-timeout=25",
+            "-max_total_time=3600",
+            "-dict=fuzz.dict",
+            "-jobs=8",
+            "-workers=4",
+            "-artifact_prefix=/out/crash-",
+            "-seed=1337",
+            "corpus",
+            "seeds",
+        ])
+        .unwrap();
+        assert_eq!(parsed.runs, Some(100));
+        assert_eq!(parsed.max_len, Some(4096));
+        assert_eq!(parsed.timeout, Duration::from_secs(25));
+        assert_eq!(parsed.max_total_time, Some(Duration::from_secs(3600)));
+        assert_eq!(parsed.dict, Some(PathBuf::from("fuzz.dict")));
+        assert_eq!(parsed.worker_count(16), 4);
+        assert_eq!(parsed.artifact_dir(), PathBuf::from("/out"));
+        assert_eq!(parsed.seed, 1337);
+        assert_eq!(
+            parsed.inputs,
+            [PathBuf::from("corpus"), PathBuf::from("seeds")]
+        );
+        assert!(!parsed.merge && !parsed.minimize_crash);
+    }
+
+    /// pass infinite runs, ignored flags and flags after `-ignore_remaining_args=1`, expect the
+    /// ignored ones to be collected into `ignored` and the remaining ones to be skipped
+    #[test]
+    #[cfg(feature = "cli")]
+    fn libfuzzer_ignored_and_remaining_flags_are_skipped() {
+        let parsed = LibfuzzerOptions::parse_from([
+            "fuzz-target",
+            "-runs=-1",
+            "-rss_limit_mb=2560",
+            "-minimize_crash=1",
+            "crash-1234",
+            "-ignore_remaining_args=1",
+            "-merge=1",
+            "--unknown",
+        ])
+        .unwrap();
+        assert_eq!(parsed.runs, None);
+        assert_eq!(parsed.ignored, ["-rss_limit_mb=2560"]);
+        assert!(parsed.minimize_crash && !parsed.merge);
+        assert_eq!(parsed.inputs, [PathBuf::from("crash-1234")]);
+    }
+
+    /// pass the unknown and malformed flags that OSS-Fuzz style scripts pass, expect them to be
+    /// ignored like `libFuzzer` does, and only invalid values of the supported flags to be errors
+    #[test]
+    #[cfg(feature = "cli")]
+    fn libfuzzer_unknown_flags_are_ignored() {
+        let parsed = LibfuzzerOptions::parse_from([
+            "fuzz-target",
+            "-exact_artifact_path=/out/crash",
+            "-print_final_stats=1",
+            "-fork=2",
+            "--merge=1",
+            "-only_ascii",
+            "-max_total_time=0",
+            "corpus",
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed.ignored,
+            [
+                "-exact_artifact_path=/out/crash",
+                "-print_final_stats=1",
+                "-fork=2",
+                "--merge=1",
+                "-only_ascii",
+            ]
+        );
+        assert!(!parsed.merge);
+        assert_eq!(parsed.max_total_time, None);
+        assert_eq!(parsed.inputs, [PathBuf::from("corpus")]);
+
+        LibfuzzerOptions::parse_from(["fuzz-target", "-runs=many"]).unwrap_err();
+        LibfuzzerOptions::parse_from(["fuzz-target", "-max_total_time=1h"]).unwrap_err();
+    }
+
     /// pass normal value to `parse_timeout` and get back Duration, simple test for happy-path
     #[test]
     #[cfg(feature = "cli")]
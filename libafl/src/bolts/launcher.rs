@@ -14,13 +14,13 @@
 use alloc::string::ToString;
 use core::fmt::{self, Debug, Formatter};
 #[cfg(feature = "std")]
-use core::marker::PhantomData;
-#[cfg(feature = "std")]
-use std::net::SocketAddr;
+use core::{marker::PhantomData, time::Duration};
 #[cfg(all(feature = "std", any(windows, not(feature = "fork"))))]
 use std::process::Stdio;
 #[cfg(all(unix, feature = "std", feature = "fork"))]
 use std::{fs::File, os::unix::io::AsRawFd};
+#[cfg(feature = "std")]
+use std::{net::SocketAddr, path::PathBuf};
 
 #[cfg(feature = "std")]
 use serde::de::DeserializeOwned;
@@ -36,7 +36,11 @@ use crate::bolts::os::{dup2, fork, ForkResult};
 use crate::inputs::UsesInput;
 #[cfg(feature = "std")]
 use crate::{
-    bolts::{core_affinity::Cores, shmem::ShMemProvider},
+    bolts::{
+        core_affinity::Cores,
+        shmem::ShMemProvider,
+        staterestore::{StateCheckpoint, DEFAULT_CHECKPOINT_INTERVAL},
+    },
     events::{EventConfig, LlmpRestartingEventManager, ManagerKind, RestartingMgr},
     monitors::Monitor,
     state::{HasClientPerfMonitor, HasExecutions},
@@ -84,6 +88,13 @@ where
     /// Then, clients launched by this [`Launcher`] can connect to the original `broker`.
     #[builder(default = true)]
     spawn_broker: bool,
+    /// A directory to periodically checkpoint the state of each client to.
+    /// If set, the clients resume from their checkpoints, for example after a reboot.
+    #[builder(default = None)]
+    checkpoint_dir: Option<PathBuf>,
+    /// The interval between two checkpoints of a client
+    #[builder(default = DEFAULT_CHECKPOINT_INTERVAL)]
+    checkpoint_interval: Duration,
     #[builder(setter(skip), default = PhantomData)]
     phantom_data: PhantomData<(&'a S, &'a SP)>,
 }
@@ -103,6 +114,7 @@ where
             .field("spawn_broker", &self.spawn_broker)
             .field("remote_broker_addr", &self.remote_broker_addr)
             .field("stdout_file", &self.stdout_file)
+            .field("checkpoint_dir", &self.checkpoint_dir)
             .finish_non_exhaustive()
     }
 }
@@ -115,6 +127,13 @@ where
     S: DeserializeOwned + UsesInput + HasExecutions + HasClientPerfMonitor,
     SP: ShMemProvider + 'static,
 {
+    /// The [`StateCheckpoint`] of the client on the given core, if checkpoints are enabled
+    fn checkpoint(&self, core_id: usize) -> Option<StateCheckpoint> {
+        self.checkpoint_dir
+            .as_ref()
+            .map(|dir| StateCheckpoint::for_client(dir, core_id, self.checkpoint_interval))
+    }
+
     /// Launch the broker and the clients and fuzz
     #[cfg(all(unix, feature = "std", feature = "fork"))]
     #[allow(clippy::similar_names)]
@@ -177,6 +196,7 @@ where
                                 cpu_core: Some(*bind_to),
                             })
                             .configuration(self.configuration)
+                            .checkpoint(self.checkpoint(bind_to.id))
                             .build()
                             .launch()?;
 
@@ -245,6 +265,7 @@ where
                         cpu_core: Some(CoreId { id: core_id }),
                     })
                     .configuration(self.configuration)
+                    .checkpoint(self.checkpoint(core_id))
                     .build()
                     .launch()?;
 
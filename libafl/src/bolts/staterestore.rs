@@ -1,12 +1,13 @@
 //! Stores and restores state when a client needs to relaunch.
 //! Uses a [`ShMem`] up to a threshold, then write to disk.
+//! A [`StateCheckpoint`] additionally stores the state on disk periodically, to resume a campaign later.
 use alloc::string::{String, ToString};
-use core::{hash::Hasher, marker::PhantomData, mem::size_of, ptr, slice};
+use core::{hash::Hasher, marker::PhantomData, mem::size_of, ptr, slice, time::Duration};
 use std::{
     env::temp_dir,
     fs::{self, File},
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     ptr::read_volatile,
 };
 
@@ -15,12 +16,16 @@ use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
     bolts::{
+        current_time,
         shmem::{ShMem, ShMemProvider},
         AsSlice,
     },
     Error,
 };
 
+/// The default interval between two saves of a [`StateCheckpoint`]
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
 /// The struct stored on the shared map, containing either the data, or the filename to read contents from.
 #[repr(C)]
 struct StateShMemContent {
@@ -240,6 +245,113 @@ where
     }
 }
 
+/// A [`StateCheckpoint`] periodically saves a state to a file on disk.
+/// Unlike the [`StateRestorer`], which only survives restarts of a client within the lifetime of its
+/// broker, a checkpoint survives the whole process tree being killed, or the machine rebooting.
+/// Loading the state of a checkpoint resumes the campaign, with its corpus, metadata, executions and rng.
+/// A checkpoint should only be loaded by the same fuzzer binary that saved it.
+#[derive(Debug, Clone)]
+pub struct StateCheckpoint {
+    path: PathBuf,
+    interval: Duration,
+    last_save: Duration,
+}
+
+impl StateCheckpoint {
+    /// Create a new [`StateCheckpoint`], saving to the given file every `interval`
+    pub fn new<P>(path: P, interval: Duration) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            interval,
+            last_save: current_time(),
+        }
+    }
+
+    /// Create a new [`StateCheckpoint`] for the client with the given id, in the given directory
+    pub fn for_client<P>(dir: P, client_id: usize, interval: Duration) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self::new(
+            dir.as_ref()
+                .join(format!("client-{client_id}.libafl_checkpoint")),
+            interval,
+        )
+    }
+
+    /// The file this [`StateCheckpoint`] saves to
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves the state to disk, atomically replacing the previous checkpoint.
+    /// The new checkpoint is synced to disk before it replaces the previous one, and the rename is
+    /// synced after, so that a crash of the machine leaves one of them, complete, at the path.
+    pub fn save<S>(&mut self, state: &S) -> Result<(), Error>
+    where
+        S: Serialize,
+    {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)?;
+        let serialized = postcard::to_allocvec(state)?;
+
+        let mut tmpfile_name = self.path.clone();
+        tmpfile_name.set_file_name(format!(
+            ".{}.tmp",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let mut tmpfile = File::create(&tmpfile_name)?;
+        tmpfile.write_all(&serialized)?;
+        tmpfile.sync_all()?;
+        drop(tmpfile);
+        fs::rename(&tmpfile_name, &self.path)?;
+        // The directories can only be opened, and synced, on unix
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
+
+        self.last_save = current_time();
+        Ok(())
+    }
+
+    /// Saves the state to disk, if the interval passed since the last save.
+    /// Returns `true` if the state was saved.
+    pub fn maybe_save<S>(&mut self, state: &S) -> Result<bool, Error>
+    where
+        S: Serialize,
+    {
+        // default to 0 here to avoid saving on clock skew
+        if current_time()
+            .checked_sub(self.last_save)
+            .unwrap_or_default()
+            < self.interval
+        {
+            return Ok(false);
+        }
+        self.save(state)?;
+        Ok(true)
+    }
+
+    /// Loads the state of the last checkpoint, if any
+    pub fn load<S>(&self) -> Result<Option<S>, Error>
+    where
+        S: DeserializeOwned,
+    {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(postcard::from_bytes(&bytes)?))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -247,12 +359,13 @@ mod tests {
         string::{String, ToString},
         vec::Vec,
     };
+    use core::time::Duration;
 
     use serial_test::serial;
 
     use crate::bolts::{
         shmem::{ShMemProvider, StdShMemProvider},
-        staterestore::StateRestorer,
+        staterestore::{StateCheckpoint, StateRestorer},
     };
 
     #[test]
@@ -304,4 +417,25 @@ mod tests {
         assert!(!state_restorer.has_content());
         assert!(!tmpfile.exists());
     }
+
+    #[test]
+    fn test_state_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("libafl_checkpoint_{}", std::process::id()));
+        let mut checkpoint = StateCheckpoint::for_client(&dir, 3, Duration::from_secs(3600));
+        assert!(checkpoint.load::<String>().unwrap().is_none());
+
+        // The interval did not pass yet
+        assert!(!checkpoint.maybe_save(&"too early".to_string()).unwrap());
+        assert!(checkpoint.load::<String>().unwrap().is_none());
+
+        checkpoint.save(&"hello world".to_string()).unwrap();
+        assert_eq!(checkpoint.path(), dir.join("client-3.libafl_checkpoint"));
+        assert!(!dir.join(".client-3.libafl_checkpoint.tmp").exists());
+
+        // A new checkpoint for the same client, as after a reboot
+        let resumed = StateCheckpoint::for_client(&dir, 3, Duration::from_secs(3600));
+        assert_eq!(resumed.load::<String>().unwrap().unwrap(), "hello world");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
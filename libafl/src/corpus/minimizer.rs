@@ -1,28 +1,42 @@
 //! Whole corpus minimizers, for reducing the number of samples/the total size/the average runtime
 //! of your corpus.
+//!
+//! The [`MapCorpusMinimizer`] needs z3, and the `cmin` feature; the [`GreedyCorpusMinimizer`] works
+//! like `afl-cmin` and is always available.
 
 use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use core::{hash::Hash, marker::PhantomData};
+use core::{cmp::Ordering, hash::Hash, marker::PhantomData};
+#[cfg(feature = "std")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use hashbrown::{HashMap, HashSet};
+use hashbrown::{hash_map::Entry, HashMap, HashSet};
+#[cfg(feature = "cmin")]
 use num_traits::ToPrimitive;
+#[cfg(feature = "cmin")]
 use z3::{ast::Bool, Config, Context, Optimize};
 
 use crate::{
     bolts::{
+        current_time,
         tuples::{MatchName, Named},
         AsIter,
     },
     corpus::Corpus,
     executors::{Executor, HasObservers},
+    inputs::UsesInput,
     observers::{MapObserver, ObserversTuple},
     schedulers::{LenTimeMulTestcaseScore, Scheduler, TestcaseScore},
     state::{HasCorpus, HasMetadata, UsesState},
     Error, HasScheduler,
 };
+#[cfg(feature = "std")]
+use crate::{corpus::Testcase, inputs::Input};
 
 /// `CorpusMinimizers` minimize corpora according to internal logic. See various implementations for
 /// details.
@@ -49,6 +63,7 @@ where
 /// Minimizes a corpus according to coverage maps, weighting by the specified `TestcaseScore`.
 ///
 /// Algorithm based on WMOPT: <https://hexhive.epfl.ch/publications/files/21ISSTA2.pdf>
+#[cfg(feature = "cmin")]
 #[derive(Debug)]
 pub struct MapCorpusMinimizer<E, O, T, TS>
 where
@@ -61,9 +76,11 @@ where
 }
 
 /// Standard corpus minimizer, which weights inputs by length and time.
+#[cfg(feature = "cmin")]
 pub type StdCorpusMinimizer<E, O, T> =
     MapCorpusMinimizer<E, O, T, LenTimeMulTestcaseScore<<E as UsesState>::State>>;
 
+#[cfg(feature = "cmin")]
 impl<E, O, T, TS> MapCorpusMinimizer<E, O, T, TS>
 where
     E: UsesState,
@@ -83,6 +100,7 @@ where
     }
 }
 
+#[cfg(feature = "cmin")]
 impl<E, O, T, TS> CorpusMinimizer<E> for MapCorpusMinimizer<E, O, T, TS>
 where
     E: UsesState,
@@ -201,3 +219,360 @@ where
         res
     }
 }
+
+/// Minimizes a corpus greedily according to coverage maps, like `afl-cmin`, without a solver.
+///
+/// Each map entry with a non-initial value (a hitcount bucket, when observing with a
+/// [`crate::observers::HitcountsMapObserver`]) is a tuple. Going from the rarest tuple to the most
+/// common one, the testcase with the lowest `TestcaseScore` for each tuple not covered yet is kept,
+/// and all of its tuples are marked as covered.
+#[derive(Debug)]
+pub struct GreedyCorpusMinimizer<E, O, T, TS>
+where
+    E: UsesState,
+    E::State: HasCorpus + HasMetadata,
+    TS: TestcaseScore<E::State>,
+{
+    obs_name: String,
+    phantom: PhantomData<(E, O, T, TS)>,
+}
+
+/// Standard greedy corpus minimizer, which keeps the smallest and fastest testcase for each tuple.
+pub type StdGreedyCorpusMinimizer<E, O, T> =
+    GreedyCorpusMinimizer<E, O, T, LenTimeMulTestcaseScore<<E as UsesState>::State>>;
+
+impl<E, O, T, TS> GreedyCorpusMinimizer<E, O, T, TS>
+where
+    E: UsesState,
+    E::State: HasCorpus + HasMetadata,
+    TS: TestcaseScore<E::State>,
+{
+    /// Constructs a new `GreedyCorpusMinimizer` from a provided observer. This observer will be used
+    /// in the future to get observed maps from an executed input.
+    pub fn new(obs: &O) -> Self
+    where
+        O: Named,
+    {
+        Self {
+            obs_name: obs.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, O, T, TS> CorpusMinimizer<E> for GreedyCorpusMinimizer<E, O, T, TS>
+where
+    E: UsesState,
+    for<'a> O: MapObserver<Entry = T> + AsIter<'a, Item = T>,
+    E::State: HasMetadata + HasCorpus,
+    T: Copy + Hash + Eq,
+    TS: TestcaseScore<E::State>,
+{
+    fn minimize<CS, EM, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        state: &mut E::State,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, Z> + HasObservers,
+        CS: Scheduler<State = E::State>,
+        EM: UsesState<State = E::State>,
+        Z: HasScheduler<Scheduler = CS, State = E::State>,
+    {
+        // The tuples of each testcase, and the best testcase and the number of testcases for each tuple
+        let mut tuples: Vec<Vec<(usize, T)>> = Vec::with_capacity(state.corpus().count());
+        let mut best: HashMap<(usize, T), (f64, usize)> = HashMap::new();
+        let mut counts: HashMap<(usize, T), usize> = HashMap::new();
+
+        for idx in 0..state.corpus().count() {
+            let input = state.corpus().get(idx)?.borrow_mut().load_input()?.clone();
+
+            // Execute the input; we cannot rely on the metadata already being present.
+            executor.observers_mut().pre_exec_all(state, &input)?;
+            let start = current_time();
+            let kind = executor.run_target(fuzzer, state, manager, &input)?;
+            let exec_time = current_time().saturating_sub(start);
+            executor
+                .observers_mut()
+                .post_exec_all(state, &input, &kind)?;
+
+            let weight = {
+                let mut testcase = state.corpus().get(idx)?.borrow_mut();
+                if testcase.exec_time().is_none() {
+                    testcase.set_exec_time(exec_time);
+                }
+                TS::compute(&mut *testcase, state)?
+            };
+
+            let obs: &O = executor
+                .observers()
+                .match_name::<O>(&self.obs_name)
+                .expect("Observer must be present.");
+            let initial = obs.initial();
+            let covered: Vec<(usize, T)> = obs
+                .as_iter()
+                .copied()
+                .enumerate()
+                .filter(|(_, e)| *e != initial)
+                .collect();
+
+            for tuple in &covered {
+                *counts.entry(*tuple).or_default() += 1;
+                match best.entry(*tuple) {
+                    Entry::Occupied(mut entry) => {
+                        if weight < entry.get().0 {
+                            entry.insert((weight, idx));
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert((weight, idx));
+                    }
+                }
+            }
+            tuples.push(covered);
+        }
+
+        // The rarest tuples first, they leave the least choice
+        let mut order: Vec<(usize, T)> = counts.keys().copied().collect();
+        order.sort_by(|t1, t2| match counts[t1].cmp(&counts[t2]) {
+            Ordering::Equal => best[t1].1.cmp(&best[t2].1),
+            ord => ord,
+        });
+
+        let mut covered = HashSet::with_capacity(order.len());
+        let mut kept = vec![false; tuples.len()];
+        for tuple in order {
+            if covered.contains(&tuple) {
+                continue;
+            }
+            let idx = best[&tuple].1;
+            kept[idx] = true;
+            covered.extend(tuples[idx].iter().copied());
+        }
+
+        // reverse order; if indexes are stored in a vec, we need to remove from back to front
+        for idx in (0..kept.len()).rev() {
+            if !kept[idx] {
+                let removed = state.corpus_mut().remove(idx)?;
+                // scheduler needs to know we've removed the input, or it will continue to try
+                // to use now-missing inputs
+                fuzzer.scheduler_mut().on_remove(state, idx, &removed)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merges the inputs of the given directories into `out_dir`, keeping only the ones selected by
+/// the [`CorpusMinimizer`], like `afl-cmin`. Returns the number of inputs written to `out_dir`.
+///
+/// The inputs are added to the corpus of the given state, which should be empty, and then
+/// minimized with the given executor.
+#[cfg(feature = "std")]
+pub fn minimize_dirs<CM, CS, E, EM, Z>(
+    minimizer: &CM,
+    fuzzer: &mut Z,
+    executor: &mut E,
+    manager: &mut EM,
+    state: &mut E::State,
+    in_dirs: &[PathBuf],
+    out_dir: &Path,
+) -> Result<usize, Error>
+where
+    CM: CorpusMinimizer<E>,
+    E: Executor<EM, Z> + HasObservers,
+    E::State: HasCorpus,
+    CS: Scheduler<State = E::State>,
+    EM: UsesState<State = E::State>,
+    Z: HasScheduler<Scheduler = CS, State = E::State>,
+{
+    let mut files = Vec::new();
+    for in_dir in in_dirs {
+        collect_files(in_dir, &mut files)?;
+    }
+    for path in files {
+        let input = <E::State as UsesInput>::Input::from_file(&path)?;
+        let idx = state.corpus_mut().add(Testcase::new(input))?;
+        fuzzer.scheduler_mut().on_add(state, idx)?;
+    }
+
+    minimizer.minimize(fuzzer, executor, manager, state)?;
+
+    fs::create_dir_all(out_dir)?;
+    let count = state.corpus().count();
+    for idx in 0..count {
+        let mut testcase = state.corpus().get(idx)?.borrow_mut();
+        let input = testcase.load_input()?;
+        input.to_file(out_dir.join(input.generate_name(idx)))?;
+    }
+    Ok(count)
+}
+
+/// Collects the non-empty files in `dir`, recursively
+#[cfg(feature = "std")]
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Ok(attr) = fs::metadata(&path) {
+            if attr.is_file() && attr.len() > 0 {
+                files.push(path);
+            } else if attr.is_dir() {
+                collect_files(&path, files)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::marker::PhantomData;
+
+    use crate::{
+        bolts::{
+            rands::StdRand,
+            tuples::{tuple_list, tuple_list_type, MatchName},
+            AsMutSlice,
+        },
+        corpus::{
+            minimizer::{CorpusMinimizer, GreedyCorpusMinimizer},
+            Corpus, InMemoryCorpus, Testcase,
+        },
+        events::NopEventManager,
+        executors::{Executor, ExitKind, HasObservers},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasBytesVec, UsesInput},
+        observers::{StdMapObserver, UsesObservers},
+        schedulers::{QueueScheduler, TestcaseScore},
+        state::{HasCorpus, HasMetadata, StdState, UsesState},
+        Error, StdFuzzer,
+    };
+
+    type TestState =
+        StdState<BytesInput, InMemoryCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>>;
+    type TestObservers = tuple_list_type!(StdMapObserver<'static, u8, false>);
+
+    /// Counts the occurrences of each byte of the input, modulo the map size
+    #[derive(Debug)]
+    struct CountingExecutor {
+        observers: TestObservers,
+    }
+
+    impl UsesState for CountingExecutor {
+        type State = TestState;
+    }
+
+    impl UsesObservers for CountingExecutor {
+        type Observers = TestObservers;
+    }
+
+    impl HasObservers for CountingExecutor {
+        fn observers(&self) -> &Self::Observers {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut Self::Observers {
+            &mut self.observers
+        }
+    }
+
+    impl<EM, Z> Executor<EM, Z> for CountingExecutor
+    where
+        EM: UsesState<State = TestState>,
+        Z: UsesState<State = TestState>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            _state: &mut Self::State,
+            _mgr: &mut EM,
+            input: &<Self::State as UsesInput>::Input,
+        ) -> Result<ExitKind, Error> {
+            let map = self
+                .observers
+                .match_name_mut::<StdMapObserver<'static, u8, false>>("map")
+                .unwrap()
+                .as_mut_slice();
+            for byte in input.bytes() {
+                map[*byte as usize % 8] += 1;
+            }
+            Ok(ExitKind::Ok)
+        }
+    }
+
+    /// Prefers the shortest testcases, deterministically
+    struct LenTestcaseScore<S> {
+        phantom: PhantomData<S>,
+    }
+
+    impl<S> TestcaseScore<S> for LenTestcaseScore<S>
+    where
+        S: HasMetadata + HasCorpus,
+        S::Input: HasBytesVec,
+    {
+        #[allow(clippy::cast_precision_loss)]
+        fn compute(entry: &mut Testcase<S::Input>, _state: &S) -> Result<f64, Error> {
+            Ok(entry.load_input()?.bytes().len() as f64)
+        }
+    }
+
+    #[test]
+    fn test_greedy_corpus_minimizer() {
+        let observer = StdMapObserver::new_owned("map", vec![0_u8; 8]);
+        let minimizer: GreedyCorpusMinimizer<_, _, u8, LenTestcaseScore<TestState>> =
+            GreedyCorpusMinimizer::new(&observer);
+        let mut executor = CountingExecutor {
+            observers: tuple_list!(observer),
+        };
+
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        // `a` is byte 1 of the map, `b` byte 2 and `c` byte 3
+        for input in ["ab", "a", "abc", "c", "aa"] {
+            state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(input.as_bytes().to_vec())))
+                .unwrap();
+        }
+        let mut fuzzer: StdFuzzer<_, _, _, TestObservers> = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+
+        minimizer
+            .minimize(
+                &mut fuzzer,
+                &mut executor,
+                &mut NopEventManager::new(),
+                &mut state,
+            )
+            .unwrap();
+
+        // `a` is covered by `ab`, the smallest one covering `b`, `abc` by `ab` and `c`,
+        // while `aa` hits `a` twice, in another hitcount
+        let kept: Vec<Vec<u8>> = (0..state.corpus().count())
+            .map(|idx| {
+                state
+                    .corpus()
+                    .get(idx)
+                    .unwrap()
+                    .borrow_mut()
+                    .load_input()
+                    .unwrap()
+                    .bytes()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(kept, [b"ab".to_vec(), b"c".to_vec(), b"aa".to_vec()]);
+    }
+}
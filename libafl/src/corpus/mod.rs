@@ -16,11 +16,9 @@ pub mod cached;
 #[cfg(feature = "std")]
 pub use cached::CachedOnDiskCorpus;
 
-#[cfg(feature = "cmin")]
 pub mod minimizer;
 use core::cell::RefCell;
 
-#[cfg(feature = "cmin")]
 pub use minimizer::*;
 
 use crate::{inputs::UsesInput, Error};
@@ -30,7 +30,11 @@ use crate::bolts::{
     llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED},
 };
 #[cfg(feature = "std")]
-use crate::bolts::{llmp::LlmpConnection, shmem::StdShMemProvider, staterestore::StateRestorer};
+use crate::bolts::{
+    llmp::LlmpConnection,
+    shmem::StdShMemProvider,
+    staterestore::{StateCheckpoint, StateRestorer},
+};
 #[cfg(all(unix, feature = "std"))]
 use crate::{
     bolts::os::unix_signals::setup_signal_handler,
@@ -600,6 +604,8 @@ where
     llmp_mgr: LlmpEventManager<S, SP>,
     /// The staterestorer to serialize the state for the next runner
     staterestorer: StateRestorer<SP>,
+    /// The checkpoint to resume the campaign from disk
+    checkpoint: Option<StateCheckpoint>,
 }
 
 #[cfg(feature = "std")]
@@ -617,6 +623,21 @@ where
     S: UsesInput + HasExecutions + HasClientPerfMonitor + HasMetadata + Serialize,
     SP: ShMemProvider,
 {
+    /// Reports the progress, then saves the state to the [`StateCheckpoint`], if due
+    fn maybe_report_progress(
+        &mut self,
+        state: &mut Self::State,
+        last_report_time: Duration,
+        monitor_timeout: Duration,
+    ) -> Result<Duration, Error> {
+        let last_report_time =
+            self.llmp_mgr
+                .maybe_report_progress(state, last_report_time, monitor_timeout)?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.maybe_save(state)?;
+        }
+        Ok(last_report_time)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -717,9 +738,17 @@ where
         Self {
             llmp_mgr,
             staterestorer,
+            checkpoint: None,
         }
     }
 
+    /// Periodically save the state to the given [`StateCheckpoint`]
+    #[must_use]
+    pub fn with_checkpoint(mut self, checkpoint: Option<StateCheckpoint>) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
     /// Get the staterestorer
     pub fn staterestorer(&self) -> &StateRestorer<SP> {
         &self.staterestorer
@@ -799,6 +828,9 @@ where
     /// The type of manager to build
     #[builder(default = ManagerKind::Any)]
     kind: ManagerKind,
+    /// The checkpoint to periodically save the state of the client to, and to resume from on the first run
+    #[builder(default = None)]
+    checkpoint: Option<StateCheckpoint>,
     #[builder(setter(skip), default = PhantomData)]
     phantom_data: PhantomData<S>,
 }
@@ -991,7 +1023,19 @@ where
                 ),
             )
         } else {
-            println!("First run. Let's set it all up");
+            // Resuming a campaign, deserialize state and corpus from disk.
+            let state = match &self.checkpoint {
+                Some(checkpoint) => checkpoint.load()?,
+                None => None,
+            };
+            if state.is_some() {
+                println!(
+                    "First run. Resuming from checkpoint {}",
+                    self.checkpoint.as_ref().unwrap().path().display()
+                );
+            } else {
+                println!("First run. Let's set it all up");
+            }
             // Mgr to send and receive msgs from/to all other fuzzer instances
             let mgr = LlmpEventManager::<S, SP>::existing_client_from_env(
                 new_shmem_provider,
@@ -999,10 +1043,11 @@ where
                 self.configuration,
             )?;
 
-            (None, LlmpRestartingEventManager::new(mgr, staterestorer))
+            (state, LlmpRestartingEventManager::new(mgr, staterestorer))
         };
         // We reset the staterestorer, the next staterestorer and receiver (after crash) will reuse the page from the initial message.
         mgr.staterestorer.reset();
+        mgr.checkpoint = self.checkpoint.take();
 
         /* TODO: Not sure if this is needed
         // We commit an empty NO_RESTART message to this buf, against infinite loops,
@@ -10,6 +10,8 @@ use alloc::{
 };
 #[cfg(all(unix, feature = "std"))]
 use core::ffi::c_void;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::{fmt, hash::Hasher, marker::PhantomData, time::Duration};
 
 use ahash::AHasher;
@@ -428,6 +430,10 @@ pub trait EventFirer: UsesState {
     }
 }
 
+/// The pid last sent by [`ProgressReporter::maybe_report_progress`], to send it once per process
+#[cfg(feature = "std")]
+static REPORTED_PID: AtomicU32 = AtomicU32::new(0);
+
 /// [`ProgressReporter`] report progress to the broker.
 pub trait ProgressReporter: EventFirer
 where
@@ -471,6 +477,23 @@ where
                 )?;
             }
 
+            // Send the pid of the client once, as the monitor may run in another process.
+            // A restarted client runs in a new process, and sends its new pid.
+            #[cfg(feature = "std")]
+            {
+                let pid = std::process::id();
+                if REPORTED_PID.swap(pid, Ordering::Relaxed) != pid {
+                    self.fire(
+                        state,
+                        Event::UpdateUserStats {
+                            name: "pid".to_string(),
+                            value: UserStats::Number(u64::from(pid)),
+                            phantom: PhantomData,
+                        },
+                    )?;
+                }
+            }
+
             // If performance monitor are requested, fire the `UpdatePerfMonitor` event
             #[cfg(feature = "introspection")]
             {
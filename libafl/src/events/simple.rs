@@ -11,6 +11,8 @@ use core::ffi::c_void;
 use core::ptr::write_volatile;
 #[cfg(feature = "std")]
 use core::sync::atomic::{compiler_fence, Ordering};
+#[cfg(feature = "std")]
+use core::time::Duration;
 use core::{fmt::Debug, marker::PhantomData};
 
 #[cfg(feature = "std")]
@@ -28,7 +30,10 @@ use crate::{
 };
 #[cfg(feature = "std")]
 use crate::{
-    bolts::{shmem::ShMemProvider, staterestore::StateRestorer},
+    bolts::{
+        shmem::ShMemProvider,
+        staterestore::{StateCheckpoint, StateRestorer},
+    },
     corpus::Corpus,
     monitors::SimplePrintingMonitor,
     state::{HasCorpus, HasSolutions},
@@ -313,6 +318,8 @@ where
     simple_event_mgr: SimpleEventManager<MT, S>,
     /// [`StateRestorer`] for restarts
     staterestorer: StateRestorer<SP>,
+    /// [`StateCheckpoint`] to resume the campaign from disk
+    checkpoint: Option<StateCheckpoint>,
 }
 
 #[cfg(feature = "std")]
@@ -399,9 +406,26 @@ where
 impl<MT, S, SP> ProgressReporter for SimpleRestartingEventManager<MT, S, SP>
 where
     MT: Monitor,
-    S: UsesInput + HasExecutions + HasClientPerfMonitor + HasMetadata,
+    S: UsesInput + HasExecutions + HasClientPerfMonitor + HasMetadata + Serialize,
     SP: ShMemProvider,
 {
+    /// Reports the progress, then saves the state to the [`StateCheckpoint`], if due
+    fn maybe_report_progress(
+        &mut self,
+        state: &mut Self::State,
+        last_report_time: Duration,
+        monitor_timeout: Duration,
+    ) -> Result<Duration, Error> {
+        let last_report_time = self.simple_event_mgr.maybe_report_progress(
+            state,
+            last_report_time,
+            monitor_timeout,
+        )?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.maybe_save(state)?;
+        }
+        Ok(last_report_time)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -425,18 +449,50 @@ where
     MT: Monitor, //TODO CE: CustomEvent,
 {
     /// Creates a new [`SimpleEventManager`].
-    fn new_launched(monitor: MT, staterestorer: StateRestorer<SP>) -> Self {
+    fn new_launched(
+        monitor: MT,
+        staterestorer: StateRestorer<SP>,
+        checkpoint: Option<StateCheckpoint>,
+    ) -> Self {
         Self {
             staterestorer,
             simple_event_mgr: SimpleEventManager::new(monitor),
+            checkpoint,
         }
     }
 
     /// Launch the simple restarting manager.
     /// This [`EventManager`] is simple and single threaded,
     /// but can still used shared maps to recover from crashes and timeouts.
+    pub fn launch(monitor: MT, shmem_provider: &mut SP) -> Result<(Option<S>, Self), Error>
+    where
+        S: DeserializeOwned + Serialize + HasCorpus + HasSolutions,
+        MT: Debug,
+    {
+        Self::launch_inner(monitor, shmem_provider, None)
+    }
+
+    /// Launch the simple restarting manager, periodically saving the state to the given [`StateCheckpoint`].
+    /// If the checkpoint exists on the first run, the campaign resumes from the state it holds,
+    /// for example after the whole process tree got killed, or the machine rebooted.
+    pub fn launch_with_checkpoint(
+        monitor: MT,
+        shmem_provider: &mut SP,
+        checkpoint: StateCheckpoint,
+    ) -> Result<(Option<S>, Self), Error>
+    where
+        S: DeserializeOwned + Serialize + HasCorpus + HasSolutions,
+        MT: Debug,
+    {
+        Self::launch_inner(monitor, shmem_provider, Some(checkpoint))
+    }
+
     #[allow(clippy::similar_names)]
-    pub fn launch(mut monitor: MT, shmem_provider: &mut SP) -> Result<(Option<S>, Self), Error>
+    fn launch_inner(
+        mut monitor: MT,
+        shmem_provider: &mut SP,
+        checkpoint: Option<StateCheckpoint>,
+    ) -> Result<(Option<S>, Self), Error>
     where
         S: DeserializeOwned + Serialize + HasCorpus + HasSolutions,
         MT: Debug,
@@ -526,32 +582,34 @@ where
         };
 
         // If we're restarting, deserialize the old state.
-        let (state, mgr) = match staterestorer.restore::<S>()? {
-            None => {
-                println!("First run. Let's set it all up");
-                // Mgr to send and receive msgs from/to all other fuzzer instances
-                (
-                    None,
-                    SimpleRestartingEventManager::new_launched(monitor, staterestorer),
-                )
-            }
-            // Restoring from a previous run, deserialize state and corpus.
-            Some(state) => {
-                println!("Subsequent run. Loaded previous state.");
-                // We reset the staterestorer, the next staterestorer and receiver (after crash) will reuse the page from the initial message.
-                staterestorer.reset();
-
-                // load the corpus size into monitor to still display the correct numbers after restart.
-                let client_stats = monitor.client_stats_mut_for(0);
-                client_stats.update_corpus_size(state.corpus().count().try_into()?);
-                client_stats.update_objective_size(state.solutions().count().try_into()?);
-
-                (
-                    Some(state),
-                    SimpleRestartingEventManager::new_launched(monitor, staterestorer),
-                )
+        let mut state = staterestorer.restore::<S>()?;
+        if state.is_some() {
+            println!("Subsequent run. Loaded previous state.");
+            // We reset the staterestorer, the next staterestorer and receiver (after crash) will reuse the page from the initial message.
+            staterestorer.reset();
+        } else if let Some(checkpoint) = &checkpoint {
+            // Resuming a campaign, deserialize state and corpus from disk.
+            state = checkpoint.load()?;
+            if state.is_some() {
+                println!(
+                    "First run. Resuming from checkpoint {}",
+                    checkpoint.path().display()
+                );
             }
-        };
+        }
+        if state.is_none() {
+            println!("First run. Let's set it all up");
+        }
+
+        if let Some(state) = &state {
+            // load the corpus size into monitor to still display the correct numbers after restart.
+            let client_stats = monitor.client_stats_mut_for(0);
+            client_stats.update_corpus_size(state.corpus().count().try_into()?);
+            client_stats.update_objective_size(state.solutions().count().try_into()?);
+        }
+
+        // Mgr to send and receive msgs from/to all other fuzzer instances
+        let mgr = SimpleRestartingEventManager::new_launched(monitor, staterestorer, checkpoint);
 
         /* TODO: Not sure if this is needed
         // We commit an empty NO_RESTART message to this buf, against infinite loops,
@@ -0,0 +1,191 @@
+//! A `ChecksumFixupExecutor` repairs the checksums detected by the
+//! [`crate::stages::ChecksumDetectionStage`] before running each input.
+//! The target never fails on a checksum comparison, as if it was patched out, at the cost of
+//! tracing each input before running it. Usually, repairing only the corpus entries with a
+//! [`crate::stages::ChecksumRepairStage`] is enough.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::HasBytesVec,
+    observers::{CmpMap, CmpObserver, ObserversTuple, UsesObservers},
+    stages::checksum::{repair_checksums, DEFAULT_CHECKSUM_FIXUP_ROUNDS},
+    state::{HasClientPerfMonitor, HasExecutions, HasMetadata, UsesState},
+    Error,
+};
+
+/// A state metadata holding the repaired version of the last executed input, if any
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RepairedInputMetadata {
+    /// The bytes of the repaired input, `None` if the last input did not need repairs
+    pub input: Option<Vec<u8>>,
+}
+
+crate::impl_serdeany!(RepairedInputMetadata);
+
+/// A [`ChecksumFixupExecutor`] wraps a primary executor and a cmplog tracer executor.
+///
+/// Before each run, the input is traced and the stored checksums listed in the
+/// [`struct@crate::stages::checksum::ChecksumMetadata`] are overwritten with the computed ones,
+/// tracing up to `max_rounds` times, each counted as an execution; then the repaired input is
+/// run in the primary executor. The repaired input is stored in the [`struct@RepairedInputMetadata`],
+/// use a [`crate::feedbacks::ChecksumRepairFeedback`] to store it instead of the original one.
+///
+/// The [`CmpObserver`] of the tracer should not add metadata to the state, or it will overwrite the
+/// `CmpValuesMetadata` of the tracing stages at each execution.
+#[derive(Debug)]
+pub struct ChecksumFixupExecutor<CM, CO, E, TE> {
+    primary: E,
+    tracer: TE,
+    cmp_observer_name: String,
+    max_rounds: usize,
+    phantom: PhantomData<(CM, CO)>,
+}
+
+impl<CM, CO, E, TE> ChecksumFixupExecutor<CM, CO, E, TE>
+where
+    E: UsesState,
+{
+    /// Create a new `ChecksumFixupExecutor`, wrapping the given `primary` executor and tracing with
+    /// the `tracer` executor and its [`CmpObserver`].
+    pub fn new<EM, Z>(primary: E, tracer: TE, cmp_observer: &CO) -> Self
+    where
+        CM: CmpMap,
+        CO: CmpObserver<CM, E::State>,
+        E: Executor<EM, Z>,
+        TE: Executor<EM, Z, State = E::State>,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        Self {
+            primary,
+            tracer,
+            cmp_observer_name: cmp_observer.name().to_string(),
+            max_rounds: DEFAULT_CHECKSUM_FIXUP_ROUNDS,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of repair rounds for each input
+    #[must_use]
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Retrieve the primary `Executor` that is wrapped by this `ChecksumFixupExecutor`.
+    pub fn primary(&mut self) -> &mut E {
+        &mut self.primary
+    }
+
+    /// Retrieve the tracer `Executor` that is wrapped by this `ChecksumFixupExecutor`.
+    pub fn tracer(&mut self) -> &mut TE {
+        &mut self.tracer
+    }
+
+    /// Traces the input and repairs its checksums, returns `None` if no checksum had to be fixed
+    pub fn repair<EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        input: &E::Input,
+    ) -> Result<Option<E::Input>, Error>
+    where
+        CM: CmpMap,
+        CO: CmpObserver<CM, E::State>,
+        TE: Executor<EM, Z, State = E::State> + HasObservers,
+        E::State: HasClientPerfMonitor + HasExecutions + HasMetadata,
+        E::Input: HasBytesVec + Clone,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        repair_checksums::<CM, CO, EM, TE, Z>(
+            &mut self.tracer,
+            &self.cmp_observer_name,
+            self.max_rounds,
+            fuzzer,
+            state,
+            mgr,
+            input,
+        )
+    }
+}
+
+impl<CM, CO, E, TE, EM, Z> Executor<EM, Z> for ChecksumFixupExecutor<CM, CO, E, TE>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, E::State>,
+    E: Executor<EM, Z>,
+    TE: Executor<EM, Z, State = E::State> + HasObservers,
+    E::State: HasClientPerfMonitor + HasExecutions + HasMetadata,
+    E::Input: HasBytesVec + Clone,
+    EM: UsesState<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let repaired = self.repair(fuzzer, state, mgr, input)?;
+
+        // Stored before the run, an in-process crash is reported from the crash handler
+        let repaired_bytes = repaired.as_ref().map(|repaired| repaired.bytes().to_vec());
+        if let Some(meta) = state.metadata_mut().get_mut::<RepairedInputMetadata>() {
+            meta.input = repaired_bytes;
+        } else {
+            state.add_metadata(RepairedInputMetadata {
+                input: repaired_bytes,
+            });
+        }
+
+        match &repaired {
+            Some(repaired) => self.primary.run_target(fuzzer, state, mgr, repaired),
+            None => self.primary.run_target(fuzzer, state, mgr, input),
+        }
+    }
+
+    fn post_run_reset(&mut self) {
+        self.primary.post_run_reset();
+    }
+}
+
+impl<CM, CO, E, TE> UsesState for ChecksumFixupExecutor<CM, CO, E, TE>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<CM, CO, E, TE> UsesObservers for ChecksumFixupExecutor<CM, CO, E, TE>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<CM, CO, E, TE> HasObservers for ChecksumFixupExecutor<CM, CO, E, TE>
+where
+    E: HasObservers,
+    E::Observers: ObserversTuple<E::State>,
+{
+    #[inline]
+    fn observers(&self) -> &Self::Observers {
+        self.primary.observers()
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        self.primary.observers_mut()
+    }
+}
@@ -19,7 +19,7 @@ use std::{
 
 use super::HasObservers;
 #[cfg(all(feature = "std", unix))]
-use crate::executors::{Executor, ExitKind};
+use crate::executors::{Executor, ExitKind, IsolatesCrashes};
 use crate::{
     bolts::{
         fs::{InputFile, INPUTFILE_STD},
@@ -392,6 +392,8 @@ where
     }
 }
 
+impl<EM, OT, S, T, Z> IsolatesCrashes for CommandExecutor<EM, OT, S, T, Z> {}
+
 /// The builder for a default [`CommandExecutor`] that should fit most use-cases.
 #[derive(Debug, Clone)]
 pub struct CommandExecutorBuilder {
@@ -32,7 +32,7 @@ use crate::{
         tuples::Prepend,
         AsMutSlice, AsSlice,
     },
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
     inputs::{HasTargetBytes, Input, UsesInput},
     mutators::Tokens,
     observers::{
@@ -1261,6 +1261,10 @@ where
     }
 }
 
+impl<OT, S, SP> IsolatesCrashes for ForkserverExecutor<OT, S, SP> where SP: ShMemProvider {}
+
+impl<E> IsolatesCrashes for TimeoutForkserverExecutor<E> where E: IsolatesCrashes {}
+
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
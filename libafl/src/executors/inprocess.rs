@@ -41,6 +41,8 @@ use crate::bolts::os::unix_signals::{ucontext_t, Handler, Signal};
 use crate::bolts::os::windows_exceptions::setup_exception_handler;
 #[cfg(all(feature = "std", unix))]
 use crate::bolts::shmem::ShMemProvider;
+#[cfg(all(feature = "std", unix))]
+use crate::executors::IsolatesCrashes;
 use crate::{
     events::{EventFirer, EventRestarter},
     executors::{Executor, ExitKind, HasObservers},
@@ -1991,6 +1993,26 @@ where
     }
 }
 
+#[cfg(all(feature = "std", unix))]
+impl<'a, H, OT, S, SP> IsolatesCrashes for InProcessForkExecutor<'a, H, OT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind + ?Sized,
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+{
+}
+
+#[cfg(all(feature = "std", target_os = "linux"))]
+impl<'a, H, OT, S, SP> IsolatesCrashes for TimeoutInProcessForkExecutor<'a, H, OT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind + ?Sized,
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+{
+}
+
 /// signal handlers and `panic_hooks` for the child process
 #[cfg(all(feature = "std", unix))]
 pub mod child_signal_handlers {
@@ -23,6 +23,9 @@ pub use forkserver::{Forkserver, ForkserverExecutor, TimeoutForkserverExecutor};
 pub mod combined;
 pub use combined::CombinedExecutor;
 
+pub mod checksum;
+pub use checksum::ChecksumFixupExecutor;
+
 pub mod shadow;
 pub use shadow::ShadowExecutor;
 
@@ -109,6 +112,10 @@ pub trait HasObservers: UsesObservers {
     fn observers_mut(&mut self) -> &mut Self::Observers;
 }
 
+/// A marker for the executors running the target isolated from the fuzzer, in another process,
+/// so that a crash of the target does not take the fuzzer down with it.
+pub trait IsolatesCrashes {}
+
 /// An executor takes the given inputs, and runs the harness/target.
 pub trait Executor<EM, Z>: UsesState + Debug
 where
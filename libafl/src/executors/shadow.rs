@@ -3,7 +3,7 @@
 use core::fmt::{self, Debug, Formatter};
 
 use crate::{
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
     observers::{ObserversTuple, UsesObservers},
     state::UsesState,
     Error,
@@ -103,3 +103,5 @@ where
         self.executor.observers_mut()
     }
 }
+
+impl<E, SOT> IsolatesCrashes for ShadowExecutor<E, SOT> where E: IsolatesCrashes {}
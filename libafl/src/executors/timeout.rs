@@ -32,7 +32,7 @@ use windows::Win32::{
 #[cfg(all(windows, feature = "std"))]
 use crate::executors::inprocess::{HasInProcessHandlers, GLOBAL_STATE};
 use crate::{
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
     observers::UsesObservers,
     state::UsesState,
     Error,
@@ -425,3 +425,5 @@ where
         self.executor.observers_mut()
     }
 }
+
+impl<E> IsolatesCrashes for TimeoutExecutor<E> where E: IsolatesCrashes {}
@@ -3,7 +3,7 @@
 use core::fmt::Debug;
 
 use crate::{
-    executors::{Executor, ExitKind, HasObservers},
+    executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
     observers::{ObserversTuple, UsesObservers},
     state::UsesState,
     Error,
@@ -63,6 +63,8 @@ where
     }
 }
 
+impl<E, OT> IsolatesCrashes for WithObservers<E, OT> where E: IsolatesCrashes {}
+
 impl<E: Debug, OT: Debug> WithObservers<E, OT> {
     /// Wraps the given [`Executor`] with the given [`ObserversTuple`] to implement [`HasObservers`].
     ///
@@ -0,0 +1,99 @@
+//! The [`ChecksumRepairFeedback`] stores the inputs repaired by the
+//! [`crate::executors::ChecksumFixupExecutor`], so that their checksums hold outside the fuzzer.
+
+use alloc::string::String;
+use core::fmt::Debug;
+
+use crate::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::{checksum::RepairedInputMetadata, ExitKind},
+    feedbacks::Feedback,
+    inputs::{HasBytesVec, UsesInput},
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+
+/// A [`Feedback`] wrapper replacing the input of each new [`Testcase`] with its repaired version,
+/// if the last execution repaired any checksum.
+/// Wrap the objective with it to report solutions that also crash without the fuzzer.
+#[derive(Debug)]
+pub struct ChecksumRepairFeedback<F> {
+    inner: F,
+    name: String,
+}
+
+impl<F, S> Feedback<S> for ChecksumRepairFeedback<F>
+where
+    F: Feedback<S>,
+    S: UsesInput + HasClientPerfMonitor + HasMetadata,
+    S::Input: HasBytesVec,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.inner
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        self.inner.append_metadata(state, testcase)?;
+
+        let repaired = state
+            .metadata()
+            .get::<RepairedInputMetadata>()
+            .and_then(|meta| meta.input.clone());
+        if let (Some(repaired), Some(input)) = (repaired, testcase.input_mut()) {
+            *input.bytes_mut() = repaired;
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+impl<F> Named for ChecksumRepairFeedback<F> {
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<F> ChecksumRepairFeedback<F>
+where
+    F: Named,
+{
+    /// Creates a new [`ChecksumRepairFeedback`] wrapping the given [`Feedback`]
+    pub fn new(inner: F) -> Self {
+        let name = format!("ChecksumRepair({})", inner.name());
+        Self { inner, name }
+    }
+
+    /// The wrapped [`Feedback`]
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+}
@@ -17,6 +17,8 @@ pub use map::*;
 
 pub mod differential;
 pub use differential::DiffFeedback;
+pub mod checksum;
+pub use checksum::ChecksumRepairFeedback;
 #[cfg(feature = "std")]
 pub mod concolic;
 #[cfg(feature = "std")]
@@ -28,6 +30,10 @@ pub mod new_hash_feedback;
 pub use new_hash_feedback::NewHashFeedback;
 #[cfg(feature = "std")]
 pub use new_hash_feedback::NewHashFeedbackMetadata;
+#[cfg(feature = "std")]
+pub mod triage;
+#[cfg(feature = "std")]
+pub use triage::{TriageFeedback, TriageMetadata};
 
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
@@ -0,0 +1,293 @@
+//! The ``TriageFeedback`` groups the solutions in buckets by [`CrashSignature`], keeping one exemplar
+//! for each bucket, and writes a summary index of the buckets next to the solutions.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData, time::Duration};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bolts::{current_time, tuples::Named},
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverName},
+    inputs::UsesInput,
+    observers::{CrashSignature, ObserverWithCrashSignature, ObserversTuple},
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+
+/// The default file name of the summary index, in the solutions directory
+pub const TRIAGE_INDEX_FILENAME: &str = ".triage.json";
+
+/// A bucket of crashes with the same [`CrashSignature`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriageBucket {
+    /// The id of the bucket, see [`CrashSignature::bucket`]
+    pub id: u64,
+    /// The signature shared by the crashes in this bucket
+    pub signature: CrashSignature,
+    /// The number of crashes in this bucket
+    pub count: usize,
+    /// The path of the exemplar of this bucket, stored in the solutions
+    pub exemplar: Option<PathBuf>,
+    /// The time of the first crash in this bucket
+    pub first_seen: Duration,
+    /// The time of the last crash in this bucket
+    pub last_seen: Duration,
+}
+
+/// A state metadata holding the triage buckets
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TriageMetadata {
+    /// The buckets, by id
+    pub buckets: HashMap<u64, TriageBucket>,
+}
+
+crate::impl_serdeany!(TriageMetadata);
+
+impl TriageMetadata {
+    /// Creates a new [`struct@TriageMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total number of crashes, in all the buckets
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.buckets.values().map(|bucket| bucket.count).sum()
+    }
+}
+
+/// A testcase metadata holding the triage bucket of a solution
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriageBucketMetadata {
+    /// The id of the bucket
+    pub bucket: u64,
+    /// The signature of the crash
+    pub signature: CrashSignature,
+}
+
+crate::impl_serdeany!(TriageBucketMetadata);
+
+/// The summary index, as written to disk
+#[derive(Serialize, Debug)]
+struct TriageIndex<'a> {
+    total: usize,
+    buckets: Vec<&'a TriageBucket>,
+}
+
+/// A [`TriageFeedback`] groups the crashes in buckets by the [`CrashSignature`] of an observer,
+/// and considers interesting only the first crash of each bucket, keeping it as exemplar.
+///
+/// Use it as objective together with a [`crate::feedbacks::CrashFeedback`], e.g. with
+/// `feedback_and_fast!(CrashFeedback::new(), TriageFeedback::new(&observer, "solutions"))`, and
+/// store the solutions in an [`crate::corpus::OnDiskCorpus`] in the same directory.
+/// The exemplars are named after their bucket, and the summary index (JSON) in the same directory
+/// lists all the buckets with their counts. Each client keeps its own buckets, give each one its
+/// own index with [`TriageFeedback::with_index_path`] when fuzzing with several clients.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriageFeedback<O, S> {
+    name: String,
+    observer_name: String,
+    solutions_dir: PathBuf,
+    index_path: PathBuf,
+    /// The signature of the last crash, if interesting
+    last_signature: Option<CrashSignature>,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> Feedback<S> for TriageFeedback<O, S>
+where
+    O: ObserverWithCrashSignature + Named + Debug,
+    S: UsesInput + Debug + HasMetadata + HasClientPerfMonitor,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        if !state.has_metadata::<TriageMetadata>() {
+            state.add_metadata(TriageMetadata::new());
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &<S as UsesInput>::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .match_name::<O>(&self.observer_name)
+            .expect("A TriageFeedback needs an ObserverWithCrashSignature");
+
+        match observer.crash_signature() {
+            Some(signature) => self.triage(state, signature),
+            // We get here if no crash happened
+            None => Ok(false),
+        }
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<<S as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        if let Some(signature) = self.last_signature.take() {
+            let id = signature.bucket();
+
+            // Name the exemplar after its bucket, the corpus stores it there
+            let exemplar = self.solutions_dir.join(format!("crash-{id:016x}"));
+            testcase.set_filename(exemplar.to_string_lossy().into_owned());
+            testcase.add_metadata(TriageBucketMetadata {
+                bucket: id,
+                signature,
+            });
+
+            let meta = state.metadata_mut().get_mut::<TriageMetadata>().unwrap();
+            if let Some(bucket) = meta.buckets.get_mut(&id) {
+                bucket.exemplar = Some(exemplar);
+            }
+            self.write_index(meta)?;
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.last_signature = None;
+        Ok(())
+    }
+}
+
+impl<O, S> Named for TriageFeedback<O, S> {
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<O, S> HasObserverName for TriageFeedback<O, S> {
+    #[inline]
+    fn observer_name(&self) -> &str {
+        &self.observer_name
+    }
+}
+
+impl<O, S> TriageFeedback<O, S>
+where
+    O: ObserverWithCrashSignature + Named + Debug,
+{
+    /// Returns a new [`TriageFeedback`], for the solutions stored in `solutions_dir`.
+    /// The summary index is written to [`TRIAGE_INDEX_FILENAME`] in the same directory.
+    #[must_use]
+    pub fn new<P>(observer: &O, solutions_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let solutions_dir = solutions_dir.as_ref().to_path_buf();
+        Self {
+            name: "triage_".to_string() + observer.name(),
+            observer_name: observer.name().to_string(),
+            index_path: solutions_dir.join(TRIAGE_INDEX_FILENAME),
+            solutions_dir,
+            last_signature: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the path of the summary index
+    #[must_use]
+    pub fn with_index_path<P>(mut self, index_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.index_path = index_path.as_ref().to_path_buf();
+        self
+    }
+
+    /// The path of the summary index
+    #[must_use]
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    /// Counts the crash in its bucket, returns `true` if the bucket is new
+    fn triage(&mut self, state: &mut S, signature: CrashSignature) -> Result<bool, Error>
+    where
+        S: HasMetadata,
+    {
+        if !state.has_metadata::<TriageMetadata>() {
+            state.add_metadata(TriageMetadata::new());
+        }
+        let meta = state.metadata_mut().get_mut::<TriageMetadata>().unwrap();
+        let now = current_time();
+        let id = signature.bucket();
+        let is_new = if let Some(bucket) = meta.buckets.get_mut(&id) {
+            bucket.count += 1;
+            bucket.last_seen = now;
+            false
+        } else {
+            meta.buckets.insert(
+                id,
+                TriageBucket {
+                    id,
+                    signature: signature.clone(),
+                    count: 1,
+                    exemplar: None,
+                    first_seen: now,
+                    last_seen: now,
+                },
+            );
+            true
+        };
+
+        if is_new {
+            // The index is written once the exemplar has a name
+            self.last_signature = Some(signature);
+        } else {
+            self.write_index(meta)?;
+        }
+        Ok(is_new)
+    }
+
+    /// Writes the summary index, the buckets sorted by number of crashes
+    fn write_index(&self, meta: &TriageMetadata) -> Result<(), Error> {
+        let mut buckets: Vec<&TriageBucket> = meta.buckets.values().collect();
+        buckets.sort_by(|b1, b2| b2.count.cmp(&b1.count).then(b1.id.cmp(&b2.id)));
+        let index = TriageIndex {
+            total: meta.total(),
+            buckets,
+        };
+
+        if let Some(dir) = self.index_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut tmpfile_name = self.index_path.clone();
+        tmpfile_name.set_file_name(format!(
+            ".{}.tmp",
+            self.index_path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+        ));
+        let mut tmpfile = File::create(&tmpfile_name)?;
+        tmpfile.write_all(&serde_json::to_vec_pretty(&index)?)?;
+        fs::rename(&tmpfile_name, &self.index_path)?;
+        Ok(())
+    }
+}
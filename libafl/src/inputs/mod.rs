@@ -12,6 +12,9 @@ pub use gramatron::*;
 pub mod generalized;
 pub use generalized::*;
 
+pub mod multi;
+pub use multi::*;
+
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 use alloc::{
@@ -0,0 +1,189 @@
+//! An input made of several named byte streams, e.g. the files and the connections a target reads
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{Hash, Hasher};
+
+use ahash::AHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bolts::HasLen,
+    inputs::{HasBytesVec, Input},
+};
+
+/// An input made of several named byte streams.
+///
+/// The bytes mutators act on the selected stream, see [`crate::mutators::StreamSelectMutator`].
+/// The selection is not part of the input: two inputs with the same streams are equal.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MultiStreamInput {
+    streams: Vec<(String, Vec<u8>)>,
+    #[serde(skip)]
+    selected: usize,
+}
+
+impl PartialEq for MultiStreamInput {
+    fn eq(&self, other: &Self) -> bool {
+        self.streams == other.streams
+    }
+}
+
+impl Eq for MultiStreamInput {}
+
+impl Hash for MultiStreamInput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.streams.hash(state);
+    }
+}
+
+impl Input for MultiStreamInput {
+    /// Generate a name for this input
+    fn generate_name(&self, _idx: usize) -> String {
+        let mut hasher = AHasher::new_with_keys(0, 0);
+        for (name, bytes) in &self.streams {
+            hasher.write(name.as_bytes());
+            hasher.write_usize(bytes.len());
+            hasher.write(bytes);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl MultiStreamInput {
+    /// Creates a new input without streams
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stream, or replaces the stream with the same name
+    #[must_use]
+    pub fn with_stream(mut self, name: &str, bytes: Vec<u8>) -> Self {
+        self.set_stream(name, bytes);
+        self
+    }
+
+    /// Adds a stream, or replaces the stream with the same name
+    pub fn set_stream(&mut self, name: &str, bytes: Vec<u8>) {
+        if let Some(idx) = self.index_of(name) {
+            self.streams[idx].1 = bytes;
+        } else {
+            self.streams.push((name.to_string(), bytes));
+        }
+    }
+
+    /// The contents of the stream `name`, if any
+    #[must_use]
+    pub fn stream(&self, name: &str) -> Option<&[u8]> {
+        self.index_of(name).map(|idx| &self.streams[idx].1[..])
+    }
+
+    /// The names and the contents of the streams
+    pub fn streams(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.streams
+            .iter()
+            .map(|(name, bytes)| (name.as_str(), &bytes[..]))
+    }
+
+    /// The number of streams
+    #[must_use]
+    pub fn streams_count(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// The index of the stream `name`, if any
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.streams.iter().position(|(n, _)| n == name)
+    }
+
+    /// The index of the stream the bytes mutators act on
+    #[must_use]
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects the stream the bytes mutators act on
+    pub fn select(&mut self, idx: usize) {
+        assert!(idx < self.streams.len(), "No stream {idx} in the input");
+        self.selected = idx;
+    }
+}
+
+impl HasBytesVec for MultiStreamInput {
+    /// The selected stream, an input without streams gets an empty one
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        self.streams
+            .get(self.selected)
+            .map_or(&[], |(_, bytes)| &bytes[..])
+    }
+
+    #[inline]
+    fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        if self.streams.is_empty() {
+            self.streams.push((String::new(), Vec::new()));
+            self.selected = 0;
+        }
+        &mut self.streams[self.selected].1
+    }
+}
+
+impl HasLen for MultiStreamInput {
+    /// The length of all the streams
+    #[inline]
+    fn len(&self) -> usize {
+        self.streams.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::{Hash, Hasher};
+
+    use ahash::AHasher;
+
+    use crate::inputs::{HasBytesVec, MultiStreamInput};
+
+    #[test]
+    fn test_multi_stream_input() {
+        let mut input = MultiStreamInput::new()
+            .with_stream("config", b"a=b".to_vec())
+            .with_stream("socket", b"GET /".to_vec());
+        assert_eq!(input.streams_count(), 2);
+        assert_eq!(input.bytes(), b"a=b");
+
+        input.select(1);
+        input.bytes_mut().extend_from_slice(b" HTTP/1.1");
+        assert_eq!(input.stream("socket"), Some(&b"GET / HTTP/1.1"[..]));
+
+        input.set_stream("config", vec![]);
+        assert_eq!(input.stream("config"), Some(&[][..]));
+        assert_eq!(input.stream("missing"), None);
+    }
+
+    #[test]
+    fn test_multi_stream_input_selection() {
+        let mut selected = MultiStreamInput::new()
+            .with_stream("config", b"a=b".to_vec())
+            .with_stream("socket", b"GET /".to_vec());
+        let other = selected.clone();
+        selected.select(1);
+
+        // The selected stream does not make a different input
+        assert_eq!(selected, other);
+        let hash = |input: &MultiStreamInput| {
+            let mut hasher = AHasher::new_with_keys(0, 0);
+            input.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&selected), hash(&other));
+
+        selected.bytes_mut().push(b'!');
+        assert_ne!(selected, other);
+        assert_ne!(hash(&selected), hash(&other));
+    }
+}
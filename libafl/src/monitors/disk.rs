@@ -1,9 +1,12 @@
 //! Monitors that wrap a base one and log on disk
 
-use alloc::{string::String, vec::Vec};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::time::Duration;
 use std::{
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::Write,
     path::PathBuf,
 };
@@ -12,7 +15,7 @@ use serde_json::json;
 
 use crate::{
     bolts::{current_time, format_duration_hms},
-    monitors::{ClientStats, Monitor, NopMonitor},
+    monitors::{ClientStats, Monitor, NopMonitor, UserStats},
 };
 
 /// Wrap a monitor and log the current state of the monitor into a TOML file.
@@ -212,3 +215,332 @@ where
         self.base.display(event_msg, sender_id);
     }
 }
+
+/// The default name of the user stats holding the edge coverage, see [`OnDiskAFLMonitor::with_coverage_stats`]
+pub const AFL_MONITOR_DEFAULT_COVERAGE_STATS: &str = "edges";
+
+/// The header of the `plot_data` file, as written by `AFL++`
+const AFL_PLOT_DATA_HEADER: &str = "# relative_time, cycles_done, cur_item, corpus_count, pending_total, pending_favs, map_size, saved_crashes, saved_hangs, max_depth, execs_per_sec, total_execs, edges_found";
+
+/// What the [`OnDiskAFLMonitor`] tracks for each client, in addition to its [`ClientStats`]
+#[derive(Debug, Clone, Copy, Default)]
+struct AFLClientProgress {
+    corpus_size: u64,
+    objective_size: u64,
+    last_find: Duration,
+    last_crash: Duration,
+    execs_at_last_crash: u64,
+}
+
+/// Wrap a monitor and write the stats of each client in the `fuzzer_stats` and `plot_data` formats
+/// of `AFL++`, to use tools like `afl-plot` and `afl-whatsup` with `LibAFL`.
+///
+/// The files of each client are written to their own `client_<id>` directory, like the ones of
+/// `AFL++` instances in a sync directory. The stability and the edge coverage are taken from the
+/// `stability` and the coverage (see [`OnDiskAFLMonitor::with_coverage_stats`]) user stats, the
+/// `fuzzer_pid` from the `pid` user stats the clients send with their progress reports.
+#[derive(Debug, Clone)]
+pub struct OnDiskAFLMonitor<M>
+where
+    M: Monitor,
+{
+    base: M,
+    out_dir: PathBuf,
+    coverage_stats: String,
+    update_interval: Duration,
+    last_update: Duration,
+    progress: Vec<AFLClientProgress>,
+}
+
+impl<M> Monitor for OnDiskAFLMonitor<M>
+where
+    M: Monitor,
+{
+    /// The client monitor, mutable
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        self.base.client_stats_mut()
+    }
+
+    /// The client monitor
+    fn client_stats(&self) -> &[ClientStats] {
+        self.base.client_stats()
+    }
+
+    /// Time this fuzzing run stated
+    fn start_time(&mut self) -> Duration {
+        self.base.start_time()
+    }
+
+    fn display(&mut self, event_msg: String, sender_id: u32) {
+        let cur_time = current_time();
+        self.update_progress(cur_time);
+
+        if cur_time.checked_sub(self.last_update).unwrap_or_default() >= self.update_interval {
+            self.last_update = cur_time;
+
+            for id in 0..self.client_stats().len() {
+                // Skip the clients that did not report anything (yet)
+                if self.client_stats()[id].executions > 0 {
+                    self.write_client(id, cur_time)
+                        .expect("Failed to write the AFL stats");
+                }
+            }
+        }
+
+        self.base.display(event_msg, sender_id);
+    }
+}
+
+impl<M> OnDiskAFLMonitor<M>
+where
+    M: Monitor,
+{
+    /// Create new [`OnDiskAFLMonitor`], writing the stats to `out_dir` every 5 seconds
+    #[must_use]
+    pub fn new<P>(out_dir: P, base: M) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            base,
+            out_dir: out_dir.into(),
+            coverage_stats: AFL_MONITOR_DEFAULT_COVERAGE_STATS.to_string(),
+            update_interval: Duration::from_secs(5),
+            last_update: current_time(),
+            progress: Vec::new(),
+        }
+    }
+
+    /// Sets the name of the user stats holding the edge coverage, that is the (lowercase) name of the
+    /// map observer of the coverage feedback
+    #[must_use]
+    pub fn with_coverage_stats(mut self, coverage_stats: &str) -> Self {
+        self.coverage_stats = coverage_stats.to_string();
+        self
+    }
+
+    /// Sets the interval between two updates of the stats
+    #[must_use]
+    pub fn with_update_interval(mut self, update_interval: Duration) -> Self {
+        self.update_interval = update_interval;
+        self
+    }
+
+    /// Records the time of the last find and of the last crash of each client
+    fn update_progress(&mut self, cur_time: Duration) {
+        let client_stats = self.base.client_stats();
+        if self.progress.len() < client_stats.len() {
+            self.progress
+                .resize(client_stats.len(), AFLClientProgress::default());
+        }
+        for (progress, client) in self.progress.iter_mut().zip(client_stats) {
+            if client.corpus_size > progress.corpus_size {
+                progress.last_find = cur_time;
+            }
+            if client.objective_size > progress.objective_size {
+                progress.last_crash = cur_time;
+                progress.execs_at_last_crash = client.executions;
+            }
+            progress.corpus_size = client.corpus_size;
+            progress.objective_size = client.objective_size;
+        }
+    }
+
+    /// Writes the `fuzzer_stats` and appends to the `plot_data` of the given client
+    #[allow(clippy::cast_precision_loss)]
+    fn write_client(&mut self, id: usize, cur_time: Duration) -> std::io::Result<()> {
+        let start_time = self.base.start_time();
+        let client = &mut self.base.client_stats_mut()[id];
+        let execs_per_sec = client.execs_per_sec(cur_time);
+        let executions = client.executions;
+        let corpus_size = client.corpus_size;
+        let objective_size = client.objective_size;
+
+        let (edges_found, total_edges) = match client.user_monitor.get(&self.coverage_stats) {
+            Some(UserStats::Ratio(filled, len)) => (*filled, *len),
+            _ => (0, 0),
+        };
+        let bitmap_cvg = if total_edges == 0 {
+            0.0
+        } else {
+            (edges_found as f64) * 100.0 / (total_edges as f64)
+        };
+        // The stability stats holds the number of unstable entries out of the entries of the map
+        let stability = match client.user_monitor.get("stability") {
+            Some(UserStats::Ratio(unstable, len)) if *len > 0 => {
+                100.0 - (*unstable as f64) * 100.0 / (*len as f64)
+            }
+            _ => 100.0,
+        };
+        // The monitor may run in the broker, the clients report their own pid
+        let fuzzer_pid = match client.user_monitor.get("pid") {
+            Some(UserStats::Number(pid)) => *pid,
+            _ => 0,
+        };
+
+        let progress = &self.progress[id];
+        let client_dir = self.out_dir.join(format!("client_{id}"));
+        fs::create_dir_all(&client_dir)?;
+
+        let run_time = cur_time.saturating_sub(start_time).as_secs();
+        let secs_or_zero = |time: Duration| {
+            if time.is_zero() {
+                0
+            } else {
+                time.as_secs()
+            }
+        };
+
+        let mut file = File::create(client_dir.join("fuzzer_stats"))?;
+        write!(
+            &mut file,
+            "start_time        : {}
+last_update       : {}
+run_time          : {run_time}
+fuzzer_pid        : {fuzzer_pid}
+cycles_done       : 0
+cycles_wo_finds   : 0
+execs_done        : {executions}
+execs_per_sec     : {execs_per_sec}.00
+corpus_count      : {corpus_size}
+corpus_favored    : 0
+corpus_found      : {corpus_size}
+corpus_imported   : 0
+max_depth         : 0
+cur_item          : 0
+pending_favs      : 0
+pending_total     : 0
+stability         : {stability:.2}%
+bitmap_cvg        : {bitmap_cvg:.2}%
+saved_crashes     : {objective_size}
+saved_hangs       : 0
+last_find         : {}
+last_crash        : {}
+last_hang         : 0
+execs_since_crash : {}
+edges_found       : {edges_found}
+total_edges       : {total_edges}
+afl_banner        : client_{id}
+afl_version       : LibAFL {}
+target_mode       : default
+command_line      : {}
+",
+            start_time.as_secs(),
+            cur_time.as_secs(),
+            secs_or_zero(progress.last_find),
+            secs_or_zero(progress.last_crash),
+            executions.saturating_sub(progress.execs_at_last_crash),
+            env!("CARGO_PKG_VERSION"),
+            std::env::args().collect::<Vec<_>>().join(" "),
+        )?;
+        drop(file);
+
+        let mut plot_data = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(client_dir.join("plot_data"))?;
+        // A restarted fuzzer appends to the plot_data of its previous run
+        if plot_data.metadata()?.len() == 0 {
+            writeln!(&mut plot_data, "{AFL_PLOT_DATA_HEADER}")?;
+        }
+        writeln!(
+            &mut plot_data,
+            "{run_time}, 0, 0, {corpus_size}, 0, 0, {bitmap_cvg:.2}%, {objective_size}, 0, 0, {execs_per_sec}.00, {executions}, {edges_found}"
+        )?;
+
+        Ok(())
+    }
+}
+
+impl OnDiskAFLMonitor<NopMonitor> {
+    /// Create new [`OnDiskAFLMonitor`] without a base
+    #[must_use]
+    pub fn nop<P>(out_dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self::new(out_dir, NopMonitor::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+    use core::time::Duration;
+    use std::fs;
+
+    use crate::monitors::{ClientStats, Monitor, NopMonitor, OnDiskAFLMonitor, UserStats};
+
+    fn add_client(monitor: &mut OnDiskAFLMonitor<NopMonitor>) {
+        let mut client = ClientStats {
+            corpus_size: 12,
+            executions: 1000,
+            objective_size: 1,
+            ..ClientStats::default()
+        };
+        client.update_user_stats("edges".to_string(), UserStats::Ratio(16, 64));
+        client.update_user_stats("stability".to_string(), UserStats::Ratio(8, 80));
+        client.update_user_stats("pid".to_string(), UserStats::Number(4242));
+        monitor.client_stats_mut().push(client);
+    }
+
+    #[test]
+    fn test_afl_monitor_formats() {
+        let out_dir =
+            std::env::temp_dir().join(format!("libafl_afl_monitor_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out_dir);
+
+        let mut monitor = OnDiskAFLMonitor::nop(&out_dir);
+        add_client(&mut monitor);
+        let start_time = monitor.start_time();
+        monitor.update_progress(start_time + Duration::from_secs(5));
+        monitor
+            .write_client(0, start_time + Duration::from_secs(10))
+            .unwrap();
+
+        let client_dir = out_dir.join("client_0");
+        let fuzzer_stats = fs::read_to_string(client_dir.join("fuzzer_stats")).unwrap();
+        let field = |name: &str| {
+            fuzzer_stats
+                .lines()
+                .find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    (key.trim_end() == name).then(|| value.trim().to_string())
+                })
+                .unwrap()
+        };
+        assert_eq!(field("run_time"), "10");
+        assert_eq!(field("fuzzer_pid"), "4242");
+        assert_eq!(field("execs_done"), "1000");
+        assert_eq!(field("corpus_count"), "12");
+        assert_eq!(field("stability"), "90.00%");
+        assert_eq!(field("bitmap_cvg"), "25.00%");
+        assert_eq!(field("saved_crashes"), "1");
+        assert_eq!(field("last_crash"), (start_time.as_secs() + 5).to_string());
+        assert_eq!(field("execs_since_crash"), "0");
+        assert_eq!(field("edges_found"), "16");
+        assert_eq!(field("total_edges"), "64");
+
+        // A restarted monitor appends to the plot_data, without repeating the header
+        let mut monitor = OnDiskAFLMonitor::nop(&out_dir);
+        add_client(&mut monitor);
+        let start_time = monitor.start_time();
+        monitor.update_progress(start_time + Duration::from_secs(20));
+        monitor
+            .write_client(0, start_time + Duration::from_secs(20))
+            .unwrap();
+
+        let plot_data = fs::read_to_string(client_dir.join("plot_data")).unwrap();
+        let lines: Vec<_> = plot_data.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("# relative_time"));
+        for line in &lines[1..] {
+            assert_eq!(line.split(", ").count(), 13);
+        }
+        assert!(lines[1].starts_with("10, 0, 0, 12, 0, 0, 25.00%, 1, 0, 0, "));
+        assert!(lines[2].starts_with("20, "));
+        assert!(lines[2].ends_with(", 1000, 16"));
+
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
@@ -16,6 +16,8 @@ pub mod grimoire;
 pub use grimoire::*;
 pub mod tuneable;
 pub use tuneable::*;
+pub mod multi;
+pub use multi::*;
 
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
@@ -0,0 +1,102 @@
+//! Mutations for the [`MultiStreamInput`]
+
+use crate::{
+    bolts::{rands::Rand, tuples::Named},
+    inputs::{MultiStreamInput, UsesInput},
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+
+/// Selects a random stream of a [`MultiStreamInput`], on which the next bytes mutations in the
+/// stack act. Add it to the bytes mutations of a [`crate::mutators::StdScheduledMutator`].
+#[derive(Default, Debug)]
+pub struct StreamSelectMutator;
+
+impl<S> Mutator<S> for StreamSelectMutator
+where
+    S: UsesInput<Input = MultiStreamInput> + HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut MultiStreamInput,
+        _stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        if input.streams_count() > 1 {
+            let idx = state.rand_mut().below(input.streams_count() as u64) as usize;
+            input.select(idx);
+        }
+        // Selecting a stream does not change the input
+        Ok(MutationResult::Skipped)
+    }
+}
+
+impl Named for StreamSelectMutator {
+    fn name(&self) -> &str {
+        "StreamSelectMutator"
+    }
+}
+
+impl StreamSelectMutator {
+    /// Creates a new [`StreamSelectMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bolts::rands::StdRand,
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{HasBytesVec, MultiStreamInput},
+        mutators::{MutationResult, Mutator, StreamSelectMutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_stream_select_mutator() {
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<MultiStreamInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let mut mutator = StreamSelectMutator::new();
+
+        let mut input = MultiStreamInput::new()
+            .with_stream("config", b"a=b".to_vec())
+            .with_stream("socket", b"GET /".to_vec())
+            .with_stream("file", b"data".to_vec());
+        let original = input.clone();
+        let mut seen = [false; 3];
+        for _ in 0..100 {
+            let result = mutator.mutate(&mut state, &mut input, 0).unwrap();
+            assert_eq!(result, MutationResult::Skipped);
+            assert_eq!(input, original);
+            seen[input.selected()] = true;
+        }
+        assert_eq!(seen, [true; 3]);
+
+        // The next bytes mutations act on the selected stream
+        let selected = input.selected();
+        input.bytes_mut().push(b'!');
+        for (idx, (name, bytes)) in input.streams().enumerate() {
+            let before = original.stream(name).unwrap();
+            if idx == selected {
+                assert_eq!(&bytes[..bytes.len() - 1], before);
+            } else {
+                assert_eq!(bytes, before);
+            }
+        }
+
+        let mut single = MultiStreamInput::new().with_stream("stdin", b"x".to_vec());
+        mutator.mutate(&mut state, &mut single, 0).unwrap();
+        assert_eq!(single.selected(), 0);
+    }
+}
@@ -22,10 +22,12 @@ use serde::{Deserialize, Serialize};
 use crate::mutators::str_decode;
 use crate::{
     bolts::{rands::Rand, AsSlice},
+    corpus::Corpus,
     inputs::{HasBytesVec, UsesInput},
     mutators::{buffer_self_copy, mutations::buffer_copy, MutationResult, Mutator, Named},
     observers::cmp::{CmpValues, CmpValuesMetadata},
-    state::{HasMaxSize, HasMetadata, HasRand},
+    stages::colorization::I2STaintMetadata,
+    state::{HasCorpus, HasMaxSize, HasMetadata, HasRand},
     Error,
 };
 
@@ -168,13 +170,17 @@ impl Tokens {
             if line.is_empty() || start == Some('#') {
                 continue;
             }
-            let Some(pos_quote) = line.find('\"') else { return Err(Error::illegal_argument(format!("Illegal line: {line}"))) };
+            let Some(pos_quote) = line.find('\"') else {
+                return Err(Error::illegal_argument(format!("Illegal line: {line}")));
+            };
             if line.chars().nth(line.len() - 1) != Some('"') {
                 return Err(Error::illegal_argument(format!("Illegal line: {line}")));
             }
 
             // extract item
-            let Some(item) = line.get(pos_quote + 1..line.len() - 1) else { return Err(Error::illegal_argument(format!("Illegal line: {line}"))) };
+            let Some(item) = line.get(pos_quote + 1..line.len() - 1) else {
+                return Err(Error::illegal_argument(format!("Illegal line: {line}")));
+            };
             if item.is_empty() {
                 continue;
             }
@@ -597,13 +603,100 @@ impl I2SRandReplace {
     }
 }
 
+/// A `I2STaintReplace` [`Mutator`] replaces a comparison operand at the exact input offset learned
+/// through colorization with the other operand (or its +/-1 variants), keeping its encoding.
+/// It needs a valid [`I2STaintMetadata`] for the current corpus entry in the state, see
+/// [`crate::stages::CmpTaintTracingStage`].
+#[derive(Debug, Default)]
+pub struct I2STaintReplace;
+
+impl<S> Mutator<S> for I2STaintReplace
+where
+    S: UsesInput + HasMetadata + HasRand + HasMaxSize + HasCorpus,
+    S::Input: HasBytesVec,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut S::Input,
+        _stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        let current = *state.corpus().current();
+        let entries_len = match state.metadata().get::<I2STaintMetadata>() {
+            // The taint of another corpus entry would be applied at random offsets
+            Some(meta) if !meta.list.is_empty() && current == Some(meta.corpus_idx) => {
+                meta.list.len()
+            }
+            _ => return Ok(MutationResult::Skipped),
+        };
+        let idx = state.rand_mut().below(entries_len as u64) as usize;
+
+        let replacements_len = state.metadata().get::<I2STaintMetadata>().unwrap().list[idx]
+            .replacements
+            .len();
+        if replacements_len == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+        let repl_idx = state.rand_mut().below(replacements_len as u64) as usize;
+
+        let max_size = state.max_size();
+        let meta = state.metadata().get::<I2STaintMetadata>().unwrap();
+        let entry = &meta.list[idx];
+        let replacement = &entry.replacements[repl_idx];
+
+        // The input may have been mutated since the correspondence was learned
+        let end = entry.offset + entry.pattern.len();
+        if end > input.bytes().len() || input.bytes()[entry.offset..end] != entry.pattern {
+            return Ok(MutationResult::Skipped);
+        }
+
+        if replacement.len() == entry.pattern.len() {
+            input.bytes_mut()[entry.offset..end].copy_from_slice(replacement);
+        } else {
+            // Variable length encodings, such as ASCII decimal numbers
+            if input.bytes().len() - entry.pattern.len() + replacement.len() > max_size {
+                return Ok(MutationResult::Skipped);
+            }
+            input
+                .bytes_mut()
+                .splice(entry.offset..end, replacement.iter().copied());
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for I2STaintReplace {
+    fn name(&self) -> &str {
+        "I2STaintReplace"
+    }
+}
+
+impl I2STaintReplace {
+    /// Creates a new `I2STaintReplace` struct.
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(feature = "std")]
     use std::fs;
 
+    use super::I2STaintReplace;
     #[cfg(feature = "std")]
     use super::Tokens;
+    use crate::{
+        bolts::rands::StdRand,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasBytesVec},
+        mutators::{MutationResult, Mutator},
+        stages::colorization::{I2SEncoding, I2STaintEntry, I2STaintMetadata},
+        state::{HasCorpus, HasMetadata, StdState},
+    };
 
     #[cfg(feature = "std")]
     #[test]
@@ -623,4 +716,57 @@ token2="B"
         assert_eq!(tokens.tokens().len(), 2);
         let _res = fs::remove_file("test.tkns");
     }
+
+    #[test]
+    fn test_i2s_taint_replace() {
+        let mut state = StdState::new(
+            StdRand::with_seed(4),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let input = BytesInput::new(b"key=abcd;".to_vec());
+        let idx = state
+            .corpus_mut()
+            .add(Testcase::new(input.clone()))
+            .unwrap();
+        *state.corpus_mut().current_mut() = Some(idx);
+
+        let mut meta = I2STaintMetadata::new(idx);
+        meta.list.push(I2STaintEntry {
+            offset: 4,
+            encoding: I2SEncoding::Bytes,
+            pattern: b"abcd".to_vec(),
+            replacements: vec![b"pass".to_vec()],
+        });
+        state.add_metadata(meta);
+
+        let mut mutated = input.clone();
+        assert_eq!(
+            I2STaintReplace.mutate(&mut state, &mut mutated, 0).unwrap(),
+            MutationResult::Mutated
+        );
+        assert_eq!(mutated.bytes(), b"key=pass;");
+
+        // The pattern is no longer at the learned offset
+        assert_eq!(
+            I2STaintReplace.mutate(&mut state, &mut mutated, 0).unwrap(),
+            MutationResult::Skipped
+        );
+
+        // The metadata was learned on another corpus entry
+        let other = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"key=abcd;".to_vec())))
+            .unwrap();
+        *state.corpus_mut().current_mut() = Some(other);
+        let mut mutated = input;
+        assert_eq!(
+            I2STaintReplace.mutate(&mut state, &mut mutated, 0).unwrap(),
+            MutationResult::Skipped
+        );
+        assert_eq!(mutated.bytes(), b"key=abcd;");
+    }
 }
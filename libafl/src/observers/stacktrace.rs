@@ -1,6 +1,10 @@
 //! the ``StacktraceObserver`` looks up the stacktrace on the execution thread and computes a hash for it for dedupe
 
-use alloc::string::{String, ToString};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{Hash, Hasher};
 use std::{
     fmt::Debug,
     fs::{self, File},
@@ -9,6 +13,7 @@ use std::{
     process::ChildStderr,
 };
 
+use ahash::AHasher;
 use backtrace::Backtrace;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -45,6 +50,43 @@ pub fn collect_backtrace() -> u64 {
     hash
 }
 
+/// The default number of top frames hashed in a [`CrashSignature`]
+pub const DEFAULT_SIGNATURE_FRAMES: usize = 3;
+
+/// The signature of a crash, used to triage solutions: crashes with the same signature are
+/// most likely the same bug
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CrashSignature {
+    /// The hash of the top frames of the stack
+    pub stack_hash: u64,
+    /// The class of the error, as reported by the sanitizer
+    pub error_class: String,
+    /// The faulting program counter, if known
+    pub pc: Option<u64>,
+    /// The module of the faulting program counter, and its offset in the module, if known
+    pub pc_offset: Option<(String, u64)>,
+}
+
+impl CrashSignature {
+    /// The id of the bucket of the crashes with this signature.
+    /// The `pc` is left out, as it changes with ASLR, but its offset in its module is kept, so
+    /// that different faulting instructions under the same top frames are told apart.
+    #[must_use]
+    pub fn bucket(&self) -> u64 {
+        let mut hasher = AHasher::new_with_keys(0, 0);
+        self.stack_hash.hash(&mut hasher);
+        self.error_class.hash(&mut hasher);
+        self.pc_offset.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A trait for [`Observer`]`s` able to compute the [`CrashSignature`] of the last crash
+pub trait ObserverWithCrashSignature {
+    /// The signature of the last crash, `None` if the last run did not crash
+    fn crash_signature(&self) -> Option<CrashSignature>;
+}
+
 /// An enum encoding the types of harnesses
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum HarnessType {
@@ -134,6 +176,19 @@ where
     }
 }
 
+impl ObserverWithCrashSignature for BacktraceObserver<'_> {
+    /// The signature of the last crash; the in-process backtrace hashes all the frames,
+    /// and does not know the sanitizer error class
+    fn crash_signature(&self) -> Option<CrashSignature> {
+        self.hash().map(|stack_hash| CrashSignature {
+            stack_hash,
+            error_class: "crash".to_string(),
+            pc: None,
+            pc_offset: None,
+        })
+    }
+}
+
 impl<'a> Named for BacktraceObserver<'a> {
     fn name(&self) -> &str {
         &self.observer_name
@@ -173,15 +228,33 @@ pub fn get_asan_runtime_flags() -> String {
 pub struct AsanBacktraceObserver {
     observer_name: String,
     hash: Option<u64>,
+    /// The frames of the crashing stack, function names if symbolized, or offsets in their module
+    frames: Vec<String>,
+    error_class: Option<String>,
+    pc: Option<u64>,
+    pc_offset: Option<(String, u64)>,
+    signature_frames: usize,
 }
 
 impl AsanBacktraceObserver {
     /// Creates a new [`BacktraceObserver`] with the given name.
     #[must_use]
     pub fn new(observer_name: &str) -> Self {
+        Self::with_signature_frames(observer_name, DEFAULT_SIGNATURE_FRAMES)
+    }
+
+    /// Creates a new [`BacktraceObserver`] with the given name, hashing the given number of
+    /// top frames in the [`CrashSignature`]
+    #[must_use]
+    pub fn with_signature_frames(observer_name: &str, signature_frames: usize) -> Self {
         Self {
             observer_name: observer_name.to_string(),
             hash: None,
+            frames: Vec::new(),
+            error_class: None,
+            pc: None,
+            pc_offset: None,
+            signature_frames,
         }
     }
 
@@ -218,6 +291,76 @@ impl AsanBacktraceObserver {
             hash ^= u64::from_str_radix(g.as_str(), 16).unwrap();
         });
         self.update_hash(hash);
+        self.parse_asan_signature(output);
+    }
+
+    /// parse the error class, the faulting pc and the frames of the crashing stack from the ASAN output
+    fn parse_asan_signature(&mut self, output: &str) {
+        let error = Regex::new("(?m)ERROR: (\\w+Sanitizer): (.+?)(?: on | at | \\(|$)").unwrap();
+        self.error_class = error
+            .captures(output)
+            .map(|m| format!("{}: {}", &m[1], m[2].trim()));
+
+        let pc = Regex::new("\\bpc 0x([0-9a-f]+)").unwrap();
+        self.pc = pc
+            .captures(output)
+            .and_then(|m| u64::from_str_radix(&m[1], 16).ok());
+
+        // Only the first stack, the crashing one: the next ones are allocation or free sites
+        self.frames.clear();
+        self.pc_offset = None;
+        let frame =
+            Regex::new("(?m)^\\s*#([0-9]+)\\s+0x([0-9a-f]+)(?:\\s+in\\s+(\\S+))?(.*)$").unwrap();
+        let module_offset = Regex::new("\\(([^()\\s]+)\\+0x([0-9a-f]+)\\)").unwrap();
+        for m in frame.captures_iter(output) {
+            if &m[1] == "0" && !self.frames.is_empty() {
+                break;
+            }
+            // Function names and offsets in modules do not change with ASLR, addresses do
+            let offset = module_offset.captures(&m[4]).and_then(|o| {
+                let module = o[1].rsplit('/').next().unwrap_or(&o[1]).to_string();
+                u64::from_str_radix(&o[2], 16)
+                    .ok()
+                    .map(|offset| (module, offset))
+            });
+            if self.frames.is_empty() {
+                self.pc_offset.clone_from(&offset);
+            }
+            let name = match (m.get(3), &offset) {
+                (Some(function), _) => function.as_str().to_string(),
+                (None, Some((module, offset))) => format!("{module}+0x{offset:x}"),
+                (None, None) => m[2].to_string(),
+            };
+            self.frames.push(name);
+        }
+        if self.pc.is_none() {
+            self.pc = frame
+                .captures(output)
+                .and_then(|m| u64::from_str_radix(&m[2], 16).ok());
+        }
+    }
+}
+
+impl ObserverWithCrashSignature for AsanBacktraceObserver {
+    fn crash_signature(&self) -> Option<CrashSignature> {
+        // No sanitizer report, no crash
+        if self.hash.is_none() || (self.frames.is_empty() && self.error_class.is_none()) {
+            return None;
+        }
+        let mut hasher = AHasher::new_with_keys(0, 0);
+        self.frames
+            .iter()
+            .take(self.signature_frames)
+            .for_each(|frame| frame.hash(&mut hasher));
+        Some(CrashSignature {
+            stack_hash: hasher.finish(),
+            error_class: self
+                .error_class
+                .clone()
+                .unwrap_or_else(|| "crash".to_string()),
+            pc: self.pc,
+            pc_offset: self.pc_offset.clone(),
+        })
     }
 }
 
@@ -236,6 +379,10 @@ impl ObserverWithHashField for AsanBacktraceObserver {
     /// Clears the current hash value
     fn clear_hash(&mut self) {
         self.hash = None;
+        self.frames.clear();
+        self.error_class = None;
+        self.pc = None;
+        self.pc_offset = None;
     }
 }
 
@@ -279,3 +426,115 @@ impl Named for AsanBacktraceObserver {
         &self.observer_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::{AsanBacktraceObserver, CrashSignature, ObserverWithCrashSignature};
+
+    const ASAN_OUTPUT: &str = "==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011 at pc 0x0000004f8a2b bp 0x7ffd5d0b1e70 sp 0x7ffd5d0b1e68
+READ of size 1 at 0x602000000011 thread T0
+    #0 0x4f8a2b in png_read_row /src/libpng/pngread.c:123:5
+    #1 0x4f7c10 in png_read_image /src/libpng/pngread.c:456:7
+    #2 0x4c1d55 in LLVMFuzzerTestOneInput /src/harness.cc:42:3
+    #3 0x4c0a11 in main
+
+0x602000000011 is located 0 bytes to the right of 1-byte region
+allocated by thread T0 here:
+    #0 0x49d2ad in malloc
+    #1 0x4f8100 in png_malloc /src/libpng/pngmem.c:12:3
+";
+
+    /// parse an ASAN report, expect the class, the pc and the crashing stack to make the signature
+    #[test]
+    fn asan_output_signature() {
+        let mut observer = AsanBacktraceObserver::new("asan");
+        observer.parse_asan_output(ASAN_OUTPUT);
+        let signature = observer.crash_signature().unwrap();
+        assert_eq!(
+            signature.error_class,
+            "AddressSanitizer: heap-buffer-overflow"
+        );
+        assert_eq!(signature.pc, Some(0x4f8a2b));
+        assert_eq!(
+            observer.frames,
+            [
+                "png_read_row",
+                "png_read_image",
+                "LLVMFuzzerTestOneInput",
+                "main"
+            ]
+        );
+
+        // Different addresses, same functions: same bucket
+        let mut other = AsanBacktraceObserver::new("asan");
+        other.parse_asan_output(&ASAN_OUTPUT.replace("4f", "5f"));
+        let other_signature = other.crash_signature().unwrap();
+        assert_ne!(other_signature.pc, signature.pc);
+        assert_eq!(other_signature.bucket(), signature.bucket());
+
+        // Same functions, another error: another bucket
+        other.parse_asan_output(&ASAN_OUTPUT.replace("heap-buffer-overflow", "use-after-free"));
+        assert_ne!(
+            other.crash_signature().unwrap().bucket(),
+            signature.bucket()
+        );
+
+        other.parse_asan_output("no crash here");
+        assert!(other.crash_signature().is_none());
+    }
+
+    const UNSYMBOLIZED_ASAN_OUTPUT: &str = "==1234==ERROR: AddressSanitizer: SEGV on unknown address 0x000000000000 (pc 0x55d0c0de1234 bp 0x7ffd5d0b1e70 sp 0x7ffd5d0b1e68 T0)
+==1234==The signal is caused by a READ memory access.
+    #0 0x55d0c0de1234  (/out/fuzzer+0x1234)
+    #1 0x55d0c0de0f00  (/out/fuzzer+0xf00) (BuildId: 0123456789abcdef)
+    #2 0x7f00deadbeef in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x29d90)
+";
+
+    /// parse an unsymbolized ASAN report, expect the frames and the pc to be kept by module and offset
+    #[test]
+    fn unsymbolized_asan_output_signature() {
+        let mut observer = AsanBacktraceObserver::new("asan");
+        observer.parse_asan_output(UNSYMBOLIZED_ASAN_OUTPUT);
+        let signature = observer.crash_signature().unwrap();
+        assert_eq!(signature.error_class, "AddressSanitizer: SEGV");
+        assert_eq!(signature.pc, Some(0x55d0_c0de_1234));
+        assert_eq!(signature.pc_offset, Some(("fuzzer".to_string(), 0x1234)));
+        assert_eq!(
+            observer.frames,
+            ["fuzzer+0x1234", "fuzzer+0xf00", "__libc_start_main"]
+        );
+
+        // Another load address: same bucket
+        let mut other = AsanBacktraceObserver::new("asan");
+        other.parse_asan_output(&UNSYMBOLIZED_ASAN_OUTPUT.replace("55d0c0de", "56aa0000"));
+        assert_eq!(
+            other.crash_signature().unwrap().bucket(),
+            signature.bucket()
+        );
+
+        // Another faulting instruction: another bucket
+        other.parse_asan_output(&UNSYMBOLIZED_ASAN_OUTPUT.replace("+0x1234", "+0x1238"));
+        assert_ne!(
+            other.crash_signature().unwrap().bucket(),
+            signature.bucket()
+        );
+    }
+
+    /// make signatures with the same stack, expect the module offset of the pc to split the bucket
+    #[test]
+    fn pc_offset_bucket() {
+        let signature = CrashSignature {
+            stack_hash: 0x1234,
+            error_class: "AddressSanitizer: SEGV".to_string(),
+            pc: Some(0x55d0_c0de_1234),
+            pc_offset: Some(("fuzzer".to_string(), 0x1234)),
+        };
+        let mut other = signature.clone();
+        other.pc = Some(0x56aa_0000_1234);
+        assert_eq!(other.bucket(), signature.bucket());
+        other.pc_offset = Some(("fuzzer".to_string(), 0x1238));
+        assert_ne!(other.bucket(), signature.bucket());
+    }
+}
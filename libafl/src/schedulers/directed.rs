@@ -0,0 +1,418 @@
+//! Directed fuzzing in the style of `AFLGo`, more details at <https://mboehme.github.io/paper/CCS17.pdf>
+//!
+//! The distance of each map entry to the targets is usually computed from the CFG dumped by
+//! the `AFLCoverage` pass, using `libafl_cc`'s `ControlFlowGraph::calculate_distances_to_functions`.
+
+use alloc::string::ToString;
+use core::{marker::PhantomData, time::Duration};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bolts::current_time,
+    corpus::{Corpus, Testcase},
+    feedbacks::MapIndexesMetadata,
+    inputs::UsesInput,
+    schedulers::{testcase_score::CorpusPowerTestcaseScore, Scheduler, TestcaseScore},
+    state::{HasCorpus, HasMetadata, UsesState},
+    Error,
+};
+
+/// The maximum factor the annealing can apply to the power of a testcase, as in `AFLGo`
+const DIRECTED_MAX_FACTOR: f64 = 32.0;
+
+/// A state metadata holding the distance of each map entry to the targets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectedMetadata {
+    /// map index -> distance to the closest target
+    distances: HashMap<usize, f64>,
+    /// The minimum testcase distance seen so far
+    min_distance: f64,
+    /// The maximum testcase distance seen so far
+    max_distance: f64,
+    /// The time after which the annealing fully exploits the closest testcases
+    time_to_exploit: Duration,
+    /// The time the directed fuzzing started
+    start_time: Duration,
+}
+
+crate::impl_serdeany!(DirectedMetadata);
+
+impl DirectedMetadata {
+    /// Creates a new [`struct@DirectedMetadata`]
+    #[must_use]
+    pub fn new(distances: HashMap<usize, f64>, time_to_exploit: Duration) -> Self {
+        Self {
+            distances,
+            min_distance: f64::MAX,
+            max_distance: 0.0,
+            time_to_exploit,
+            start_time: current_time(),
+        }
+    }
+
+    /// The distance of each map entry to the closest target
+    #[must_use]
+    pub fn distances(&self) -> &HashMap<usize, f64> {
+        &self.distances
+    }
+
+    /// The minimum testcase distance seen so far
+    #[must_use]
+    pub fn min_distance(&self) -> f64 {
+        self.min_distance
+    }
+
+    /// The maximum testcase distance seen so far
+    #[must_use]
+    pub fn max_distance(&self) -> f64 {
+        self.max_distance
+    }
+
+    /// The time after which the annealing fully exploits the closest testcases
+    #[must_use]
+    pub fn time_to_exploit(&self) -> Duration {
+        self.time_to_exploit
+    }
+
+    /// The time the directed fuzzing started
+    #[must_use]
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// Computes the distance of a testcase that hit the given map entries.
+    /// Returns `None` if none of the entries can reach a target.
+    #[must_use]
+    pub fn testcase_distance(&self, indexes: &[usize]) -> Option<f64> {
+        let mut sum = 0.0;
+        let mut count: u32 = 0;
+        for idx in indexes {
+            if let Some(distance) = self.distances.get(idx) {
+                sum += distance;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            None
+        } else {
+            Some(sum / f64::from(count))
+        }
+    }
+
+    /// Registers a new testcase distance, updating the minimum and maximum seen so far
+    pub fn update_bounds(&mut self, distance: f64) {
+        if distance < self.min_distance {
+            self.min_distance = distance;
+        }
+        if distance > self.max_distance {
+            self.max_distance = distance;
+        }
+    }
+
+    /// The distance normalized to `[0, 1]` using the bounds seen so far
+    #[must_use]
+    pub fn normalized_distance(&self, distance: f64) -> f64 {
+        if self.max_distance > self.min_distance {
+            (distance - self.min_distance) / (self.max_distance - self.min_distance)
+        } else {
+            0.0
+        }
+    }
+
+    /// The simulated annealing temperature at the given time, using an exponential cooling schedule
+    #[must_use]
+    pub fn temperature(&self, now: Duration) -> f64 {
+        if self.time_to_exploit.is_zero() {
+            return 0.0;
+        }
+        let progress =
+            now.saturating_sub(self.start_time).as_secs_f64() / self.time_to_exploit.as_secs_f64();
+        1.0 / libm::pow(20.0, progress)
+    }
+
+    /// The factor the annealing applies to the power of a testcase at `distance` from the
+    /// targets, at the given time
+    #[must_use]
+    pub fn power_factor(&self, distance: f64, now: Duration) -> f64 {
+        let temperature = self.temperature(now);
+        let normalized = self.normalized_distance(distance);
+        let p = (1.0 - normalized) * (1.0 - temperature) + 0.5 * temperature;
+        libm::pow(2.0, 2.0 * libm::log2(DIRECTED_MAX_FACTOR) * (p - 0.5))
+    }
+}
+
+/// A testcase metadata holding the distance of the testcase to the targets
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TestcaseDistanceMetadata {
+    /// The average distance of the map entries hit by this testcase
+    pub distance: f64,
+}
+
+crate::impl_serdeany!(TestcaseDistanceMetadata);
+
+impl TestcaseDistanceMetadata {
+    /// Creates a new [`struct@TestcaseDistanceMetadata`]
+    #[must_use]
+    pub fn new(distance: f64) -> Self {
+        Self { distance }
+    }
+}
+
+/// A scheduler wrapper computing the distance of each new testcase to the targets.
+///
+/// The hit map entries are taken from the [`MapIndexesMetadata`], so the map feedback has to
+/// track indexes. The distance is then used by [`DirectedPowerTestcaseScore`] to anneal the
+/// energy toward inputs close to the targets.
+#[derive(Debug, Clone)]
+pub struct DirectedScheduler<CS> {
+    base: CS,
+    distances: HashMap<usize, f64>,
+    time_to_exploit: Duration,
+}
+
+impl<CS> UsesState for DirectedScheduler<CS>
+where
+    CS: UsesState,
+{
+    type State = CS::State;
+}
+
+impl<CS> Scheduler for DirectedScheduler<CS>
+where
+    CS: Scheduler,
+    CS::State: HasCorpus + HasMetadata,
+{
+    fn on_add(&self, state: &mut Self::State, idx: usize) -> Result<(), Error> {
+        self.base.on_add(state, idx)?;
+        self.update_distance(state, idx)
+    }
+
+    fn on_replace(
+        &self,
+        state: &mut Self::State,
+        idx: usize,
+        prev: &Testcase<<Self::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, idx, prev)?;
+        self.update_distance(state, idx)
+    }
+
+    fn on_remove(
+        &self,
+        state: &mut Self::State,
+        idx: usize,
+        testcase: &Option<Testcase<<Self::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, idx, testcase)
+    }
+
+    fn next(&self, state: &mut Self::State) -> Result<usize, Error> {
+        self.base.next(state)
+    }
+}
+
+impl<CS> DirectedScheduler<CS>
+where
+    CS: Scheduler,
+    CS::State: HasCorpus + HasMetadata,
+{
+    /// Computes the distance of the testcase at the given index and stores it as metadata
+    pub fn update_distance(&self, state: &mut CS::State, idx: usize) -> Result<(), Error> {
+        if !state.has_metadata::<DirectedMetadata>() {
+            state.add_metadata(DirectedMetadata::new(
+                self.distances.clone(),
+                self.time_to_exploit,
+            ));
+        }
+
+        let distance = {
+            let testcase = state.corpus().get(idx)?.borrow();
+            let indexes = testcase
+                .metadata()
+                .get::<MapIndexesMetadata>()
+                .ok_or_else(|| {
+                    Error::key_not_found(
+                        "MapIndexesMetadata not found, enable track_indexes".to_string(),
+                    )
+                })?;
+            state
+                .metadata()
+                .get::<DirectedMetadata>()
+                .unwrap()
+                .testcase_distance(&indexes.list)
+        };
+
+        if let Some(distance) = distance {
+            state
+                .metadata_mut()
+                .get_mut::<DirectedMetadata>()
+                .unwrap()
+                .update_bounds(distance);
+            state
+                .corpus()
+                .get(idx)?
+                .borrow_mut()
+                .add_metadata(TestcaseDistanceMetadata::new(distance));
+        }
+        Ok(())
+    }
+}
+
+impl<CS> DirectedScheduler<CS> {
+    /// Creates a new [`DirectedScheduler`] wrapping `base`, given the distance of each map entry
+    /// to the closest target and the time after which the closest testcases are fully exploited.
+    #[must_use]
+    pub fn new(base: CS, distances: HashMap<usize, f64>, time_to_exploit: Duration) -> Self {
+        Self {
+            base,
+            distances,
+            time_to_exploit,
+        }
+    }
+}
+
+/// The power of a corpus entry, annealed toward the testcases closer to the targets.
+/// Requires the [`DirectedScheduler`] and a power scheduler such as
+/// [`super::PowerQueueScheduler`] or [`super::WeightedScheduler`].
+#[derive(Debug, Clone)]
+pub struct DirectedPowerTestcaseScore<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S> TestcaseScore<S> for DirectedPowerTestcaseScore<S>
+where
+    S: HasCorpus + HasMetadata,
+{
+    fn compute(entry: &mut Testcase<S::Input>, state: &S) -> Result<f64, Error> {
+        let perf_score = CorpusPowerTestcaseScore::<S>::compute(entry, state)?;
+
+        let distance = match entry.metadata().get::<TestcaseDistanceMetadata>() {
+            Some(meta) => meta.distance,
+            // Testcases that cannot reach any target keep their original power
+            None => return Ok(perf_score),
+        };
+
+        let dmeta = state
+            .metadata()
+            .get::<DirectedMetadata>()
+            .ok_or_else(|| Error::key_not_found("DirectedMetadata not found".to_string()))?;
+
+        Ok(perf_score * dmeta.power_factor(distance, current_time()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use hashbrown::HashMap;
+
+    use crate::{
+        bolts::rands::StdRand,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::{ConstFeedback, MapIndexesMetadata},
+        inputs::BytesInput,
+        schedulers::{
+            directed::{DirectedMetadata, TestcaseDistanceMetadata},
+            DirectedScheduler, QueueScheduler, Scheduler,
+        },
+        state::{HasCorpus, HasMetadata, StdState},
+    };
+
+    fn test_distances() -> HashMap<usize, f64> {
+        HashMap::from([(1, 1.0), (2, 3.0), (3, 8.0)])
+    }
+
+    #[test]
+    fn test_testcase_distance() {
+        let meta = DirectedMetadata::new(test_distances(), Duration::from_secs(60));
+        // The entries that cannot reach a target are ignored
+        assert_eq!(meta.testcase_distance(&[1, 2, 42]), Some(2.0));
+        assert_eq!(meta.testcase_distance(&[3]), Some(8.0));
+        assert_eq!(meta.testcase_distance(&[42]), None);
+        assert_eq!(meta.testcase_distance(&[]), None);
+    }
+
+    #[test]
+    fn test_normalized_distance() {
+        let mut meta = DirectedMetadata::new(test_distances(), Duration::from_secs(60));
+        meta.update_bounds(2.0);
+        // A single distance has nothing to compare with
+        assert!(meta.normalized_distance(2.0).abs() < f64::EPSILON);
+        meta.update_bounds(6.0);
+        meta.update_bounds(4.0);
+        assert!((meta.min_distance() - 2.0).abs() < f64::EPSILON);
+        assert!((meta.max_distance() - 6.0).abs() < f64::EPSILON);
+        assert!((meta.normalized_distance(4.0) - 0.5).abs() < f64::EPSILON);
+        assert!((meta.normalized_distance(6.0) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_power_factor() {
+        let mut meta = DirectedMetadata::new(test_distances(), Duration::from_secs(60));
+        meta.update_bounds(2.0);
+        meta.update_bounds(6.0);
+        let start = meta.start_time();
+
+        // At the start, the annealing explores: every testcase keeps its power
+        assert!((meta.temperature(start) - 1.0).abs() < f64::EPSILON);
+        assert!((meta.power_factor(2.0, start) - 1.0).abs() < 1e-9);
+        assert!((meta.power_factor(6.0, start) - 1.0).abs() < 1e-9);
+
+        // Once exploiting, the closest testcases get up to 32 times the power
+        let later = start + Duration::from_secs(600);
+        assert!(meta.temperature(later) < 1e-9);
+        assert!((meta.power_factor(2.0, later) - 32.0).abs() < 1e-6);
+        assert!((meta.power_factor(6.0, later) - 1.0 / 32.0).abs() < 1e-6);
+        assert!((meta.power_factor(4.0, later) - 1.0).abs() < 1e-6);
+
+        // In between, the closer testcase still has more power
+        let middle = start + Duration::from_secs(30);
+        assert!(meta.power_factor(2.0, middle) > meta.power_factor(6.0, middle));
+    }
+
+    #[test]
+    fn test_directed_scheduler() {
+        let rand = StdRand::with_seed(4);
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            rand,
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+
+        let scheduler =
+            DirectedScheduler::new(QueueScheduler::new(), test_distances(), Duration::ZERO);
+
+        for indexes in [vec![1, 2], vec![3, 42], vec![42]] {
+            let mut testcase = Testcase::new(BytesInput::new(vec![0]));
+            testcase.add_metadata(MapIndexesMetadata::new(indexes));
+            let idx = state.corpus_mut().add(testcase).unwrap();
+            scheduler.on_add(&mut state, idx).unwrap();
+        }
+
+        let distance = |state: &StdState<_, InMemoryCorpus<BytesInput>, _, _>, idx| {
+            state
+                .corpus()
+                .get(idx)
+                .unwrap()
+                .borrow()
+                .metadata()
+                .get::<TestcaseDistanceMetadata>()
+                .map(|meta| meta.distance)
+        };
+        assert_eq!(distance(&state, 0), Some(2.0));
+        assert_eq!(distance(&state, 1), Some(8.0));
+        assert_eq!(distance(&state, 2), None);
+
+        let meta = state.metadata().get::<DirectedMetadata>().unwrap();
+        assert!((meta.min_distance() - 2.0).abs() < f64::EPSILON);
+        assert!((meta.max_distance() - 8.0).abs() < f64::EPSILON);
+    }
+}
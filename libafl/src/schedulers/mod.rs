@@ -30,6 +30,9 @@ pub use powersched::PowerQueueScheduler;
 pub mod tuneable;
 pub use tuneable::*;
 
+pub mod directed;
+pub use directed::{DirectedPowerTestcaseScore, DirectedScheduler};
+
 use crate::{
     bolts::rands::Rand,
     corpus::{Corpus, Testcase},
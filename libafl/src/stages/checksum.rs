@@ -0,0 +1,597 @@
+//! Detection of checksum comparisons, in the style of `Redqueen`.
+//!
+//! A checksum comparison always fails and compares a value computed from a large region of the
+//! input (a CRC, a length field) with a value stored verbatim in the input.
+//! The [`ChecksumDetectionStage`] finds them correlating the comparisons of the original and the
+//! colorized input (see [`super::ColorizationStage`]), and stores them in the [`struct@ChecksumMetadata`].
+//! The [`ChecksumRepairStage`] then repairs the corpus entries and evaluates the repaired inputs.
+//! Alternatively, the [`crate::executors::ChecksumFixupExecutor`] repairs each input before running
+//! it, and the [`crate::feedbacks::ChecksumRepairFeedback`] stores the repaired inputs in the corpus
+//! and in the solutions.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "introspection")]
+use crate::monitors::PerfFeature;
+use crate::{
+    corpus::Corpus,
+    executors::{Executor, HasObservers},
+    fuzzer::Evaluator,
+    inputs::{HasBytesVec, UsesInput},
+    mark_feature_time,
+    observers::{CmpMap, CmpObserver, CmpValues},
+    stages::{
+        colorization::{
+            current_taint, encode_as, encode_numeric, numeric_operands, trace_cmps, I2SEncoding,
+            TaintMetadata,
+        },
+        Stage,
+    },
+    start_timer,
+    state::{HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata, UsesState},
+    Error,
+};
+
+/// The default minimum number of tainted input bytes, besides the stored value, a computed
+/// operand has to depend on to be considered a checksum
+pub const DEFAULT_MIN_CHECKSUM_REGION: usize = 16;
+
+/// The default maximum number of repair rounds, to fix nested checksums
+pub const DEFAULT_CHECKSUM_FIXUP_ROUNDS: usize = 4;
+
+/// A comparison detected as checksum
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumCmp {
+    /// The index of the comparison in the [`CmpMap`]
+    pub cmp_id: usize,
+    /// The operand (`0` or `1`) stored verbatim in the input, the other one is computed
+    pub stored_operand: usize,
+    /// How the stored operand is encoded in the input
+    pub encoding: I2SEncoding,
+    /// The size of the operands, in bytes
+    pub size: usize,
+    /// The offset of the stored operand in the input where the checksum was detected
+    pub offset: usize,
+}
+
+impl ChecksumCmp {
+    /// Given the values logged for this comparison, returns the offset of the stored operand in
+    /// `input` and the encoded computed operand to write there, if the checksum does not hold
+    #[must_use]
+    pub fn fix(&self, values: &CmpValues, input: &[u8]) -> Option<(usize, Vec<u8>)> {
+        let (v0, v1, size) = numeric_operands(values)?;
+        if size != self.size || v0 == v1 {
+            return None;
+        }
+        let (stored, computed) = if self.stored_operand == 0 {
+            (v0, v1)
+        } else {
+            (v1, v0)
+        };
+        let pattern = encode_as(stored, size, self.encoding)?;
+        let replacement = encode_as(computed, size, self.encoding)?;
+        if replacement.len() != pattern.len() || pattern.len() > input.len() {
+            return None;
+        }
+
+        // Offsets change while fuzzing, prefer the closest occurrence to the detected one
+        let offset = (0..=input.len() - pattern.len())
+            .filter(|&i| input[i..i + pattern.len()] == *pattern)
+            .min_by_key(|&i| i.abs_diff(self.offset))?;
+        Some((offset, replacement))
+    }
+}
+
+/// A state metadata holding the comparisons detected as checksums
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChecksumMetadata {
+    /// The checksum comparisons
+    pub list: Vec<ChecksumCmp>,
+}
+
+crate::impl_serdeany!(ChecksumMetadata);
+
+impl ChecksumMetadata {
+    /// Creates a new [`struct@ChecksumMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns if the given comparison is already known as checksum
+    #[must_use]
+    pub fn contains(&self, cmp_id: usize) -> bool {
+        self.list.iter().any(|c| c.cmp_id == cmp_id)
+    }
+}
+
+/// A stage detecting checksum comparisons, needs a [`super::ColorizationStage`] before it.
+///
+/// A numeric comparison is a checksum if it fails both for the original and the colorized input,
+/// one operand is found in the input as is, and the other one changed with the colorization
+/// without being found in the input, while enough other input bytes were colorized.
+#[derive(Clone, Debug)]
+pub struct ChecksumDetectionStage<CM, CO, EM, TE, Z> {
+    tracer_executor: TE,
+    cmp_observer_name: String,
+    min_region: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(CM, CO, EM, TE, Z)>,
+}
+
+impl<CM, CO, EM, TE, Z> UsesState for ChecksumDetectionStage<CM, CO, EM, TE, Z>
+where
+    TE: UsesState,
+{
+    type State = TE::State;
+}
+
+impl<E, CM, CO, EM, TE, Z> Stage<E, EM, Z> for ChecksumDetectionStage<CM, CO, EM, TE, Z>
+where
+    E: UsesState<State = TE::State>,
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasCorpus + HasMetadata,
+    <TE::State as UsesInput>::Input: HasBytesVec,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut TE::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        start_timer!(state);
+        let original = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input()?
+            .clone();
+        mark_feature_time!(state, PerfFeature::GetInputFromCorpus);
+
+        let taint = current_taint(state, corpus_idx, original.bytes().len())?;
+        let mut colorized = original.clone();
+        colorized.bytes_mut().clone_from(&taint.input_vec);
+
+        let orig_cmps = trace_cmps::<CM, CO, EM, TE, Z>(
+            &mut self.tracer_executor,
+            &self.cmp_observer_name,
+            fuzzer,
+            state,
+            manager,
+            &original,
+        )?;
+        let colorized_cmps = trace_cmps::<CM, CO, EM, TE, Z>(
+            &mut self.tracer_executor,
+            &self.cmp_observer_name,
+            fuzzer,
+            state,
+            manager,
+            &colorized,
+        )?;
+
+        if !state.has_metadata::<ChecksumMetadata>() {
+            state.add_metadata(ChecksumMetadata::new());
+        }
+        let meta = state.metadata_mut().get_mut::<ChecksumMetadata>().unwrap();
+
+        for (cmp_id, (orig_execs, colorized_execs)) in
+            orig_cmps.iter().zip(colorized_cmps.iter()).enumerate()
+        {
+            if meta.contains(cmp_id) {
+                continue;
+            }
+            for (orig, col) in orig_execs.iter().zip(colorized_execs.iter()) {
+                if let Some(checksum) =
+                    detect(cmp_id, original.bytes(), &taint, orig, col, self.min_region)
+                {
+                    meta.list.push(checksum);
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<CM, CO, EM, TE, Z> ChecksumDetectionStage<CM, CO, EM, TE, Z>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasCorpus + HasMetadata,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    /// Creates a new [`ChecksumDetectionStage`], tracing with the given executor and [`CmpObserver`]
+    pub fn new(tracer_executor: TE, cmp_observer: &CO) -> Self {
+        Self::with_min_region(tracer_executor, cmp_observer, DEFAULT_MIN_CHECKSUM_REGION)
+    }
+
+    /// Creates a new [`ChecksumDetectionStage`], with the minimum number of colorized input bytes
+    /// a computed operand has to depend on to be considered a checksum
+    pub fn with_min_region(tracer_executor: TE, cmp_observer: &CO, min_region: usize) -> Self {
+        Self {
+            tracer_executor,
+            cmp_observer_name: cmp_observer.name().to_string(),
+            min_region,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Gets the underlying tracer executor
+    pub fn executor(&self) -> &TE {
+        &self.tracer_executor
+    }
+}
+
+/// Traces `input` and repairs the checksums listed in the [`struct@ChecksumMetadata`], tracing again
+/// after each round to fix the checksums computed over repaired ones, up to `max_rounds` times.
+/// Returns `None` if no checksum had to be fixed. Each trace counts as an execution.
+pub fn repair_checksums<CM, CO, EM, TE, Z>(
+    tracer_executor: &mut TE,
+    cmp_observer_name: &str,
+    max_rounds: usize,
+    fuzzer: &mut Z,
+    state: &mut TE::State,
+    manager: &mut EM,
+    input: &<TE::State as UsesInput>::Input,
+) -> Result<Option<<TE::State as UsesInput>::Input>, Error>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasMetadata,
+    <TE::State as UsesInput>::Input: HasBytesVec + Clone,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    let checksums = match state.metadata().get::<ChecksumMetadata>() {
+        Some(meta) if !meta.list.is_empty() => meta.list.clone(),
+        _ => return Ok(None),
+    };
+
+    let mut repaired = input.clone();
+    let mut changed = false;
+    for _ in 0..max_rounds {
+        let cmps = trace_cmps::<CM, CO, EM, TE, Z>(
+            tracer_executor,
+            cmp_observer_name,
+            fuzzer,
+            state,
+            manager,
+            &repaired,
+        )?;
+        tracer_executor.post_run_reset();
+
+        let mut fixed = false;
+        for checksum in &checksums {
+            let fix = cmps.get(checksum.cmp_id).and_then(|values| {
+                values
+                    .iter()
+                    .find_map(|values| checksum.fix(values, repaired.bytes()))
+            });
+            if let Some((offset, bytes)) = fix {
+                repaired.bytes_mut()[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                fixed = true;
+            }
+        }
+
+        // Repairing a checksum may invalidate another one computed over it, so try again
+        if !fixed {
+            break;
+        }
+        changed = true;
+    }
+
+    Ok(if changed { Some(repaired) } else { None })
+}
+
+/// A testcase metadata holding how many checksums were known when the entry was last repaired
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChecksumRepairedMetadata {
+    /// The number of entries of the [`struct@ChecksumMetadata`] at the last repair
+    pub checksums: usize,
+}
+
+crate::impl_serdeany!(ChecksumRepairedMetadata);
+
+/// A stage repairing the checksums of the corpus entries, needs a [`ChecksumDetectionStage`] before it.
+///
+/// Each entry is repaired once for every new checksum detected, the repaired input is then
+/// evaluated, and added to the corpus or to the solutions if interesting.
+#[derive(Clone, Debug)]
+pub struct ChecksumRepairStage<CM, CO, EM, TE, Z> {
+    tracer_executor: TE,
+    cmp_observer_name: String,
+    max_rounds: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(CM, CO, EM, TE, Z)>,
+}
+
+impl<CM, CO, EM, TE, Z> UsesState for ChecksumRepairStage<CM, CO, EM, TE, Z>
+where
+    TE: UsesState,
+{
+    type State = TE::State;
+}
+
+impl<E, CM, CO, EM, TE, Z> Stage<E, EM, Z> for ChecksumRepairStage<CM, CO, EM, TE, Z>
+where
+    E: UsesState<State = TE::State>,
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasCorpus + HasMetadata,
+    <TE::State as UsesInput>::Input: HasBytesVec + Clone,
+    EM: UsesState<State = TE::State>,
+    Z: Evaluator<E, EM, State = TE::State>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut TE::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        let checksums = state
+            .metadata()
+            .get::<ChecksumMetadata>()
+            .map_or(0, |meta| meta.list.len());
+        let repaired_with = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow()
+            .metadata()
+            .get::<ChecksumRepairedMetadata>()
+            .map(|meta| meta.checksums);
+        if checksums == 0 || repaired_with == Some(checksums) {
+            return Ok(());
+        }
+
+        start_timer!(state);
+        let input = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input()?
+            .clone();
+        mark_feature_time!(state, PerfFeature::GetInputFromCorpus);
+
+        let repaired = repair_checksums::<CM, CO, EM, TE, Z>(
+            &mut self.tracer_executor,
+            &self.cmp_observer_name,
+            self.max_rounds,
+            fuzzer,
+            state,
+            manager,
+            &input,
+        )?;
+
+        let mut testcase = state.corpus().get(corpus_idx)?.borrow_mut();
+        if let Some(meta) = testcase
+            .metadata_mut()
+            .get_mut::<ChecksumRepairedMetadata>()
+        {
+            meta.checksums = checksums;
+        } else {
+            testcase.add_metadata(ChecksumRepairedMetadata { checksums });
+        }
+        drop(testcase);
+
+        if let Some(repaired) = repaired {
+            fuzzer.evaluate_input(state, executor, manager, repaired)?;
+        }
+        Ok(())
+    }
+}
+
+impl<CM, CO, EM, TE, Z> ChecksumRepairStage<CM, CO, EM, TE, Z>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    /// Creates a new [`ChecksumRepairStage`], tracing with the given executor and [`CmpObserver`]
+    pub fn new(tracer_executor: TE, cmp_observer: &CO) -> Self {
+        Self {
+            tracer_executor,
+            cmp_observer_name: cmp_observer.name().to_string(),
+            max_rounds: DEFAULT_CHECKSUM_FIXUP_ROUNDS,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of repair rounds for each input
+    #[must_use]
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Gets the underlying tracer executor
+    pub fn executor(&self) -> &TE {
+        &self.tracer_executor
+    }
+}
+
+/// Checks if a comparison is a checksum, given its values in the original and in the colorized run
+fn detect(
+    cmp_id: usize,
+    input: &[u8],
+    taint: &TaintMetadata,
+    orig: &CmpValues,
+    colorized: &CmpValues,
+    min_region: usize,
+) -> Option<ChecksumCmp> {
+    let (o0, o1, size) = numeric_operands(orig)?;
+    let (c0, c1, _) = numeric_operands(colorized)?;
+    // Byte-sized values are flags, not checksums, and the comparison has to always fail
+    if size < 2 || o0 == o1 || c0 == c1 {
+        return None;
+    }
+
+    for (stored_operand, (stored, col_stored), (computed, col_computed)) in
+        [(0, (o0, c0), (o1, c1)), (1, (o1, c1), (o0, c0))]
+    {
+        // The computed operand depends on the input, but is not input-to-state
+        if computed == col_computed
+            || encode_numeric(computed, size)
+                .iter()
+                .any(|(_, enc)| find(input, enc).is_some())
+        {
+            continue;
+        }
+        for (encoding, pattern, col_pattern) in
+            encode_numeric(stored, size)
+                .into_iter()
+                .filter_map(|(encoding, pattern)| {
+                    encode_as(col_stored, size, encoding)
+                        .map(|col_pattern| (encoding, pattern, col_pattern))
+                })
+        {
+            let found = (0..input.len().saturating_sub(pattern.len() - 1)).find(|&offset| {
+                input[offset..offset + pattern.len()] == *pattern
+                    && taint.input_vec.len() >= offset + col_pattern.len()
+                    && taint.input_vec[offset..offset + col_pattern.len()] == *col_pattern
+            });
+            if let Some(offset) = found {
+                let stored_range = offset..offset + pattern.len();
+                let region: usize = taint
+                    .ranges
+                    .iter()
+                    .flat_map(Clone::clone)
+                    .filter(|i| !stored_range.contains(i))
+                    .count();
+                if region < min_region {
+                    continue;
+                }
+                return Some(ChecksumCmp {
+                    cmp_id,
+                    stored_operand,
+                    encoding,
+                    size,
+                    offset,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Finds the first occurrence of `pattern` in `input`
+fn find(input: &[u8], pattern: &[u8]) -> Option<usize> {
+    if pattern.is_empty() || pattern.len() > input.len() {
+        return None;
+    }
+    input.windows(pattern.len()).position(|w| w == pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        observers::CmpValues,
+        stages::{
+            checksum::{detect, ChecksumCmp},
+            colorization::{I2SEncoding, TaintMetadata},
+        },
+    };
+
+    #[test]
+    fn test_checksum_fix() {
+        let checksum = ChecksumCmp {
+            cmp_id: 0,
+            stored_operand: 0,
+            encoding: I2SEncoding::Native,
+            size: 4,
+            offset: 8,
+        };
+        let mut input = b"AAAAAAAAAAAAAAAA".to_vec();
+        input[0..4].copy_from_slice(&0x1122_3344_u32.to_ne_bytes());
+        input[9..13].copy_from_slice(&0x1122_3344_u32.to_ne_bytes());
+
+        // The closest occurrence to the detected offset is repaired
+        assert_eq!(
+            checksum.fix(&CmpValues::U32((0x1122_3344, 0xaabb_ccdd)), &input),
+            Some((9, 0xaabb_ccdd_u32.to_ne_bytes().to_vec()))
+        );
+        // The checksum already holds
+        assert_eq!(
+            checksum.fix(&CmpValues::U32((0x1122_3344, 0x1122_3344)), &input),
+            None
+        );
+        // Another comparison size
+        assert_eq!(
+            checksum.fix(&CmpValues::U16((0x3344, 0xccdd)), &input),
+            None
+        );
+        // The stored value is not in the input anymore
+        assert_eq!(
+            checksum.fix(&CmpValues::U32((0x5566_7788, 0xaabb_ccdd)), &input),
+            None
+        );
+
+        let checksum = ChecksumCmp {
+            stored_operand: 1,
+            encoding: I2SEncoding::Swapped,
+            ..checksum
+        };
+        let input = b"AAAA\x11\x22\x33\x44AAAA".to_vec();
+        assert_eq!(
+            checksum.fix(&CmpValues::U32((0xaabb_ccdd, 0x1122_3344)), &input),
+            Some((4, 0xaabb_ccdd_u32.to_be_bytes().to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_checksum_detect() {
+        let mut input = b"AAAAAAAAAAAAAAAAAAAAAAAA".to_vec();
+        input[0..4].copy_from_slice(&0xdead_beef_u32.to_ne_bytes());
+        let mut colorized = b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec();
+        colorized[0..4].copy_from_slice(&0x0102_0304_u32.to_ne_bytes());
+        let taint = TaintMetadata::new(0, colorized, vec![0..4, 4..24]);
+
+        let orig = CmpValues::U32((0xdead_beef, 0x1234_5678));
+        let col = CmpValues::U32((0x0102_0304, 0x9abc_def0));
+        assert_eq!(
+            detect(7, &input, &taint, &orig, &col, 16),
+            Some(ChecksumCmp {
+                cmp_id: 7,
+                stored_operand: 0,
+                encoding: I2SEncoding::Native,
+                size: 4,
+                offset: 0,
+            })
+        );
+        // The computed value depends on too few input bytes
+        assert_eq!(detect(7, &input, &taint, &orig, &col, 32), None);
+        // The computed value does not depend on the input
+        let col = CmpValues::U32((0x0102_0304, 0x1234_5678));
+        assert_eq!(detect(7, &input, &taint, &orig, &col, 16), None);
+        // The comparison holds
+        let orig = CmpValues::U32((0xdead_beef, 0xdead_beef));
+        let col = CmpValues::U32((0x0102_0304, 0x9abc_def0));
+        assert_eq!(detect(7, &input, &taint, &orig, &col, 16), None);
+        // Byte-sized values are flags
+        let orig = CmpValues::U8((0xef, 0x78));
+        let col = CmpValues::U8((0x04, 0xf0));
+        assert_eq!(detect(7, &input, &taint, &orig, &col, 16), None);
+    }
+}
@@ -0,0 +1,725 @@
+//! The colorization stages learn which input bytes end up in comparison operands, in the style of
+//! `Redqueen` and the `AFL++` cmplog colorization.
+//!
+//! The [`ColorizationStage`] randomizes the bytes of a corpus entry as much as possible while the
+//! coverage hash stays the same. The [`CmpTaintTracingStage`] then traces comparisons for both the
+//! original and the colorized input and correlates them, learning the exact input offsets of each
+//! operand. The [`crate::mutators::I2STaintReplace`] mutator uses those locations.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "introspection")]
+use crate::monitors::PerfFeature;
+use crate::{
+    bolts::{rands::Rand, tuples::MatchName},
+    corpus::Corpus,
+    executors::{Executor, HasObservers},
+    inputs::{HasBytesVec, UsesInput},
+    mark_feature_time,
+    observers::{CmpMap, CmpObserver, CmpValues, MapObserver, ObserversTuple},
+    stages::Stage,
+    start_timer,
+    state::{HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata, HasRand, UsesState},
+    Error,
+};
+
+/// The default maximum number of executions spent colorizing a single corpus entry
+pub const DEFAULT_COLORIZATION_MAX_EXECS: usize = 1000;
+
+/// The maximum number of input-to-state correspondences kept for a single corpus entry
+const MAX_TAINT_ENTRIES: usize = 4096;
+
+/// A state metadata holding the colorized version of the current corpus entry
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TaintMetadata {
+    /// The corpus index this metadata refers to
+    pub corpus_idx: usize,
+    /// The colorized input bytes
+    pub input_vec: Vec<u8>,
+    /// The ranges of the input that could be randomized without changing the coverage
+    pub ranges: Vec<Range<usize>>,
+}
+
+crate::impl_serdeany!(TaintMetadata);
+
+impl TaintMetadata {
+    /// Creates a new [`struct@TaintMetadata`]
+    #[must_use]
+    pub fn new(corpus_idx: usize, input_vec: Vec<u8>, ranges: Vec<Range<usize>>) -> Self {
+        Self {
+            corpus_idx,
+            input_vec,
+            ranges,
+        }
+    }
+
+    /// Returns if the given input offset is tainted, i.e. can change without changing the coverage
+    #[must_use]
+    pub fn is_tainted(&self, offset: usize) -> bool {
+        self.ranges.iter().any(|r| r.contains(&offset))
+    }
+}
+
+/// How a comparison operand is encoded in the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2SEncoding {
+    /// The operand is stored in native byte order
+    Native,
+    /// The operand is stored byte-swapped
+    Swapped,
+    /// The operand is stored as ASCII decimal number
+    AsciiDecimal,
+    /// The operand is a buffer, as in `memcmp` or `strcmp`
+    Bytes,
+}
+
+/// An input-to-state correspondence learned correlating the original and the colorized traces
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2STaintEntry {
+    /// The offset in the input where the operand was found
+    pub offset: usize,
+    /// How the operand is encoded in the input
+    pub encoding: I2SEncoding,
+    /// The encoded operand as found in the input
+    pub pattern: Vec<u8>,
+    /// The encoded candidates to write in place of `pattern`: the other operand and its +/-1 variants
+    pub replacements: Vec<Vec<u8>>,
+}
+
+/// A state metadata holding the input-to-state correspondences of the current corpus entry
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct I2STaintMetadata {
+    /// The corpus index this metadata refers to
+    pub corpus_idx: usize,
+    /// The learned correspondences
+    pub list: Vec<I2STaintEntry>,
+}
+
+crate::impl_serdeany!(I2STaintMetadata);
+
+impl I2STaintMetadata {
+    /// Creates a new [`struct@I2STaintMetadata`] for the given corpus entry
+    #[must_use]
+    pub fn new(corpus_idx: usize) -> Self {
+        Self {
+            corpus_idx,
+            list: Vec::new(),
+        }
+    }
+}
+
+/// Replace a byte with a random one of the same kind (digit, hex letter, letter, whitespace),
+/// as `AFL++` does. The returned byte is always different from the original one.
+fn type_replace<R: Rand>(rand: &mut R, byte: u8) -> u8 {
+    const WHITESPACES: [u8; 4] = [b'\t', b'\n', b'\r', b' '];
+
+    let (base, count) = match byte {
+        b'0'..=b'9' => (b'0', 10),
+        b'A'..=b'F' => (b'A', 6),
+        b'a'..=b'f' => (b'a', 6),
+        b'G'..=b'Z' => (b'G', 20),
+        b'g'..=b'z' => (b'g', 20),
+        b'\t' | b'\n' | b'\r' | b' ' => {
+            let idx = WHITESPACES.iter().position(|&c| c == byte).unwrap() as u64;
+            return WHITESPACES[((idx + 1 + rand.below(3)) % 4) as usize];
+        }
+        _ => {
+            #[allow(clippy::cast_possible_truncation)]
+            return byte.wrapping_add(1 + rand.below(255) as u8);
+        }
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let offset = (u64::from(byte - base) + 1 + rand.below(count - 1)) % count;
+    base + offset as u8
+}
+
+/// A stage that colorizes the current corpus entry: it replaces as many bytes as possible with
+/// random ones while preserving the coverage hash of the given map observer.
+/// The result is stored in the [`struct@TaintMetadata`].
+#[derive(Clone, Debug)]
+pub struct ColorizationStage<EM, O, OT, Z> {
+    map_observer_name: String,
+    max_execs: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(EM, O, OT, Z)>,
+}
+
+impl<EM, O, OT, Z> UsesState for ColorizationStage<EM, O, OT, Z>
+where
+    EM: UsesState,
+{
+    type State = EM::State;
+}
+
+impl<E, EM, O, Z> Stage<E, EM, Z> for ColorizationStage<EM, O, E::Observers, Z>
+where
+    O: MapObserver,
+    E: Executor<EM, Z> + HasObservers,
+    E::Observers: ObserversTuple<E::State>,
+    E::State: HasClientPerfMonitor + HasExecutions + HasMetadata + HasCorpus + HasRand,
+    <E::State as UsesInput>::Input: HasBytesVec,
+    EM: UsesState<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        start_timer!(state);
+        let original = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input()?
+            .clone();
+        mark_feature_time!(state, PerfFeature::GetInputFromCorpus);
+
+        let orig_bytes = original.bytes().to_vec();
+        let orig_hash = self.get_raw_map_hash_run(fuzzer, executor, state, manager, &original)?;
+
+        let mut changed = orig_bytes.clone();
+        for byte in &mut changed {
+            *byte = type_replace(state.rand_mut(), *byte);
+        }
+
+        let mut input = original;
+        let mut ranges = Vec::new();
+        let mut to_check = Vec::new();
+        if !orig_bytes.is_empty() {
+            to_check.push(0..orig_bytes.len());
+        }
+        let mut execs = 0;
+
+        // Binary search the ranges that can be randomized, bigger ranges first
+        while let Some(range) = to_check.pop() {
+            if execs >= self.max_execs {
+                break;
+            }
+            input.bytes_mut()[range.clone()].copy_from_slice(&changed[range.clone()]);
+            let hash = self.get_raw_map_hash_run(fuzzer, executor, state, manager, &input)?;
+            execs += 1;
+
+            if hash == orig_hash {
+                ranges.push(range);
+            } else {
+                input.bytes_mut()[range.clone()].copy_from_slice(&orig_bytes[range.clone()]);
+                if range.len() > 1 {
+                    let mid = range.start + range.len() / 2;
+                    to_check.push(mid..range.end);
+                    to_check.push(range.start..mid);
+                }
+            }
+            to_check.sort_by_key(ExactSizeIterator::len);
+        }
+
+        ranges.sort_by_key(|r| r.start);
+        state.add_metadata(TaintMetadata::new(
+            corpus_idx,
+            input.bytes().to_vec(),
+            ranges,
+        ));
+
+        Ok(())
+    }
+}
+
+impl<EM, O, OT, Z> ColorizationStage<EM, O, OT, Z>
+where
+    EM: UsesState,
+    O: MapObserver,
+    OT: ObserversTuple<EM::State>,
+    EM::State: HasClientPerfMonitor + HasExecutions + HasMetadata + HasCorpus,
+{
+    /// Creates a new [`ColorizationStage`]
+    #[must_use]
+    pub fn new(map_observer: &O) -> Self {
+        Self::with_max_execs(map_observer, DEFAULT_COLORIZATION_MAX_EXECS)
+    }
+
+    /// Creates a new [`ColorizationStage`] spending at most `max_execs` executions per corpus entry
+    #[must_use]
+    pub fn with_max_execs(map_observer: &O, max_execs: usize) -> Self {
+        Self {
+            map_observer_name: map_observer.name().to_string(),
+            max_execs,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Runs the input and returns the hash of the map observer
+    fn get_raw_map_hash_run<E>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut EM::State,
+        manager: &mut EM,
+        input: &<EM::State as UsesInput>::Input,
+    ) -> Result<u64, Error>
+    where
+        E: Executor<EM, Z> + HasObservers<Observers = OT, State = EM::State>,
+        Z: UsesState<State = EM::State>,
+    {
+        start_timer!(state);
+        executor.observers_mut().pre_exec_all(state, input)?;
+        mark_feature_time!(state, PerfFeature::PreExecObservers);
+
+        start_timer!(state);
+        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+        mark_feature_time!(state, PerfFeature::TargetExecution);
+
+        *state.executions_mut() += 1;
+
+        start_timer!(state);
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+        mark_feature_time!(state, PerfFeature::PostExecObservers);
+
+        let observer = executor
+            .observers()
+            .match_name::<O>(&self.map_observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+
+        Ok(observer.hash())
+    }
+}
+
+/// Encode a numeric comparison operand of `size` bytes in all the supported encodings
+pub(crate) fn encode_numeric(value: u64, size: usize) -> Vec<(I2SEncoding, Vec<u8>)> {
+    #[allow(clippy::cast_possible_truncation)]
+    let native = match size {
+        1 => vec![value as u8],
+        2 => (value as u16).to_ne_bytes().to_vec(),
+        4 => (value as u32).to_ne_bytes().to_vec(),
+        _ => value.to_ne_bytes().to_vec(),
+    };
+    let mut encodings = Vec::with_capacity(3);
+    if size > 1 {
+        let mut swapped = native.clone();
+        swapped.reverse();
+        encodings.push((I2SEncoding::Swapped, swapped));
+    }
+    encodings.push((I2SEncoding::Native, native));
+    // Small decimal numbers would match almost everywhere
+    if value >= 10 {
+        encodings.push((I2SEncoding::AsciiDecimal, value.to_string().into_bytes()));
+    }
+    encodings
+}
+
+/// Re-encode a numeric value using the given encoding
+pub(crate) fn encode_as(value: u64, size: usize, encoding: I2SEncoding) -> Option<Vec<u8>> {
+    encode_numeric(value, size)
+        .into_iter()
+        .find(|(e, _)| *e == encoding)
+        .map(|(_, bytes)| bytes)
+}
+
+/// Returns the operands of a numeric [`CmpValues`] and their size in bytes
+pub(crate) fn numeric_operands(values: &CmpValues) -> Option<(u64, u64, usize)> {
+    let size = match values {
+        CmpValues::U8(_) => 1,
+        CmpValues::U16(_) => 2,
+        CmpValues::U32(_) => 4,
+        CmpValues::U64(_) => 8,
+        CmpValues::Bytes(_) => return None,
+    };
+    values.to_u64_tuple().map(|(v0, v1)| (v0, v1, size))
+}
+
+/// Returns the [`struct@TaintMetadata`] of the given corpus entry, computed by a [`ColorizationStage`]
+pub(crate) fn current_taint<S>(
+    state: &S,
+    corpus_idx: usize,
+    input_len: usize,
+) -> Result<TaintMetadata, Error>
+where
+    S: HasMetadata,
+{
+    match state.metadata().get::<TaintMetadata>() {
+        Some(taint) if taint.corpus_idx == corpus_idx && taint.input_vec.len() == input_len => {
+            Ok(taint.clone())
+        }
+        _ => Err(Error::key_not_found(
+            "TaintMetadata for the current corpus entry not found, add a ColorizationStage before"
+                .to_string(),
+        )),
+    }
+}
+
+/// Runs the tracer executor and collects the logged values for each cmp of the [`CmpObserver`]
+pub(crate) fn trace_cmps<CM, CO, EM, TE, Z>(
+    tracer_executor: &mut TE,
+    cmp_observer_name: &str,
+    fuzzer: &mut Z,
+    state: &mut TE::State,
+    manager: &mut EM,
+    input: &<TE::State as UsesInput>::Input,
+) -> Result<Vec<Vec<CmpValues>>, Error>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    start_timer!(state);
+    tracer_executor.observers_mut().pre_exec_all(state, input)?;
+    mark_feature_time!(state, PerfFeature::PreExecObservers);
+
+    start_timer!(state);
+    let exit_kind = tracer_executor.run_target(fuzzer, state, manager, input)?;
+    mark_feature_time!(state, PerfFeature::TargetExecution);
+
+    *state.executions_mut() += 1;
+
+    start_timer!(state);
+    tracer_executor
+        .observers_mut()
+        .post_exec_all(state, input, &exit_kind)?;
+    mark_feature_time!(state, PerfFeature::PostExecObservers);
+
+    let observer = tracer_executor
+        .observers()
+        .match_name::<CO>(cmp_observer_name)
+        .ok_or_else(|| Error::key_not_found("CmpObserver not found".to_string()))?;
+    let map = observer.cmp_map();
+
+    let mut cmps = Vec::with_capacity(observer.usable_count());
+    for i in 0..observer.usable_count() {
+        let execs = map.usable_executions_for(i);
+        cmps.push(
+            (0..execs)
+                .filter_map(|j| map.values_of(i, j))
+                .collect::<Vec<_>>(),
+        );
+    }
+    Ok(cmps)
+}
+
+/// A stage that traces the comparisons of both the original and the colorized input (see
+/// [`ColorizationStage`]) using a tracer executor, and correlates them to learn the exact input
+/// offsets of each comparison operand.
+/// The result is stored in the [`struct@I2STaintMetadata`].
+#[derive(Clone, Debug)]
+pub struct CmpTaintTracingStage<CM, CO, EM, TE, Z> {
+    tracer_executor: TE,
+    cmp_observer_name: String,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(CM, CO, EM, TE, Z)>,
+}
+
+impl<CM, CO, EM, TE, Z> UsesState for CmpTaintTracingStage<CM, CO, EM, TE, Z>
+where
+    TE: UsesState,
+{
+    type State = TE::State;
+}
+
+impl<E, CM, CO, EM, TE, Z> Stage<E, EM, Z> for CmpTaintTracingStage<CM, CO, EM, TE, Z>
+where
+    E: UsesState<State = TE::State>,
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasCorpus + HasMetadata,
+    <TE::State as UsesInput>::Input: HasBytesVec,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut TE::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        start_timer!(state);
+        let original = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input()?
+            .clone();
+        mark_feature_time!(state, PerfFeature::GetInputFromCorpus);
+
+        let taint = current_taint(state, corpus_idx, original.bytes().len())?;
+
+        let mut colorized = original.clone();
+        colorized.bytes_mut().clone_from(&taint.input_vec);
+
+        let orig_cmps = trace_cmps::<CM, CO, EM, TE, Z>(
+            &mut self.tracer_executor,
+            &self.cmp_observer_name,
+            fuzzer,
+            state,
+            manager,
+            &original,
+        )?;
+        let colorized_cmps = trace_cmps::<CM, CO, EM, TE, Z>(
+            &mut self.tracer_executor,
+            &self.cmp_observer_name,
+            fuzzer,
+            state,
+            manager,
+            &colorized,
+        )?;
+
+        let mut meta = I2STaintMetadata::new(corpus_idx);
+        for (orig_execs, colorized_execs) in orig_cmps.iter().zip(colorized_cmps.iter()) {
+            for (orig, col) in orig_execs.iter().zip(colorized_execs.iter()) {
+                correlate(&mut meta, original.bytes(), &taint, orig, col);
+                if meta.list.len() >= MAX_TAINT_ENTRIES {
+                    break;
+                }
+            }
+        }
+        state.add_metadata(meta);
+
+        Ok(())
+    }
+}
+
+impl<CM, CO, EM, TE, Z> CmpTaintTracingStage<CM, CO, EM, TE, Z>
+where
+    CM: CmpMap,
+    CO: CmpObserver<CM, TE::State>,
+    TE: Executor<EM, Z> + HasObservers,
+    TE::State: HasClientPerfMonitor + HasExecutions + HasCorpus + HasMetadata,
+    <TE::State as UsesInput>::Input: HasBytesVec,
+    EM: UsesState<State = TE::State>,
+    Z: UsesState<State = TE::State>,
+{
+    /// Creates a new [`CmpTaintTracingStage`], tracing with the given executor and [`CmpObserver`]
+    pub fn new(tracer_executor: TE, cmp_observer: &CO) -> Self {
+        Self {
+            tracer_executor,
+            cmp_observer_name: cmp_observer.name().to_string(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Gets the underlying tracer executor
+    pub fn executor(&self) -> &TE {
+        &self.tracer_executor
+    }
+}
+
+/// Learns where the operands of a comparison come from, given its values in the original and
+/// in the colorized run. Only operands that changed with the colorization are input-derived.
+fn correlate(
+    meta: &mut I2STaintMetadata,
+    input: &[u8],
+    taint: &TaintMetadata,
+    orig: &CmpValues,
+    colorized: &CmpValues,
+) {
+    if let (CmpValues::Bytes(o), CmpValues::Bytes(c)) = (orig, colorized) {
+        if o.0 == o.1 {
+            return;
+        }
+        if o.0 != c.0 {
+            search(meta, input, taint, I2SEncoding::Bytes, &o.0, &c.0, &[&o.1]);
+        }
+        if o.1 != c.1 {
+            search(meta, input, taint, I2SEncoding::Bytes, &o.1, &c.1, &[&o.0]);
+        }
+    } else if let (Some((o0, o1, size)), Some((c0, c1, _))) =
+        (numeric_operands(orig), numeric_operands(colorized))
+    {
+        if o0 == o1 {
+            return;
+        }
+        for (pattern, col, other) in [(o0, c0, o1), (o1, c1, o0)] {
+            if pattern == col {
+                continue;
+            }
+            for (encoding, encoded) in encode_numeric(pattern, size) {
+                if let Some(encoded_col) = encode_as(col, size, encoding) {
+                    let replacements: Vec<Vec<u8>> =
+                        [other, other.wrapping_add(1), other.wrapping_sub(1)]
+                            .into_iter()
+                            .filter_map(|v| encode_as(v, size, encoding))
+                            .collect();
+                    let replacements: Vec<&[u8]> = replacements.iter().map(Vec::as_slice).collect();
+                    search(
+                        meta,
+                        input,
+                        taint,
+                        encoding,
+                        &encoded,
+                        &encoded_col,
+                        &replacements,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Searches the tainted ranges of the input for `pattern`, where the colorized input contains
+/// the `colorized` pattern instead, and registers the found offsets
+fn search(
+    meta: &mut I2STaintMetadata,
+    input: &[u8],
+    taint: &TaintMetadata,
+    encoding: I2SEncoding,
+    pattern: &[u8],
+    colorized: &[u8],
+    replacements: &[&[u8]],
+) {
+    if pattern.is_empty() || pattern.len() > input.len() || replacements.is_empty() {
+        return;
+    }
+    for range in &taint.ranges {
+        for offset in range.clone() {
+            if offset + pattern.len() > input.len() {
+                break;
+            }
+            if input[offset..offset + pattern.len()] != *pattern {
+                continue;
+            }
+            let col_end = offset + colorized.len();
+            if col_end > taint.input_vec.len() || taint.input_vec[offset..col_end] != *colorized {
+                continue;
+            }
+            let entry = I2STaintEntry {
+                offset,
+                encoding,
+                pattern: pattern.to_vec(),
+                replacements: replacements.iter().map(|r| r.to_vec()).collect(),
+            };
+            if meta.list.len() < MAX_TAINT_ENTRIES && !meta.list.contains(&entry) {
+                meta.list.push(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bolts::rands::StdRand,
+        observers::CmpValues,
+        stages::colorization::{
+            correlate, encode_numeric, type_replace, I2SEncoding, I2STaintMetadata, TaintMetadata,
+        },
+    };
+
+    #[test]
+    fn test_type_replace() {
+        let mut rand = StdRand::with_seed(1337);
+        for _ in 0..100 {
+            for byte in [b'0', b'9', b'A', b'f', b'G', b'z', b' ', 0, 0xff] {
+                let replaced = type_replace(&mut rand, byte);
+                assert_ne!(replaced, byte);
+                if byte.is_ascii_digit() {
+                    assert!(replaced.is_ascii_digit());
+                } else if byte.is_ascii_whitespace() {
+                    assert!(replaced.is_ascii_whitespace());
+                } else if byte.is_ascii_alphabetic() {
+                    assert!(replaced.is_ascii_alphabetic());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_numeric() {
+        let encodings = encode_numeric(0x1234, 2);
+        assert!(encodings.contains(&(I2SEncoding::Native, 0x1234_u16.to_ne_bytes().to_vec())));
+        assert!(encodings.contains(&(I2SEncoding::Swapped, 0x1234_u16.to_be_bytes().to_vec())));
+        assert!(encodings.contains(&(I2SEncoding::AsciiDecimal, b"4660".to_vec())));
+        // Small decimal numbers are too common to be searched for
+        assert_eq!(encode_numeric(7, 1), vec![(I2SEncoding::Native, vec![7])]);
+    }
+
+    #[test]
+    fn test_correlate() {
+        // The magic is compared at offset 4, the first bytes do not reach the comparison
+        let input = b"AAAA\x78\x56\x34\x12BBBB".to_vec();
+        let mut colorized = input.clone();
+        colorized[4..8].copy_from_slice(&0x1111_1111_u32.to_ne_bytes());
+        let taint = TaintMetadata::new(3, colorized, vec![0..4, 4..12]);
+
+        let mut meta = I2STaintMetadata::new(3);
+        correlate(
+            &mut meta,
+            &input,
+            &taint,
+            &CmpValues::U32((0x1234_5678, 0xdead_beef)),
+            &CmpValues::U32((0x1111_1111, 0xdead_beef)),
+        );
+        let entry = meta
+            .list
+            .iter()
+            .find(|entry| entry.encoding == I2SEncoding::Native)
+            .unwrap();
+        assert_eq!(entry.offset, 4);
+        assert_eq!(entry.pattern, 0x1234_5678_u32.to_ne_bytes());
+        assert!(entry
+            .replacements
+            .contains(&0xdead_beef_u32.to_ne_bytes().to_vec()));
+        assert!(entry
+            .replacements
+            .contains(&0xdead_bef0_u32.to_ne_bytes().to_vec()));
+
+        // Operands that did not change with the colorization do not come from the input
+        let mut meta = I2STaintMetadata::new(3);
+        correlate(
+            &mut meta,
+            &input,
+            &taint,
+            &CmpValues::U32((0x1234_5678, 0xdead_beef)),
+            &CmpValues::U32((0x1234_5678, 0xdead_beef)),
+        );
+        assert!(meta.list.is_empty());
+
+        // Nor do the bytes outside of the tainted ranges
+        let taint = TaintMetadata::new(3, taint.input_vec, vec![0..2, 2..4]);
+        let mut meta = I2STaintMetadata::new(3);
+        correlate(
+            &mut meta,
+            &input,
+            &taint,
+            &CmpValues::U32((0x1234_5678, 0xdead_beef)),
+            &CmpValues::U32((0x1111_1111, 0xdead_beef)),
+        );
+        assert!(meta.list.is_empty());
+    }
+
+    #[test]
+    fn test_correlate_bytes() {
+        let input = b"key=abcd;".to_vec();
+        let mut colorized = input.clone();
+        colorized[4..8].copy_from_slice(b"wxyz");
+        let taint = TaintMetadata::new(0, colorized, vec![4..6, 6..8]);
+
+        let mut meta = I2STaintMetadata::new(0);
+        correlate(
+            &mut meta,
+            &input,
+            &taint,
+            &CmpValues::Bytes((b"abcd".to_vec(), b"pass".to_vec())),
+            &CmpValues::Bytes((b"wxyz".to_vec(), b"pass".to_vec())),
+        );
+        assert_eq!(meta.list.len(), 1);
+        assert_eq!(meta.list[0].offset, 4);
+        assert_eq!(meta.list[0].encoding, I2SEncoding::Bytes);
+        assert_eq!(meta.list[0].replacements, vec![b"pass".to_vec()]);
+    }
+}
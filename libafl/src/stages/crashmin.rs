@@ -0,0 +1,476 @@
+//! The [`SolutionMinimizerStage`] is a stage which will attempt to minimize the solutions,
+//! preserving their crash signature, like the [`super::TMinMutationalStage`] does for corpus entries.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData};
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "introspection")]
+use crate::monitors::PerfFeature;
+use crate::{
+    bolts::{
+        tuples::{MatchName, Named},
+        HasLen,
+    },
+    corpus::Corpus,
+    events::EventFirer,
+    executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
+    feedbacks::{Feedback, HasObserverName},
+    inputs::{Input, UsesInput},
+    mark_feature_time,
+    mutators::Mutator,
+    observers::{CrashSignature, ObserverWithCrashSignature, ObserversTuple},
+    stages::Stage,
+    start_timer,
+    state::{HasClientPerfMonitor, HasMaxSize, HasMetadata, HasSolutions, UsesState},
+    Error, ExecutesInput,
+};
+
+/// A feedback which checks if a crash has the same [`ExitKind`] and [`CrashSignature`] as the
+/// original crash provided
+#[derive(Clone, Debug)]
+pub struct CrashSignatureFeedback<O, S> {
+    name: String,
+    obs_name: String,
+    orig_exit_kind: ExitKind,
+    orig_signature: Option<CrashSignature>,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> CrashSignatureFeedback<O, S> {
+    /// Create a new crash signature feedback -- can be used with feedback logic
+    #[must_use]
+    pub fn new(
+        obs_name: &str,
+        orig_exit_kind: ExitKind,
+        orig_signature: Option<CrashSignature>,
+    ) -> Self {
+        Self {
+            name: "CrashSignatureEq".to_string(),
+            obs_name: obs_name.to_string(),
+            orig_exit_kind,
+            orig_signature,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O, S> Named for CrashSignatureFeedback<O, S> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<O, S> HasObserverName for CrashSignatureFeedback<O, S> {
+    fn observer_name(&self) -> &str {
+        &self.obs_name
+    }
+}
+
+impl<O, S> Feedback<S> for CrashSignatureFeedback<O, S>
+where
+    O: ObserverWithCrashSignature + Named + Debug,
+    S: UsesInput + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &<S as UsesInput>::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if *exit_kind != self.orig_exit_kind {
+            return Ok(false);
+        }
+        let obs = observers
+            .match_name::<O>(self.observer_name())
+            .expect("Should have been provided valid observer name.");
+        Ok(obs.crash_signature() == self.orig_signature)
+    }
+}
+
+/// The solutions already minimized by the [`SolutionMinimizerStage`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct SolutionMinimizerMetadata {
+    /// The number of solutions already minimized
+    pub minimized: usize,
+}
+
+crate::impl_serdeany!(SolutionMinimizerMetadata);
+
+/// A stage which minimizes each new solution, keeping the smaller inputs with the same
+/// [`ExitKind`] and [`CrashSignature`] as the original crash, and replaces the stored solution.
+///
+/// The reduced inputs are not evaluated by the fuzzer, so they will not end up in the corpus or
+/// as duplicate solutions. You must provide at least one mutator that actually reduces size.
+/// Solutions are re-executed until they crash, so the executor has to isolate the crashes from the
+/// fuzzer, see [`IsolatesCrashes`]: an in-process executor would restart the fuzzer on the first run.
+/// Use [`SolutionMinimizerStage::minimize_dir`] to minimize an existing solutions directory offline.
+#[derive(Clone, Debug)]
+pub struct SolutionMinimizerStage<E, EM, M, O, Z> {
+    mutator: M,
+    obs_name: String,
+    runs: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, O, Z)>,
+}
+
+impl<E, EM, M, O, Z> UsesState for SolutionMinimizerStage<E, EM, M, O, Z>
+where
+    Z: UsesState,
+{
+    type State = Z::State;
+}
+
+impl<E, EM, M, O, Z> Stage<E, EM, Z> for SolutionMinimizerStage<E, EM, M, O, Z>
+where
+    E: Executor<EM, Z> + HasObservers<State = Z::State> + IsolatesCrashes,
+    EM: EventFirer<State = Z::State>,
+    M: Mutator<Z::State>,
+    O: ObserverWithCrashSignature + Named + Debug,
+    Z: ExecutesInput<E, EM>,
+    Z::State: HasClientPerfMonitor + HasSolutions + HasMaxSize + HasMetadata + Debug,
+    <Z::State as UsesInput>::Input: HasLen,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Z::State,
+        manager: &mut EM,
+        _corpus_idx: usize,
+    ) -> Result<(), Error> {
+        let first = state
+            .metadata()
+            .get::<SolutionMinimizerMetadata>()
+            .map_or(0, |meta| meta.minimized);
+        let count = state.solutions().count();
+
+        for idx in first..count {
+            // Mark the solution as done first, if minimizing it takes the fuzzer down, skip it
+            state.add_metadata(SolutionMinimizerMetadata { minimized: idx + 1 });
+
+            let base = state
+                .solutions()
+                .get(idx)?
+                .borrow_mut()
+                .load_input()?
+                .clone();
+            if let Some(input) = self.minimize(fuzzer, executor, state, manager, base)? {
+                // Replace the input in place, the solution keeps its file name and metadata
+                let mut testcase = state.solutions().get(idx)?.borrow_mut();
+                testcase.set_input(input);
+                testcase.store_input()?;
+            }
+        }
+
+        #[cfg(feature = "introspection")]
+        state.introspection_monitor_mut().finish_stage();
+
+        Ok(())
+    }
+}
+
+impl<E, EM, M, O, Z> SolutionMinimizerStage<E, EM, M, O, Z>
+where
+    E: Executor<EM, Z> + HasObservers<State = Z::State> + IsolatesCrashes,
+    EM: EventFirer<State = Z::State>,
+    M: Mutator<Z::State>,
+    O: ObserverWithCrashSignature + Named + Debug,
+    Z: ExecutesInput<E, EM>,
+    Z::State: HasClientPerfMonitor + HasMaxSize + Debug,
+    <Z::State as UsesInput>::Input: HasLen,
+{
+    /// Creates a new solution minimizing stage, preserving the crash signature of the given
+    /// observer, and trying `runs` mutations for each reduction
+    pub fn new(mutator: M, observer: &O, runs: usize) -> Self {
+        Self {
+            mutator,
+            obs_name: observer.name().to_string(),
+            runs,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Minimizes a crashing input, returns the minimized input if smaller than the given one.
+    /// Returns `None` if the input does not crash (anymore).
+    #[allow(clippy::cast_possible_wrap)] // more than i32 stages on 32 bit system - highly unlikely...
+    pub fn minimize(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Z::State,
+        manager: &mut EM,
+        mut base: <Z::State as UsesInput>::Input,
+    ) -> Result<Option<<Z::State as UsesInput>::Input>, Error> {
+        let exit_kind = fuzzer.execute_input(state, executor, manager, &base)?;
+        if exit_kind == ExitKind::Ok {
+            // Flaky or fixed, there is no crash signature to preserve
+            return Ok(None);
+        }
+        let signature = executor
+            .observers()
+            .match_name::<O>(&self.obs_name)
+            .expect("Should have been provided valid observer name.")
+            .crash_signature();
+        let mut feedback =
+            CrashSignatureFeedback::<O, Z::State>::new(&self.obs_name, exit_kind, signature);
+
+        let orig_max_size = state.max_size();
+        let mut minimized = false;
+
+        let mut i = 0;
+        while i < self.runs {
+            let mut next_i = i + 1;
+            let mut input = base.clone();
+
+            let before_len = input.len();
+
+            state.set_max_size(before_len);
+
+            start_timer!(state);
+            self.mutator.mutate(state, &mut input, i as i32)?;
+            mark_feature_time!(state, PerfFeature::Mutate);
+
+            // skip any mutations that do not reduce size, as in the corpus minimizing stage
+            if input.len() < before_len {
+                let exit_kind = fuzzer.execute_input(state, executor, manager, &input)?;
+                let observers = executor.observers();
+
+                if feedback.is_interesting(state, manager, &input, observers, &exit_kind)? {
+                    // we found a reduced crash! use the smaller base
+                    base = input;
+                    minimized = true;
+
+                    // do more runs! maybe we can minify further
+                    next_i = 0;
+                }
+            }
+
+            start_timer!(state);
+            self.mutator.post_exec(state, i as i32, None)?;
+            mark_feature_time!(state, PerfFeature::MutatePostExec);
+
+            i = next_i;
+        }
+
+        state.set_max_size(orig_max_size);
+
+        Ok(minimized.then_some(base))
+    }
+
+    /// Minimizes all the solutions in an existing directory, offline, overwriting each file with
+    /// the minimized input. Hidden files, such as metadata, are skipped.
+    /// Returns the number of minimized solutions.
+    pub fn minimize_dir<P>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Z::State,
+        manager: &mut EM,
+        dir: P,
+    ) -> Result<usize, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        let mut count = 0;
+        for path in entries {
+            let hidden = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .starts_with('.');
+            if hidden || !path.is_file() {
+                continue;
+            }
+            let base = <Z::State as UsesInput>::Input::from_file(&path)?;
+            if let Some(input) = self.minimize(fuzzer, executor, state, manager, base)? {
+                input.to_file(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        bolts::{
+            rands::StdRand,
+            tuples::{tuple_list, tuple_list_type, MatchName, Named},
+        },
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::{Executor, ExitKind, HasObservers, IsolatesCrashes},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasBytesVec, UsesInput},
+        mutators::BytesDeleteMutator,
+        observers::{CrashSignature, Observer, ObserverWithCrashSignature, UsesObservers},
+        schedulers::QueueScheduler,
+        stages::{crashmin::SolutionMinimizerMetadata, SolutionMinimizerStage, Stage},
+        state::{HasExecutions, HasMetadata, HasSolutions, StdState, UsesState},
+        Error, StdFuzzer,
+    };
+
+    type TestState =
+        StdState<BytesInput, InMemoryCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>>;
+    type TestObservers = tuple_list_type!(SignatureObserver);
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct SignatureObserver {
+        signature: Option<CrashSignature>,
+    }
+
+    impl<S> Observer<S> for SignatureObserver where S: UsesInput {}
+
+    impl Named for SignatureObserver {
+        fn name(&self) -> &str {
+            "signature"
+        }
+    }
+
+    impl ObserverWithCrashSignature for SignatureObserver {
+        fn crash_signature(&self) -> Option<CrashSignature> {
+            self.signature.clone()
+        }
+    }
+
+    /// Crashes on `X` and on `Y`, with a different signature for each combination
+    #[derive(Debug)]
+    struct CrashingExecutor {
+        observers: TestObservers,
+    }
+
+    impl UsesState for CrashingExecutor {
+        type State = TestState;
+    }
+
+    impl UsesObservers for CrashingExecutor {
+        type Observers = TestObservers;
+    }
+
+    impl HasObservers for CrashingExecutor {
+        fn observers(&self) -> &Self::Observers {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut Self::Observers {
+            &mut self.observers
+        }
+    }
+
+    impl IsolatesCrashes for CrashingExecutor {}
+
+    impl<EM, Z> Executor<EM, Z> for CrashingExecutor
+    where
+        EM: UsesState<State = TestState>,
+        Z: UsesState<State = TestState>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            _state: &mut Self::State,
+            _mgr: &mut EM,
+            input: &<Self::State as UsesInput>::Input,
+        ) -> Result<ExitKind, Error> {
+            let stack_hash = u64::from(input.bytes().contains(&b'X'))
+                + 2 * u64::from(input.bytes().contains(&b'Y'));
+            let signature = (stack_hash != 0).then(|| CrashSignature {
+                stack_hash,
+                error_class: "crash".to_string(),
+                pc: None,
+                pc_offset: None,
+            });
+            let crashed = signature.is_some();
+            self.observers
+                .match_name_mut::<SignatureObserver>("signature")
+                .unwrap()
+                .signature = signature;
+            Ok(if crashed {
+                ExitKind::Crash
+            } else {
+                ExitKind::Ok
+            })
+        }
+    }
+
+    #[test]
+    fn test_solution_minimizer_stage() {
+        let observer = SignatureObserver { signature: None };
+        let mut stage = SolutionMinimizerStage::new(BytesDeleteMutator::new(), &observer, 64);
+        let mut executor = CrashingExecutor {
+            observers: tuple_list!(observer),
+        };
+
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        state
+            .solutions_mut()
+            .add(Testcase::new(BytesInput::new(b"aaXaaaaYaa".to_vec())))
+            .unwrap();
+        let mut fuzzer: StdFuzzer<_, _, _, TestObservers> = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr, 0)
+            .unwrap();
+
+        // Both crashing bytes are kept, as the signature changes without one of them
+        let minimized = state
+            .solutions()
+            .get(0)
+            .unwrap()
+            .borrow_mut()
+            .load_input()
+            .unwrap()
+            .bytes()
+            .to_vec();
+        assert!(minimized.len() < 10);
+        assert!(minimized.contains(&b'X') && minimized.contains(&b'Y'));
+        assert_eq!(
+            state
+                .metadata()
+                .get::<SolutionMinimizerMetadata>()
+                .unwrap()
+                .minimized,
+            1
+        );
+
+        // The solution is not minimized again
+        let execs = *state.executions();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr, 0)
+            .unwrap();
+        assert_eq!(*state.executions(), execs);
+    }
+}
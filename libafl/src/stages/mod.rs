@@ -13,11 +13,22 @@ pub use tmin::{
     MapEqualityFactory, MapEqualityFeedback, StdTMinMutationalStage, TMinMutationalStage,
 };
 
+#[cfg(feature = "std")]
+pub mod crashmin;
+#[cfg(feature = "std")]
+pub use crashmin::{CrashSignatureFeedback, SolutionMinimizerStage};
+
 pub mod push;
 
 pub mod tracing;
 pub use tracing::{ShadowTracingStage, TracingStage};
 
+pub mod colorization;
+pub use colorization::{CmpTaintTracingStage, ColorizationStage};
+
+pub mod checksum;
+pub use checksum::{ChecksumDetectionStage, ChecksumRepairStage};
+
 pub mod calibrate;
 pub use calibrate::CalibrationStage;
 
@@ -1,6 +1,6 @@
 //! LLVM style control flow graph with information of AFL-style index of the each
 //! edges, use together with ``AFLCoverage`` pass having --dump-afl-cfg flag enabled.
-use core::borrow::Borrow;
+use core::{borrow::Borrow, cmp::Reverse};
 use std::{
     collections::{BinaryHeap, HashMap, HashSet},
     marker::PhantomData,
@@ -331,6 +331,85 @@ where
         }
         distances
     }
+
+    /// Get the indexes of all edges contained in the function ``func_name``.
+    #[must_use]
+    pub fn get_function_edges(&self, func_name: &str) -> Vec<usize> {
+        self.edges
+            .iter()
+            .flatten()
+            .filter(|edge| edge.calling_func == func_name)
+            .map(|edge| edge.xored_loc)
+            .collect()
+    }
+
+    /// Calculate the shortest distance from every edge to the closest edge in ``targets``.
+    ///
+    /// The distance of a target edge is its own weight. Edges that cannot reach any of the
+    /// ``targets`` would not be inserted in the returned hash map.
+    #[must_use]
+    pub fn calculate_distances_to_targets(&self, targets: &[usize]) -> HashMap<usize, u32> {
+        let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for edge in self.edges.iter().flatten() {
+            for successor in &edge.successor_edges {
+                predecessors
+                    .entry(*successor)
+                    .or_default()
+                    .push(edge.xored_loc);
+            }
+        }
+
+        let mut distances: HashMap<usize, u32> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut to_visit = BinaryHeap::new(); // BinaryHeap<Reverse<(distance, loc)>>
+        for target in targets {
+            if let Some(edge_info) = self.get_edge(*target) {
+                let weight = edge_info.get_weight();
+                let is_shorter = distances
+                    .get(target)
+                    .map_or(true, |&current| weight < current);
+                if is_shorter {
+                    distances.insert(*target, weight);
+                    to_visit.push(Reverse((weight, *target)));
+                }
+            }
+        }
+
+        while let Some(Reverse((distance, edge))) = to_visit.pop() {
+            if !visited.insert(edge) {
+                continue;
+            }
+            if let Some(preds) = predecessors.get(&edge) {
+                for predecessor in preds {
+                    let new_distance = distance
+                        + self
+                            .get_edge(*predecessor)
+                            .expect("unknown predecessor added")
+                            .get_weight();
+                    let is_shorter = distances
+                        .get(predecessor)
+                        .map_or(true, |&current| new_distance < current);
+
+                    if is_shorter {
+                        distances.insert(*predecessor, new_distance);
+                        to_visit.push(Reverse((new_distance, *predecessor)));
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    /// Calculate the shortest distance from every edge to the closest edge of any of the
+    /// functions in ``func_names``, see [`ControlFlowGraph::calculate_distances_to_targets`].
+    #[must_use]
+    pub fn calculate_distances_to_functions(&self, func_names: &[&str]) -> HashMap<usize, u32> {
+        let targets: Vec<usize> = func_names
+            .iter()
+            .flat_map(|func_name| self.get_function_edges(func_name))
+            .collect();
+        self.calculate_distances_to_targets(&targets)
+    }
 }
 
 impl<T> Default for ControlFlowGraph<T>
@@ -399,4 +478,41 @@ mod tests {
         assert_eq!(*distances.get(&((26911 >> 1) ^ 41925)).unwrap(), 2);
         assert!(distances.get(&((41864 >> 1) ^ 52706)).is_none());
     }
+
+    #[test]
+    fn test_distances_to_targets() {
+        let cfg: ControlFlowGraph<TestMetaData> = ControlFlowGraph::from_content(TEST_GRAPH_STR);
+        let distances = cfg.calculate_distances_to_targets(&[(26911 >> 1) ^ 41925]);
+        assert_eq!(*distances.get(&((26911 >> 1) ^ 41925)).unwrap(), 1);
+        assert_eq!(*distances.get(&((41864 >> 1) ^ 26911)).unwrap(), 2);
+        assert!(distances.get(&((26911 >> 1) ^ 52706)).is_none());
+        assert!(distances.get(&((41864 >> 1) ^ 52706)).is_none());
+
+        let distances = cfg.calculate_distances_to_functions(&["_ZN7MyClass1VEi"]);
+        assert_eq!(*distances.get(&((50306 >> 1) ^ 19123)).unwrap(), 1);
+        assert!(distances.get(&((41864 >> 1) ^ 26911)).is_none());
+    }
+
+    #[test]
+    fn test_distances_to_closest_target() {
+        let cfg: ControlFlowGraph<TestMetaData> = ControlFlowGraph::from_content(TEST_GRAPH_STR);
+        let edges = cfg.get_function_edges("main");
+        assert!(edges.contains(&((41864 >> 1) ^ 26911)));
+        assert!(edges.contains(&((26911 >> 1) ^ 41925)));
+        assert!(!edges.contains(&((50306 >> 1) ^ 19123)));
+
+        let distances = cfg.calculate_distances_to_targets(&[(26911 >> 1) ^ 41925]);
+        assert_eq!(*distances.get(&41864).unwrap(), 3);
+
+        // Each edge is as far as its closest target
+        let distances =
+            cfg.calculate_distances_to_targets(&[(26911 >> 1) ^ 41925, (41864 >> 1) ^ 26911]);
+        assert_eq!(*distances.get(&((41864 >> 1) ^ 26911)).unwrap(), 1);
+        assert_eq!(*distances.get(&41864).unwrap(), 2);
+
+        assert!(cfg.calculate_distances_to_targets(&[]).is_empty());
+        assert!(cfg
+            .calculate_distances_to_functions(&["missing"])
+            .is_empty());
+    }
 }
@@ -237,6 +237,13 @@ impl Allocator {
         self.shadow_bit as u32
     }
 
+    /// The size of the pages
+    #[inline]
+    #[must_use]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
     #[inline]
     #[must_use]
     fn round_up_to_page(&self, size: usize) -> usize {
@@ -479,6 +486,13 @@ impl Allocator {
         }
     }
 
+    /// Poison the memory from `start` to `end`, which does not have to be aligned to the
+    /// granularity of the shadow, e.g. the padding between two globals
+    pub fn poison_bytes(&self, start: usize, end: usize) {
+        let shadow = map_to_shadow!(self, start) as *mut u8;
+        unsafe { poison_shadow_bits(shadow, start, end) };
+    }
+
     /// Map shadow memory for a region, and optionally unpoison it
     pub fn map_shadow_for_region(
         &mut self,
@@ -564,3 +578,39 @@ impl Allocator {
         });
     }
 }
+
+/// Clears the shadow bits of the bytes from `start` to `end`, `shadow` being the shadow byte of
+/// `start`. The first byte of each 8 is the most significant bit of its shadow byte.
+///
+/// # Safety
+/// The shadow of the whole range must be mapped.
+unsafe fn poison_shadow_bits(shadow: *mut u8, start: usize, end: usize) {
+    for address in start..end {
+        *shadow.add((address >> 3) - (start >> 3)) &= !(0x80 >> (address & 7));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::poison_shadow_bits;
+
+    #[test]
+    fn test_poison_bytes() {
+        let mut shadow = [0xff_u8; 3];
+        // From the 4th byte of the first 8 to the 3rd byte of the third 8
+        unsafe { poison_shadow_bits(shadow.as_mut_ptr(), 0x1003, 0x1013) };
+        assert_eq!(shadow, [0b1110_0000, 0, 0b0001_1111]);
+
+        // Poisoning is idempotent, and leaves the other bytes accessible
+        let mut shadow = [0xff_u8; 2];
+        unsafe {
+            poison_shadow_bits(shadow.as_mut_ptr(), 0x2001, 0x2002);
+            poison_shadow_bits(shadow.as_mut_ptr(), 0x2001, 0x2002);
+        }
+        assert_eq!(shadow, [0b1011_1111, 0xff]);
+
+        // An empty range poisons nothing
+        unsafe { poison_shadow_bits(shadow.as_mut_ptr(), 0x2008, 0x2008) };
+        assert_eq!(shadow, [0b1011_1111, 0xff]);
+    }
+}
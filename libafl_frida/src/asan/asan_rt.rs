@@ -52,9 +52,19 @@ use rangemap::RangeMap;
 use crate::utils::instruction_width;
 use crate::{
     alloc::Allocator,
-    asan::errors::{AsanError, AsanErrors, AsanReadWriteError, ASAN_ERRORS},
+    asan::{
+        errors::{
+            AsanError, AsanErrors, AsanFakeFrameError, AsanGlobal, AsanGlobalError,
+            AsanReadWriteError, ASAN_ERRORS,
+        },
+        fake_stack::{
+            call_on_stack, fake_stack_replacement, FakeStack, FakeStackFunction, FakeStackHook,
+            FAKE_STACK_ARGS,
+        },
+        globals::{global_redzones, module_globals},
+    },
     helper::FridaRuntime,
-    utils::writer_register,
+    utils::{function_address, writer_register},
 };
 
 extern "C" {
@@ -138,6 +148,11 @@ pub struct AsanRuntime {
     module_map: Option<ModuleMap>,
     suppressed_addresses: Vec<usize>,
     shadow_check_func: Option<extern "C" fn(*const c_void, usize) -> bool>,
+    globals: Vec<AsanGlobal>,
+    /// The padding after the globals, to the index of the global in `globals`
+    global_redzones: RangeMap<usize, usize>,
+    fake_stack: Option<FakeStack>,
+    fake_stack_hooks: Vec<Box<FakeStackHook>>,
 
     #[cfg(target_arch = "aarch64")]
     eh_frame: [u32; ASAN_EH_FRAME_DWORD_COUNT],
@@ -150,6 +165,8 @@ impl Debug for AsanRuntime {
             .field("options", &self.options)
             .field("module_map", &"<ModuleMap>")
             .field("suppressed_addresses", &self.suppressed_addresses)
+            .field("globals", &self.globals.len())
+            .field("fake_stack", &self.fake_stack)
             .finish_non_exhaustive()
     }
 }
@@ -182,7 +199,14 @@ impl FridaRuntime for AsanRuntime {
             }
         }
 
+        if self.options.asan_globals {
+            self.poison_global_redzones(modules_to_instrument);
+        }
+
         self.hook_functions(gum);
+        if !self.options.asan_fake_stack.is_empty() {
+            self.hook_fake_stack_functions(gum);
+        }
         /*
         unsafe {
             let mem = self.allocator.alloc(0xac + 2, 8);
@@ -314,6 +338,10 @@ impl AsanRuntime {
             module_map: None,
             suppressed_addresses: Vec::new(),
             shadow_check_func: None,
+            globals: Vec::new(),
+            global_redzones: RangeMap::new(),
+            fake_stack: None,
+            fake_stack_hooks: Vec::new(),
 
             #[cfg(target_arch = "aarch64")]
             eh_frame: [0; ASAN_EH_FRAME_DWORD_COUNT],
@@ -387,6 +415,112 @@ impl AsanRuntime {
         self.allocator.unpoison_all_existing_memory();
     }
 
+    /// Poison the padding between the writable globals of the instrumented modules
+    fn poison_global_redzones(&mut self, modules_to_instrument: &[&str]) {
+        for module in modules_to_instrument {
+            let globals = module_globals(module);
+            for (idx, start, end) in global_redzones(&globals) {
+                self.allocator.poison_bytes(start, end);
+                self.global_redzones
+                    .insert(start..end, self.globals.len() + idx);
+            }
+            self.globals.extend(globals);
+        }
+    }
+
+    /// Hook the functions given by `--asan-fake-stack`, to run them on the fake stack
+    fn hook_fake_stack_functions(&mut self, gum: &Gum) {
+        self.fake_stack = Some(FakeStack::new(
+            &mut self.allocator,
+            self.options.asan_fake_stack_size,
+        ));
+        let mut interceptor = Interceptor::obtain(gum);
+        for (module, target) in self.options.asan_fake_stack.clone() {
+            let address = function_address(&module, &target)
+                .expect("Failed to find a function to run on the fake stack");
+            let mut hook = Box::new(FakeStackHook {
+                runtime: self as *mut _,
+                name: format!("{module}@{target}"),
+                original: None,
+            });
+            let original = interceptor
+                .replace(
+                    NativePointer(address as *mut c_void),
+                    NativePointer(fake_stack_replacement as *mut c_void),
+                    NativePointer(addr_of_mut!(*hook).cast::<c_void>()),
+                )
+                .expect("Failed to hook a function to run on the fake stack");
+            hook.original =
+                Some(unsafe { core::mem::transmute::<*mut c_void, FakeStackFunction>(original.0) });
+            self.fake_stack_hooks.push(hook);
+        }
+    }
+
+    /// Runs the hooked function on a frame of the fake stack, which is poisoned once it returns.
+    /// The calls nested deeper than the frames of the fake stack run on the thread stack.
+    pub(crate) unsafe fn call_on_fake_stack(
+        &mut self,
+        hook: &FakeStackHook,
+        args: [usize; FAKE_STACK_ARGS],
+    ) -> usize {
+        let original = hook.original.unwrap();
+        let fake_stack = self.fake_stack.as_mut().unwrap();
+        let frame_size = fake_stack.frame_size();
+        if let Some(frame) = fake_stack.acquire(&mut self.allocator, &hook.name) {
+            let ret = call_on_stack(original, args, frame + frame_size);
+            // The runtime may have been reentered by the call
+            self.fake_stack
+                .as_mut()
+                .unwrap()
+                .release(&self.allocator, frame);
+            ret
+        } else {
+            original(
+                args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
+            )
+        }
+    }
+
+    /// The error for a fault in the padding of a global or in a returned frame of the fake stack,
+    /// which the allocator knows nothing about
+    fn global_or_fake_stack_error(
+        &self,
+        is_write: bool,
+        pc: usize,
+        fault: (Option<u16>, Option<u16>, usize, usize),
+        backtrace: &Backtrace,
+    ) -> Option<AsanError> {
+        let fault_address = fault.3;
+        if let Some(idx) = self.global_redzones.get(&fault_address) {
+            let error = AsanGlobalError {
+                registers: self.regs,
+                pc,
+                fault,
+                global: self.globals[*idx].clone(),
+                backtrace: backtrace.clone(),
+            };
+            return Some(if is_write {
+                AsanError::GlobalOobWrite(error)
+            } else {
+                AsanError::GlobalOobRead(error)
+            });
+        }
+
+        let frame = self.fake_stack.as_ref()?.returned_frame(fault_address)?;
+        let error = AsanFakeFrameError {
+            registers: self.regs,
+            pc,
+            fault,
+            frame,
+            backtrace: backtrace.clone(),
+        };
+        Some(if is_write {
+            AsanError::StackUseAfterReturnWrite(error)
+        } else {
+            AsanError::StackUseAfterReturnRead(error)
+        })
+    }
+
     /// Register the current thread with the runtime, implementing shadow memory for its stack and
     /// tls mappings.
     #[allow(clippy::unused_self)]
@@ -987,6 +1121,15 @@ impl AsanRuntime {
                         backtrace,
                     )),
                 }
+            } else if let Some(error) = access_type.as_ref().and_then(|typ| {
+                self.global_or_fake_stack_error(
+                    !matches!(typ, RegAccessType::ReadOnly),
+                    actual_pc,
+                    (base_idx, index_idx, disp as usize, fault_address),
+                    &backtrace,
+                )
+            }) {
+                error
             } else if base_value.is_some() {
                 if let Some(metadata) = self
                     .allocator
@@ -1187,6 +1330,18 @@ impl AsanRuntime {
                     backtrace,
                 ))
             }
+        } else if let Some(error) = self.global_or_fake_stack_error(
+            !insn.mnemonic().unwrap().starts_with('l'),
+            actual_pc,
+            (
+                Some(base_reg),
+                Some(index_reg),
+                displacement as usize,
+                fault_address,
+            ),
+            &backtrace,
+        ) {
+            error
         } else if let Some(metadata) = self
             .allocator
             .find_metadata(fault_address, self.regs[base_reg as usize])
@@ -34,6 +34,41 @@ pub(crate) struct AsanReadWriteError {
     pub backtrace: Backtrace,
 }
 
+/// A global variable found in the symbol table of an instrumented module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AsanGlobal {
+    pub module: String,
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AsanGlobalError {
+    pub registers: [usize; ASAN_SAVE_REGISTER_COUNT],
+    pub pc: usize,
+    pub fault: (Option<u16>, Option<u16>, usize, usize),
+    pub global: AsanGlobal,
+    pub backtrace: Backtrace,
+}
+
+/// A frame of the fake stack, and the function that ran on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AsanFakeFrame {
+    pub function: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AsanFakeFrameError {
+    pub registers: [usize; ASAN_SAVE_REGISTER_COUNT],
+    pub pc: usize,
+    pub fault: (Option<u16>, Option<u16>, usize, usize),
+    pub frame: AsanFakeFrame,
+    pub backtrace: Backtrace,
+}
+
 #[allow(clippy::type_complexity)]
 #[derive(Debug, Clone, Serialize, Deserialize, SerdeAny)]
 pub(crate) enum AsanError {
@@ -70,6 +105,10 @@ pub(crate) enum AsanError {
     ),
     BadFuncArgRead((String, usize, usize, usize, Backtrace)),
     BadFuncArgWrite((String, usize, usize, usize, Backtrace)),
+    GlobalOobRead(AsanGlobalError),
+    GlobalOobWrite(AsanGlobalError),
+    StackUseAfterReturnRead(AsanFakeFrameError),
+    StackUseAfterReturnWrite(AsanFakeFrameError),
 }
 
 impl AsanError {
@@ -87,6 +126,10 @@ impl AsanError {
             AsanError::StackOobWrite(_) => "stack out-of-bounds write",
             AsanError::BadFuncArgRead(_) => "function arg resulting in bad read",
             AsanError::BadFuncArgWrite(_) => "function arg resulting in bad write",
+            AsanError::GlobalOobRead(_) => "global out-of-bounds read",
+            AsanError::GlobalOobWrite(_) => "global out-of-bounds write",
+            AsanError::StackUseAfterReturnRead(_) => "stack use-after-return read",
+            AsanError::StackUseAfterReturnWrite(_) => "stack use-after-return write",
         }
     }
 }
@@ -418,120 +461,188 @@ impl AsanErrors {
             AsanError::Unknown((registers, pc, fault, backtrace))
             | AsanError::StackOobRead((registers, pc, fault, backtrace))
             | AsanError::StackOobWrite((registers, pc, fault, backtrace)) => {
-                let (basereg, indexreg, _displacement, fault_address) = fault;
-
-                if let Some(module_details) = ModuleDetails::with_address(pc as u64) {
-                    writeln!(
-                        output,
-                        " at 0x{:x} ({}:0x{:04x}), faulting address 0x{:x}",
-                        pc,
-                        module_details.path(),
-                        pc - module_details.range().base_address().0 as usize,
-                        fault_address
-                    )
-                    .unwrap();
-                } else {
-                    writeln!(output, " at 0x{pc:x}, faulting address 0x{fault_address:x}").unwrap();
-                }
-                output.reset().unwrap();
+                Self::report_fault(
+                    output,
+                    &backtrace_printer,
+                    &registers,
+                    pc,
+                    fault,
+                    &backtrace,
+                );
+            }
+            AsanError::GlobalOobRead(error) | AsanError::GlobalOobWrite(error) => {
+                let fault_address = error.fault.3;
+                Self::report_fault(
+                    output,
+                    &backtrace_printer,
+                    &error.registers,
+                    error.pc,
+                    error.fault,
+                    &error.backtrace,
+                );
 
                 #[allow(clippy::non_ascii_literal)]
-                writeln!(output, "{:━^100}", " REGISTERS ").unwrap();
+                writeln!(output, "{:━^100}", " GLOBAL INFO ").unwrap();
+                writeln!(
+                    output,
+                    "access is {:#x} to the right of the {:#x} byte global '{}' of {} at {:#x}",
+                    fault_address - (error.global.address + error.global.size),
+                    error.global.size,
+                    error.global.name,
+                    error.global.module,
+                    error.global.address
+                )
+                .unwrap();
+            }
+            AsanError::StackUseAfterReturnRead(error)
+            | AsanError::StackUseAfterReturnWrite(error) => {
+                let fault_address = error.fault.3;
+                Self::report_fault(
+                    output,
+                    &backtrace_printer,
+                    &error.registers,
+                    error.pc,
+                    error.fault,
+                    &error.backtrace,
+                );
 
-                #[cfg(target_arch = "aarch64")]
-                for (reg, val) in registers.iter().enumerate().take(30 + 1) {
-                    if basereg.is_some() && reg == basereg.unwrap() as usize {
-                        output
-                            .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
-                            .unwrap();
-                    } else if indexreg.is_some() && reg == indexreg.unwrap() as usize {
-                        output
-                            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-                            .unwrap();
-                    }
-                    write!(output, "x{reg:02}: 0x{val:016x} ").unwrap();
-                    output.reset().unwrap();
-                    if reg % 4 == 3 {
-                        writeln!(output).unwrap();
-                    }
-                }
-                #[cfg(target_arch = "aarch64")]
-                writeln!(output, "pc : 0x{pc:016x} ").unwrap();
+                #[allow(clippy::non_ascii_literal)]
+                writeln!(output, "{:━^100}", " FRAME INFO ").unwrap();
+                writeln!(
+                    output,
+                    "access is {:#x} below the end of the fake stack frame {:#x}-{:#x} of {}, which returned",
+                    error.frame.end - fault_address,
+                    error.frame.start,
+                    error.frame.end,
+                    error.frame.function
+                )
+                .unwrap();
+            }
+        };
 
-                #[cfg(target_arch = "x86_64")]
-                for reg in 0..ASAN_SAVE_REGISTER_COUNT {
-                    if basereg.is_some() && reg == basereg.unwrap() as usize {
-                        output
-                            .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
-                            .unwrap();
-                    } else if indexreg.is_some() && reg == indexreg.unwrap() as usize {
-                        output
-                            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-                            .unwrap();
-                    }
-                    write!(
-                        output,
-                        "{}: 0x{:016x} ",
-                        ASAN_SAVE_REGISTER_NAMES[reg], registers[reg]
-                    )
-                    .unwrap();
-                    output.reset().unwrap();
-                    if reg % 4 == 3 {
-                        writeln!(output).unwrap();
-                    }
-                }
+        #[allow(clippy::manual_assert)]
+        if !self.options.continue_on_error {
+            panic!("ASAN: Crashing target!");
+        }
+    }
 
-                #[cfg(target_arch = "x86_64")]
-                writeln!(output, "Rip: 0x{pc:016x}").unwrap();
+    /// Reports the faulting access at `pc`, with the registers, the code and the backtrace
+    #[allow(clippy::too_many_lines)]
+    fn report_fault(
+        output: &mut dyn WriteColor,
+        backtrace_printer: &BacktracePrinter,
+        registers: &[usize; ASAN_SAVE_REGISTER_COUNT],
+        pc: usize,
+        fault: (Option<u16>, Option<u16>, usize, usize),
+        backtrace: &Backtrace,
+    ) {
+        let (basereg, indexreg, _displacement, fault_address) = fault;
+
+        if let Some(module_details) = ModuleDetails::with_address(pc as u64) {
+            writeln!(
+                output,
+                " at 0x{:x} ({}:0x{:04x}), faulting address 0x{:x}",
+                pc,
+                module_details.path(),
+                pc - module_details.range().base_address().0 as usize,
+                fault_address
+            )
+            .unwrap();
+        } else {
+            writeln!(output, " at 0x{pc:x}, faulting address 0x{fault_address:x}").unwrap();
+        }
+        output.reset().unwrap();
 
-                #[allow(clippy::non_ascii_literal)]
-                writeln!(output, "{:━^100}", " CODE ").unwrap();
+        #[allow(clippy::non_ascii_literal)]
+        writeln!(output, "{:━^100}", " REGISTERS ").unwrap();
 
-                #[cfg(target_arch = "aarch64")]
-                let mut cs = Capstone::new()
-                    .arm64()
-                    .mode(capstone::arch::arm64::ArchMode::Arm)
-                    .build()
+        #[cfg(target_arch = "aarch64")]
+        for (reg, val) in registers.iter().enumerate().take(30 + 1) {
+            if basereg.is_some() && reg == basereg.unwrap() as usize {
+                output
+                    .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+                    .unwrap();
+            } else if indexreg.is_some() && reg == indexreg.unwrap() as usize {
+                output
+                    .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
                     .unwrap();
+            }
+            write!(output, "x{reg:02}: 0x{val:016x} ").unwrap();
+            output.reset().unwrap();
+            if reg % 4 == 3 {
+                writeln!(output).unwrap();
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        writeln!(output, "pc : 0x{pc:016x} ").unwrap();
+
+        #[cfg(target_arch = "x86_64")]
+        for reg in 0..ASAN_SAVE_REGISTER_COUNT {
+            if basereg.is_some() && reg == basereg.unwrap() as usize {
+                output
+                    .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+                    .unwrap();
+            } else if indexreg.is_some() && reg == indexreg.unwrap() as usize {
+                output
+                    .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
+                    .unwrap();
+            }
+            write!(
+                output,
+                "{}: 0x{:016x} ",
+                ASAN_SAVE_REGISTER_NAMES[reg], registers[reg]
+            )
+            .unwrap();
+            output.reset().unwrap();
+            if reg % 4 == 3 {
+                writeln!(output).unwrap();
+            }
+        }
 
-                #[cfg(target_arch = "x86_64")]
-                let mut cs = Capstone::new()
-                    .x86()
-                    .mode(capstone::arch::x86::ArchMode::Mode64)
-                    .detail(true)
-                    .build()
-                    .expect("Failed to create Capstone object");
+        #[cfg(target_arch = "x86_64")]
+        writeln!(output, "Rip: 0x{pc:016x}").unwrap();
 
-                cs.set_skipdata(true).expect("failed to set skipdata");
+        #[allow(clippy::non_ascii_literal)]
+        writeln!(output, "{:━^100}", " CODE ").unwrap();
 
-                let start_pc = pc;
-                for insn in cs
-                    .disasm_count(
-                        unsafe { std::slice::from_raw_parts(start_pc as *mut u8, 4 * 11) },
-                        start_pc as u64,
-                        11,
-                    )
-                    .expect("failed to disassemble instructions")
-                    .iter()
-                {
-                    if insn.address() as usize == pc {
-                        output
-                            .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
-                            .unwrap();
-                        writeln!(output, "\t => {insn}").unwrap();
-                        output.reset().unwrap();
-                    } else {
-                        writeln!(output, "\t    {insn}").unwrap();
-                    }
-                }
-                backtrace_printer.print_trace(&backtrace, output).unwrap();
-            }
-        };
+        #[cfg(target_arch = "aarch64")]
+        let mut cs = Capstone::new()
+            .arm64()
+            .mode(capstone::arch::arm64::ArchMode::Arm)
+            .build()
+            .unwrap();
 
-        #[allow(clippy::manual_assert)]
-        if !self.options.continue_on_error {
-            panic!("ASAN: Crashing target!");
+        #[cfg(target_arch = "x86_64")]
+        let mut cs = Capstone::new()
+            .x86()
+            .mode(capstone::arch::x86::ArchMode::Mode64)
+            .detail(true)
+            .build()
+            .expect("Failed to create Capstone object");
+
+        cs.set_skipdata(true).expect("failed to set skipdata");
+
+        let start_pc = pc;
+        for insn in cs
+            .disasm_count(
+                unsafe { std::slice::from_raw_parts(start_pc as *mut u8, 4 * 11) },
+                start_pc as u64,
+                11,
+            )
+            .expect("failed to disassemble instructions")
+            .iter()
+        {
+            if insn.address() as usize == pc {
+                output
+                    .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+                    .unwrap();
+                writeln!(output, "\t => {insn}").unwrap();
+                output.reset().unwrap();
+            } else {
+                writeln!(output, "\t    {insn}").unwrap();
+            }
         }
+        backtrace_printer.print_trace(backtrace, output).unwrap();
     }
 }
 
@@ -0,0 +1,272 @@
+//! Fake stacks, to detect the uses of the stack of a function after it returned.
+//!
+//! The functions given by `--asan-fake-stack` are hooked to run, with their callees, on a frame
+//! of a separate mapping instead of the thread stack. When a call returns, its frame is poisoned,
+//! and it is reused only after all the other frames, so that the accesses to the locals of the
+//! call through dangling pointers fault in the shadow check. Each frame, of
+//! `--asan-fake-stack-size` bytes, is preceded by a guard page, so that a call overflowing its
+//! frame crashes instead of writing to the frame below.
+//!
+//! Only the integer arguments in registers are forwarded to the hooked functions, and only the
+//! integer return register is returned: the functions taking floating point, vector or stack
+//! arguments, or returning a floating point value or a large struct, can not be run on the fake
+//! stack.
+use core::{arch::asm, ffi::c_void};
+
+use nix::sys::mman::{mmap, mprotect, MapFlags, ProtFlags};
+
+use crate::{
+    alloc::Allocator,
+    asan::{asan_rt::AsanRuntime, errors::AsanFakeFrame},
+};
+
+#[cfg(target_vendor = "apple")]
+const ANONYMOUS_FLAG: MapFlags = MapFlags::MAP_ANON;
+#[cfg(not(target_vendor = "apple"))]
+const ANONYMOUS_FLAG: MapFlags = MapFlags::MAP_ANONYMOUS;
+
+/// The number of frames of the fake stack
+pub(crate) const FAKE_STACK_FRAME_COUNT: usize = 32;
+
+/// The number of integer arguments passed to the functions run on the fake stack
+pub(crate) const FAKE_STACK_ARGS: usize = 8;
+
+pub(crate) type FakeStackFunction =
+    unsafe extern "C" fn(usize, usize, usize, usize, usize, usize, usize, usize) -> usize;
+
+/// A function run on the fake stack, the replacement data of its hook
+#[derive(Debug)]
+pub(crate) struct FakeStackHook {
+    pub runtime: *mut AsanRuntime,
+    pub name: String,
+    pub original: Option<FakeStackFunction>,
+}
+
+/// The frames of the fake stack, each preceded by a guard page
+#[derive(Debug)]
+pub(crate) struct FakeStack {
+    start: usize,
+    /// The size of a frame, for a call and its callees
+    frame_size: usize,
+    guard_size: usize,
+    next: usize,
+    in_use: Vec<bool>,
+    /// The function that runs, or last ran, on each frame
+    functions: Vec<Option<String>>,
+}
+
+impl FakeStack {
+    /// Maps the fake stack, with frames of `frame_size` bytes rounded up to pages, poisoned until
+    /// they are used
+    pub fn new(allocator: &mut Allocator, frame_size: usize) -> Self {
+        let guard_size = allocator.page_size();
+        let frame_size = (frame_size.max(1) + guard_size - 1) & !(guard_size - 1);
+        let size = (guard_size + frame_size) * FAKE_STACK_FRAME_COUNT;
+        let start = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                size,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                ANONYMOUS_FLAG | MapFlags::MAP_PRIVATE,
+                -1,
+                0,
+            )
+            .expect("Failed to map the fake stack")
+        } as usize;
+        allocator.map_shadow_for_region(start, start + size, false);
+        let stack = Self {
+            start,
+            frame_size,
+            guard_size,
+            next: 0,
+            in_use: vec![false; FAKE_STACK_FRAME_COUNT],
+            functions: vec![None; FAKE_STACK_FRAME_COUNT],
+        };
+        for idx in 0..FAKE_STACK_FRAME_COUNT {
+            let guard = stack.frame_start(idx) - guard_size;
+            unsafe { mprotect(guard as *mut c_void, guard_size, ProtFlags::PROT_NONE) }
+                .expect("Failed to protect a guard page of the fake stack");
+        }
+        stack
+    }
+
+    /// The size of the frames
+    #[must_use]
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// The start of the frame `idx`, after its guard page
+    fn frame_start(&self, idx: usize) -> usize {
+        self.start + idx * (self.guard_size + self.frame_size) + self.guard_size
+    }
+
+    /// Takes the least recently used free frame for a call to `function`, and unpoisons it
+    pub fn acquire(&mut self, allocator: &mut Allocator, function: &str) -> Option<usize> {
+        for _ in 0..FAKE_STACK_FRAME_COUNT {
+            let idx = self.next;
+            self.next = (self.next + 1) % FAKE_STACK_FRAME_COUNT;
+            if !self.in_use[idx] {
+                self.in_use[idx] = true;
+                self.functions[idx] = Some(function.to_string());
+                let start = self.frame_start(idx);
+                allocator.map_shadow_for_region(start, start + self.frame_size, true);
+                return Some(start);
+            }
+        }
+        None
+    }
+
+    /// Poisons the frame at `start` once its call returned
+    pub fn release(&mut self, allocator: &Allocator, start: usize) {
+        self.in_use[(start - self.start) / (self.guard_size + self.frame_size)] = false;
+        Allocator::poison(allocator.map_to_shadow(start), self.frame_size);
+    }
+
+    /// The frame containing `address`, if it was used by a call that returned
+    #[must_use]
+    pub fn returned_frame(&self, address: usize) -> Option<AsanFakeFrame> {
+        let stride = self.guard_size + self.frame_size;
+        if address < self.start || address >= self.start + stride * FAKE_STACK_FRAME_COUNT {
+            return None;
+        }
+        let idx = (address - self.start) / stride;
+        let start = self.frame_start(idx);
+        // The guard pages are not part of the frames
+        if address < start || self.in_use[idx] {
+            return None;
+        }
+        self.functions[idx].clone().map(|function| AsanFakeFrame {
+            function,
+            start,
+            end: start + self.frame_size,
+        })
+    }
+}
+
+/// Calls `function` with `args` on the stack ending at `stack_end`
+#[cfg(target_arch = "x86_64")]
+pub(crate) unsafe fn call_on_stack(
+    function: FakeStackFunction,
+    args: [usize; FAKE_STACK_ARGS],
+    stack_end: usize,
+) -> usize {
+    let ret: usize;
+    // The last two arguments go on the new stack, which stays 16 bytes aligned for the call
+    asm!(
+        "mov r12, rsp",
+        "mov rsp, {stack}",
+        "push {a7}",
+        "push {a6}",
+        "call {function}",
+        "mov rsp, r12",
+        stack = in(reg) stack_end,
+        a6 = in(reg) args[6],
+        a7 = in(reg) args[7],
+        function = in(reg) function,
+        in("rdi") args[0],
+        in("rsi") args[1],
+        in("rdx") args[2],
+        in("rcx") args[3],
+        in("r8") args[4],
+        in("r9") args[5],
+        out("r12") _,
+        lateout("rax") ret,
+        clobber_abi("C"),
+    );
+    ret
+}
+
+/// Calls `function` with `args` on the stack ending at `stack_end`
+#[cfg(target_arch = "aarch64")]
+pub(crate) unsafe fn call_on_stack(
+    function: FakeStackFunction,
+    args: [usize; FAKE_STACK_ARGS],
+    stack_end: usize,
+) -> usize {
+    let ret: usize;
+    asm!(
+        "mov x20, sp",
+        "mov sp, {stack}",
+        "blr {function}",
+        "mov sp, x20",
+        stack = in(reg) stack_end,
+        function = in(reg) function,
+        inlateout("x0") args[0] => ret,
+        in("x1") args[1],
+        in("x2") args[2],
+        in("x3") args[3],
+        in("x4") args[4],
+        in("x5") args[5],
+        in("x6") args[6],
+        in("x7") args[7],
+        out("x20") _,
+        clobber_abi("C"),
+    );
+    ret
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe extern "C" fn fake_stack_replacement(
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+) -> usize {
+    let mut invocation = frida_gum::interceptor::Interceptor::current_invocation();
+    let hook = &*(invocation.replacement_data().unwrap().0 as *const FakeStackHook);
+    (*hook.runtime).call_on_fake_stack(hook, [a0, a1, a2, a3, a4, a5, a6, a7])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FakeStack, FAKE_STACK_FRAME_COUNT};
+
+    const FRAME_SIZE: usize = 0x4_0000;
+    const GUARD_SIZE: usize = 0x1000;
+
+    #[test]
+    fn test_returned_frame() {
+        let start = 0x10_0000;
+        let mut stack = FakeStack {
+            start,
+            frame_size: FRAME_SIZE,
+            guard_size: GUARD_SIZE,
+            next: 0,
+            in_use: vec![false; FAKE_STACK_FRAME_COUNT],
+            functions: vec![None; FAKE_STACK_FRAME_COUNT],
+        };
+        let second = start + 2 * GUARD_SIZE + FRAME_SIZE;
+        assert_eq!(stack.frame_start(1), second);
+        let in_second = second + 0x20;
+
+        // Out of the fake stack, or never used
+        assert!(stack.returned_frame(start - 1).is_none());
+        assert!(stack
+            .returned_frame(start + (GUARD_SIZE + FRAME_SIZE) * FAKE_STACK_FRAME_COUNT)
+            .is_none());
+        assert!(stack.returned_frame(in_second).is_none());
+
+        // Still running
+        stack.in_use[1] = true;
+        stack.functions[1] = Some("parse".to_string());
+        assert!(stack.returned_frame(in_second).is_none());
+
+        // Returned
+        stack.in_use[1] = false;
+        let frame = stack.returned_frame(in_second).unwrap();
+        assert_eq!(frame.function, "parse");
+        assert_eq!(frame.start, second);
+        assert_eq!(frame.end, second + FRAME_SIZE);
+        assert!(stack.returned_frame(second + FRAME_SIZE - 1).is_some());
+        assert!(stack.returned_frame(start + GUARD_SIZE + 0x20).is_none());
+
+        // The guard pages below the frame and below the next one
+        assert!(stack.returned_frame(second - 1).is_none());
+        assert!(stack.returned_frame(second + FRAME_SIZE).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Red zones for the global variables of the instrumented modules.
+//!
+//! The globals are found in the symbol tables of the modules, so the modules must not be stripped.
+//! A binary-only target has no room between its globals, so only the alignment padding between
+//! two writable globals is poisoned, and an overflow is detected once it reaches the padding.
+use std::fs;
+
+use frida_gum::ModuleDetails;
+use goblin::elf::{
+    header::ET_DYN, program_header::PT_LOAD, section_header::SHF_WRITE, sym::STT_OBJECT, Elf,
+};
+
+use crate::asan::errors::AsanGlobal;
+
+/// The largest gap between two globals that is taken for alignment padding
+pub(crate) const GLOBAL_REDZONE_MAX_PADDING: usize = 64;
+
+/// The writable globals of the module `name`, sorted by address
+#[must_use]
+pub(crate) fn module_globals(name: &str) -> Vec<AsanGlobal> {
+    let module_details = match ModuleDetails::with_name(name.to_string()) {
+        Some(module_details) => module_details,
+        None => return vec![],
+    };
+    let base = module_details.range().base_address().0 as usize;
+    let bytes = match fs::read(module_details.path()) {
+        Ok(bytes) => bytes,
+        Err(_) => return vec![],
+    };
+    // Not an ELF, e.g. on apple
+    let elf = match Elf::parse(&bytes) {
+        Ok(elf) => elf,
+        Err(_) => return vec![],
+    };
+
+    // The symbols of the shared objects and of the PIEs are relative to the load address
+    let bias = if elf.header.e_type == ET_DYN {
+        let first_vaddr = elf
+            .program_headers
+            .iter()
+            .filter(|header| header.p_type == PT_LOAD)
+            .map(|header| header.p_vaddr as usize)
+            .min()
+            .unwrap_or(0);
+        base - (first_vaddr & !0xfff)
+    } else {
+        0
+    };
+
+    let mut globals = vec![];
+    for (syms, strtab) in [(&elf.syms, &elf.strtab), (&elf.dynsyms, &elf.dynstrtab)] {
+        for sym in syms.iter() {
+            if sym.st_type() != STT_OBJECT || sym.st_size == 0 {
+                continue;
+            }
+            let writable = elf
+                .section_headers
+                .get(sym.st_shndx)
+                .map_or(false, |section| {
+                    section.sh_flags & u64::from(SHF_WRITE) != 0
+                });
+            if !writable {
+                continue;
+            }
+            globals.push(AsanGlobal {
+                module: name.to_string(),
+                name: strtab.get_at(sym.st_name).unwrap_or_default().to_string(),
+                address: bias + sym.st_value as usize,
+                size: sym.st_size as usize,
+            });
+        }
+    }
+    globals.sort_by_key(|global| global.address);
+    // The same globals are often in both tables, or aliased
+    globals.dedup_by_key(|global| global.address);
+    globals
+}
+
+/// The padding after each global, up to the next one, as (global, start, end)
+#[must_use]
+pub(crate) fn global_redzones(globals: &[AsanGlobal]) -> Vec<(usize, usize, usize)> {
+    globals
+        .windows(2)
+        .enumerate()
+        .filter_map(|(idx, pair)| {
+            let end = pair[0].address + pair[0].size;
+            let next = pair[1].address;
+            if pair[0].module == pair[1].module
+                && end < next
+                && next - end < GLOBAL_REDZONE_MAX_PADDING
+            {
+                Some((idx, end, next))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global_redzones, GLOBAL_REDZONE_MAX_PADDING};
+    use crate::asan::errors::AsanGlobal;
+
+    fn global(module: &str, address: usize, size: usize) -> AsanGlobal {
+        AsanGlobal {
+            module: module.to_string(),
+            name: format!("global_{address:x}"),
+            address,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_global_redzones() {
+        let far = 0x1020 + GLOBAL_REDZONE_MAX_PADDING;
+        let globals = [
+            // Padded up to the next one
+            global("a.so", 0x1000, 0x4),
+            // Adjacent to the next one
+            global("a.so", 0x1008, 0x8),
+            // Too far from the next one for padding
+            global("a.so", 0x1010, 0x10),
+            // Padded, but the next one is in another module
+            global("a.so", far, 0x4),
+            // Overlapping the next one, e.g. a global and one of its fields
+            global("b.so", far + 0x8, 0x10),
+            global("b.so", far + 0x10, 0x4),
+        ];
+        assert_eq!(global_redzones(&globals), vec![(0, 0x1004, 0x1008)]);
+        assert!(global_redzones(&globals[..1]).is_empty());
+    }
+}
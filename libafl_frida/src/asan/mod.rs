@@ -1,5 +1,7 @@
 //! Address sanitization using [`frida`](https://frida.re/)
 pub mod asan_rt;
 pub mod errors;
+mod fake_stack;
+mod globals;
 #[allow(missing_docs)]
 pub mod hook_funcs;
@@ -3,8 +3,15 @@
 //! This allows the fuzzer to potentially solve the compares, if a compare value is directly
 //! related to the input.
 //! Read the [`RedQueen`](https://www.ndss-symposium.org/ndss-paper/redqueen-fuzzing-with-input-to-state-correspondence/) paper for the general concepts.
+//!
+//! On `x86_64`, the compares are logged by stalker callouts, and the calls of the instrumented
+//! modules to the [`CMPLOG_ROUTINES`] by an interceptor listener.
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+use std::collections::HashSet;
+#[cfg(target_arch = "aarch64")]
 use std::ffi::c_void;
 
+#[cfg(target_arch = "aarch64")]
 use dynasmrt::{dynasm, DynasmApi, DynasmLabelApi};
 use libafl::{
     inputs::{HasTargetBytes, Input},
@@ -12,6 +19,8 @@ use libafl::{
 };
 use libafl_targets::{self, CMPLOG_MAP_W};
 use rangemap::RangeMap;
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+use rangemap::RangeSet;
 
 use crate::helper::FridaRuntime;
 extern "C" {
@@ -19,6 +28,29 @@ extern "C" {
     pub fn __libafl_targets_cmplog_instructions(k: u64, shape: u8, arg1: u64, arg2: u64);
 }
 
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+use capstone::{
+    arch::{x86::X86OperandType, ArchOperand::X86Operand},
+    Capstone, Insn, RegId,
+};
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+use frida_gum::{
+    interceptor::{Interceptor, InvocationContext, InvocationListener},
+    stalker::Instruction,
+    CpuContext, Module,
+};
+
+/// The routines whose first two arguments are logged when the instrumented modules call them
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+pub const CMPLOG_ROUTINES: [&str; 6] = [
+    "strcmp",
+    "strncmp",
+    "strcasecmp",
+    "strncasecmp",
+    "memcmp",
+    "bcmp",
+];
+
 #[cfg(target_arch = "aarch64")]
 use frida_gum::{
     instruction_writer::{Aarch64Register, IndexMode, InstructionWriter},
@@ -72,25 +104,223 @@ pub enum CmplogOperandType {
     Mem(capstone::RegId, capstone::RegId, i32, u32),
 }
 
+/// The type of an operand loggged during `CmpLog`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+pub enum CmplogOperandType {
+    /// A Register
+    Regid(capstone::RegId),
+    /// An immediate value
+    Imm(u64),
+    /// A memory operand, as base, index, scale and displacement.
+    /// The `rip`-relative operands have no base, and the absolute displacement.
+    Mem(capstone::RegId, capstone::RegId, i32, i64),
+}
+
+/// The value of the capstone register `reg` in the `context` of a callout
+/// capstone registers: <https://docs.rs/capstone-sys/0.14.0/capstone_sys/x86_reg/index.html>
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[must_use]
+fn register_value(context: &CpuContext, reg: RegId) -> u64 {
+    register_value_with(
+        |idx| match idx {
+            0 => context.rax(),
+            1 => context.rbx(),
+            2 => context.rcx(),
+            3 => context.rdx(),
+            4 => context.rbp(),
+            5 => context.rsp(),
+            6 => context.rsi(),
+            7 => context.rdi(),
+            8 => context.r8(),
+            9 => context.r9(),
+            10 => context.r10(),
+            11 => context.r11(),
+            12 => context.r12(),
+            13 => context.r13(),
+            14 => context.r14(),
+            _ => context.r15(),
+        },
+        reg,
+    )
+}
+
+/// The value of the capstone register `reg`, reading the full general purpose registers with
+/// `gpr`, by their index in `rax`, `rbx`, `rcx`, `rdx`, `rbp`, `rsp`, `rsi`, `rdi`, `r8`-`r15`
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[must_use]
+fn register_value_with<G: Fn(usize) -> u64>(gpr: G, reg: RegId) -> u64 {
+    // The full register, and the width and the shift of the part of it
+    let (idx, bits, shift) = match reg.0 {
+        35 => (0, 64, 0),
+        19 => (0, 32, 0),
+        3 => (0, 16, 0),
+        2 => (0, 8, 0),
+        1 => (0, 8, 8),
+        37 => (1, 64, 0),
+        21 => (1, 32, 0),
+        8 => (1, 16, 0),
+        5 => (1, 8, 0),
+        4 => (1, 8, 8),
+        38 => (2, 64, 0),
+        22 => (2, 32, 0),
+        12 => (2, 16, 0),
+        10 => (2, 8, 0),
+        9 => (2, 8, 8),
+        40 => (3, 64, 0),
+        24 => (3, 32, 0),
+        18 => (3, 16, 0),
+        16 => (3, 8, 0),
+        13 => (3, 8, 8),
+        36 => (4, 64, 0),
+        20 => (4, 32, 0),
+        6 => (4, 16, 0),
+        7 => (4, 8, 0),
+        44 => (5, 64, 0),
+        30 => (5, 32, 0),
+        47 => (5, 16, 0),
+        48 => (5, 8, 0),
+        43 => (6, 64, 0),
+        29 => (6, 32, 0),
+        45 => (6, 16, 0),
+        46 => (6, 8, 0),
+        39 => (7, 64, 0),
+        23 => (7, 32, 0),
+        14 => (7, 16, 0),
+        15 => (7, 8, 0),
+        // r8-r15, then their byte, dword and word parts
+        106..=113 => (usize::from(reg.0 - 106) + 8, 64, 0),
+        218..=225 => (usize::from(reg.0 - 218) + 8, 8, 0),
+        226..=233 => (usize::from(reg.0 - 226) + 8, 32, 0),
+        234..=241 => (usize::from(reg.0 - 234) + 8, 16, 0),
+        _ => return 0,
+    };
+    truncate(gpr(idx) >> shift, bits / 8)
+}
+
+/// The lowest `size` bytes of `value`
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[must_use]
+fn truncate(value: u64, size: u8) -> u64 {
+    if size >= 8 {
+        value
+    } else {
+        value & ((1 << (u32::from(size) * 8)) - 1)
+    }
+}
+
+/// The value of the operand `op` of `size` bytes in the `context` of a callout
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[must_use]
+fn operand_value(context: &CpuContext, op: &CmplogOperandType, size: u8) -> u64 {
+    operand_value_with(|reg| register_value(context, reg), op, size)
+}
+
+/// The value of the operand `op` of `size` bytes, reading the registers with `register`
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[must_use]
+#[allow(clippy::cast_sign_loss)]
+fn operand_value_with<R: Fn(RegId) -> u64>(register: R, op: &CmplogOperandType, size: u8) -> u64 {
+    match *op {
+        CmplogOperandType::Regid(reg) => register(reg),
+        CmplogOperandType::Imm(value) => truncate(value, size),
+        CmplogOperandType::Mem(basereg, indexreg, scale, disp) => {
+            let mut address = disp as u64;
+            if basereg.0 != 0 {
+                address = address.wrapping_add(register(basereg));
+            }
+            if indexreg.0 != 0 {
+                address = address.wrapping_add(register(indexreg).wrapping_mul(scale as u64));
+            }
+            unsafe {
+                match size {
+                    1 => u64::from(*(address as *const u8)),
+                    2 => u64::from((address as *const u16).read_unaligned()),
+                    4 => u64::from((address as *const u32).read_unaligned()),
+                    _ => (address as *const u64).read_unaligned(),
+                }
+            }
+        }
+    }
+}
+
 /// `Frida`-based binary-only innstrumentation that logs compares to the fuzzer
 /// `LibAFL` can use this knowledge for powerful mutations.
 #[derive(Debug)]
 pub struct CmpLogRuntime {
+    #[cfg(target_arch = "aarch64")]
     ops_save_register_and_blr_to_populate: Option<Box<[u8]>>,
+    #[cfg(target_arch = "aarch64")]
     ops_handle_tbz_masking: Option<Box<[u8]>>,
+    #[cfg(target_arch = "aarch64")]
     ops_handle_tbnz_masking: Option<Box<[u8]>>,
+    /// The listener of the [`CMPLOG_ROUTINES`], boxed so that it does not move with the runtime
+    #[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+    routine_listener: Box<CmpLogRoutineListener>,
+}
+
+/// Logs the first two arguments of the calls to the [`CMPLOG_ROUTINES`] made by the
+/// instrumented modules.
+///
+/// The routines run out of the stalked ranges, and may be replaced by the `AsanRuntime`, so they
+/// are attached to rather than instrumented.
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+#[derive(Debug, Default)]
+struct CmpLogRoutineListener {
+    instrumented: RangeSet<usize>,
+}
+
+#[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+impl InvocationListener for CmpLogRoutineListener {
+    fn on_enter(&mut self, context: InvocationContext) {
+        let retaddr = context.return_addr();
+        if !self.instrumented.contains(&retaddr) {
+            return;
+        }
+        let k = ((retaddr >> 4) ^ (retaddr << 8)) & (CMPLOG_MAP_W - 1);
+        unsafe {
+            libafl_targets::__libafl_targets_cmplog_routines(
+                k,
+                context.arg(0) as *const u8,
+                context.arg(1) as *const u8,
+            );
+        }
+    }
+
+    fn on_leave(&mut self, _context: InvocationContext) {}
 }
 
 impl FridaRuntime for CmpLogRuntime {
     /// Initialize this `CmpLog` runtime.
-    /// This will generate the instrumentation blobs for the current arch.
+    /// This will generate the instrumentation blobs on `aarch64`, and hook the routines on `x86_64`.
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
     fn init(
         &mut self,
-        _gum: &frida_gum::Gum,
-        _ranges: &RangeMap<usize, (u16, String)>,
+        gum: &frida_gum::Gum,
+        ranges: &RangeMap<usize, (u16, String)>,
         _modules_to_instrument: &[&str],
     ) {
+        #[cfg(target_arch = "aarch64")]
         self.generate_instrumentation_blobs();
+
+        #[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+        {
+            self.routine_listener.instrumented =
+                ranges.iter().map(|(range, _)| range.clone()).collect();
+            let mut interceptor = Interceptor::obtain(gum);
+            // Each module may export its own copy of a routine, e.g. a statically linked libc,
+            // and the instrumented modules call the one they are linked against
+            let mut attached = HashSet::new();
+            for module in Module::enumerate_modules() {
+                for name in CMPLOG_ROUTINES {
+                    if let Some(address) = Module::find_export_by_name(Some(&module.name), name) {
+                        if attached.insert(address.0 as usize) {
+                            interceptor.attach(address, &mut *self.routine_listener);
+                        }
+                    }
+                }
+            }
+        }
     }
 
     fn pre_exec<I: Input + HasTargetBytes>(&mut self, _input: &I) -> Result<(), Error> {
@@ -107,13 +337,19 @@ impl CmpLogRuntime {
     #[must_use]
     pub fn new() -> CmpLogRuntime {
         Self {
+            #[cfg(target_arch = "aarch64")]
             ops_save_register_and_blr_to_populate: None,
+            #[cfg(target_arch = "aarch64")]
             ops_handle_tbz_masking: None,
+            #[cfg(target_arch = "aarch64")]
             ops_handle_tbnz_masking: None,
+            #[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+            routine_listener: Box::default(),
         }
     }
 
     /// Call the external function that populates the `cmplog_map` with the relevant values
+    #[cfg(target_arch = "aarch64")]
     #[allow(clippy::unused_self)]
     extern "C" fn populate_lists(&mut self, op1: u64, op2: u64, retaddr: u64) {
         // println!(
@@ -130,6 +366,7 @@ impl CmpLogRuntime {
     }
 
     /// Generate the instrumentation blobs for the current arch.
+    #[cfg(target_arch = "aarch64")]
     #[allow(clippy::similar_names)]
     fn generate_instrumentation_blobs(&mut self) {
         macro_rules! blr_to_populate {
@@ -243,6 +480,7 @@ impl CmpLogRuntime {
     }
 
     /// Get the blob which saves the context, jumps to the populate function and restores the context
+    #[cfg(target_arch = "aarch64")]
     #[inline]
     #[must_use]
     pub fn ops_save_register_and_blr_to_populate(&self) -> &[u8] {
@@ -250,6 +488,7 @@ impl CmpLogRuntime {
     }
 
     /// Get the blob which handles the tbz opcode masking
+    #[cfg(target_arch = "aarch64")]
     #[inline]
     #[must_use]
     pub fn ops_handle_tbz_masking(&self) -> &[u8] {
@@ -257,6 +496,7 @@ impl CmpLogRuntime {
     }
 
     /// Get the blob which handles the tbnz opcode masking
+    #[cfg(target_arch = "aarch64")]
     #[inline]
     #[must_use]
     pub fn ops_handle_tbnz_masking(&self) -> &[u8] {
@@ -681,6 +921,108 @@ impl CmpLogRuntime {
             None
         }
     }
+
+    /// Emit the callout which logs the operands of the compare at `address` to the cmplog map
+    #[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+    #[allow(clippy::unused_self)]
+    #[inline]
+    pub fn emit_comparison_handling(
+        &self,
+        address: u64,
+        instruction: &Instruction,
+        op1: CmplogOperandType,
+        op2: CmplogOperandType,
+        size: u8,
+    ) {
+        let mut k = (address >> 4) ^ (address << 8);
+        k &= (CMPLOG_MAP_W as u64) - 1;
+        instruction.put_callout(move |context| {
+            let op1 = operand_value(&context, &op1, size);
+            let op2 = operand_value(&context, &op2, size);
+            unsafe {
+                __libafl_targets_cmplog_instructions(k, size, op1, op2);
+            }
+        });
+    }
+
+    #[cfg(all(feature = "cmplog", target_arch = "x86_64"))]
+    #[allow(clippy::similar_names)]
+    #[inline]
+    /// Check if the current instruction is cmplog relevant one (`cmp`, `test` or `sub`), and
+    /// return its operands and their size in bytes
+    #[must_use]
+    pub fn cmplog_is_interesting_instruction(
+        capstone: &Capstone,
+        address: u64,
+        instr: &Insn,
+    ) -> Option<(CmplogOperandType, CmplogOperandType, u8)> {
+        let mnemonic = instr.mnemonic().unwrap();
+        match mnemonic {
+            "cmp" | "test" | "sub" => (),
+            _ => return None,
+        }
+        let operands = capstone
+            .insn_detail(instr)
+            .unwrap()
+            .arch_detail()
+            .operands();
+        if operands.len() != 2 {
+            return None;
+        }
+
+        // The rip-relative operands are relative to the next instruction
+        let next_address = address + instr.bytes().len() as u64;
+        let mut size = 0;
+        let mut ops = Vec::with_capacity(2);
+        for operand in &operands {
+            if let X86Operand(x86operand) = operand {
+                size = size.max(x86operand.size);
+                #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+                ops.push(match x86operand.op_type {
+                    X86OperandType::Reg(reg) => CmplogOperandType::Regid(reg),
+                    X86OperandType::Imm(value) => CmplogOperandType::Imm(value as u64),
+                    X86OperandType::Mem(opmem) => {
+                        if opmem.segment() != RegId(0) {
+                            return None;
+                        }
+                        // rip
+                        if opmem.base().0 == 41 {
+                            CmplogOperandType::Mem(
+                                RegId(0),
+                                opmem.index(),
+                                opmem.scale(),
+                                opmem.disp() + next_address as i64,
+                            )
+                        } else {
+                            CmplogOperandType::Mem(
+                                opmem.base(),
+                                opmem.index(),
+                                opmem.scale(),
+                                opmem.disp(),
+                            )
+                        }
+                    }
+                    _ => return None,
+                });
+            } else {
+                return None;
+            }
+        }
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return None;
+        }
+
+        let (op1, mut op2) = (ops[0], ops[1]);
+        // sub on the stack pointer allocates the frame, rsp or esp
+        if mnemonic == "sub" && matches!(op1, CmplogOperandType::Regid(RegId(44 | 30))) {
+            return None;
+        }
+        // test reg, reg compares reg with 0
+        if mnemonic == "test" && op1 == op2 {
+            op2 = CmplogOperandType::Imm(0);
+        }
+        Some((op1, op2, size))
+    }
 }
 
 impl Default for CmpLogRuntime {
@@ -689,3 +1031,144 @@ impl Default for CmpLogRuntime {
         Self::new()
     }
 }
+
+#[cfg(all(test, feature = "cmplog", target_arch = "x86_64"))]
+mod tests {
+    use capstone::{
+        arch::{self, BuildsCapstone},
+        Capstone, RegId,
+    };
+
+    use super::{operand_value_with, register_value_with, CmpLogRuntime, CmplogOperandType};
+
+    const RAX: RegId = RegId(35);
+    const EAX: RegId = RegId(19);
+    const RBX: RegId = RegId(37);
+    const RCX: RegId = RegId(38);
+
+    /// The operands of the instruction `bytes` at `address`, if interesting
+    fn interesting(
+        bytes: &[u8],
+        address: u64,
+    ) -> Option<(CmplogOperandType, CmplogOperandType, u8)> {
+        let capstone = Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode64)
+            .detail(true)
+            .build()
+            .unwrap();
+        let instrs = capstone.disasm_count(bytes, address, 1).unwrap();
+        CmpLogRuntime::cmplog_is_interesting_instruction(&capstone, address, &instrs[0])
+    }
+
+    #[test]
+    fn test_cmplog_is_interesting_instruction() {
+        // cmp rax, rbx
+        assert_eq!(
+            interesting(&[0x48, 0x39, 0xd8], 0x1000),
+            Some((
+                CmplogOperandType::Regid(RAX),
+                CmplogOperandType::Regid(RBX),
+                8
+            ))
+        );
+        // cmp eax, 5
+        assert_eq!(
+            interesting(&[0x83, 0xf8, 0x05], 0x1000),
+            Some((CmplogOperandType::Regid(EAX), CmplogOperandType::Imm(5), 4))
+        );
+        // test eax, eax compares with 0
+        assert_eq!(
+            interesting(&[0x85, 0xc0], 0x1000),
+            Some((CmplogOperandType::Regid(EAX), CmplogOperandType::Imm(0), 4))
+        );
+        // cmp byte ptr [rbx + rcx], 0x41
+        assert_eq!(
+            interesting(&[0x80, 0x3c, 0x0b, 0x41], 0x1000),
+            Some((
+                CmplogOperandType::Mem(RBX, RCX, 1, 0),
+                CmplogOperandType::Imm(0x41),
+                1
+            ))
+        );
+        // cmp eax, dword ptr [rip + 0x10], relative to the next instruction
+        assert_eq!(
+            interesting(&[0x3b, 0x05, 0x10, 0x00, 0x00, 0x00], 0x1000),
+            Some((
+                CmplogOperandType::Regid(EAX),
+                CmplogOperandType::Mem(RegId(0), RegId(0), 1, 0x1016),
+                4
+            ))
+        );
+        // sub rsp, 0x28 allocates the frame
+        assert_eq!(interesting(&[0x48, 0x83, 0xec, 0x28], 0x1000), None);
+        // cmp rax, qword ptr fs:[0x28] reads the stack canary
+        assert_eq!(
+            interesting(
+                &[0x64, 0x48, 0x3b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00],
+                0x1000
+            ),
+            None
+        );
+        // mov rbx, rax
+        assert_eq!(interesting(&[0x48, 0x89, 0xc3], 0x1000), None);
+    }
+
+    /// Each full register holds its index in each byte, above 0xf0 for the high ones
+    fn gpr(idx: usize) -> u64 {
+        u64::from_le_bytes([idx as u8, 0xf0 | idx as u8, 2, 3, 4, 5, 6, 7])
+    }
+
+    #[test]
+    fn test_register_value() {
+        assert_eq!(register_value_with(gpr, RAX), gpr(0));
+        assert_eq!(register_value_with(gpr, EAX), 0x0302_f000);
+        // ax, al, ah
+        assert_eq!(register_value_with(gpr, RegId(3)), 0xf000);
+        assert_eq!(register_value_with(gpr, RegId(2)), 0x00);
+        assert_eq!(register_value_with(gpr, RegId(1)), 0xf0);
+        // bh, rdi, rsp
+        assert_eq!(register_value_with(gpr, RegId(4)), 0xf1);
+        assert_eq!(register_value_with(gpr, RegId(39)), gpr(7));
+        assert_eq!(register_value_with(gpr, RegId(44)), gpr(5));
+        // r8, r9b, r10d, r15w
+        assert_eq!(register_value_with(gpr, RegId(106)), gpr(8));
+        assert_eq!(register_value_with(gpr, RegId(219)), 0x09);
+        assert_eq!(register_value_with(gpr, RegId(228)), 0x0302_fa0a);
+        assert_eq!(register_value_with(gpr, RegId(241)), 0xff0f);
+        // Not a general purpose register, e.g. rip
+        assert_eq!(register_value_with(gpr, RegId(41)), 0);
+    }
+
+    #[test]
+    fn test_operand_value() {
+        let data: [u8; 16] = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ];
+        let base = data.as_ptr() as u64;
+        let register = |reg: RegId| match reg {
+            RBX => base,
+            RCX => 1,
+            _ => 0x1234_5678_9abc_def0,
+        };
+
+        assert_eq!(
+            operand_value_with(register, &CmplogOperandType::Regid(RAX), 8),
+            0x1234_5678_9abc_def0
+        );
+        assert_eq!(
+            operand_value_with(register, &CmplogOperandType::Imm(0x1_0000_0005), 4),
+            5
+        );
+        // [rbx + rcx * 4 + 1], of each size
+        let op = CmplogOperandType::Mem(RBX, RCX, 4, 1);
+        assert_eq!(operand_value_with(register, &op, 1), 0x55);
+        assert_eq!(operand_value_with(register, &op, 2), 0x6655);
+        assert_eq!(operand_value_with(register, &op, 4), 0x8877_6655);
+        assert_eq!(operand_value_with(register, &op, 8), 0xccbb_aa99_8877_6655);
+        // An absolute address, e.g. rip-relative
+        let op = CmplogOperandType::Mem(RegId(0), RegId(0), 1, base as i64 + 3);
+        assert_eq!(operand_value_with(register, &op, 4), 0x6655_4433);
+    }
+}
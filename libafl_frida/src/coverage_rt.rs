@@ -2,6 +2,7 @@
 use core::ptr::addr_of_mut;
 use std::{
     cell::{Ref, RefCell},
+    collections::HashSet,
     marker::PhantomPinned,
     ops::Deref,
     pin::Pin,
@@ -13,7 +14,10 @@ use dynasmrt::{dynasm, DynasmApi, DynasmLabelApi};
 use frida_gum::instruction_writer::X86Register;
 #[cfg(target_arch = "aarch64")]
 use frida_gum::instruction_writer::{Aarch64Register, IndexMode};
-use frida_gum::{instruction_writer::InstructionWriter, stalker::StalkerOutput};
+use frida_gum::{
+    instruction_writer::InstructionWriter,
+    stalker::{Instruction, StalkerOutput},
+};
 use libafl::bolts::xxh3_rrmxmx_mixer;
 use rangemap::RangeMap;
 
@@ -22,16 +26,83 @@ use crate::helper::FridaRuntime;
 /// (Default) map size for frida coverage reporting
 pub const MAP_SIZE: usize = 64 * 1024;
 
+/// The number of pending calls tracked by the calling context, the deeper calls run in the
+/// context of their caller
+const CONTEXT_STACK_SIZE: usize = 1024;
+
+/// The calling context, updated by the call and return blobs, see [`CallContext::call`] and
+/// [`CallContext::return_to`]. The blobs depend on its layout.
+#[repr(C)]
+#[derive(Debug)]
+struct CallContext {
+    /// The context, already masked to the map size
+    context: u64,
+    /// The number of pending calls
+    depth: u64,
+    /// The return sites of the pending calls, and the context to restore there
+    stack: [[u64; 2]; CONTEXT_STACK_SIZE],
+}
+
+impl CallContext {
+    /// Enters the call returning to `return_site`, hashed to `hash`. The context is shifted
+    /// before the hash is mixed in, so that the calls of a direct recursion do not cancel each
+    /// other.
+    fn call(&mut self, return_site: u64, hash: u64) {
+        let depth = self.depth as usize;
+        if depth < CONTEXT_STACK_SIZE {
+            self.stack[depth] = [return_site, self.context];
+            self.context = ((self.context >> 1) ^ hash) & (MAP_SIZE as u64 - 1);
+            self.depth += 1;
+        }
+    }
+
+    fn return_to(&mut self, return_site: u64) {
+        // Also drops the frames left by longjmp or exceptions
+        let depth = self.depth as usize;
+        if let Some(idx) = self.stack[..depth]
+            .iter()
+            .rposition(|[site, _]| *site == return_site)
+        {
+            self.context = self.stack[idx][1];
+            self.depth = idx as u64;
+        }
+    }
+}
+
+/// The hash of a call site mixed in the calling context, already masked to the map size
+fn call_site_hash(return_site: u64) -> u64 {
+    xxh3_rrmxmx_mixer(return_site) & (MAP_SIZE as u64 - 1)
+}
+
 #[derive(Debug)]
 struct CoverageRuntimeInner {
-    map: [u8; MAP_SIZE],
-    previous_pc: u64,
-    current_log_impl: u64,
-    blob_maybe_log: Option<Box<[u8]>>,
+    /// One region of [`MAP_SIZE`] per map
+    map: Vec<u8>,
+    /// The previous block of each map
+    previous_pc: Vec<u64>,
+    /// Boxed, the blobs keep a pointer to it
+    call_context: Box<CallContext>,
+    return_sites: HashSet<u64>,
+    hitcounts: bool,
+    call_context: bool,
+    module_maps: bool,
+    ranges: RangeMap<usize, (u16, String)>,
+    /// The last copy of the `maybe_log` blob of each map
+    current_log_impl: Vec<u64>,
+    blob_maybe_log: Vec<Box<[u8]>>,
+    /// The last copies of the call and return blobs, for [`CoverageRuntime::with_call_context`]
+    current_call_impl: u64,
+    current_return_impl: u64,
+    blob_call: Box<[u8]>,
+    blob_return: Box<[u8]>,
     _pinned: PhantomPinned,
 }
 
-/// Frida binary-only coverage
+/// Frida binary-only coverage.
+///
+/// By default, each edge increments its hitcount in a single map. The edges can instead be only
+/// marked as taken, be combined with the calling context, or go to a separate map per
+/// instrumented module, see [`CoverageRuntime::module_map_ptrs_mut`].
 #[derive(Debug)]
 pub struct CoverageRuntime(Pin<Rc<RefCell<CoverageRuntimeInner>>>);
 
@@ -47,16 +118,34 @@ impl FridaRuntime for CoverageRuntime {
     fn init(
         &mut self,
         _gum: &frida_gum::Gum,
-        _ranges: &RangeMap<usize, (u16, String)>,
+        ranges: &RangeMap<usize, (u16, String)>,
         _modules_to_instrument: &[&str],
     ) {
+        {
+            let mut inner = self.0.borrow_mut();
+            inner.ranges = ranges.clone();
+            if inner.module_maps {
+                let maps = ranges
+                    .iter()
+                    .map(|(_, (id, _))| usize::from(*id) + 1)
+                    .max()
+                    .unwrap_or(1);
+                inner.map = vec![0; maps * MAP_SIZE];
+            }
+        }
         self.generate_maybe_log_blob();
+        if self.0.borrow().call_context {
+            self.generate_call_context_blobs();
+        }
     }
 
     fn pre_exec<I: libafl::inputs::Input + libafl::inputs::HasTargetBytes>(
         &mut self,
         _input: &I,
     ) -> Result<(), libafl::Error> {
+        let mut inner = self.0.borrow_mut();
+        inner.call_context.context = 0;
+        inner.call_context.depth = 0;
         Ok(())
     }
 
@@ -73,30 +162,295 @@ impl CoverageRuntime {
     #[must_use]
     pub fn new() -> Self {
         Self(Rc::pin(RefCell::new(CoverageRuntimeInner {
-            map: [0_u8; MAP_SIZE],
-            previous_pc: 0,
-            current_log_impl: 0,
-            blob_maybe_log: None,
+            map: vec![0_u8; MAP_SIZE],
+            previous_pc: vec![],
+            call_context: Box::new(CallContext {
+                context: 0,
+                depth: 0,
+                stack: [[0; 2]; CONTEXT_STACK_SIZE],
+            }),
+            return_sites: HashSet::new(),
+            hitcounts: true,
+            call_context: false,
+            module_maps: false,
+            ranges: RangeMap::new(),
+            current_log_impl: vec![],
+            blob_maybe_log: vec![],
+            current_call_impl: 0,
+            current_return_impl: 0,
+            blob_call: Box::default(),
+            blob_return: Box::default(),
             _pinned: PhantomPinned,
         })))
     }
 
-    /// Retrieve the coverage map pointer
+    /// Only marks the edges as taken, instead of counting their hits
+    #[must_use]
+    pub fn without_hitcounts(self) -> Self {
+        self.0.borrow_mut().hitcounts = false;
+        self
+    }
+
+    /// Combines the edges with the calling context, i.e. the hash of the call sites of the
+    /// pending calls, so that the same edge reached from different callers, or deeper in a
+    /// recursion, is a new entry
+    #[must_use]
+    pub fn with_call_context(self) -> Self {
+        self.0.borrow_mut().call_context = true;
+        self
+    }
+
+    /// Gives each instrumented module its own map of [`MAP_SIZE`], see
+    /// [`CoverageRuntime::module_map_ptrs_mut`]
+    #[must_use]
+    pub fn with_module_maps(self) -> Self {
+        self.0.borrow_mut().module_maps = true;
+        self
+    }
+
+    /// Retrieve the coverage map pointer, the maps of all the modules one after the other
     pub fn map_ptr_mut(&mut self) -> *mut u8 {
         self.0.borrow_mut().map.as_mut_ptr()
     }
 
-    /// Retrieve the `maybe_log` code blob, that will write coverage into the map
+    /// The number of maps, one per instrumented module with [`CoverageRuntime::with_module_maps`]
     #[must_use]
+    pub fn maps_count(&self) -> usize {
+        self.0.borrow().map.len() / MAP_SIZE
+    }
+
+    /// The pointer and the length of the map of each instrumented module, in the order of
+    /// `libs_to_instrument` after the harness, to be observed with a
+    /// [`libafl::observers::MultiMapObserver`]. The maps are owned by the runtime, the slices made
+    /// from them must not outlive it.
+    pub fn module_map_ptrs_mut(&mut self) -> Vec<(*mut u8, usize)> {
+        let ptr = self.map_ptr_mut();
+        (0..self.maps_count())
+            .map(|i| (ptr.wrapping_add(i * MAP_SIZE), MAP_SIZE))
+            .collect()
+    }
+
+    /// Retrieve the `maybe_log` code blob, that will write coverage into the first map
+    #[must_use]
+    #[deprecated(
+        since = "0.8.2",
+        note = "Use CoverageRuntime::blob_maybe_log_of, each map has its own blob"
+    )]
     pub fn blob_maybe_log(&self) -> impl Deref<Target = Box<[u8]>> + '_ {
-        Ref::map(self.0.borrow(), |s| s.blob_maybe_log.as_ref().unwrap())
+        self.blob_maybe_log_of(0)
+    }
+
+    /// Retrieve the `maybe_log` code blob, that will write coverage into the map `map`
+    #[must_use]
+    pub fn blob_maybe_log_of(&self, map: usize) -> impl Deref<Target = Box<[u8]>> + '_ {
+        Ref::map(self.0.borrow(), |s| &s.blob_maybe_log[map])
+    }
+
+    /// Generates the `maybe_log` blob of each map
+    pub fn generate_maybe_log_blob(&mut self) {
+        let maps = self.maps_count();
+        let blobs = (0..maps).map(|map| self.generate_map_blob(map)).collect();
+        let mut inner = self.0.borrow_mut();
+        inner.blob_maybe_log = blobs;
+        inner.current_log_impl = vec![0; maps];
+        // Not resized anymore, the blobs keep pointers to it
+        inner.previous_pc = vec![0; maps];
+    }
+
+    /// The map of the module of `address`, that must be in an instrumented module
+    fn map_of(&self, address: u64) -> usize {
+        let inner = self.0.borrow();
+        if inner.module_maps {
+            let (id, _) = inner
+                .ranges
+                .get(&(address as usize))
+                .expect("The coverage is only emitted in the instrumented modules");
+            usize::from(*id)
+        } else {
+            0
+        }
+    }
+
+    /// Generates the call and return blobs, for [`CoverageRuntime::with_call_context`]
+    fn generate_call_context_blobs(&mut self) {
+        let (blob_call, blob_return) = self.generate_context_blobs();
+        let mut inner = self.0.borrow_mut();
+        inner.blob_call = blob_call;
+        inner.blob_return = blob_return;
+    }
+
+    /// The call blob, entering the call returning to `x0` with the hash `x1`, and the return
+    /// blob, returning to `x0`, see [`CallContext`]. The flags are clobbered, they do not live
+    /// across calls.
+    #[cfg(target_arch = "aarch64")]
+    fn generate_context_blobs(&mut self) -> (Box<[u8]>, Box<[u8]>) {
+        let call_context = addr_of_mut!(*self.0.borrow_mut().call_context);
+
+        let mut ops = dynasmrt::VecAssembler::<dynasmrt::aarch64::Aarch64Relocation>::new(0);
+        dynasm!(ops
+            ;   .arch aarch64
+            ;   stp x2, x3, [sp, -0x10]!
+            ;   stp x4, x5, [sp, -0x10]!
+            ;   ldr x2, >call_context
+            ;   ldr x3, [x2, #8]
+            ;   mov x4, CONTEXT_STACK_SIZE as u64
+            ;   cmp x3, x4
+            ;   b.hs >done
+            ;   add x4, x2, x3, LSL #4
+            ;   ldr x5, [x2]
+            ;   stp x0, x5, [x4, #16]
+            ;   eor x5, x1, x5, LSR #1
+            ;   mov x4, u64::from((MAP_SIZE - 1) as u32)
+            ;   and x5, x5, x4
+            ;   str x5, [x2]
+            ;   add x3, x3, #1
+            ;   str x3, [x2, #8]
+            ;done:
+            ;   ldp x4, x5, [sp], #0x10
+            ;   ldp x2, x3, [sp], #0x10
+            ;   ret
+            ;call_context:
+            ;.qword call_context as i64
+        );
+        let blob_call = ops.finalize().unwrap().into_boxed_slice();
+
+        let mut ops = dynasmrt::VecAssembler::<dynasmrt::aarch64::Aarch64Relocation>::new(0);
+        dynasm!(ops
+            ;   .arch aarch64
+            ;   stp x1, x2, [sp, -0x10]!
+            ;   stp x3, x4, [sp, -0x10]!
+            ;   ldr x1, >call_context
+            ;   ldr x2, [x1, #8]
+            ;scan:
+            ;   cbz x2, >done
+            ;   sub x2, x2, #1
+            ;   add x3, x1, x2, LSL #4
+            ;   ldr x4, [x3, #16]
+            ;   cmp x4, x0
+            ;   b.ne <scan
+            ;   str x2, [x1, #8]
+            ;   ldr x4, [x3, #24]
+            ;   str x4, [x1]
+            ;done:
+            ;   ldp x3, x4, [sp], #0x10
+            ;   ldp x1, x2, [sp], #0x10
+            ;   ret
+            ;call_context:
+            ;.qword call_context as i64
+        );
+        let blob_return = ops.finalize().unwrap().into_boxed_slice();
+        (blob_call, blob_return)
+    }
+
+    /// The call blob, entering the call returning to `rdi` with the hash `rsi`, and the return
+    /// blob, returning to `rdi`, see [`CallContext`]
+    #[cfg(target_arch = "x86_64")]
+    fn generate_context_blobs(&mut self) -> (Box<[u8]>, Box<[u8]>) {
+        let call_context = addr_of_mut!(*self.0.borrow_mut().call_context);
+
+        let mut ops = dynasmrt::VecAssembler::<dynasmrt::x64::X64Relocation>::new(0);
+        dynasm!(ops
+            ;   .arch x64
+            ;   pushfq
+            ;   push rax
+            ;   push rcx
+            ;   push rdx
+            ;   lea rax, [>call_context]
+            ;   mov rax, QWORD [rax]
+            ;   mov rcx, QWORD [rax + 8]
+            ;   cmp rcx, CONTEXT_STACK_SIZE as i32
+            ;   jae >done
+            ;   shl rcx, 4
+            ;   mov QWORD [rax + rcx + 16], rdi
+            ;   mov rdx, QWORD [rax]
+            ;   mov QWORD [rax + rcx + 24], rdx
+            ;   shr rdx, 1
+            ;   xor rdx, rsi
+            ;   and rdx, (MAP_SIZE - 1) as i32
+            ;   mov QWORD [rax], rdx
+            ;   shr rcx, 4
+            ;   inc rcx
+            ;   mov QWORD [rax + 8], rcx
+            ;done:
+            ;   pop rdx
+            ;   pop rcx
+            ;   pop rax
+            ;   popfq
+            ;   ret
+            ;call_context:
+            ;.qword call_context as i64
+        );
+        let blob_call = ops.finalize().unwrap().into_boxed_slice();
+
+        let mut ops = dynasmrt::VecAssembler::<dynasmrt::x64::X64Relocation>::new(0);
+        dynasm!(ops
+            ;   .arch x64
+            ;   pushfq
+            ;   push rax
+            ;   push rcx
+            ;   push rdx
+            ;   lea rax, [>call_context]
+            ;   mov rax, QWORD [rax]
+            ;   mov rcx, QWORD [rax + 8]
+            ;scan:
+            ;   test rcx, rcx
+            ;   jz >done
+            ;   dec rcx
+            ;   mov rdx, rcx
+            ;   shl rdx, 4
+            ;   cmp QWORD [rax + rdx + 16], rdi
+            ;   jne <scan
+            ;   mov QWORD [rax + 8], rcx
+            ;   mov rdx, QWORD [rax + rdx + 24]
+            ;   mov QWORD [rax], rdx
+            ;done:
+            ;   pop rdx
+            ;   pop rcx
+            ;   pop rax
+            ;   popfq
+            ;   ret
+            ;call_context:
+            ;.qword call_context as i64
+        );
+        let blob_return = ops.finalize().unwrap().into_boxed_slice();
+        (blob_call, blob_return)
+    }
+
+    /// Puts a copy of `blob` in the code, unless the copy at `last` can be reached with a direct
+    /// branch, and returns the address of the copy to call
+    fn put_blob_copy(output: &StalkerOutput, last: u64, blob: &[u8]) -> u64 {
+        let writer = output.writer();
+        if last != 0
+            && writer.can_branch_directly_to(last)
+            && writer.can_branch_directly_between(writer.pc() + 128, last)
+        {
+            return last;
+        }
+        let after_blob = writer.code_offset() + 1;
+
+        #[cfg(target_arch = "x86_64")]
+        writer.put_jmp_near_label(after_blob);
+        #[cfg(target_arch = "aarch64")]
+        writer.put_b_label(after_blob);
+
+        let copy = writer.pc();
+        writer.put_bytes(blob);
+        writer.put_label(after_blob);
+        copy
     }
 
     /// A minimal `maybe_log` implementation. We insert this into the transformed instruction stream
     /// every time we need a copy that is within a direct branch of the start of the transformed basic
     /// block.
     #[cfg(target_arch = "aarch64")]
-    pub fn generate_maybe_log_blob(&mut self) {
+    fn generate_map_blob(&mut self, map: usize) -> Box<[u8]> {
+        let (hitcounts, call_context) = {
+            let inner = self.0.borrow();
+            (inner.hitcounts, inner.call_context)
+        };
+        let map_addr = unsafe { self.map_ptr_mut().add(map * MAP_SIZE) };
+        let context_addr = addr_of_mut!(self.0.borrow_mut().call_context.context);
+
         let mut ops = dynasmrt::VecAssembler::<dynasmrt::aarch64::Aarch64Relocation>::new(0);
         dynasm!(ops
             ;   .arch aarch64
@@ -106,31 +460,64 @@ impl CoverageRuntime {
             ;   ldr x2, >previous_loc
             ;   ldr x4, [x2]
             ;   eor x4, x4, x0
+        );
+        if call_context {
+            dynasm!(ops
+                ;   .arch aarch64
+                ;   ldr x3, >context_addr
+                ;   ldr x3, [x3]
+                ;   eor x4, x4, x3
+            );
+        }
+        dynasm!(ops
+            ;   .arch aarch64
             ;   mov x3, u64::from((MAP_SIZE - 1) as u32)
             ;   and x4, x4, x3
-            ;   ldr x3, [x1, x4]
-            ;   add x3, x3, #1
-            ;   str x3, [x1, x4]
+        );
+        if hitcounts {
+            dynasm!(ops
+                ;   .arch aarch64
+                ;   ldrb w3, [x1, x4]
+                ;   add w3, w3, #1
+                ;   strb w3, [x1, x4]
+            );
+        } else {
+            dynasm!(ops
+                ;   .arch aarch64
+                ;   mov w3, #1
+                ;   strb w3, [x1, x4]
+            );
+        }
+        dynasm!(ops
+            ;   .arch aarch64
             ;   add x0, xzr, x0, LSR #1
             ;   str x0, [x2]
             ;   ldp x3, x4, [sp], #0x10
             ;   ldp x1, x2, [sp], #0x10
             ;   ret
             ;map_addr:
-            ;.qword addr_of_mut!(self.0.borrow_mut().map) as i64
+            ;.qword map_addr as i64
+            ;context_addr:
+            ;.qword context_addr as i64
             ;previous_loc:
             ;.qword 0
         );
         let ops_vec = ops.finalize().unwrap();
-        self.0.borrow_mut().blob_maybe_log =
-            Some(ops_vec[..ops_vec.len() - 8].to_vec().into_boxed_slice());
+        ops_vec[..ops_vec.len() - 8].to_vec().into_boxed_slice()
     }
 
     /// A minimal `maybe_log` implementation. We insert this into the transformed instruction stream
     /// every time we need a copy that is within a direct branch of the start of the transformed basic
     /// block.
     #[cfg(target_arch = "x86_64")]
-    pub fn generate_maybe_log_blob(&mut self) {
+    fn generate_map_blob(&mut self, map: usize) -> Box<[u8]> {
+        let (hitcounts, call_context) = {
+            let inner = self.0.borrow();
+            (inner.hitcounts, inner.call_context)
+        };
+        let map_addr = unsafe { self.map_ptr_mut().add(map * MAP_SIZE) };
+        let context_addr = addr_of_mut!(self.0.borrow_mut().call_context.context);
+
         let mut ops = dynasmrt::VecAssembler::<dynasmrt::x64::X64Relocation>::new(0);
         dynasm!(ops
             ;   .arch x64
@@ -138,13 +525,38 @@ impl CoverageRuntime {
             ;   push rax
             ;   push rcx
             ;   push rdx
-            ;   lea rax, [>map_addr]
-            ;   mov rax, QWORD [rax]
             ;   lea rcx, [>previous_loc]
             ;   mov rdx, QWORD [rcx]
             ;   mov rdx, QWORD [rdx]
             ;   xor rdx, rdi
-            ;   inc BYTE [rax + rdx]
+        );
+        // The context is masked already, the index stays in the map
+        if call_context {
+            dynasm!(ops
+                ;   .arch x64
+                ;   lea rax, [>context_addr]
+                ;   mov rax, QWORD [rax]
+                ;   xor rdx, QWORD [rax]
+            );
+        }
+        dynasm!(ops
+            ;   .arch x64
+            ;   lea rax, [>map_addr]
+            ;   mov rax, QWORD [rax]
+        );
+        if hitcounts {
+            dynasm!(ops
+                ;   .arch x64
+                ;   inc BYTE [rax + rdx]
+            );
+        } else {
+            dynasm!(ops
+                ;   .arch x64
+                ;   mov BYTE [rax + rdx], 1
+            );
+        }
+        dynasm!(ops
+            ;   .arch x64
             ;   shr rdi, 1
             ;   mov rax, QWORD [rcx]
             ;   mov QWORD [rax], rdi
@@ -154,27 +566,29 @@ impl CoverageRuntime {
             ;   popfq
             ;   ret
             ;map_addr:
-            ;.qword addr_of_mut!(self.0.borrow_mut().map) as i64
+            ;.qword map_addr as i64
+            ;context_addr:
+            ;.qword context_addr as i64
             ;previous_loc:
             ;.qword 0
         );
         let ops_vec = ops.finalize().unwrap();
-        self.0.borrow_mut().blob_maybe_log =
-            Some(ops_vec[..ops_vec.len() - 8].to_vec().into_boxed_slice());
+        ops_vec[..ops_vec.len() - 8].to_vec().into_boxed_slice()
     }
 
     /// Emits coverage mapping into the current basic block.
     #[inline]
     pub fn emit_coverage_mapping(&mut self, address: u64, output: &StalkerOutput) {
         let h64 = xxh3_rrmxmx_mixer(address);
+        let map = self.map_of(address);
 
         let writer = output.writer();
         #[allow(clippy::cast_possible_wrap)] // gum redzone size is u32, we need an offset as i32.
         let redzone_size = i64::from(frida_gum_sys::GUM_RED_ZONE_SIZE);
-        if self.0.borrow().current_log_impl == 0
-            || !writer.can_branch_directly_to(self.0.borrow().current_log_impl)
-            || !writer
-                .can_branch_directly_between(writer.pc() + 128, self.0.borrow().current_log_impl)
+        let current_log_impl = self.0.borrow().current_log_impl[map];
+        if current_log_impl == 0
+            || !writer.can_branch_directly_to(current_log_impl)
+            || !writer.can_branch_directly_between(writer.pc() + 128, current_log_impl)
         {
             let after_log_impl = writer.code_offset() + 1;
 
@@ -183,9 +597,9 @@ impl CoverageRuntime {
             #[cfg(target_arch = "aarch64")]
             writer.put_b_label(after_log_impl);
 
-            self.0.borrow_mut().current_log_impl = writer.pc();
-            writer.put_bytes(&self.blob_maybe_log());
-            let prev_loc_pointer = addr_of_mut!(self.0.borrow_mut().previous_pc) as u64; // Get the pointer to self.previous_pc
+            self.0.borrow_mut().current_log_impl[map] = writer.pc();
+            writer.put_bytes(&self.blob_maybe_log_of(map));
+            let prev_loc_pointer = addr_of_mut!(self.0.borrow_mut().previous_pc[map]) as u64; // Get the pointer to the previous_pc of the map
 
             writer.put_bytes(&prev_loc_pointer.to_ne_bytes());
 
@@ -196,7 +610,7 @@ impl CoverageRuntime {
             writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, -(redzone_size));
             writer.put_push_reg(X86Register::Rdi);
             writer.put_mov_reg_address(X86Register::Rdi, h64 & (MAP_SIZE as u64 - 1));
-            writer.put_call_address(self.0.borrow().current_log_impl);
+            writer.put_call_address(self.0.borrow().current_log_impl[map]);
             writer.put_pop_reg(X86Register::Rdi);
             writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, redzone_size);
         }
@@ -211,7 +625,131 @@ impl CoverageRuntime {
             );
             writer.put_ldr_reg_u64(Aarch64Register::X0, h64 & (MAP_SIZE as u64 - 1));
 
-            writer.put_bl_imm(self.0.borrow().current_log_impl);
+            writer.put_bl_imm(self.0.borrow().current_log_impl[map]);
+            writer.put_ldp_reg_reg_reg_offset(
+                Aarch64Register::Lr,
+                Aarch64Register::X0,
+                Aarch64Register::Sp,
+                16 + redzone_size,
+                IndexMode::PostAdjust,
+            );
+        }
+    }
+
+    /// Emits the restore of the calling context at the start of a block, if the block is the
+    /// return site of a call, for [`CoverageRuntime::with_call_context`].
+    /// Must be emitted before the coverage mapping of the block.
+    pub fn emit_return_site(&mut self, address: u64, output: &StalkerOutput) {
+        {
+            let inner = self.0.borrow();
+            if !inner.call_context || !inner.return_sites.contains(&address) {
+                return;
+            }
+        }
+        let current_return_impl = {
+            let inner = self.0.borrow();
+            Self::put_blob_copy(output, inner.current_return_impl, &inner.blob_return)
+        };
+        self.0.borrow_mut().current_return_impl = current_return_impl;
+
+        let writer = output.writer();
+        #[allow(clippy::cast_possible_wrap)] // gum redzone size is u32, we need an offset as i32.
+        let redzone_size = i64::from(frida_gum_sys::GUM_RED_ZONE_SIZE);
+        #[cfg(target_arch = "x86_64")]
+        {
+            writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, -(redzone_size));
+            writer.put_push_reg(X86Register::Rdi);
+            writer.put_mov_reg_address(X86Register::Rdi, address);
+            writer.put_call_address(current_return_impl);
+            writer.put_pop_reg(X86Register::Rdi);
+            writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, redzone_size);
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            writer.put_stp_reg_reg_reg_offset(
+                Aarch64Register::Lr,
+                Aarch64Register::X0,
+                Aarch64Register::Sp,
+                -(16 + redzone_size),
+                IndexMode::PreAdjust,
+            );
+            writer.put_ldr_reg_u64(Aarch64Register::X0, address);
+            writer.put_bl_imm(current_return_impl);
+            writer.put_ldp_reg_reg_reg_offset(
+                Aarch64Register::Lr,
+                Aarch64Register::X0,
+                Aarch64Register::Sp,
+                16 + redzone_size,
+                IndexMode::PostAdjust,
+            );
+        }
+    }
+
+    /// Emits the update of the calling context before a call instruction, for
+    /// [`CoverageRuntime::with_call_context`]
+    pub fn emit_call_site(
+        &mut self,
+        address: u64,
+        instruction: &Instruction,
+        output: &StalkerOutput,
+    ) {
+        if !self.0.borrow().call_context {
+            return;
+        }
+        let instr = instruction.instr();
+        if !matches!(instr.mnemonic(), Some("call" | "bl" | "blr")) {
+            return;
+        }
+        // The call returns to the next instruction, which starts a new block
+        let return_site = address + instr.bytes().len() as u64;
+        self.0.borrow_mut().return_sites.insert(return_site);
+        let current_call_impl = {
+            let inner = self.0.borrow();
+            Self::put_blob_copy(output, inner.current_call_impl, &inner.blob_call)
+        };
+        self.0.borrow_mut().current_call_impl = current_call_impl;
+
+        let writer = output.writer();
+        #[allow(clippy::cast_possible_wrap)] // gum redzone size is u32, we need an offset as i32.
+        let redzone_size = i64::from(frida_gum_sys::GUM_RED_ZONE_SIZE);
+        #[cfg(target_arch = "x86_64")]
+        {
+            writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, -(redzone_size));
+            writer.put_push_reg(X86Register::Rdi);
+            writer.put_push_reg(X86Register::Rsi);
+            writer.put_mov_reg_address(X86Register::Rdi, return_site);
+            writer.put_mov_reg_address(X86Register::Rsi, call_site_hash(return_site));
+            writer.put_call_address(current_call_impl);
+            writer.put_pop_reg(X86Register::Rsi);
+            writer.put_pop_reg(X86Register::Rdi);
+            writer.put_lea_reg_reg_offset(X86Register::Rsp, X86Register::Rsp, redzone_size);
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            writer.put_stp_reg_reg_reg_offset(
+                Aarch64Register::Lr,
+                Aarch64Register::X0,
+                Aarch64Register::Sp,
+                -(16 + redzone_size),
+                IndexMode::PreAdjust,
+            );
+            writer.put_stp_reg_reg_reg_offset(
+                Aarch64Register::X1,
+                Aarch64Register::X2,
+                Aarch64Register::Sp,
+                -16,
+                IndexMode::PreAdjust,
+            );
+            writer.put_ldr_reg_u64(Aarch64Register::X0, return_site);
+            writer.put_ldr_reg_u64(Aarch64Register::X1, call_site_hash(return_site));
+            writer.put_bl_imm(current_call_impl);
+            writer.put_ldp_reg_reg_reg_offset(
+                Aarch64Register::X1,
+                Aarch64Register::X2,
+                Aarch64Register::Sp,
+                16,
+                IndexMode::PostAdjust,
+            );
             writer.put_ldp_reg_reg_reg_offset(
                 Aarch64Register::Lr,
                 Aarch64Register::X0,
@@ -222,3 +760,79 @@ impl CoverageRuntime {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::ptr::addr_of;
+
+    use super::{call_site_hash, CallContext, CoverageRuntime, CONTEXT_STACK_SIZE, MAP_SIZE};
+
+    #[test]
+    fn test_context_stack() {
+        fn call(cc: &mut CallContext, site: u64) {
+            cc.call(site, call_site_hash(site));
+        }
+
+        let runtime = CoverageRuntime::new();
+        let mut inner = runtime.0.borrow_mut();
+        let cc = &mut *inner.call_context;
+
+        call(cc, 0x1000);
+        let in_first = cc.context;
+        assert_ne!(in_first, 0);
+        assert!(in_first < MAP_SIZE as u64);
+        call(cc, 0x2000);
+        let in_second = cc.context;
+        assert_ne!(in_second, in_first);
+
+        // Returning restores the context of the caller, unknown return sites are ignored
+        cc.return_to(0x3000);
+        assert_eq!(cc.context, in_second);
+        cc.return_to(0x2000);
+        assert_eq!(cc.context, in_first);
+        cc.return_to(0x1000);
+        assert_eq!(cc.context, 0);
+        assert_eq!(cc.depth, 0);
+
+        // A direct recursion does not cancel its context
+        call(cc, 0x1000);
+        call(cc, 0x1000);
+        assert_ne!(cc.context, 0);
+        assert_ne!(cc.context, in_first);
+        cc.return_to(0x1000);
+        assert_eq!(cc.context, in_first);
+        assert_eq!(cc.depth, 1);
+
+        // A longjmp over the pending calls drops their frames
+        call(cc, 0x2000);
+        call(cc, 0x4000);
+        cc.return_to(0x1000);
+        assert_eq!(cc.context, 0);
+        assert_eq!(cc.depth, 0);
+
+        // Past the stack, the calls run in the context of their caller
+        for site in 0..CONTEXT_STACK_SIZE as u64 {
+            call(cc, 0x1_0000 + site);
+        }
+        let deepest = cc.context;
+        call(cc, 0x2_0000);
+        assert_eq!(cc.context, deepest);
+        assert_eq!(cc.depth, CONTEXT_STACK_SIZE as u64);
+        cc.return_to(0x1_0000);
+        assert_eq!(cc.context, 0);
+        assert_eq!(cc.depth, 0);
+    }
+
+    /// The call and return blobs address the fields of the context by offset
+    #[test]
+    fn test_call_context_layout() {
+        let runtime = CoverageRuntime::new();
+        let inner = runtime.0.borrow();
+        let cc = &*inner.call_context;
+        let base = addr_of!(*cc) as usize;
+        assert_eq!(addr_of!(cc.context) as usize - base, 0);
+        assert_eq!(addr_of!(cc.depth) as usize - base, 8);
+        assert_eq!(addr_of!(cc.stack[0][0]) as usize - base, 16);
+        assert_eq!(addr_of!(cc.stack[1][1]) as usize - base, 16 + 16 + 8);
+    }
+}
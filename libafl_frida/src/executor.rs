@@ -1,10 +1,24 @@
 use core::fmt::{self, Debug, Formatter};
+#[cfg(unix)]
+use core::{
+    ptr::{self, null_mut},
+    slice,
+    time::Duration,
+};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 use std::{ffi::c_void, marker::PhantomData};
 
 use frida_gum::{
     stalker::{NoneEventSink, Stalker},
     Gum, MemoryRange, NativePointer,
 };
+#[cfg(unix)]
+use libafl::bolts::{
+    os::unix_signals::{setup_signal_handler, ucontext_t, Handler, Signal, CRASH_SIGNALS},
+    shmem::{ShMem, ShMemProvider},
+    AsMutSlice, AsSlice,
+};
 #[cfg(windows)]
 use libafl::{
     executors::inprocess::{HasInProcessHandlers, InProcessHandlers},
@@ -17,12 +31,57 @@ use libafl::{
     state::UsesState,
     Error,
 };
+#[cfg(unix)]
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        signal::{kill, Signal as NixSignal},
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::{close, fork, pipe, read, write, ForkResult, Pid},
+};
 
 #[cfg(unix)]
-use crate::asan::errors::ASAN_ERRORS;
+use libafl_targets::{CmpLogMap, CMPLOG_MAP_PTR};
+use rangemap::RangeMap;
+
+#[cfg(all(unix, feature = "cmplog"))]
+use crate::cmplog_rt::CmpLogRuntime;
 use crate::helper::{FridaInstrumentationHelper, FridaRuntimeTuple};
 #[cfg(windows)]
 use crate::windows_hooks::initialize;
+#[cfg(unix)]
+use crate::{
+    asan::errors::{AsanErrors, ASAN_ERRORS},
+    coverage_rt::{CoverageRuntime, MAP_SIZE},
+};
+
+/// Creates a [`Stalker`] following the instrumented ranges and the fuzzer, and excluding the rest
+fn new_stalker<'a>(gum: &'a Gum, ranges: &RangeMap<usize, (u16, String)>) -> Stalker<'a> {
+    let mut stalker = Stalker::new(gum);
+    // Include the current module (the fuzzer) in stalked ranges. We clone the ranges so that
+    // we don't add it to the INSTRUMENTED ranges.
+    let mut ranges = ranges.clone();
+    for module in frida_gum::Module::enumerate_modules() {
+        if module.base_address < new_stalker as usize
+            && (new_stalker as usize) < module.base_address + module.size
+        {
+            ranges.insert(
+                module.base_address..(module.base_address + module.size),
+                (0xffff, "fuzzer".to_string()),
+            );
+            break;
+        }
+    }
+    for range in ranges.gaps(&(0..usize::MAX)) {
+        stalker.exclude(&MemoryRange::new(
+            NativePointer(range.start as *mut c_void),
+            range.end - range.start,
+        ));
+    }
+    stalker
+}
 
 /// The [`FridaInProcessExecutor`] is an [`Executor`] that executes the target in the same process, usinig [`frida`](https://frida.re/) for binary-only instrumentation.
 pub struct FridaInProcessExecutor<'a, 'b, 'c, H, OT, RT, S>
@@ -96,7 +155,6 @@ where
         #[cfg(unix)]
         unsafe {
             if ASAN_ERRORS.is_some() && !ASAN_ERRORS.as_ref().unwrap().is_empty() {
-                println!("Crashing target as it had ASAN errors");
                 libc::raise(libc::SIGABRT);
             }
         }
@@ -157,28 +215,7 @@ where
         base: InProcessExecutor<'a, H, OT, S>,
         helper: &'c mut FridaInstrumentationHelper<'b, RT>,
     ) -> Self {
-        let mut stalker = Stalker::new(gum);
-        // Include the current module (the fuzzer) in stalked ranges. We clone the ranges so that
-        // we don't add it to the INSTRUMENTED ranges.
-        let mut ranges = helper.ranges().clone();
-        for module in frida_gum::Module::enumerate_modules() {
-            if module.base_address < Self::new as usize
-                && (Self::new as usize) < module.base_address + module.size
-            {
-                ranges.insert(
-                    module.base_address..(module.base_address + module.size),
-                    (0xffff, "fuzzer".to_string()),
-                );
-                break;
-            }
-        }
-        for range in ranges.gaps(&(0..usize::MAX)) {
-            println!("excluding range: {:x}-{:x}", range.start, range.end);
-            stalker.exclude(&MemoryRange::new(
-                NativePointer(range.start as *mut c_void),
-                range.end - range.start,
-            ));
-        }
+        let stalker = new_stalker(gum, helper.ranges());
 
         #[cfg(windows)]
         initialize(&gum);
@@ -210,3 +247,771 @@ where
         &self.base.handlers()
     }
 }
+
+/// The room for the header of the shared memory of the [`FridaInProcessForkExecutor`]
+#[cfg(unix)]
+const FORK_HEADER_SIZE: usize = 0x100;
+
+/// The largest serialized input sent to a running child, a larger one is run in a new child
+#[cfg(unix)]
+pub const FORK_INPUT_SIZE: usize = 1 << 20;
+
+/// The room for the serialized ASan errors of a run
+#[cfg(unix)]
+const FORK_ASAN_ERRORS_SIZE: usize = 1 << 20;
+
+#[cfg(unix)]
+const FORK_INPUT_OFFSET: usize = FORK_HEADER_SIZE;
+#[cfg(unix)]
+const FORK_ASAN_ERRORS_OFFSET: usize = FORK_INPUT_OFFSET + FORK_INPUT_SIZE;
+#[cfg(unix)]
+const FORK_MAPS_OFFSET: usize = FORK_ASAN_ERRORS_OFFSET + FORK_ASAN_ERRORS_SIZE;
+
+/// How long the parent waits past the timeout for the child to share its run, before killing it
+#[cfg(unix)]
+const FORK_TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+/// The header of the shared memory, followed by the input, the ASan errors, the maps and the
+/// cmplog map
+#[cfg(unix)]
+#[repr(C)]
+#[derive(Debug)]
+struct ForkSharedHeader {
+    /// The length of the next input, sent to a running child
+    input_len: usize,
+    /// The length of the ASan errors of the run, if any
+    asan_errors_len: usize,
+    /// If the child shared the results of the run
+    shared: bool,
+    exit_kind: u8,
+}
+
+#[cfg(unix)]
+fn exit_kind_to_u8(exit_kind: ExitKind) -> u8 {
+    match exit_kind {
+        ExitKind::Crash => 1,
+        ExitKind::Oom => 2,
+        ExitKind::Timeout => 3,
+        _ => 0,
+    }
+}
+
+#[cfg(unix)]
+fn exit_kind_from_u8(exit_kind: u8) -> ExitKind {
+    match exit_kind {
+        1 => ExitKind::Crash,
+        2 => ExitKind::Oom,
+        3 => ExitKind::Timeout,
+        _ => ExitKind::Ok,
+    }
+}
+
+/// What a child shares back with the parent, also from its crash handler
+#[cfg(unix)]
+#[derive(Debug)]
+struct ForkChildData {
+    shared: *mut u8,
+    maps: Vec<(*mut u8, usize)>,
+    /// The offset of the cmplog map in the shared memory, if it is shared
+    cmplog_offset: Option<usize>,
+}
+
+#[cfg(unix)]
+impl ForkChildData {
+    /// Copies the maps and the exit kind of the run into the shared memory.
+    /// Only copies raw bytes, as it also runs in the crash and timeout handlers.
+    unsafe fn share(&self, exit_kind: ExitKind) {
+        let header = &mut *self.shared.cast::<ForkSharedHeader>();
+        let mut offset = FORK_MAPS_OFFSET;
+        for &(map, len) in &self.maps {
+            ptr::copy_nonoverlapping(map, self.shared.add(offset), len);
+            offset += len;
+        }
+        if let Some(offset) = self.cmplog_offset {
+            (*self.shared.add(offset).cast::<CmpLogMap>()).copy_hits_from(&*CMPLOG_MAP_PTR);
+        }
+        header.exit_kind = exit_kind_to_u8(exit_kind);
+        header.shared = true;
+    }
+
+    /// Serializes the ASan errors of the run into the shared memory, out of the signal handlers.
+    /// The errors are not shared if they do not fit.
+    unsafe fn share_asan_errors(&self) {
+        let header = &mut *self.shared.cast::<ForkSharedHeader>();
+        header.asan_errors_len = 0;
+        if let Some(errors) = ASAN_ERRORS.as_ref() {
+            if !errors.is_empty() {
+                let area = slice::from_raw_parts_mut(
+                    self.shared.add(FORK_ASAN_ERRORS_OFFSET),
+                    FORK_ASAN_ERRORS_SIZE,
+                );
+                if let Ok(used) = postcard::to_slice(errors, area) {
+                    header.asan_errors_len = used.len();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Handler for ForkChildData {
+    fn handle(&mut self, signal: Signal, _info: libc::siginfo_t, _context: &mut ucontext_t) {
+        let exit_kind = if matches!(signal, Signal::SigAlarm) {
+            ExitKind::Timeout
+        } else {
+            ExitKind::Crash
+        };
+        unsafe {
+            self.share(exit_kind);
+            libc::_exit(128 + signal as i32);
+        }
+    }
+
+    fn signals(&self) -> Vec<Signal> {
+        let mut signals = CRASH_SIGNALS.to_vec();
+        signals.push(Signal::SigAlarm);
+        signals
+    }
+}
+
+/// Arms the timer of the child, whose `SIGALRM` shares the run as a timeout, or disarms it
+#[cfg(unix)]
+#[allow(clippy::cast_possible_wrap)]
+fn set_child_timer(timeout: Option<Duration>) {
+    let timeout = timeout.unwrap_or_default();
+    let value = libc::itimerval {
+        it_interval: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        it_value: libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        },
+    };
+    unsafe { libc::setitimer(libc::ITIMER_REAL, &value, null_mut()) };
+}
+
+/// The data of the crash handler of the current child
+#[cfg(unix)]
+static mut FORK_CHILD_DATA: ForkChildData = ForkChildData {
+    shared: null_mut(),
+    maps: Vec::new(),
+    cmplog_offset: None,
+};
+
+/// A child of the [`FridaInProcessForkExecutor`], waiting for its next input between the runs
+#[cfg(unix)]
+#[derive(Debug)]
+struct ForkChild {
+    pid: Pid,
+    /// The inputs run so far
+    execs: usize,
+    /// Wakes the child up for the next input
+    input_pipe: RawFd,
+    /// Tells the parent that the run is done
+    done_pipe: RawFd,
+}
+
+/// The [`FridaInProcessForkExecutor`] is an [`Executor`] that instruments the target once, in the
+/// fuzzer, and runs the inputs in forked children, for targets that leak, abort or corrupt their
+/// global state.
+///
+/// Gum and the runtimes are initialized in the parent only, and the stalker follows the parent
+/// once before the first fork, so that each child only has to activate it. A child runs up to
+/// `execs_per_fork` inputs, the next ones being sent through the shared memory. The coverage map,
+/// the cmplog map and the ASan errors of each run are copied back to the parent through the shared
+/// memory, also when the child crashes, panics or times out, so the observers of the parent see
+/// them as if the input had run in-process. Only the entries of the cmplog map with hits are
+/// copied, the map is mostly made of unused values.
+#[cfg(unix)]
+pub struct FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S::Input: HasTargetBytes,
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+    'a: 'b,
+{
+    harness_fn: &'a mut H,
+    observers: OT,
+    /// Frida's dynamic rewriting engine
+    stalker: Stalker<'a>,
+    /// User provided callback for instrumentation
+    helper: &'c mut FridaInstrumentationHelper<'b, RT>,
+    followed: bool,
+    gum: &'b Gum,
+    shmem_provider: SP,
+    shmem: SP::ShMem,
+    /// The maps copied back from the children
+    maps: Vec<(*mut u8, usize)>,
+    /// The offset of the cmplog map in the shared memory, if the cmplog runtime is used
+    cmplog_offset: Option<usize>,
+    execs_per_fork: usize,
+    timeout: Option<Duration>,
+    child: Option<ForkChild>,
+    phantom: PhantomData<S>,
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> Debug
+    for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FridaInProcessForkExecutor")
+            .field("observers", &self.observers)
+            .field("helper", &self.helper)
+            .field("shmem_provider", &self.shmem_provider)
+            .field("execs_per_fork", &self.execs_per_fork)
+            .field("timeout", &self.timeout)
+            .field("child", &self.child)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, EM, H, OT, RT, S, SP, Z> Executor<EM, Z>
+    for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    EM: UsesState<State = S>,
+    H: FnMut(&S::Input) -> ExitKind,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    OT: ObserversTuple<S>,
+    RT: FridaRuntimeTuple,
+    SP: ShMemProvider,
+    Z: UsesState<State = S>,
+{
+    /// Runs the input in the current child, or in a new one
+    #[inline]
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        self.header().shared = false;
+        self.header().asan_errors_len = 0;
+
+        let mut sent = false;
+        if matches!(&self.child, Some(child) if child.execs < self.execs_per_fork) {
+            let serialized = postcard::to_allocvec(input)?;
+            if serialized.len() <= FORK_INPUT_SIZE {
+                self.shmem.as_mut_slice()[FORK_INPUT_OFFSET..FORK_INPUT_OFFSET + serialized.len()]
+                    .copy_from_slice(&serialized);
+                self.header().input_len = serialized.len();
+                let child = self.child.as_mut().unwrap();
+                write(child.input_pipe, &[0])?;
+                child.execs += 1;
+                sent = true;
+            }
+        }
+        if !sent {
+            self.stop_child()?;
+            self.fork_child(state, input)?;
+        }
+
+        let exit_kind = self.wait_for_child()?;
+        self.collect_results()?;
+        Ok(exit_kind)
+    }
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    OT: ObserversTuple<S>,
+    RT: FridaRuntimeTuple,
+    SP: ShMemProvider,
+{
+    /// Creates a new [`FridaInProcessForkExecutor`], forking a child per input
+    pub fn new(
+        gum: &'a Gum,
+        harness_fn: &'a mut H,
+        observers: OT,
+        helper: &'c mut FridaInstrumentationHelper<'b, RT>,
+        mut shmem_provider: SP,
+    ) -> Result<Self, Error> {
+        let stalker = new_stalker(gum, helper.ranges());
+
+        let mut maps = vec![];
+        if let Some(rt) = helper.runtime_mut::<CoverageRuntime>() {
+            maps.push((rt.map_ptr_mut(), rt.maps_count() * MAP_SIZE));
+        }
+        let maps_len: usize = maps.iter().map(|(_, len)| len).sum();
+        let maps_end = FORK_MAPS_OFFSET + maps_len;
+
+        #[cfg(feature = "cmplog")]
+        let cmplog_offset = helper.runtime::<CmpLogRuntime>().map(|_| {
+            let align = core::mem::align_of::<CmpLogMap>();
+            (maps_end + align - 1) & !(align - 1)
+        });
+        #[cfg(not(feature = "cmplog"))]
+        let cmplog_offset = None;
+        let shmem_len = cmplog_offset.map_or(maps_end, |offset| {
+            offset + core::mem::size_of::<CmpLogMap>()
+        });
+        let shmem = shmem_provider.new_shmem(shmem_len)?;
+
+        Ok(Self {
+            harness_fn,
+            observers,
+            stalker,
+            helper,
+            followed: false,
+            gum,
+            shmem_provider,
+            shmem,
+            maps,
+            cmplog_offset,
+            execs_per_fork: 1,
+            timeout: None,
+            child: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Runs up to `execs_per_fork` inputs in each child, instead of one
+    #[must_use]
+    pub fn with_execs_per_fork(mut self, execs_per_fork: usize) -> Self {
+        self.execs_per_fork = execs_per_fork.max(1);
+        self
+    }
+
+    /// Stops the child once a run takes longer than `timeout`: the child shares the run, as a
+    /// timeout, and exits. A child stuck with the signals blocked is killed after a grace period.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn header(&mut self) -> &mut ForkSharedHeader {
+        unsafe { self.shmem.as_object_mut::<ForkSharedHeader>() }
+    }
+
+    /// Forks a new child, which runs `input` first
+    fn fork_child(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        let (input_read, input_write) = pipe()?;
+        let (done_read, done_write) = pipe()?;
+
+        // Warm up the stalker in the parent, so that the children inherit it
+        if self.helper.stalker_enabled() && !self.followed {
+            self.followed = true;
+            let transformer = self.helper.transformer(self.gum);
+            self.stalker.follow_me::<NoneEventSink>(&transformer, None);
+            self.stalker.deactivate();
+        }
+
+        self.shmem_provider.pre_fork()?;
+        unsafe { frida_gum_sys::gum_prepare_to_fork() };
+        let forked = unsafe { fork() };
+        if !matches!(forked, Ok(ForkResult::Child)) {
+            unsafe { frida_gum_sys::gum_recover_from_fork_in_parent() };
+        }
+        match forked? {
+            ForkResult::Child => {
+                unsafe { frida_gum_sys::gum_recover_from_fork_in_child() };
+                self.shmem_provider.post_fork(true)?;
+                close(input_write)?;
+                close(done_read)?;
+                self.run_child(state, input, input_read, done_write)
+            }
+            ForkResult::Parent { child } => {
+                self.shmem_provider.post_fork(false)?;
+                close(input_read)?;
+                close(done_write)?;
+                self.child = Some(ForkChild {
+                    pid: child,
+                    execs: 1,
+                    input_pipe: input_write,
+                    done_pipe: done_read,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// The loop of the child, running the inputs until the parent stops it or the target crashes
+    fn run_child(
+        &mut self,
+        state: &mut S,
+        first_input: &S::Input,
+        input_pipe: RawFd,
+        done_pipe: RawFd,
+    ) -> ! {
+        unsafe {
+            FORK_CHILD_DATA.shared = self.shmem.as_mut_slice().as_mut_ptr();
+            FORK_CHILD_DATA.maps = self.maps.clone();
+            FORK_CHILD_DATA.cmplog_offset = self.cmplog_offset;
+            setup_signal_handler(&mut FORK_CHILD_DATA)
+                .expect("Failed to set up the crash handler of the child");
+        }
+        // The ASan errors are reported with a panic, which would unwind into the forked fuzzer:
+        // share the run as a crash and exit from the panic hook
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            unsafe {
+                FORK_CHILD_DATA.share_asan_errors();
+                FORK_CHILD_DATA.share(ExitKind::Crash);
+            }
+            hook(info);
+            unsafe { libc::_exit(128 + libc::SIGABRT) };
+        }));
+
+        let mut next_input: Option<S::Input> = None;
+        for exec in 0..self.execs_per_fork {
+            if exec > 0 {
+                // Wait for the next input, or for the parent to stop the child
+                let mut buf = [0];
+                if !matches!(read(input_pipe, &mut buf), Ok(1)) {
+                    break;
+                }
+                let input_len = self.header().input_len;
+                next_input = Some(
+                    postcard::from_bytes(
+                        &self.shmem.as_slice()[FORK_INPUT_OFFSET..FORK_INPUT_OFFSET + input_len],
+                    )
+                    .expect("Failed to receive the input"),
+                );
+            }
+            let input = next_input.as_ref().unwrap_or(first_input);
+            if exec > 0 {
+                // The observers of the parent were reset for this input, not those of the child
+                self.observers
+                    .pre_exec_all(state, input)
+                    .expect("Failed to run pre_exec on observers");
+            }
+
+            let exit_kind = self.run_in_child(state, input);
+            unsafe {
+                FORK_CHILD_DATA.share_asan_errors();
+                FORK_CHILD_DATA.share(exit_kind);
+            }
+            if write(done_pipe, &[0]).is_err() || exit_kind != ExitKind::Ok {
+                break;
+            }
+        }
+        unsafe { libc::_exit(0) }
+    }
+
+    /// Runs `input` with the stalker, in the child
+    fn run_in_child(&mut self, state: &mut S, input: &S::Input) -> ExitKind {
+        self.observers
+            .pre_exec_child_all(state, input)
+            .expect("Failed to run pre_exec on observers");
+        self.helper
+            .pre_exec(input)
+            .expect("Failed to run pre_exec on the runtimes");
+        if self.helper.stalker_enabled() {
+            if self.followed {
+                self.stalker.activate(NativePointer(null_mut()));
+            } else {
+                self.followed = true;
+                let transformer = self.helper.transformer(self.gum);
+                self.stalker.follow_me::<NoneEventSink>(&transformer, None);
+            }
+        }
+        set_child_timer(self.timeout);
+        let mut exit_kind = (self.harness_fn)(input);
+        set_child_timer(None);
+        if self.helper.stalker_enabled() {
+            self.stalker.deactivate();
+        }
+        unsafe {
+            if ASAN_ERRORS.is_some() && !ASAN_ERRORS.as_ref().unwrap().is_empty() {
+                exit_kind = ExitKind::Crash;
+            }
+        }
+        self.helper
+            .post_exec(input)
+            .expect("Failed to run post_exec on the runtimes");
+        self.observers
+            .post_exec_child_all(state, input, &exit_kind)
+            .expect("Failed to run post_exec on observers");
+        exit_kind
+    }
+
+    /// Waits for the child to finish the run, or to die
+    fn wait_for_child(&mut self) -> Result<ExitKind, Error> {
+        let child = self.child.as_ref().unwrap();
+        // The child stops itself at the timeout, and is killed if it hangs past the grace period
+        let timeout = self.timeout.map_or(-1, |timeout| {
+            i32::try_from((timeout + FORK_TIMEOUT_GRACE).as_millis()).unwrap_or(i32::MAX)
+        });
+        let mut fds = [PollFd::new(child.done_pipe, PollFlags::POLLIN)];
+        let ready = loop {
+            match poll(&mut fds, timeout) {
+                Err(Errno::EINTR) => continue,
+                res => break res?,
+            }
+        };
+        if ready == 0 {
+            kill(child.pid, NixSignal::SIGKILL)?;
+            self.stop_child()?;
+            return Ok(ExitKind::Timeout);
+        }
+
+        let mut buf = [0];
+        if matches!(read(child.done_pipe, &mut buf), Ok(1)) {
+            let exit_kind = exit_kind_from_u8(self.header().exit_kind);
+            // The child exits after a failed run
+            if exit_kind != ExitKind::Ok {
+                self.stop_child()?;
+            }
+            return Ok(exit_kind);
+        }
+
+        // The child died during the run, and shared it if it stopped from its crash or timeout
+        // handler
+        let status = self.stop_child()?;
+        if self.header().shared {
+            return Ok(exit_kind_from_u8(self.header().exit_kind));
+        }
+        match status {
+            Some(WaitStatus::Signaled(_, _, _)) => Ok(ExitKind::Crash),
+            Some(WaitStatus::Exited(_, code)) if code > 128 && code < 160 => {
+                // Signal exit codes, from the crash handler
+                Ok(ExitKind::Crash)
+            }
+            _ => Ok(ExitKind::Ok),
+        }
+    }
+
+    /// Stops the current child, if any, and reaps it
+    fn stop_child(&mut self) -> Result<Option<WaitStatus>, Error> {
+        if let Some(child) = self.child.take() {
+            // A waiting child exits when its input pipe is closed
+            close(child.input_pipe)?;
+            close(child.done_pipe)?;
+            Ok(Some(waitpid(child.pid, None)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Copies the maps and the ASan errors shared by the child into the parent
+    fn collect_results(&mut self) -> Result<(), Error> {
+        if !self.header().shared {
+            return Ok(());
+        }
+        let shared = self.shmem.as_slice();
+        let mut offset = FORK_MAPS_OFFSET;
+        for &(map, len) in &self.maps {
+            unsafe { ptr::copy_nonoverlapping(shared.as_ptr().add(offset), map, len) };
+            offset += len;
+        }
+        if let Some(offset) = self.cmplog_offset {
+            unsafe {
+                (*CMPLOG_MAP_PTR).copy_hits_from(&*shared.as_ptr().add(offset).cast::<CmpLogMap>());
+            }
+        }
+        let asan_errors_len = self.header().asan_errors_len;
+        if asan_errors_len > 0 {
+            let errors: AsanErrors = postcard::from_bytes(
+                &self.shmem.as_slice()
+                    [FORK_ASAN_ERRORS_OFFSET..FORK_ASAN_ERRORS_OFFSET + asan_errors_len],
+            )?;
+            unsafe { ASAN_ERRORS = Some(errors) };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> Drop for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+{
+    fn drop(&mut self) {
+        if let Some(child) = self.child.take() {
+            let _ = close(child.input_pipe);
+            let _ = close(child.done_pipe);
+            let _ = waitpid(child.pid, None);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> UsesObservers
+    for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    OT: ObserversTuple<S>,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    SP: ShMemProvider,
+{
+    type Observers = OT;
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> UsesState
+    for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    OT: ObserversTuple<S>,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    SP: ShMemProvider,
+{
+    type State = S;
+}
+
+#[cfg(unix)]
+impl<'a, 'b, 'c, H, OT, RT, S, SP> HasObservers
+    for FridaInProcessForkExecutor<'a, 'b, 'c, H, OT, RT, S, SP>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S::Input: HasTargetBytes,
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+{
+    #[inline]
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use core::time::Duration;
+    use std::thread;
+
+    use backtrace::Backtrace;
+    use clap::Parser;
+    use frida_gum::Gum;
+    use libafl::{
+        bolts::{
+            cli::FuzzerOptions,
+            rands::StdRand,
+            shmem::{ShMemProvider, StdShMemProvider},
+            tuples::tuple_list,
+            AsSlice,
+        },
+        corpus::InMemoryCorpus,
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasTargetBytes},
+        schedulers::QueueScheduler,
+        state::StdState,
+        StdFuzzer,
+    };
+    use serial_test::serial;
+
+    use super::FridaInProcessForkExecutor;
+    use crate::{
+        asan::errors::{AsanError, AsanErrors, ASAN_ERRORS},
+        helper::FridaInstrumentationHelper,
+    };
+
+    #[test]
+    #[serial]
+    fn test_fork_executor_exit_kinds() {
+        let gum = Gum::obtain();
+        let exe = std::env::current_exe().unwrap();
+        let options =
+            FuzzerOptions::parse_from(["test", "-H", exe.to_str().unwrap(), "--disable-coverage"]);
+        let mut helper = FridaInstrumentationHelper::new(&gum, &options, tuple_list!());
+        unsafe { ASAN_ERRORS = Some(AsanErrors::new(options.clone())) };
+
+        let mut harness = |input: &BytesInput| {
+            let target = input.target_bytes();
+            match target.as_slice().first() {
+                Some(b'c') => unsafe {
+                    libc::raise(libc::SIGSEGV);
+                },
+                Some(b't') => loop {
+                    thread::sleep(Duration::from_millis(10));
+                },
+                // Reports an ASan error, which panics
+                Some(b'a') => AsanErrors::get_mut()
+                    .report_error(AsanError::UnallocatedFree((0x1234, Backtrace::new()))),
+                _ => {}
+            }
+            ExitKind::Ok
+        };
+
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let mut fuzzer: StdFuzzer<_, _, _, ()> = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+
+        let mut executor = FridaInProcessForkExecutor::new(
+            &gum,
+            &mut harness,
+            tuple_list!(),
+            &mut helper,
+            StdShMemProvider::new().unwrap(),
+        )
+        .unwrap()
+        .with_execs_per_fork(4)
+        .with_timeout(Duration::from_millis(200));
+
+        macro_rules! run {
+            ($input:expr) => {
+                executor
+                    .run_target(
+                        &mut fuzzer,
+                        &mut state,
+                        &mut mgr,
+                        &BytesInput::new($input.to_vec()),
+                    )
+                    .unwrap()
+            };
+        }
+
+        assert_eq!(run!(b"ok"), ExitKind::Ok);
+        let pid = executor.child.as_ref().unwrap().pid;
+        assert_eq!(run!(b"ok"), ExitKind::Ok);
+        assert_eq!(executor.child.as_ref().unwrap().pid, pid);
+
+        // The crashing child is replaced by a new one
+        assert_eq!(run!(b"crash"), ExitKind::Crash);
+        assert!(executor.child.is_none());
+        assert_eq!(run!(b"ok"), ExitKind::Ok);
+
+        // The child shares the run from its timeout handler
+        assert_eq!(run!(b"timeout"), ExitKind::Timeout);
+        assert!(executor.header().shared);
+        assert_eq!(run!(b"ok"), ExitKind::Ok);
+
+        // The child shares the ASan errors and the crash from its panic hook
+        assert_eq!(run!(b"asan"), ExitKind::Crash);
+        assert!(executor.header().shared);
+        assert_eq!(unsafe { ASAN_ERRORS.as_ref() }.unwrap().len(), 1);
+        unsafe { ASAN_ERRORS = None };
+    }
+}
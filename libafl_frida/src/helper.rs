@@ -26,7 +26,10 @@ use libafl_targets::drcov::DrCovBasicBlock;
 use nix::sys::mman::{mmap, MapFlags, ProtFlags};
 use rangemap::RangeMap;
 
-#[cfg(all(feature = "cmplog", target_arch = "aarch64"))]
+#[cfg(all(
+    feature = "cmplog",
+    any(target_arch = "aarch64", all(target_arch = "x86_64", unix))
+))]
 use crate::cmplog_rt::CmpLogRuntime;
 use crate::coverage_rt::CoverageRuntime;
 #[cfg(unix)]
@@ -294,6 +297,7 @@ where
                         first = false;
                         //println!("block @ {:x} transformed to {:x}", address, output.writer().pc());
                         if let Some(rt) = self.runtime_mut::<CoverageRuntime>() {
+                            rt.emit_return_site(address, &output);
                             rt.emit_coverage_mapping(address, &output);
                         }
 
@@ -311,6 +315,10 @@ where
                         }
                     }
 
+                    if let Some(rt) = self.runtime_mut::<CoverageRuntime>() {
+                        rt.emit_call_site(address, &instruction, &output);
+                    }
+
                     #[cfg(unix)]
                     let res = if let Some(_rt) = self.runtime::<AsanRuntime>() {
                         AsanRuntime::asan_is_interesting_instruction(&self.capstone, address, instr)
@@ -357,6 +365,19 @@ where
                         }
                     }
 
+                    #[cfg(all(feature = "cmplog", target_arch = "x86_64", unix))]
+                    if let Some(rt) = self.runtime::<CmpLogRuntime>() {
+                        if let Some((op1, op2, size)) =
+                            CmpLogRuntime::cmplog_is_interesting_instruction(
+                                &self.capstone,
+                                address,
+                                instr,
+                            )
+                        {
+                            rt.emit_comparison_handling(address, &instruction, op1, op2, size);
+                        }
+                    }
+
                     #[cfg(unix)]
                     if let Some(rt) = self.runtime_mut::<AsanRuntime>() {
                         rt.add_stalked_address(
@@ -409,6 +430,13 @@ where
             .map(CoverageRuntime::map_ptr_mut)
     }
 
+    /// The pointer and the length of the coverage map of each instrumented module, see
+    /// [`CoverageRuntime::module_map_ptrs_mut`]
+    pub fn module_map_ptrs_mut(&mut self) -> Option<Vec<(*mut u8, usize)>> {
+        self.runtime_mut::<CoverageRuntime>()
+            .map(CoverageRuntime::module_map_ptrs_mut)
+    }
+
     /// Ranges
     pub fn ranges(&self) -> &RangeMap<usize, (u16, String)> {
         &self.ranges
@@ -89,7 +89,10 @@ pub mod helper;
 
 pub mod drcov_rt;
 
-/// The frida executor
+/// The hook-and-loop persistent mode
+pub mod persistent_rt;
+
+/// The frida executors, in-process and fork
 pub mod executor;
 
 /// Utilities
@@ -194,10 +197,10 @@ impl FridaOptions {
                     }
                     "cmplog" => {
                         options.enable_cmplog = value.parse().unwrap();
-                        #[cfg(not(target_arch = "aarch64"))]
+                        #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
                         assert!(
                             !options.enable_cmplog,
-                            "cmplog is not currently supported on targets other than aarch64"
+                            "cmplog is not currently supported on targets other than aarch64 and x86_64"
                         );
 
                         if options.enable_cmplog {
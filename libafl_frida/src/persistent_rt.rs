@@ -0,0 +1,241 @@
+//! The hook-and-loop persistent mode, for functions of closed-source libraries that cannot be
+//! called directly by the harness.
+//!
+//! The [`PersistentRuntime`] intercepts the function given by `--persistent-function`. When the
+//! target calls it during an execution, the input is injected into the pointer and length
+//! arguments given by `--persistent-args`, and the function is run `--persistent-iterations`
+//! times, with its arguments restored before each run. The arguments of the first call are saved,
+//! so that the harness can also run the function directly with [`PersistentRuntime::replay`].
+use core::{ffi::c_void, ptr};
+
+use frida_gum::{interceptor::Interceptor, Gum, NativePointer};
+use libafl::{
+    bolts::{cli::FuzzerOptions, AsSlice},
+    executors::ExitKind,
+    inputs::{HasTargetBytes, Input},
+    Error,
+};
+use rangemap::RangeMap;
+
+#[cfg(unix)]
+use crate::asan::errors::ASAN_ERRORS;
+use crate::{helper::FridaRuntime, utils::function_address};
+
+/// The number of integer arguments saved and restored by the persistent mode; the floating point
+/// and stack arguments are not
+pub const PERSISTENT_ARGS: usize = 8;
+
+type PersistentFunction =
+    unsafe extern "C" fn(usize, usize, usize, usize, usize, usize, usize, usize) -> usize;
+
+/// The state of the hook, boxed so that it does not move with the runtime
+#[derive(Debug, Default)]
+struct PersistentHook {
+    original: Option<PersistentFunction>,
+    saved_args: Option<[usize; PERSISTENT_ARGS]>,
+    input_args: Vec<(usize, usize)>,
+    iterations: usize,
+    input: Vec<u8>,
+    buffer: Vec<u8>,
+    running: bool,
+    looping: bool,
+    /// How the iterations of the current execution finished, if not [`ExitKind::Ok`]
+    exit_kind: Option<ExitKind>,
+}
+
+/// The hook of the [`PersistentRuntime`], for [`PersistentRuntime::replay`]
+static mut PERSISTENT_HOOK: *mut PersistentHook = ptr::null_mut();
+
+/// How the last iteration finished, a crash if it reported ASAN errors
+fn iteration_exit_kind() -> ExitKind {
+    #[cfg(unix)]
+    unsafe {
+        if ASAN_ERRORS
+            .as_ref()
+            .map_or(false, |errors| !errors.is_empty())
+        {
+            return ExitKind::Crash;
+        }
+    }
+    ExitKind::Ok
+}
+
+impl PersistentHook {
+    /// Runs the original function on the input, restoring the arguments before each iteration.
+    /// Stops at the first iteration that does not exit with [`ExitKind::Ok`], and records it.
+    unsafe fn run(&mut self, mut args: [usize; PERSISTENT_ARGS]) -> usize {
+        let original = self
+            .original
+            .expect("The persistent function is not hooked yet");
+        // Recursive calls, and the calls out of the executions, go to the original function
+        if !self.running || self.looping {
+            return original(
+                args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
+            );
+        }
+
+        self.looping = true;
+        let mut ret = 0;
+        for _ in 0..self.iterations {
+            // The function may modify the input in place
+            self.buffer.clear();
+            self.buffer.extend_from_slice(&self.input);
+            for &(pointer, length) in &self.input_args {
+                args[pointer] = self.buffer.as_mut_ptr() as usize;
+                args[length] = self.buffer.len();
+            }
+            ret = original(
+                args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
+            );
+            let exit_kind = iteration_exit_kind();
+            if exit_kind != ExitKind::Ok {
+                self.exit_kind = Some(exit_kind);
+                break;
+            }
+        }
+        self.looping = false;
+        ret
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn persistent_replacement(
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+) -> usize {
+    let mut invocation = Interceptor::current_invocation();
+    let hook = &mut *(invocation.replacement_data().unwrap().0 as *mut PersistentHook);
+    let args = [a0, a1, a2, a3, a4, a5, a6, a7];
+    if hook.saved_args.is_none() && !hook.looping {
+        hook.saved_args = Some(args);
+    }
+    hook.run(args)
+}
+
+/// The hook-and-loop persistent mode, configured by the persistent options of [`FuzzerOptions`].
+///
+/// Only the first [`PERSISTENT_ARGS`] integer arguments are saved and restored, which covers the
+/// integer register arguments on `x86_64` and `aarch64`. The floating point and vector argument
+/// registers (`xmm0`-`xmm7`, `v0`-`v7`) and the stack arguments are neither saved nor restored, so
+/// the functions taking such arguments are not supported. The arguments saved for
+/// [`PersistentRuntime::replay`] must stay valid after the first call, e.g. point to globals or
+/// to the heap.
+#[derive(Debug)]
+pub struct PersistentRuntime {
+    function: (String, String),
+    hook: Box<PersistentHook>,
+}
+
+impl FridaRuntime for PersistentRuntime {
+    /// Hooks the persistent function
+    fn init(
+        &mut self,
+        gum: &Gum,
+        _ranges: &RangeMap<usize, (u16, String)>,
+        _modules_to_instrument: &[&str],
+    ) {
+        let address = self.function_address();
+        let mut interceptor = Interceptor::obtain(gum);
+        let original = interceptor
+            .replace(
+                NativePointer(address as *mut c_void),
+                NativePointer(persistent_replacement as *mut c_void),
+                NativePointer(ptr::addr_of_mut!(*self.hook).cast::<c_void>()),
+            )
+            .expect("Failed to hook the persistent function");
+        unsafe {
+            self.hook.original = Some(core::mem::transmute::<*mut c_void, PersistentFunction>(
+                original.0,
+            ));
+            PERSISTENT_HOOK = ptr::addr_of_mut!(*self.hook);
+        }
+    }
+
+    /// Stages the input for the calls to the persistent function
+    fn pre_exec<I: Input + HasTargetBytes>(&mut self, input: &I) -> Result<(), Error> {
+        self.hook.input.clear();
+        self.hook
+            .input
+            .extend_from_slice(input.target_bytes().as_slice());
+        self.hook.exit_kind = None;
+        self.hook.running = true;
+        Ok(())
+    }
+
+    /// Lets the next calls out of the executions through
+    fn post_exec<I: Input + HasTargetBytes>(&mut self, _input: &I) -> Result<(), Error> {
+        self.hook.running = false;
+        Ok(())
+    }
+}
+
+impl PersistentRuntime {
+    /// Creates a new [`PersistentRuntime`] from the persistent options
+    #[must_use]
+    pub fn new(options: &FuzzerOptions) -> Self {
+        let function = options
+            .persistent_function
+            .clone()
+            .expect("The persistent mode needs --persistent-function");
+        for &(pointer, length) in &options.persistent_args {
+            assert!(
+                pointer < PERSISTENT_ARGS && length < PERSISTENT_ARGS,
+                "The persistent mode injects the input into the first {PERSISTENT_ARGS} arguments only"
+            );
+        }
+        Self {
+            function,
+            hook: Box::new(PersistentHook {
+                input_args: options.persistent_args.clone(),
+                iterations: options.persistent_iterations.max(1),
+                ..PersistentHook::default()
+            }),
+        }
+    }
+
+    /// The address of the persistent function, from the export or the offset in the module
+    #[must_use]
+    pub fn function_address(&self) -> usize {
+        let (module, target) = &self.function;
+        function_address(module, target).expect("Failed to find the persistent function")
+    }
+
+    /// If the arguments of the first call to the persistent function were saved
+    #[must_use]
+    pub fn has_saved_args(&self) -> bool {
+        self.hook.saved_args.is_some()
+    }
+
+    /// How the calls to the persistent function in the current execution finished, for the
+    /// harnesses that let the target call it
+    #[must_use]
+    pub fn exit_kind(&self) -> ExitKind {
+        self.hook.exit_kind.unwrap_or(ExitKind::Ok)
+    }
+
+    /// Runs the persistent function with the saved arguments and the current input, to be called
+    /// by the harness instead of the code that calls the function in the target.
+    /// Returns how the iterations finished, e.g. [`ExitKind::Crash`] if one reported ASAN errors.
+    ///
+    /// # Panics
+    /// Panics if the target did not call the persistent function yet.
+    #[must_use]
+    pub fn replay() -> ExitKind {
+        unsafe {
+            let hook = PERSISTENT_HOOK
+                .as_mut()
+                .expect("The persistent function is not hooked yet");
+            let args = hook
+                .saved_args
+                .expect("The target did not call the persistent function yet");
+            hook.run(args);
+            hook.exit_kind.unwrap_or(ExitKind::Ok)
+        }
+    }
+}
@@ -7,6 +7,7 @@ use capstone::{
 use frida_gum::instruction_writer::Aarch64Register;
 #[cfg(target_arch = "x86_64")]
 use frida_gum::instruction_writer::X86Register;
+use frida_gum::{Module, ModuleDetails};
 #[cfg(target_arch = "aarch64")]
 use num_traits::cast::FromPrimitive;
 
@@ -132,3 +133,16 @@ pub fn writer_register(reg: capstone::RegId) -> X86Register {
         _ => X86Register::None, // Ignore Xax..Xip
     }
 }
+
+/// The address of the function `target` of `module`, given as an export name or as an offset in
+/// the module, e.g. the function locations of the `FuzzerOptions`
+#[must_use]
+pub fn function_address(module: &str, target: &str) -> Option<usize> {
+    if let Some(offset) = target.strip_prefix("0x") {
+        let offset = usize::from_str_radix(offset, 16).ok()?;
+        let module_details = ModuleDetails::with_name(module.to_string())?;
+        Some(module_details.range().base_address().0 as usize + offset)
+    } else {
+        Module::find_export_by_name(Some(module), target).map(|address| address.0 as usize)
+    }
+}
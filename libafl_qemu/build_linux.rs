@@ -28,6 +28,12 @@ pub fn build() {
         "i386".to_string()
     } else if cfg!(feature = "mips") {
         "mips".to_string()
+    } else if cfg!(feature = "riscv32") {
+        "riscv32".to_string()
+    } else if cfg!(feature = "riscv64") {
+        "riscv64".to_string()
+    } else if cfg!(feature = "ppc") {
+        "ppc".to_string()
     } else {
         env::var("CPU_TARGET").unwrap_or_else(|_| {
             "x86_64".to_string()
@@ -4,6 +4,8 @@ use pyo3::prelude::*;
 pub use strum_macros::EnumIter;
 pub use syscall_numbers::aarch64::*;
 
+use crate::{ArchExtras, GuestAddr, CPU};
+
 #[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, EnumIter)]
 #[repr(i32)]
 pub enum Regs {
@@ -50,6 +52,42 @@ impl Regs {
     pub const Lr: Regs = Regs::X30;
 }
 
+/// The registers of the integer arguments of a function, in the calling convention of the platform
+pub const ARGUMENT_REGS: [Regs; 8] = [
+    Regs::X0,
+    Regs::X1,
+    Regs::X2,
+    Regs::X3,
+    Regs::X4,
+    Regs::X5,
+    Regs::X6,
+    Regs::X7,
+];
+
+impl ArchExtras for CPU {
+    fn read_return_address(&self) -> Result<GuestAddr, String> {
+        self.read_reg(Regs::Lr)
+    }
+
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String> {
+        self.write_reg(Regs::Lr, val)
+    }
+
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.read_reg(*reg)
+    }
+
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.write_reg(*reg, val)
+    }
+}
+
 #[cfg(feature = "python")]
 impl IntoPy<PyObject> for Regs {
     fn into_py(self, py: Python) -> PyObject {
@@ -1,13 +1,54 @@
+use core::{
+    hash::{Hash, Hasher},
+    ptr,
+};
+use std::collections::hash_map::DefaultHasher;
+
 use capstone::prelude::*;
-use libafl::inputs::UsesInput;
+use libafl::{
+    bolts::{ownedref::OwnedRefMut, tuples::Named},
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{
+        CrashSignature, HarnessType, Observer, ObserverWithCrashSignature, ObserverWithHashField,
+        DEFAULT_SIGNATURE_FRAMES,
+    },
+    Error,
+};
+use serde::{Deserialize, Serialize};
 
+#[cfg(emulation_mode = "usermode")]
+use crate::elf::mapped_files;
 use crate::{
     capstone,
-    helper::{QemuHelper, QemuHelperTuple, QemuInstrumentationFilter},
+    elf::EasyElf,
+    helper::{hash_me, QemuHelper, QemuHelperTuple, QemuInstrumentationFilter},
     hooks::QemuHooks,
-    Emulator, GuestAddr, Regs,
+    ArchExtras, Emulator, GuestAddr,
 };
 
+/// The callstack of the [`QemuCallTracerHelper`], read by the [`QemuCallstackObserver`]
+static mut CALLSTACK_PTR: *const Vec<GuestAddr> = ptr::null();
+
+/// The xor of the [`context_entry`] of the return addresses in the callstack, the calling
+/// context of the context-sensitive edge coverage
+pub(crate) static mut CALLSTACK_CTX: u64 = 0;
+
+/// The part of the calling context of the return address `addr` at `depth` in the callstack.
+///
+/// The depth is hashed along with the address, otherwise the return addresses pushed by a
+/// direct recursion would be equal, and cancel each other in the xor.
+#[must_use]
+pub(crate) fn context_entry(addr: GuestAddr, depth: usize) -> u64 {
+    hash_me(u64::from(addr) ^ ((depth as u64) << 48))
+}
+
+/// Tracks the callstack of the target, hooking the calls and the returns found in each block.
+///
+/// On PowerPC and RISC-V, where the calls and the returns are plain branches that capstone does
+/// not always put in its call and return groups, they are told apart by their mnemonic.
+/// A return pops the callstack down to its return address, and leaves it as is if the address
+/// is not in the callstack, so that a missed call does not empty it.
 #[derive(Debug)]
 pub struct QemuCallTracerHelper {
     filter: QemuInstrumentationFilter,
@@ -37,6 +78,9 @@ impl QemuCallTracerHelper {
 
     pub fn reset(&mut self) {
         self.callstack.clear();
+        unsafe {
+            CALLSTACK_CTX = 0;
+        }
     }
 }
 
@@ -54,6 +98,10 @@ where
     where
         QT: QemuHelperTuple<S>,
     {
+        // The helpers are boxed in the hooks, so the callstack does not move anymore
+        unsafe {
+            CALLSTACK_PTR = &self.callstack;
+        }
         hooks.blocks(Some(gen_blocks_calls::<QT, S>), None);
     }
 
@@ -74,36 +122,13 @@ where
     S: UsesInput,
     QT: QemuHelperTuple<S>,
 {
-    #[cfg(cpu_target = "x86_64")]
-    let ret_addr = {
-        let emu = hooks.emulator();
-        let stack_ptr: GuestAddr = emu.read_reg(Regs::Rsp).unwrap();
-        let mut ret_addr = [0; 8];
-        unsafe { emu.read_mem(stack_ptr, &mut ret_addr) };
-        GuestAddr::from_le_bytes(ret_addr)
-    };
-
-    #[cfg(cpu_target = "i386")]
-    let ret_addr = {
-        let emu = hooks.emulator();
-        let stack_ptr: GuestAddr = emu.read_reg(Regs::Esp).unwrap();
-        let mut ret_addr = [0; 4];
-        unsafe { emu.read_mem(stack_ptr, &mut ret_addr) };
-        GuestAddr::from_le_bytes(ret_addr)
-    };
-
-    #[cfg(any(cpu_target = "arm", cpu_target = "aarch64"))]
-    let ret_addr = {
-        let emu = hooks.emulator();
-        let ret_addr: GuestAddr = emu.read_reg(Regs::Lr).unwrap();
-        ret_addr
-    };
-
-    #[cfg(cpu_target = "mips")]
-    let ret_addr = {
-        let emu = hooks.emulator();
-        let ret_addr: GuestAddr = emu.read_reg(Regs::Ra).unwrap();
-        ret_addr
+    // Without the return address, the callstack is left as is rather than emptied
+    let Some(ret_addr) = hooks
+        .emulator()
+        .current_cpu()
+        .and_then(|cpu| cpu.read_return_address().ok())
+    else {
+        return;
     };
 
     // eprintln!("RET @ 0x{:#x}", ret_addr);
@@ -112,9 +137,13 @@ where
         .helpers_mut()
         .match_first_type_mut::<QemuCallTracerHelper>()
     {
-        while let Some(addr) = h.callstack.pop() {
-            if addr == ret_addr {
-                break;
+        let Some(frame) = h.callstack.iter().rposition(|addr| *addr == ret_addr) else {
+            return;
+        };
+        while h.callstack.len() > frame {
+            let addr = h.callstack.pop().unwrap();
+            unsafe {
+                CALLSTACK_CTX ^= context_entry(addr, h.callstack.len());
             }
         }
     }
@@ -157,8 +186,28 @@ where
             }
             let insn = insns.first().unwrap();
             let insn_detail: InsnDetail = h.cs.insn_detail(insn).unwrap();
-            for detail in insn_detail.groups() {
-                match u32::from(detail.0) {
+            #[allow(unused_mut)]
+            let mut groups: Vec<u32> = insn_detail
+                .groups()
+                .iter()
+                .map(|group| u32::from(group.0))
+                .collect();
+            #[cfg(cpu_target = "ppc")]
+            let call_or_ret = ppc_call_or_ret_group(insn);
+            #[cfg(any(cpu_target = "riscv32", cpu_target = "riscv64"))]
+            let call_or_ret = riscv_call_or_ret_group(insn);
+            #[cfg(any(cpu_target = "ppc", cpu_target = "riscv32", cpu_target = "riscv64"))]
+            {
+                groups.retain(|group| {
+                    *group != capstone::InsnGroupType::CS_GRP_CALL
+                        && *group != capstone::InsnGroupType::CS_GRP_RET
+                });
+                if let Some(group) = call_or_ret {
+                    groups.insert(0, group);
+                }
+            }
+            for group in groups {
+                match group {
                     capstone::InsnGroupType::CS_GRP_CALL => {
                         // hooks.instruction_closure(insn.address() as GuestAddr, on_call, false);
                         let call_len = insn.bytes().len() as GuestAddr;
@@ -168,6 +217,10 @@ where
                                 .helpers_mut()
                                 .match_first_type_mut::<QemuCallTracerHelper>()
                             {
+                                unsafe {
+                                    CALLSTACK_CTX ^=
+                                        context_entry(pc + call_len, h.callstack.len());
+                                }
                                 h.callstack.push(pc + call_len);
                             }
                         };
@@ -208,3 +261,406 @@ where
 
     None
 }
+
+/// The capstone group of the RISC-V calls, the jumps linking `ra`, and returns, the jumps to `ra`
+#[cfg(any(test, cpu_target = "riscv32", cpu_target = "riscv64"))]
+fn riscv_call_or_ret_group(insn: &Insn) -> Option<u32> {
+    let op_str = insn.op_str().unwrap_or_default();
+    match insn.mnemonic()? {
+        "ret" => Some(capstone::InsnGroupType::CS_GRP_RET),
+        "jr" | "c.jr" if op_str == "ra" => Some(capstone::InsnGroupType::CS_GRP_RET),
+        "jalr" if op_str.starts_with("zero,") && op_str.ends_with("(ra)") => {
+            Some(capstone::InsnGroupType::CS_GRP_RET)
+        }
+        // Without a destination register, `ra` is linked
+        "jal" | "jalr" | "c.jal" | "c.jalr"
+            if !op_str.contains(',') || op_str.starts_with("ra,") =>
+        {
+            Some(capstone::InsnGroupType::CS_GRP_CALL)
+        }
+        _ => None,
+    }
+}
+
+/// The capstone group of the PowerPC calls, the unconditional branches setting the link
+/// register, and returns, the unconditional branch to the link register.
+///
+/// The conditional variants (`beqlr`, `bnel`, `bdnzlr`, ...) are left out, the instruction hooks
+/// run even when the branch is not taken.
+#[cfg(any(test, cpu_target = "ppc"))]
+fn ppc_call_or_ret_group(insn: &Insn) -> Option<u32> {
+    match insn.mnemonic()? {
+        "blr" => Some(capstone::InsnGroupType::CS_GRP_RET),
+        "bl" | "bla" | "blrl" | "bctrl" => Some(capstone::InsnGroupType::CS_GRP_CALL),
+        _ => None,
+    }
+}
+
+/// A function symbol of the guest, used to symbolize the callstack
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QemuCallstackSymbol {
+    pub name: String,
+    pub start: GuestAddr,
+    pub end: GuestAddr,
+}
+
+/// An observer hashing the guest callstack tracked by the [`QemuCallTracerHelper`] when the
+/// target crashes, to dedup the guest crashes with a [`libafl::feedbacks::NewHashFeedback`].
+///
+/// The frames in a known function are hashed as the name of the function and the offset in it,
+/// so that the hash does not depend on where the guest ELFs are loaded.
+/// The symbols are not serialized with the observer, only the hash is needed by the feedbacks.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QemuCallstackObserver<'a> {
+    observer_name: String,
+    hash: OwnedRefMut<'a, Option<u64>>,
+    harness_type: HarnessType,
+    frames: usize,
+    #[serde(skip)]
+    symbols: Vec<QemuCallstackSymbol>,
+}
+
+impl<'a> QemuCallstackObserver<'a> {
+    /// Creates a new [`QemuCallstackObserver`] with the given name, hashing the top
+    /// [`DEFAULT_SIGNATURE_FRAMES`] frames
+    #[must_use]
+    pub fn new(
+        observer_name: &str,
+        callstack_hash: &'a mut Option<u64>,
+        harness_type: HarnessType,
+    ) -> Self {
+        Self {
+            observer_name: observer_name.to_string(),
+            hash: OwnedRefMut::Ref(callstack_hash),
+            harness_type,
+            frames: DEFAULT_SIGNATURE_FRAMES,
+            symbols: vec![],
+        }
+    }
+
+    /// Sets the number of top frames to hash, `0` to hash the whole callstack
+    #[must_use]
+    pub fn with_frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// Adds the functions of an ELF loaded at `load_addr` to the symbols
+    #[must_use]
+    pub fn with_elf(mut self, elf: &EasyElf, load_addr: GuestAddr) -> Self {
+        self.add_elf(elf, load_addr);
+        self
+    }
+
+    /// Adds the functions of all the ELFs mapped in the guest to the symbols
+    #[cfg(emulation_mode = "usermode")]
+    #[must_use]
+    pub fn with_mapped_elfs(mut self, emulator: &Emulator) -> Self {
+        for (path, load_addr) in mapped_files(emulator) {
+            let mut elf_buffer = Vec::new();
+            if let Ok(elf) = EasyElf::from_file(&path, &mut elf_buffer) {
+                self.add_elf(&elf, load_addr);
+            }
+        }
+        self
+    }
+
+    /// Adds the functions of an ELF loaded at `load_addr` to the symbols
+    pub fn add_elf(&mut self, elf: &EasyElf, load_addr: GuestAddr) {
+        for (name, range) in elf.functions(load_addr) {
+            self.symbols.push(QemuCallstackSymbol {
+                name: name.to_string(),
+                start: range.start,
+                end: range.end,
+            });
+        }
+        self.symbols.sort_by_key(|sym| sym.start);
+    }
+
+    #[must_use]
+    pub fn symbols(&self) -> &[QemuCallstackSymbol] {
+        &self.symbols
+    }
+
+    /// The function containing `addr` and the offset of `addr` in it, if known
+    #[must_use]
+    pub fn symbolize(&self, addr: GuestAddr) -> Option<(&str, GuestAddr)> {
+        let idx = self.symbols.partition_point(|sym| sym.start <= addr);
+        if idx == 0 {
+            return None;
+        }
+        let sym = &self.symbols[idx - 1];
+        if addr < sym.end {
+            Some((&sym.name, addr - sym.start))
+        } else {
+            None
+        }
+    }
+
+    /// Hashes the top frames of the callstack, the innermost last
+    #[must_use]
+    pub fn callstack_hash(&self, callstack: &[GuestAddr]) -> u64 {
+        let frames = if self.frames == 0 {
+            callstack.len()
+        } else {
+            self.frames
+        };
+        let mut hasher = DefaultHasher::new();
+        for addr in callstack.iter().rev().take(frames) {
+            if let Some((name, offset)) = self.symbolize(*addr) {
+                name.hash(&mut hasher);
+                offset.hash(&mut hasher);
+            } else {
+                addr.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn update_callstack_hash(&mut self, exit_kind: &ExitKind) {
+        if exit_kind == &ExitKind::Crash {
+            let hash =
+                unsafe { CALLSTACK_PTR.as_ref() }.map(|callstack| self.callstack_hash(callstack));
+            *self.hash.as_mut() = hash;
+        } else {
+            self.clear_hash();
+        }
+    }
+}
+
+impl<'a> ObserverWithHashField for QemuCallstackObserver<'a> {
+    /// Gets the hash value of this observer.
+    fn hash(&self) -> &Option<u64> {
+        self.hash.as_ref()
+    }
+
+    /// Updates the hash value of this observer.
+    fn update_hash(&mut self, hash: u64) {
+        *self.hash.as_mut() = Some(hash);
+    }
+
+    /// Clears the current hash value
+    fn clear_hash(&mut self) {
+        *self.hash.as_mut() = None;
+    }
+}
+
+impl<'a, S> Observer<S> for QemuCallstackObserver<'a>
+where
+    S: UsesInput,
+{
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        if self.harness_type == HarnessType::InProcess {
+            self.update_callstack_hash(exit_kind);
+        }
+        Ok(())
+    }
+
+    fn post_exec_child(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        if self.harness_type == HarnessType::Child {
+            self.update_callstack_hash(exit_kind);
+        }
+        Ok(())
+    }
+}
+
+impl ObserverWithCrashSignature for QemuCallstackObserver<'_> {
+    /// The signature of the last crash; QEMU does not know the error class
+    fn crash_signature(&self) -> Option<CrashSignature> {
+        self.hash().map(|stack_hash| CrashSignature {
+            stack_hash,
+            error_class: "crash".to_string(),
+            pc: None,
+            pc_offset: None,
+        })
+    }
+}
+
+impl<'a> Named for QemuCallstackObserver<'a> {
+    fn name(&self) -> &str {
+        &self.observer_name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capstone::prelude::*;
+    use libafl::observers::HarnessType;
+
+    use super::{
+        ppc_call_or_ret_group, riscv_call_or_ret_group, QemuCallstackObserver, QemuCallstackSymbol,
+    };
+    use crate::GuestAddr;
+
+    const CALL: Option<u32> = Some(capstone::InsnGroupType::CS_GRP_CALL);
+    const RET: Option<u32> = Some(capstone::InsnGroupType::CS_GRP_RET);
+
+    /// Classifies the single instruction encoded in `code`
+    fn classify(
+        cs: &Capstone,
+        code: &[u8],
+        group_of: fn(&capstone::Insn) -> Option<u32>,
+    ) -> Option<u32> {
+        let insns = cs.disasm_count(code, 0x1000, 1).unwrap();
+        group_of(insns.first().unwrap())
+    }
+
+    #[test]
+    fn test_ppc_call_or_ret_group() {
+        let cs = Capstone::new()
+            .ppc()
+            .mode(capstone::arch::ppc::ArchMode::Mode32)
+            .endian(capstone::Endian::Big)
+            .build()
+            .unwrap();
+        let group = |insn: u32| classify(&cs, &insn.to_be_bytes(), ppc_call_or_ret_group);
+
+        // blr, bl, bla, blrl and bctrl
+        assert_eq!(group(0x4e80_0020), RET);
+        assert_eq!(group(0x4800_0011), CALL);
+        assert_eq!(group(0x4800_0103), CALL);
+        assert_eq!(group(0x4e80_0021), CALL);
+        assert_eq!(group(0x4e80_0421), CALL);
+        // b and bctr do not link
+        assert_eq!(group(0x4800_0010), None);
+        assert_eq!(group(0x4e80_0420), None);
+        // The conditional beqlr, bnelr, bdnzlr, beql and beqlrl may not be taken
+        assert_eq!(group(0x4d82_0020), None);
+        assert_eq!(group(0x4c82_0020), None);
+        assert_eq!(group(0x4e00_0020), None);
+        assert_eq!(group(0x4182_0011), None);
+        assert_eq!(group(0x4d82_0021), None);
+        // addi r3, r3, 1
+        assert_eq!(group(0x3863_0001), None);
+    }
+
+    #[test]
+    fn test_riscv_call_or_ret_group() {
+        for mode in [
+            capstone::arch::riscv::ArchMode::RiscV32,
+            capstone::arch::riscv::ArchMode::RiscV64,
+        ] {
+            let cs = Capstone::new()
+                .riscv()
+                .mode(mode)
+                .extra_mode(
+                    [capstone::arch::riscv::ArchExtraMode::RiscVC]
+                        .iter()
+                        .copied(),
+                )
+                .build()
+                .unwrap();
+            let group = |insn: u32| classify(&cs, &insn.to_le_bytes(), riscv_call_or_ret_group);
+            let compressed_group =
+                |insn: u16| classify(&cs, &insn.to_le_bytes(), riscv_call_or_ret_group);
+
+            // ret, c.jr ra
+            assert_eq!(group(0x0000_8067), RET);
+            assert_eq!(compressed_group(0x8082), RET);
+            // jal ra, 16 and jalr ra, 0(a5)
+            assert_eq!(group(0x0100_00ef), CALL);
+            assert_eq!(group(0x0007_80e7), CALL);
+            // j 16 and jr a5 do not link
+            assert_eq!(group(0x0100_006f), None);
+            assert_eq!(group(0x0007_8067), None);
+            // addi a0, a0, 1
+            assert_eq!(group(0x0015_0513), None);
+        }
+    }
+
+    #[test]
+    fn test_x86_64_call_and_ret_groups() {
+        // Capstone puts the x86 calls and returns in its groups, no mnemonic is needed
+        let cs = Capstone::new()
+            .x86()
+            .mode(capstone::arch::x86::ArchMode::Mode64)
+            .detail(true)
+            .build()
+            .unwrap();
+        let groups = |code: &[u8]| -> Vec<u32> {
+            let insns = cs.disasm_count(code, 0x1000, 1).unwrap();
+            let detail = cs.insn_detail(insns.first().unwrap()).unwrap();
+            detail
+                .groups()
+                .iter()
+                .map(|group| u32::from(group.0))
+                .collect()
+        };
+
+        assert!(groups(&[0xe8, 0, 0, 0, 0]).contains(&CALL.unwrap()));
+        assert!(groups(&[0xff, 0xd0]).contains(&CALL.unwrap()));
+        assert!(groups(&[0xc3]).contains(&RET.unwrap()));
+        let jmp = groups(&[0xeb, 0]);
+        assert!(!jmp.contains(&CALL.unwrap()) && !jmp.contains(&RET.unwrap()));
+    }
+
+    fn observer_at(hash: &mut Option<u64>, load_addr: GuestAddr) -> QemuCallstackObserver<'_> {
+        let mut observer = QemuCallstackObserver::new("callstack", hash, HarnessType::InProcess);
+        for (name, start, end) in [("foo", 0x1000, 0x1100), ("bar", 0x2000, 0x2080)] {
+            observer.symbols.push(QemuCallstackSymbol {
+                name: name.to_string(),
+                start: load_addr + start,
+                end: load_addr + end,
+            });
+        }
+        observer
+    }
+
+    #[test]
+    fn test_symbolize() {
+        let mut hash = None;
+        let observer = observer_at(&mut hash, 0);
+        assert_eq!(observer.symbolize(0x1000), Some(("foo", 0)));
+        assert_eq!(observer.symbolize(0x10ff), Some(("foo", 0xff)));
+        assert_eq!(observer.symbolize(0x2010), Some(("bar", 0x10)));
+        assert_eq!(observer.symbolize(0xfff), None);
+        assert_eq!(observer.symbolize(0x1100), None);
+        assert_eq!(observer.symbolize(0x3000), None);
+    }
+
+    #[test]
+    fn test_callstack_hash() {
+        let mut hash = None;
+        let observer = observer_at(&mut hash, 0);
+        let mut moved_hash = None;
+        let moved = observer_at(&mut moved_hash, 0x10000);
+
+        // The known frames do not depend on the load address, the unknown ones do
+        let callstack = [0x2010, 0x1020];
+        let moved_callstack = [0x12010, 0x11020];
+        assert_eq!(
+            observer.callstack_hash(&callstack),
+            moved.callstack_hash(&moved_callstack)
+        );
+        assert_ne!(
+            observer.callstack_hash(&[0x5000, 0x1020]),
+            moved.callstack_hash(&[0x15000, 0x11020])
+        );
+
+        // Only the innermost frames are hashed
+        let observer = observer.with_frames(1);
+        assert_eq!(
+            observer.callstack_hash(&[0x2010, 0x1020]),
+            observer.callstack_hash(&[0x2040, 0x1020])
+        );
+        assert_ne!(
+            observer.callstack_hash(&[0x2010, 0x1020]),
+            observer.callstack_hash(&[0x2010, 0x1024])
+        );
+        let observer = observer.with_frames(0);
+        assert_ne!(
+            observer.callstack_hash(&[0x2010, 0x1020]),
+            observer.callstack_hash(&[0x2040, 0x1020])
+        );
+    }
+}
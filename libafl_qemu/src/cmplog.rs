@@ -1,17 +1,44 @@
 use hashbrown::HashMap;
 use libafl::{inputs::UsesInput, state::HasMetadata};
+#[cfg(emulation_mode = "usermode")]
+pub use libafl_targets::cmplog::{
+    __libafl_targets_cmplog_routines, __libafl_targets_cmplog_routines_len,
+};
+#[cfg(emulation_mode = "usermode")]
+use libafl_targets::CMPLOG_RTN_LEN;
 pub use libafl_targets::{
     cmplog::__libafl_targets_cmplog_instructions, CmpLogMap, CmpLogObserver, CMPLOG_MAP,
     CMPLOG_MAP_H, CMPLOG_MAP_PTR, CMPLOG_MAP_SIZE, CMPLOG_MAP_W,
 };
 use serde::{Deserialize, Serialize};
 
+#[cfg(emulation_mode = "usermode")]
+use crate::{
+    elf::{mapped_files, EasyElf},
+    emu::ArchExtras,
+    Emulator,
+};
 use crate::{
     helper::{hash_me, QemuHelper, QemuHelperTuple, QemuInstrumentationFilter},
     hooks::QemuHooks,
     GuestAddr,
 };
 
+/// The comparison routines hooked by the [`QemuCmpLogRoutinesHelper`], with the indexes of the
+/// two compared arguments, and of the argument bounding the compared length, if any
+#[cfg(emulation_mode = "usermode")]
+pub const CMPLOG_ROUTINES: &[(&str, u8, u8, Option<u8>)] = &[
+    ("strcmp", 0, 1, None),
+    ("strncmp", 0, 1, Some(2)),
+    ("strcasecmp", 0, 1, None),
+    ("strncasecmp", 0, 1, Some(2)),
+    ("memcmp", 0, 1, Some(2)),
+    ("bcmp", 0, 1, Some(2)),
+    ("strstr", 0, 1, None),
+    ("strcasestr", 0, 1, None),
+    ("memmem", 0, 2, None),
+];
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct QemuCmpsMapMetadata {
     pub map: HashMap<u64, u64>,
@@ -115,6 +142,90 @@ where
     }
 }
 
+/// A helper logging the arguments of the comparison routines of the libc, like `strcmp` and
+/// `memcmp`, as routine entries of the [`CmpLogMap`], for [`libafl::mutators::I2SRandReplace`].
+///
+/// The routines are resolved in the binary and in the libraries mapped in the guest when the
+/// helper is created, so create it once the libraries are loaded, e.g. after running to `main`.
+/// When a routine is an indirect function (`IFUNC`), as for many string functions of glibc, the
+/// implementation is found in the GOT of the callers, run the target with `LD_BIND_NOW=1` so that
+/// the GOT is filled in before the first call.
+#[cfg(emulation_mode = "usermode")]
+#[derive(Debug)]
+pub struct QemuCmpLogRoutinesHelper {
+    filter: QemuInstrumentationFilter,
+    routines: HashMap<GuestAddr, (u8, u8, Option<u8>)>,
+}
+
+#[cfg(emulation_mode = "usermode")]
+impl QemuCmpLogRoutinesHelper {
+    /// Creates a new [`QemuCmpLogRoutinesHelper`], the `filter` applies to the callers
+    #[must_use]
+    pub fn new(emulator: &Emulator, filter: QemuInstrumentationFilter) -> Self {
+        let mut helper = Self {
+            filter,
+            routines: HashMap::new(),
+        };
+        helper.resolve_routines(emulator);
+        helper
+    }
+
+    #[must_use]
+    pub fn must_instrument(&self, addr: u64) -> bool {
+        self.filter.allowed(addr)
+    }
+
+    /// The addresses of the hooked routines, with the indexes of their compared arguments and of
+    /// their length argument
+    #[must_use]
+    pub fn routines(&self) -> &HashMap<GuestAddr, (u8, u8, Option<u8>)> {
+        &self.routines
+    }
+
+    /// Resolves the [`CMPLOG_ROUTINES`] in the ELFs mapped in the guest
+    pub fn resolve_routines(&mut self, emulator: &Emulator) {
+        for (path, load_addr) in mapped_files(emulator) {
+            let mut elf_buffer = Vec::new();
+            // Not all the mappings are ELFs, e.g. [stack]
+            if let Ok(elf) = EasyElf::from_file(&path, &mut elf_buffer) {
+                for &(name, arg0, arg1, len_arg) in CMPLOG_ROUTINES {
+                    if let Some(addr) = elf.resolve_function(name, load_addr) {
+                        self.routines.insert(addr, (arg0, arg1, len_arg));
+                    }
+                    if let Some(got) = elf.resolve_got_entry(name, load_addr) {
+                        let mut addr = [0; core::mem::size_of::<GuestAddr>()];
+                        unsafe { emulator.read_mem(got, &mut addr) };
+                        #[cfg(any(feature = "be", cpu_target = "ppc"))]
+                        let addr = GuestAddr::from_be_bytes(addr);
+                        #[cfg(not(any(feature = "be", cpu_target = "ppc")))]
+                        let addr = GuestAddr::from_le_bytes(addr);
+                        if addr != 0 {
+                            self.routines.entry(addr).or_insert((arg0, arg1, len_arg));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(emulation_mode = "usermode")]
+impl<S> QemuHelper<S> for QemuCmpLogRoutinesHelper
+where
+    S: UsesInput,
+{
+    const HOOKS_DO_SIDE_EFFECTS: bool = false;
+
+    fn first_exec<QT>(&self, hooks: &QemuHooks<'_, QT, S>)
+    where
+        QT: QemuHelperTuple<S>,
+    {
+        for addr in self.routines.keys() {
+            hooks.instruction(*addr, on_cmplog_routine::<QT, S>, true);
+        }
+    }
+}
+
 pub fn gen_unique_cmp_ids<QT, S>(
     hooks: &mut QemuHooks<'_, QT, S>,
     state: Option<&mut S>,
@@ -189,3 +300,50 @@ pub extern "C" fn trace_cmp8_cmplog(id: u64, v0: u64, v1: u64, _data: u64) {
         __libafl_targets_cmplog_instructions(id as usize, 8, v0, v1);
     }
 }
+
+/// Logs the compared arguments of a routine, with an id hashed from its call site like
+/// `__cmplog_rtn_hook` does for the compiled targets
+#[cfg(emulation_mode = "usermode")]
+pub fn on_cmplog_routine<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let emu = hooks.emulator();
+    if let Some(h) = hooks.match_helper::<QemuCmpLogRoutinesHelper>() {
+        if let Some(&(arg0, arg1, len_arg)) = h.routines.get(&pc) {
+            let cpu = emu.current_cpu().unwrap();
+            let ret_addr: GuestAddr = cpu.read_return_address().unwrap();
+            if !h.must_instrument(ret_addr.into()) {
+                return;
+            }
+
+            let ptr0: GuestAddr = cpu.read_function_argument(arg0).unwrap();
+            let ptr1: GuestAddr = cpu.read_function_argument(arg1).unwrap();
+            if ptr0 == 0 || ptr1 == 0 {
+                return;
+            }
+            // Past the length argument, the bytes are not compared, and may not be mapped
+            let len = match len_arg {
+                Some(len_arg) => cpu.read_function_argument(len_arg).unwrap() as usize,
+                None => CMPLOG_RTN_LEN,
+            };
+
+            let mut k: u64 = ret_addr.into();
+            k = (k >> 4) ^ (k << 8);
+            k &= CMPLOG_MAP_W as u64 - 1;
+
+            unsafe {
+                __libafl_targets_cmplog_routines_len(
+                    k as usize,
+                    emu.g2h::<u8>(ptr0),
+                    emu.g2h::<u8>(ptr1),
+                    len.min(CMPLOG_RTN_LEN),
+                );
+            }
+        }
+    }
+}
@@ -8,7 +8,8 @@ pub use libafl_targets::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    emu::GuestAddr,
+    calls::{QemuCallTracerHelper, CALLSTACK_CTX},
+    emu::{Emulator, GuestAddr},
     helper::{hash_me, QemuHelper, QemuHelperTuple, QemuInstrumentationFilter},
     hooks::QemuHooks,
 };
@@ -31,10 +32,28 @@ impl QemuEdgesMapMetadata {
 
 libafl::impl_serdeany!(QemuEdgesMapMetadata);
 
+/// The maximum size of the N-grams of the [`QemuEdgeCoverageMode::NGram`] mode
+pub const MAX_NGRAM_SIZE: usize = 16;
+
+/// How the [`QemuEdgeCoverageHelper`] maps the edges taken to entries of the coverage map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QemuEdgeCoverageMode {
+    /// One entry per edge
+    Plain,
+    /// One entry per edge and calling context, i.e. the hash of the return addresses in the
+    /// guest callstack and of their depths, so that direct recursions are told apart.
+    /// Needs a [`QemuCallTracerHelper`] in the helpers.
+    CallContext,
+    /// One entry per path of N edges, the current one and the N-1 taken before it.
+    /// N goes from 2 to [`MAX_NGRAM_SIZE`].
+    NGram(usize),
+}
+
 #[derive(Debug)]
 pub struct QemuEdgeCoverageHelper {
     filter: QemuInstrumentationFilter,
     use_hitcounts: bool,
+    mode: QemuEdgeCoverageMode,
 }
 
 impl QemuEdgeCoverageHelper {
@@ -43,6 +62,7 @@ impl QemuEdgeCoverageHelper {
         Self {
             filter,
             use_hitcounts: true,
+            mode: QemuEdgeCoverageMode::Plain,
         }
     }
 
@@ -51,7 +71,26 @@ impl QemuEdgeCoverageHelper {
         Self {
             filter,
             use_hitcounts: false,
+            mode: QemuEdgeCoverageMode::Plain,
+        }
+    }
+
+    /// Sets the coverage mode, the context-sensitive modes spread the edges over all the map
+    #[must_use]
+    pub fn with_mode(mut self, mode: QemuEdgeCoverageMode) -> Self {
+        if let QemuEdgeCoverageMode::NGram(n) = mode {
+            assert!(
+                (2..=MAX_NGRAM_SIZE).contains(&n),
+                "The N-gram size must be between 2 and {MAX_NGRAM_SIZE}"
+            );
         }
+        self.mode = mode;
+        self
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> QemuEdgeCoverageMode {
+        self.mode
     }
 
     #[must_use]
@@ -74,13 +113,44 @@ where
     where
         QT: QemuHelperTuple<S>,
     {
-        if self.use_hitcounts {
-            hooks.edges_raw(
-                Some(gen_unique_edge_ids::<QT, S>),
-                Some(trace_edge_hitcount),
-            );
-        } else {
-            hooks.edges_raw(Some(gen_unique_edge_ids::<QT, S>), Some(trace_edge_single));
+        let trace: extern "C" fn(u64, u64) = match (self.mode, self.use_hitcounts) {
+            (QemuEdgeCoverageMode::Plain, true) => trace_edge_hitcount,
+            (QemuEdgeCoverageMode::Plain, false) => trace_edge_single,
+            (QemuEdgeCoverageMode::CallContext, use_hitcounts) => {
+                assert!(
+                    hooks.match_helper::<QemuCallTracerHelper>().is_some(),
+                    "The CallContext edge coverage needs a QemuCallTracerHelper"
+                );
+                if use_hitcounts {
+                    trace_edge_hitcount_ctx
+                } else {
+                    trace_edge_single_ctx
+                }
+            }
+            (QemuEdgeCoverageMode::NGram(n), use_hitcounts) => {
+                unsafe {
+                    NGRAM_SIZE = n;
+                }
+                if use_hitcounts {
+                    trace_edge_hitcount_ngram
+                } else {
+                    trace_edge_single_ngram
+                }
+            }
+        };
+        if self.mode != QemuEdgeCoverageMode::Plain {
+            unsafe {
+                MAX_EDGES_NUM = EDGES_MAP_SIZE;
+            }
+        }
+        hooks.edges_raw(Some(gen_unique_edge_ids::<QT, S>), Some(trace));
+    }
+
+    fn pre_exec(&mut self, _emulator: &Emulator, _input: &S::Input) {
+        if let QemuEdgeCoverageMode::NGram(_) = self.mode {
+            unsafe {
+                NGRAM_HISTORY = [0; MAX_NGRAM_SIZE];
+            }
         }
     }
 }
@@ -160,10 +230,13 @@ where
     S: UsesInput,
     QT: QemuHelperTuple<S>,
 {
+    // The context-sensitive modes use all the map
+    let mut plain = true;
     if let Some(h) = hooks.helpers().match_first_type::<QemuEdgeCoverageHelper>() {
         if !h.must_instrument(src.into()) && !h.must_instrument(dest.into()) {
             return None;
         }
+        plain = h.mode == QemuEdgeCoverageMode::Plain;
     }
     let state = state.expect("The gen_unique_edge_ids hook works only for in-process fuzzing");
     if state.metadata().get::<QemuEdgesMapMetadata>().is_none() {
@@ -178,8 +251,10 @@ where
         Entry::Occupied(e) => {
             let id = *e.get();
             let nxt = (id as usize + 1) & (EDGES_MAP_SIZE - 1);
-            unsafe {
-                MAX_EDGES_NUM = max(MAX_EDGES_NUM, nxt);
+            if plain {
+                unsafe {
+                    MAX_EDGES_NUM = max(MAX_EDGES_NUM, nxt);
+                }
             }
             Some(id)
         }
@@ -187,8 +262,10 @@ where
             let id = meta.current_id;
             e.insert(id);
             meta.current_id = (id + 1) & (EDGES_MAP_SIZE as u64 - 1);
-            unsafe {
-                MAX_EDGES_NUM = meta.current_id as usize;
+            if plain {
+                unsafe {
+                    MAX_EDGES_NUM = meta.current_id as usize;
+                }
             }
             // GuestAddress is u32 for 32 bit guests
             #[allow(clippy::unnecessary_cast)]
@@ -209,6 +286,55 @@ pub extern "C" fn trace_edge_single(id: u64, _data: u64) {
     }
 }
 
+/// The hashed ids of the last edges, the most recent first
+static mut NGRAM_HISTORY: [u64; MAX_NGRAM_SIZE] = [0; MAX_NGRAM_SIZE];
+static mut NGRAM_SIZE: usize = 2;
+
+/// The entry of the edge `id` in the calling context `ctx`
+#[inline]
+fn ctx_edge_index(id: u64, ctx: u64) -> usize {
+    ((id ^ ctx) as usize) & (EDGES_MAP_SIZE - 1)
+}
+
+/// Combines the edge with the N-1 previous ones in `history`, and records it there
+#[inline]
+fn ngram_edge_index(history: &mut [u64], id: u64) -> usize {
+    let mut x = id;
+    for (i, h) in history.iter().enumerate() {
+        // Rotate by the age, so that the order of the edges matters
+        x ^= h.rotate_left(i as u32 + 1);
+    }
+    history.copy_within(..history.len() - 1, 1);
+    history[0] = hash_me(id);
+    (x as usize) & (EDGES_MAP_SIZE - 1)
+}
+
+pub extern "C" fn trace_edge_hitcount_ctx(id: u64, _data: u64) {
+    unsafe {
+        let idx = ctx_edge_index(id, CALLSTACK_CTX);
+        EDGES_MAP[idx] = EDGES_MAP[idx].wrapping_add(1);
+    }
+}
+
+pub extern "C" fn trace_edge_single_ctx(id: u64, _data: u64) {
+    unsafe {
+        EDGES_MAP[ctx_edge_index(id, CALLSTACK_CTX)] = 1;
+    }
+}
+
+pub extern "C" fn trace_edge_hitcount_ngram(id: u64, _data: u64) {
+    unsafe {
+        let idx = ngram_edge_index(&mut NGRAM_HISTORY[..NGRAM_SIZE - 1], id);
+        EDGES_MAP[idx] = EDGES_MAP[idx].wrapping_add(1);
+    }
+}
+
+pub extern "C" fn trace_edge_single_ngram(id: u64, _data: u64) {
+    unsafe {
+        EDGES_MAP[ngram_edge_index(&mut NGRAM_HISTORY[..NGRAM_SIZE - 1], id)] = 1;
+    }
+}
+
 pub fn gen_hashed_edge_ids<QT, S>(
     hooks: &mut QemuHooks<'_, QT, S>,
     _state: Option<&mut S>,
@@ -295,3 +421,69 @@ pub extern "C" fn trace_block_transition_single(id: u64, _data: u64) {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ctx_edge_index, ngram_edge_index, EDGES_MAP_SIZE};
+    use crate::calls::context_entry;
+
+    fn ngram_indexes(n: usize, ids: &[u64]) -> Vec<usize> {
+        let mut history = vec![0; n - 1];
+        ids.iter()
+            .map(|id| ngram_edge_index(&mut history, *id))
+            .collect()
+    }
+
+    #[test]
+    fn test_ngram_edge_index() {
+        // Without history, an edge is its own entry
+        assert_eq!(ngram_indexes(4, &[7])[0], 7);
+        assert_eq!(ngram_indexes(4, &[1, 5, 7]), ngram_indexes(4, &[1, 5, 7]));
+        assert!(ngram_indexes(4, &[1, 2, 3])
+            .iter()
+            .all(|idx| *idx < EDGES_MAP_SIZE));
+
+        // The order of the previous edges matters
+        assert_ne!(
+            ngram_indexes(3, &[5, 6, 7])[2],
+            ngram_indexes(3, &[6, 5, 7])[2]
+        );
+
+        // Only the N-1 previous edges matter
+        assert_eq!(
+            ngram_indexes(2, &[1, 5, 7])[2],
+            ngram_indexes(2, &[2, 5, 7])[2]
+        );
+        assert_ne!(
+            ngram_indexes(3, &[1, 5, 7])[2],
+            ngram_indexes(3, &[2, 5, 7])[2]
+        );
+        assert_eq!(
+            ngram_indexes(3, &[1, 4, 5, 7])[3],
+            ngram_indexes(3, &[2, 4, 5, 7])[3]
+        );
+    }
+
+    #[test]
+    fn test_call_context() {
+        let (site, other_site) = (0x1000, 0x2000);
+        assert_eq!(ctx_edge_index(3, 0), 3);
+
+        let mut ctx = context_entry(site, 0);
+        let once = ctx;
+        assert_ne!(ctx_edge_index(3, once), 3);
+        assert_ne!(once, context_entry(other_site, 0));
+
+        // A direct recursion pushes the same return address again, at another depth
+        ctx ^= context_entry(site, 1);
+        assert_ne!(ctx, 0);
+        assert_ne!(ctx, once);
+        assert_ne!(ctx_edge_index(3, ctx), ctx_edge_index(3, once));
+
+        // Returning restores the context of the caller
+        ctx ^= context_entry(site, 1);
+        assert_eq!(ctx, once);
+        ctx ^= context_entry(site, 0);
+        assert_eq!(ctx, 0);
+    }
+}
@@ -1,12 +1,37 @@
 //! Utilities to parse and process ELFs
 
+use core::ops::Range;
 use std::{convert::AsRef, fs::File, io::Read, path::Path, str};
 
-use goblin::elf::{header::ET_DYN, Elf};
+use goblin::elf::{
+    header::ET_DYN,
+    sym::{Sym, STT_FUNC},
+    Elf,
+};
 use libafl::Error;
 
+#[cfg(emulation_mode = "usermode")]
+use crate::Emulator;
 use crate::GuestAddr;
 
+/// The paths and the load addresses of the files mapped in the guest, most of them ELFs
+#[cfg(emulation_mode = "usermode")]
+#[must_use]
+pub fn mapped_files(emulator: &Emulator) -> Vec<(String, GuestAddr)> {
+    let mut files: Vec<(String, GuestAddr)> = vec![];
+    for map in emulator.mappings() {
+        if map.offset() != 0 {
+            continue;
+        }
+        if let Some(path) = map.path() {
+            if !path.is_empty() && !files.iter().any(|(p, _)| p == path) {
+                files.push((path.to_string(), map.start()));
+            }
+        }
+    }
+    files
+}
+
 pub struct EasyElf<'a> {
     elf: Elf<'a>,
 }
@@ -41,33 +66,190 @@ impl<'a> EasyElf<'a> {
 
     #[must_use]
     pub fn resolve_symbol(&self, name: &str, load_addr: GuestAddr) -> Option<GuestAddr> {
-        for sym in self.elf.syms.iter() {
-            if let Some(sym_name) = self.elf.strtab.get_at(sym.st_name) {
-                if sym_name == name {
-                    return if sym.st_value == 0 {
-                        None
-                    } else if self.is_pic() {
-                        #[cfg(cpu_target = "arm")]
-                        // Required because of arm interworking addresses aka bit(0) for thumb mode
-                        let addr = (sym.st_value as GuestAddr + load_addr) & !(0x1 as GuestAddr);
-                        #[cfg(not(cpu_target = "arm"))]
-                        let addr = sym.st_value as GuestAddr + load_addr;
-                        Some(addr)
+        self.find_symbol(name)
+            .map(|sym| self.symbol_address(sym.st_value, load_addr))
+    }
+
+    /// Resolve a function, ignoring the other kinds of symbols and the resolvers of the indirect
+    /// functions (`IFUNC`), that are not the functions themselves
+    #[must_use]
+    pub fn resolve_function(&self, name: &str, load_addr: GuestAddr) -> Option<GuestAddr> {
+        self.find_symbol(name)
+            .filter(|sym| sym.st_type() == STT_FUNC)
+            .map(|sym| self.symbol_address(sym.st_value, load_addr))
+    }
+
+    /// Resolve the GOT entry of an imported function, that holds its address once bound.
+    /// The entry is the target of a PLT relocation, or of a dynamic relocation for the binaries
+    /// that call the imported functions through the GOT without a PLT, e.g. built with `-fno-plt`.
+    #[must_use]
+    pub fn resolve_got_entry(&self, name: &str, load_addr: GuestAddr) -> Option<GuestAddr> {
+        let dynrelocs = self.elf.dynrelas.iter().chain(self.elf.dynrels.iter());
+        for reloc in self
+            .elf
+            .pltrelocs
+            .iter()
+            .chain(dynrelocs.filter(|reloc| reloc.r_addend.unwrap_or(0) == 0))
+        {
+            if let Some(sym) = self.elf.dynsyms.get(reloc.r_sym) {
+                if self.elf.dynstrtab.get_at(sym.st_name) == Some(name) {
+                    return Some(if self.is_pic() {
+                        reloc.r_offset as GuestAddr + load_addr
                     } else {
-                        #[cfg(cpu_target = "arm")]
-                        // Required because of arm interworking addresses aka bit(0) for thumb mode
-                        let addr = (sym.st_value as GuestAddr) & !(0x1 as GuestAddr);
-                        #[cfg(not(cpu_target = "arm"))]
-                        let addr = sym.st_value as GuestAddr;
-                        Some(addr)
-                    };
+                        reloc.r_offset as GuestAddr
+                    });
                 }
             }
         }
         None
     }
 
+    /// The entry point of the binary, where the loader jumps to once done
+    #[must_use]
+    pub fn entry_point(&self, load_addr: GuestAddr) -> GuestAddr {
+        self.symbol_address(self.elf.entry, load_addr)
+    }
+
+    /// The defined functions, with their address ranges
+    #[must_use]
+    pub fn functions(&self, load_addr: GuestAddr) -> Vec<(&'a str, Range<GuestAddr>)> {
+        let mut functions = vec![];
+        for (syms, strtab) in [
+            (&self.elf.syms, &self.elf.strtab),
+            (&self.elf.dynsyms, &self.elf.dynstrtab),
+        ] {
+            for sym in syms.iter() {
+                if sym.st_value == 0 || sym.st_size == 0 || sym.st_type() != STT_FUNC {
+                    continue;
+                }
+                if let Some(name) = strtab.get_at(sym.st_name) {
+                    let start = self.symbol_address(sym.st_value, load_addr);
+                    functions.push((name, start..start + sym.st_size as GuestAddr));
+                }
+            }
+        }
+        functions
+    }
+
+    /// Find a defined symbol in the symbol table, or in the dynamic symbol table
+    fn find_symbol(&self, name: &str) -> Option<Sym> {
+        self.elf
+            .syms
+            .iter()
+            .find(|sym| sym.st_value != 0 && self.elf.strtab.get_at(sym.st_name) == Some(name))
+            .or_else(|| {
+                self.elf.dynsyms.iter().find(|sym| {
+                    sym.st_value != 0 && self.elf.dynstrtab.get_at(sym.st_name) == Some(name)
+                })
+            })
+    }
+
+    #[allow(clippy::let_and_return)]
+    fn symbol_address(&self, value: u64, load_addr: GuestAddr) -> GuestAddr {
+        let addr = if self.is_pic() {
+            value as GuestAddr + load_addr
+        } else {
+            value as GuestAddr
+        };
+        #[cfg(cpu_target = "arm")]
+        // Required because of arm interworking addresses aka bit(0) for thumb mode
+        let addr = addr & !(0x1 as GuestAddr);
+        addr
+    }
+
     fn is_pic(&self) -> bool {
         self.elf.header.e_type == ET_DYN
     }
 }
+
+#[cfg(all(test, any(cpu_target = "x86_64", cpu_target = "aarch64")))]
+mod tests {
+    use super::EasyElf;
+    use crate::GuestAddr;
+
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn libafl_qemu_elf_test_function() -> u32 {
+        0x1234
+    }
+
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn libafl_qemu_elf_test_other_function() -> u32 {
+        0x5678
+    }
+
+    #[no_mangle]
+    static LIBAFL_QEMU_ELF_TEST_DATA: u64 = 0x1234;
+
+    /// resolve the symbols of this test binary, expect them at their addresses in the process
+    #[test]
+    fn test_resolve_in_test_binary() {
+        let mut buffer = Vec::new();
+        let elf = EasyElf::from_file(std::env::current_exe().unwrap(), &mut buffer).unwrap();
+
+        // The offset of the function in the binary gives the load address
+        let function = libafl_qemu_elf_test_function as usize as GuestAddr;
+        let offset = elf
+            .resolve_function("libafl_qemu_elf_test_function", 0)
+            .unwrap();
+        let load_addr = function - offset;
+        assert_eq!(
+            elf.resolve_function("libafl_qemu_elf_test_function", load_addr),
+            Some(function)
+        );
+        assert_eq!(
+            elf.resolve_function("libafl_qemu_elf_test_other_function", load_addr),
+            Some(libafl_qemu_elf_test_other_function as usize as GuestAddr)
+        );
+        assert_eq!(
+            elf.resolve_symbol("libafl_qemu_elf_test_function", load_addr),
+            Some(function)
+        );
+
+        // Not a function, only resolved as a symbol
+        let data = core::ptr::addr_of!(LIBAFL_QEMU_ELF_TEST_DATA) as usize as GuestAddr;
+        assert_eq!(
+            elf.resolve_symbol("LIBAFL_QEMU_ELF_TEST_DATA", load_addr),
+            Some(data)
+        );
+        assert!(elf
+            .resolve_function("LIBAFL_QEMU_ELF_TEST_DATA", load_addr)
+            .is_none());
+
+        assert!(elf
+            .resolve_symbol("libafl_qemu_elf_no_such_symbol", load_addr)
+            .is_none());
+        let functions = elf.functions(load_addr);
+        let (_, range) = functions
+            .iter()
+            .find(|(name, _)| *name == "libafl_qemu_elf_test_function")
+            .unwrap();
+        assert_eq!(range.start, function);
+    }
+
+    /// resolve the GOT entry of a libc function imported by this test binary, expect it to hold
+    /// the address of the function once bound
+    #[test]
+    fn test_resolve_got_entry_in_test_binary() {
+        let mut buffer = Vec::new();
+        let elf = EasyElf::from_file(std::env::current_exe().unwrap(), &mut buffer).unwrap();
+        let function = libafl_qemu_elf_test_function as usize as GuestAddr;
+        let load_addr = function
+            - elf
+                .resolve_function("libafl_qemu_elf_test_function", 0)
+                .unwrap();
+
+        // Called by the test, so imported and bound
+        let pid = unsafe { libc::getpid() };
+        assert!(pid > 0);
+        let got = elf.resolve_got_entry("getpid", load_addr).unwrap();
+        let bound = unsafe { *(got as usize as *const usize) };
+        assert_eq!(bound, libc::getpid as usize);
+
+        // Defined in the binary, not imported
+        assert!(elf
+            .resolve_got_entry("libafl_qemu_elf_test_function", load_addr)
+            .is_none());
+    }
+}
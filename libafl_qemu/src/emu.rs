@@ -666,6 +666,21 @@ impl CPU {
     }
 }
 
+/// Calling convention helpers, implemented for [`CPU`] by each architecture module
+pub trait ArchExtras {
+    /// Read the return address of the current function, at its entry or at its return
+    fn read_return_address(&self) -> Result<GuestAddr, String>;
+
+    /// Write the return address of the current function, at its entry
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String>;
+
+    /// Read the `idx`-th integer argument of the current function, at its entry
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String>;
+
+    /// Write the `idx`-th integer argument of the current function, at its entry
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String>;
+}
+
 static mut EMULATOR_IS_INITIALIZED: bool = false;
 
 #[derive(Clone, Debug)]
@@ -1113,15 +1128,22 @@ impl Emulator {
 
 #[cfg(feature = "python")]
 pub mod pybind {
+    #[cfg(emulation_mode = "usermode")]
     use std::convert::TryFrom;
 
-    use pyo3::{exceptions::PyValueError, prelude::*, types::PyInt};
+    #[cfg(emulation_mode = "usermode")]
+    use pyo3::types::PyInt;
+    use pyo3::{exceptions::PyValueError, prelude::*};
 
-    use super::{GuestAddr, GuestUsize, MmapPerms, SyscallHookResult};
+    use super::{GuestAddr, GuestUsize};
+    #[cfg(emulation_mode = "usermode")]
+    use super::{MmapPerms, SyscallHookResult};
 
+    #[cfg(emulation_mode = "usermode")]
     static mut PY_SYSCALL_HOOK: Option<PyObject> = None;
     static mut PY_GENERIC_HOOKS: Vec<(GuestAddr, PyObject)> = vec![];
 
+    #[cfg(emulation_mode = "usermode")]
     extern "C" fn py_syscall_hook_wrapper(
         sys_num: i32,
         a0: u64,
@@ -1220,18 +1242,22 @@ pub mod pybind {
             }
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn g2h(&self, addr: GuestAddr) -> u64 {
             self.emu.g2h::<*const u8>(addr) as u64
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn h2g(&self, addr: u64) -> GuestAddr {
             self.emu.h2g(addr as *const u8)
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn binary_path(&self) -> String {
             self.emu.binary_path().to_owned()
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn load_addr(&self) -> GuestAddr {
             self.emu.load_addr()
         }
@@ -1240,6 +1266,7 @@ pub mod pybind {
             self.emu.flush_jit();
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn map_private(&self, addr: GuestAddr, size: usize, perms: i32) -> PyResult<GuestAddr> {
             if let Ok(p) = MmapPerms::try_from(perms) {
                 self.emu
@@ -1250,6 +1277,7 @@ pub mod pybind {
             }
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn map_fixed(&self, addr: GuestAddr, size: usize, perms: i32) -> PyResult<GuestAddr> {
             if let Ok(p) = MmapPerms::try_from(perms) {
                 self.emu
@@ -1260,6 +1288,7 @@ pub mod pybind {
             }
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn mprotect(&self, addr: GuestAddr, size: usize, perms: i32) -> PyResult<()> {
             if let Ok(p) = MmapPerms::try_from(perms) {
                 self.emu
@@ -1270,10 +1299,12 @@ pub mod pybind {
             }
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn unmap(&self, addr: GuestAddr, size: usize) -> PyResult<()> {
             self.emu.unmap(addr, size).map_err(PyValueError::new_err)
         }
 
+        #[cfg(emulation_mode = "usermode")]
         fn set_syscall_hook(&self, hook: PyObject) {
             unsafe {
                 PY_SYSCALL_HOOK = Some(hook);
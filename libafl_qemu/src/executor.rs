@@ -1,11 +1,29 @@
 //! A `QEMU`-based executor for binary-only instrumentation in `LibAFL`
 use core::fmt::{self, Debug, Formatter};
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+use core::{marker::PhantomData, time::Duration};
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 #[cfg(feature = "fork")]
 use libafl::{
     bolts::shmem::ShMemProvider, events::EventManager, executors::InProcessForkExecutor,
     state::HasMetadata,
 };
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+use libafl::{
+    bolts::{
+        os::{fork, ForkResult},
+        AsSlice,
+    },
+    inputs::HasTargetBytes,
+};
 use libafl::{
     events::{EventFirer, EventRestarter},
     executors::{Executor, ExitKind, HasObservers, InProcessExecutor},
@@ -17,6 +35,8 @@ use libafl::{
     Error,
 };
 
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+use crate::{elf::EasyElf, emu::GuestAddr};
 use crate::{emu::Emulator, helper::QemuHelperTuple, hooks::QemuHooks};
 
 pub struct QemuExecutor<'a, H, OT, QT, S>
@@ -326,3 +346,426 @@ where
         self.inner.observers_mut()
     }
 }
+
+/// Where the [`QemuForkserverExecutor`] passes the input to the target
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QemuInputLocation {
+    /// The input is passed on the standard input of the target
+    Stdin,
+    /// The input is written to this file, which the target reads
+    File(PathBuf),
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl QemuInputLocation {
+    /// Replaces `@@` in the arguments of the target with the given input file, like `afl-qemu-trace`.
+    /// If no argument is `@@`, the input is passed on the standard input of the target.
+    pub fn from_args<P>(args: &mut [String], input_file: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let input_file = input_file.as_ref();
+        let mut location = Self::Stdin;
+        for arg in args.iter_mut().filter(|arg| arg.as_str() == "@@") {
+            *arg = input_file.to_string_lossy().into_owned();
+            location = Self::File(input_file.to_path_buf());
+        }
+        location
+    }
+}
+
+/// An executor for unmodified binaries running in `QEMU` usermode, like `afl-qemu-trace`.
+///
+/// The target runs until the entry point once, when the executor is created (the one of the
+/// binary, after the loader, unless another one is given), then, for each input, the executor forks the
+/// emulator there (as a forkserver would) and runs the child until the target exits.
+/// The input is passed through a file or the standard input, see [`QemuInputLocation::from_args`],
+/// so no per-target harness is needed.
+/// As the hooks run in the child, collect the edges with the [`crate::QemuEdgeCoverageChildHelper`],
+/// the variant of the [`crate::QemuEdgeCoverageHelper`] for forking executors, and a map in
+/// shared memory (see `EDGES_MAP_PTR`).
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+pub struct QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+{
+    hooks: &'a mut QemuHooks<'a, QT, S>,
+    observers: OT,
+    entry: GuestAddr,
+    input_location: QemuInputLocation,
+    stdin_file: PathBuf,
+    timeout: Duration,
+    shmem_provider: SP,
+    phantom: PhantomData<S>,
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> Debug for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QemuForkserverExecutor")
+            .field("hooks", &self.hooks)
+            .field("observers", &self.observers)
+            .field("entry", &self.entry)
+            .field("input_location", &self.input_location)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+{
+    /// Creates a new [`QemuForkserverExecutor`] for the target loaded in the emulator of the `hooks`,
+    /// and runs the target until `entry`, or the entry point of the binary if `None`, where the
+    /// children are forked from. The edges of the run before are reset with the map before the
+    /// first execution.
+    pub fn new(
+        hooks: &'a mut QemuHooks<'a, QT, S>,
+        observers: OT,
+        entry: Option<GuestAddr>,
+        input_location: QemuInputLocation,
+        timeout: Duration,
+        shmem_provider: SP,
+    ) -> Result<Self, Error> {
+        assert!(!QT::HOOKS_DO_SIDE_EFFECTS, "When using QemuForkserverExecutor, the hooks must not do any side effect as they will happen in the child process and then discarded");
+
+        let stdin_file =
+            std::env::temp_dir().join(format!(".libafl_qemu_stdin_{}", std::process::id()));
+
+        hooks.helpers().first_exec_all(hooks);
+        let entry = Self::start_forkserver(hooks.emulator(), entry)?;
+
+        Ok(Self {
+            hooks,
+            observers,
+            entry,
+            input_location,
+            stdin_file,
+            timeout,
+            shmem_provider,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn hooks(&self) -> &QemuHooks<'a, QT, S> {
+        self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut QemuHooks<'a, QT, S> {
+        self.hooks
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        self.hooks.emulator()
+    }
+
+    /// The file the target reads the input from, the standard input being redirected to it if needed
+    fn input_file(&self) -> &Path {
+        match &self.input_location {
+            QemuInputLocation::Stdin => &self.stdin_file,
+            QemuInputLocation::File(path) => path,
+        }
+    }
+
+    /// Runs the target until the entry point, where all the children are forked from, and returns it
+    fn start_forkserver(emu: &Emulator, entry: Option<GuestAddr>) -> Result<GuestAddr, Error> {
+        let entry = if let Some(entry) = entry {
+            entry
+        } else {
+            let mut elf_buffer = Vec::new();
+            let elf = EasyElf::from_file(emu.binary_path(), &mut elf_buffer)?;
+            elf.entry_point(emu.load_addr())
+        };
+        emu.set_breakpoint(entry);
+        unsafe { emu.run() };
+        emu.remove_breakpoint(entry);
+        Ok(entry)
+    }
+}
+
+/// Waits for the child, killing it after the timeout
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+fn wait_child(pid: libc::pid_t, timeout: Duration) -> Result<ExitKind, Error> {
+    let deadline = Instant::now() + timeout;
+    let mut status = 0;
+    unsafe {
+        #[allow(clippy::cast_possible_truncation)]
+        let pidfd = libc::syscall(libc::SYS_pidfd_open, pid, 0) as libc::c_int;
+        if pidfd >= 0 {
+            let mut pollfd = libc::pollfd {
+                fd: pidfd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // Interrupted polls resume with the time left
+            let ready = loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let timeout_ms =
+                    libc::c_int::try_from(remaining.as_millis()).unwrap_or(libc::c_int::MAX);
+                let ready = libc::poll(&mut pollfd, 1, timeout_ms);
+                if ready >= 0 || std::io::Error::last_os_error().kind() != ErrorKind::Interrupted {
+                    break ready;
+                }
+            };
+            libc::close(pidfd);
+            if ready == 0 {
+                libc::kill(pid, libc::SIGKILL);
+                libc::waitpid(pid, &mut status, 0);
+                return Ok(ExitKind::Timeout);
+            }
+        }
+        // Reap the child, or, without pidfds (before Linux 5.3), poll it until the timeout
+        loop {
+            let waited = libc::waitpid(pid, &mut status, libc::WNOHANG);
+            if waited < 0 {
+                return Err(Error::unknown(format!(
+                    "Failed to wait for the child {pid}: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            if waited == pid {
+                break;
+            }
+            if Instant::now() >= deadline {
+                libc::kill(pid, libc::SIGKILL);
+                libc::waitpid(pid, &mut status, 0);
+                return Ok(ExitKind::Timeout);
+            }
+            std::thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        Ok(ExitKind::Crash)
+    } else if libc::WIFEXITED(status) {
+        let code = libc::WEXITSTATUS(status);
+        if code > 128 && code < 160 {
+            // Signal exit codes
+            Ok(ExitKind::Crash)
+        } else {
+            Ok(ExitKind::Ok)
+        }
+    } else {
+        Ok(ExitKind::Ok)
+    }
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, EM, OT, QT, S, SP, Z> Executor<EM, Z> for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    EM: UsesState<State = S>,
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let emu = Emulator::new_empty();
+        fs::write(self.input_file(), input.target_bytes().as_slice())?;
+
+        self.hooks.helpers_mut().pre_exec_all(&emu, input);
+
+        self.shmem_provider.pre_fork()?;
+        let res = match unsafe { fork() }? {
+            ForkResult::Child => {
+                self.shmem_provider.post_fork(true)?;
+
+                if self.input_location == QemuInputLocation::Stdin {
+                    let stdin = File::open(&self.stdin_file).expect("Failed to open the input");
+                    unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDIN_FILENO) };
+                }
+
+                self.observers
+                    .pre_exec_child_all(state, input)
+                    .expect("Failed to run pre_exec on observers");
+
+                // Runs until the target exits, a crash of the target kills the child with its signal
+                unsafe { emu.run() };
+
+                self.observers
+                    .post_exec_child_all(state, input, &ExitKind::Ok)
+                    .expect("Failed to run post_exec on observers");
+
+                std::process::exit(0);
+            }
+            ForkResult::Parent(child) => {
+                self.shmem_provider.post_fork(false)?;
+                wait_child(child.pid, self.timeout)
+            }
+        };
+
+        self.hooks.helpers_mut().post_exec_all(&emu, input);
+        res
+    }
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> UsesObservers for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    S: UsesInput,
+    SP: ShMemProvider,
+{
+    type Observers = OT;
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> UsesState for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    S: UsesInput,
+    SP: ShMemProvider,
+{
+    type State = S;
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> HasObservers for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+{
+    #[inline]
+    fn observers(&self) -> &OT {
+        &self.observers
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> &mut OT {
+        &mut self.observers
+    }
+}
+
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+impl<'a, OT, QT, S, SP> Drop for QemuForkserverExecutor<'a, OT, QT, S, SP>
+where
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+    SP: ShMemProvider,
+{
+    fn drop(&mut self) {
+        if self.input_location == QemuInputLocation::Stdin {
+            drop(fs::remove_file(&self.stdin_file));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fork", emulation_mode = "usermode"))]
+mod tests {
+    use core::time::Duration;
+    use std::path::Path;
+
+    use libafl::executors::ExitKind;
+
+    use super::{wait_child, QemuInputLocation};
+
+    /// Forks a child running `child`, that must only make async-signal-safe calls
+    fn spawn(child: fn()) -> libc::pid_t {
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0, "fork failed");
+            if pid == 0 {
+                child();
+                libc::_exit(0);
+            }
+            pid
+        }
+    }
+
+    #[test]
+    fn test_input_location_from_args() {
+        let mut args = vec!["target".to_string(), "-f".to_string(), "@@".to_string()];
+        let location = QemuInputLocation::from_args(&mut args, "/tmp/input");
+        assert_eq!(location, QemuInputLocation::File("/tmp/input".into()));
+        assert_eq!(Path::new(&args[2]), Path::new("/tmp/input"));
+
+        let mut args = vec!["target".to_string(), "-".to_string()];
+        let location = QemuInputLocation::from_args(&mut args, "/tmp/input");
+        assert_eq!(location, QemuInputLocation::Stdin);
+        assert_eq!(args, ["target", "-"]);
+    }
+
+    #[test]
+    fn test_wait_child_exit_kinds() {
+        let timeout = Duration::from_secs(10);
+
+        let pid = spawn(|| unsafe { libc::_exit(1) });
+        assert_eq!(wait_child(pid, timeout).unwrap(), ExitKind::Ok);
+
+        let pid = spawn(|| unsafe { libc::abort() });
+        assert_eq!(wait_child(pid, timeout).unwrap(), ExitKind::Crash);
+
+        // The exit code of a target killed by a signal, as reported by a shell
+        let pid = spawn(|| unsafe { libc::_exit(128 + libc::SIGABRT) });
+        assert_eq!(wait_child(pid, timeout).unwrap(), ExitKind::Crash);
+    }
+
+    #[test]
+    fn test_wait_child_timeout() {
+        let pid = spawn(|| loop {
+            unsafe { libc::pause() };
+        });
+        assert_eq!(
+            wait_child(pid, Duration::from_millis(50)).unwrap(),
+            ExitKind::Timeout
+        );
+        // The child was killed and reaped
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+    }
+
+    /// A signal interrupting the wait does not restart the timeout
+    #[test]
+    fn test_wait_child_interrupted() {
+        extern "C" fn ignore(_: libc::c_int) {}
+
+        unsafe {
+            let mut action: libc::sigaction = core::mem::zeroed();
+            action.sa_sigaction = ignore as usize;
+            libc::sigaction(libc::SIGUSR1, &action, core::ptr::null_mut());
+        }
+        let waiter = unsafe { libc::pthread_self() };
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(60));
+            unsafe { libc::pthread_kill(waiter, libc::SIGUSR1) };
+        });
+
+        let pid = spawn(|| loop {
+            unsafe { libc::pause() };
+        });
+        let start = std::time::Instant::now();
+        assert_eq!(
+            wait_child(pid, Duration::from_millis(100)).unwrap(),
+            ExitKind::Timeout
+        );
+        assert!(start.elapsed() < Duration::from_millis(150));
+        interrupter.join().unwrap();
+    }
+}
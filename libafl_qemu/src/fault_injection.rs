@@ -0,0 +1,616 @@
+//! Fault injection for QEMU usermode: make selected syscalls fail, to fuzz the error handling
+//! paths of the target.
+
+use core::marker::PhantomData;
+
+use hashbrown::HashMap;
+use libafl::{
+    bolts::{rands::Rand, tuples::Named, AsSlice},
+    corpus::{Corpus, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    fuzzer::Evaluator,
+    inputs::{HasTargetBytes, UsesInput},
+    observers::ObserversTuple,
+    stages::Stage,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata, HasRand, UsesState},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(any(cpu_target = "arm", cpu_target = "riscv32")))]
+use crate::SYS_mmap;
+#[cfg(any(
+    cpu_target = "arm",
+    cpu_target = "mips",
+    cpu_target = "ppc",
+    cpu_target = "riscv32"
+))]
+use crate::SYS_mmap2;
+#[cfg(not(any(cpu_target = "aarch64", cpu_target = "riscv32", cpu_target = "riscv64")))]
+use crate::SYS_open;
+use crate::{
+    emu::{Emulator, SyscallHookResult},
+    helper::{QemuHelper, QemuHelperTuple},
+    hooks::QemuHooks,
+    SYS_brk, SYS_openat, SYS_read,
+};
+
+/// A fault: the `nth` call to `syscall` in an execution, counting from 0, fails with `errno`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QemuFault {
+    pub syscall: i64,
+    pub nth: usize,
+    pub errno: i32,
+}
+
+/// The faults injected during an execution
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct QemuFaultSchedule {
+    pub faults: Vec<QemuFault>,
+}
+
+libafl::impl_serdeany!(QemuFaultSchedule);
+
+/// A syscall that can be made fail, and the errno it fails with.
+/// A failing `brk` returns the current break, as the kernel does.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QemuFaultKind {
+    pub syscall: i64,
+    pub errno: i32,
+}
+
+impl QemuFaultKind {
+    #[must_use]
+    pub fn new(syscall: i64, errno: i32) -> Self {
+        Self { syscall, errno }
+    }
+}
+
+/// The `mmap`, `read`, `open` and `brk` faults
+#[must_use]
+pub fn default_fault_kinds() -> Vec<QemuFaultKind> {
+    vec![
+        #[cfg(not(any(cpu_target = "arm", cpu_target = "riscv32")))]
+        QemuFaultKind::new(SYS_mmap, libc::ENOMEM),
+        #[cfg(any(
+            cpu_target = "arm",
+            cpu_target = "mips",
+            cpu_target = "ppc",
+            cpu_target = "riscv32"
+        ))]
+        QemuFaultKind::new(SYS_mmap2, libc::ENOMEM),
+        QemuFaultKind::new(SYS_read, libc::EIO),
+        #[cfg(not(any(cpu_target = "aarch64", cpu_target = "riscv32", cpu_target = "riscv64")))]
+        QemuFaultKind::new(SYS_open, libc::ENOENT),
+        QemuFaultKind::new(SYS_openat, libc::ENOENT),
+        QemuFaultKind::new(SYS_brk, libc::ENOMEM),
+    ]
+}
+
+/// Where the [`QemuFaultSchedule`] of an execution comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QemuFaultSource {
+    /// Decoded from the first `len` bytes of the input, that the harness must skip.
+    /// Each pair of bytes selects a fault kind, or none, and the call to make fail.
+    InputPrefix { len: usize },
+    /// The [`QemuFaultSchedule`] metadata of the state, set by the [`QemuFaultScheduleStage`],
+    /// or else the one of the current testcase, that the inputs mutated from it run with
+    Metadata,
+}
+
+/// The fault kinds and the source of the schedule, shared by the [`QemuFaultInjectionHelper`]
+/// and the [`QemuFaultScheduleFeedback`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QemuFaultConfig {
+    kinds: Vec<QemuFaultKind>,
+    source: QemuFaultSource,
+}
+
+impl QemuFaultConfig {
+    #[must_use]
+    pub fn new(kinds: Vec<QemuFaultKind>, source: QemuFaultSource) -> Self {
+        Self { kinds, source }
+    }
+
+    #[must_use]
+    pub fn kinds(&self) -> &[QemuFaultKind] {
+        &self.kinds
+    }
+
+    #[must_use]
+    pub fn source(&self) -> QemuFaultSource {
+        self.source
+    }
+
+    /// The number of bytes the harness must skip at the start of the input
+    #[must_use]
+    pub fn input_offset(&self) -> usize {
+        match self.source {
+            QemuFaultSource::InputPrefix { len } => len,
+            QemuFaultSource::Metadata => 0,
+        }
+    }
+
+    /// Decodes the schedule from the prefix of the input
+    #[must_use]
+    pub fn decode(&self, bytes: &[u8]) -> QemuFaultSchedule {
+        let mut schedule = QemuFaultSchedule::default();
+        let n = self.kinds.len();
+        if n == 0 {
+            return schedule;
+        }
+        let prefix = &bytes[..bytes.len().min(self.input_offset())];
+        for pair in prefix.chunks_exact(2) {
+            // Half of the values select no fault
+            let idx = pair[0] as usize % (2 * n);
+            if idx < n {
+                schedule.faults.push(QemuFault {
+                    syscall: self.kinds[idx].syscall,
+                    nth: pair[1] as usize,
+                    errno: self.kinds[idx].errno,
+                });
+            }
+        }
+        schedule
+    }
+
+    /// The schedule for an execution of `input`
+    #[must_use]
+    pub fn schedule<S>(&self, state: Option<&S>, input: &S::Input) -> QemuFaultSchedule
+    where
+        S: HasCorpus + HasMetadata,
+        S::Input: HasTargetBytes,
+    {
+        match self.source {
+            QemuFaultSource::InputPrefix { .. } => self.decode(input.target_bytes().as_slice()),
+            QemuFaultSource::Metadata => state.map(metadata_schedule).unwrap_or_default(),
+        }
+    }
+}
+
+/// The schedule of the [`QemuFaultSource::Metadata`] source: the one of the state, set by the
+/// [`QemuFaultScheduleStage`], or else the one of the current testcase
+#[must_use]
+pub fn metadata_schedule<S>(state: &S) -> QemuFaultSchedule
+where
+    S: HasCorpus + HasMetadata,
+{
+    if let Some(schedule) = state.metadata().get::<QemuFaultSchedule>() {
+        return schedule.clone();
+    }
+    (*state.corpus().current())
+        .and_then(|idx| state.corpus().get(idx).ok())
+        .and_then(|testcase| {
+            testcase
+                .try_borrow()
+                .ok()?
+                .metadata()
+                .get::<QemuFaultSchedule>()
+                .cloned()
+        })
+        .unwrap_or_default()
+}
+
+/// A helper making the syscalls of the target fail following a [`QemuFaultSchedule`]
+#[derive(Debug)]
+pub struct QemuFaultInjectionHelper {
+    config: QemuFaultConfig,
+    schedule: QemuFaultSchedule,
+    counters: HashMap<i64, usize>,
+    loaded: bool,
+}
+
+impl QemuFaultInjectionHelper {
+    #[must_use]
+    pub fn new(config: QemuFaultConfig) -> Self {
+        Self {
+            config,
+            schedule: QemuFaultSchedule::default(),
+            counters: HashMap::new(),
+            loaded: false,
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &QemuFaultConfig {
+        &self.config
+    }
+
+    /// The schedule of the current execution
+    #[must_use]
+    pub fn schedule(&self) -> &QemuFaultSchedule {
+        &self.schedule
+    }
+
+    /// Counts a call to `syscall`, and returns the fault to inject, if any
+    pub fn fault_for(&mut self, syscall: i64) -> Option<QemuFault> {
+        let counter = self.counters.entry(syscall).or_insert(0);
+        let nth = *counter;
+        *counter += 1;
+        self.schedule
+            .faults
+            .iter()
+            .find(|f| f.syscall == syscall && f.nth == nth)
+            .copied()
+    }
+}
+
+impl<S> QemuHelper<S> for QemuFaultInjectionHelper
+where
+    S: HasCorpus + HasMetadata,
+    S::Input: HasTargetBytes,
+{
+    fn first_exec<QT>(&self, hooks: &QemuHooks<'_, QT, S>)
+    where
+        QT: QemuHelperTuple<S>,
+    {
+        hooks.syscalls(fault_injection_syscall::<QT, S>);
+    }
+
+    fn pre_exec(&mut self, _emulator: &Emulator, input: &S::Input) {
+        self.counters.clear();
+        // The helpers do not get the state before the execution, the schedule of the state or of
+        // the current testcase is read at the first syscall
+        self.loaded = self.config.source != QemuFaultSource::Metadata;
+        if self.loaded {
+            self.schedule = self.config.schedule::<S>(None, input);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn fault_injection_syscall<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    state: Option<&mut S>,
+    sys_num: i32,
+    _a0: u64,
+    _a1: u64,
+    _a2: u64,
+    _a3: u64,
+    _a4: u64,
+    _a5: u64,
+    _a6: u64,
+    _a7: u64,
+) -> SyscallHookResult
+where
+    S: HasCorpus + HasMetadata,
+    QT: QemuHelperTuple<S>,
+{
+    let brk = hooks.emulator().get_brk();
+    let h = hooks
+        .match_helper_mut::<QemuFaultInjectionHelper>()
+        .unwrap();
+    if !h.loaded {
+        h.schedule = state
+            .map(|state| metadata_schedule(&*state))
+            .unwrap_or_default();
+        h.loaded = true;
+    }
+
+    let sys_num = i64::from(sys_num);
+    match h.fault_for(sys_num) {
+        Some(_) if sys_num == SYS_brk => SyscallHookResult::new(Some(u64::from(brk))),
+        Some(fault) => SyscallHookResult::new(Some(-i64::from(fault.errno) as u64)),
+        None => SyscallHookResult::new(None),
+    }
+}
+
+/// A feedback adding the [`QemuFaultSchedule`] active during the execution to the new testcases.
+/// It is never interesting by itself, combine it with the other feedbacks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QemuFaultScheduleFeedback {
+    config: QemuFaultConfig,
+    last: Option<QemuFaultSchedule>,
+}
+
+impl QemuFaultScheduleFeedback {
+    /// Creates a new [`QemuFaultScheduleFeedback`], with the same config of the helper
+    #[must_use]
+    pub fn new(config: QemuFaultConfig) -> Self {
+        Self { config, last: None }
+    }
+}
+
+impl<S> Feedback<S> for QemuFaultScheduleFeedback
+where
+    S: HasCorpus + HasClientPerfMonitor + HasMetadata,
+    S::Input: HasTargetBytes,
+{
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last = Some(self.config.schedule::<S>(Some(&*state), input));
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        if let Some(schedule) = self.last.take() {
+            testcase.add_metadata(schedule);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.last = None;
+        Ok(())
+    }
+}
+
+impl Named for QemuFaultScheduleFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        "QemuFaultScheduleFeedback"
+    }
+}
+
+/// The default number of schedules the [`QemuFaultScheduleStage`] tries for each testcase
+pub const DEFAULT_FAULT_SCHEDULE_ITERATIONS: usize = 16;
+
+/// The default bound of the calls made to fail by the [`QemuFaultScheduleStage`]
+pub const DEFAULT_FAULT_MAX_NTH: usize = 32;
+
+/// A stage fuzzing the fault schedules, for the [`QemuFaultSource::Metadata`] source.
+///
+/// The testcase is run with mutations of its schedule (the empty one for the testcases without),
+/// put in the [`QemuFaultSchedule`] metadata of the state for the [`QemuFaultInjectionHelper`].
+/// The interesting runs are added to the corpus, the [`QemuFaultScheduleFeedback`] keeping their
+/// schedule with them. The metadata is removed afterwards, so the other stages run with the
+/// schedule of the testcase.
+#[derive(Debug)]
+pub struct QemuFaultScheduleStage<E, EM, Z> {
+    config: QemuFaultConfig,
+    iterations: usize,
+    max_nth: usize,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> QemuFaultScheduleStage<E, EM, Z> {
+    /// Creates a new [`QemuFaultScheduleStage`], with the same config of the helper
+    #[must_use]
+    pub fn new(config: QemuFaultConfig) -> Self {
+        Self {
+            config,
+            iterations: DEFAULT_FAULT_SCHEDULE_ITERATIONS,
+            max_nth: DEFAULT_FAULT_MAX_NTH,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the number of schedules tried for each testcase
+    #[must_use]
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets the bound of the calls made to fail, the faults fail one of the first `max_nth` calls
+    #[must_use]
+    pub fn with_max_nth(mut self, max_nth: usize) -> Self {
+        self.max_nth = max_nth;
+        self
+    }
+
+    /// Adds a random fault to the schedule, or changes or removes one of its faults
+    fn mutate<R>(&self, rand: &mut R, base: &QemuFaultSchedule) -> QemuFaultSchedule
+    where
+        R: Rand,
+    {
+        let mut schedule = base.clone();
+        let kinds = self.config.kinds();
+        if kinds.is_empty() {
+            return schedule;
+        }
+        let len = schedule.faults.len() as u64;
+        match rand.below(3) {
+            0 if len > 0 => {
+                schedule.faults.remove(rand.below(len) as usize);
+            }
+            1 if len > 0 => {
+                let nth = rand.below(self.max_nth as u64) as usize;
+                schedule.faults[rand.below(len) as usize].nth = nth;
+            }
+            _ => {
+                let kind = kinds[rand.below(kinds.len() as u64) as usize];
+                schedule.faults.push(QemuFault {
+                    syscall: kind.syscall,
+                    nth: rand.below(self.max_nth as u64) as usize,
+                    errno: kind.errno,
+                });
+            }
+        }
+        schedule
+    }
+}
+
+impl<E, EM, Z> UsesState for QemuFaultScheduleStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for QemuFaultScheduleStage<E, EM, Z>
+where
+    E: UsesState,
+    E::State: HasCorpus + HasMetadata + HasRand,
+    <E::State as UsesInput>::Input: Clone,
+    EM: UsesState<State = E::State>,
+    Z: Evaluator<E, EM, State = E::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        if self.config.source() != QemuFaultSource::Metadata {
+            return Ok(());
+        }
+
+        let mut testcase = state.corpus().get(corpus_idx)?.borrow_mut();
+        let base = testcase
+            .metadata()
+            .get::<QemuFaultSchedule>()
+            .cloned()
+            .unwrap_or_default();
+        let input = testcase.load_input()?.clone();
+        drop(testcase);
+
+        let mut res = Ok(());
+        for _ in 0..self.iterations {
+            let schedule = self.mutate(state.rand_mut(), &base);
+            state.add_metadata(schedule);
+            if let Err(err) = fuzzer.evaluate_input(state, executor, manager, input.clone()) {
+                res = Err(err);
+                break;
+            }
+        }
+        state.metadata_mut().remove::<QemuFaultSchedule>();
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        bolts::rands::StdRand,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        state::{HasCorpus, HasMetadata, StdState},
+    };
+
+    use super::{
+        metadata_schedule, QemuFault, QemuFaultConfig, QemuFaultKind, QemuFaultSchedule,
+        QemuFaultScheduleStage, QemuFaultSource,
+    };
+
+    fn config(source: QemuFaultSource) -> QemuFaultConfig {
+        QemuFaultConfig::new(
+            vec![
+                QemuFaultKind::new(1, libc::EIO),
+                QemuFaultKind::new(2, libc::ENOMEM),
+            ],
+            source,
+        )
+    }
+
+    #[test]
+    fn test_fault_decode() {
+        let config = config(QemuFaultSource::InputPrefix { len: 6 });
+        assert_eq!(config.input_offset(), 6);
+
+        // Kind indexes 0 and 1 select a fault, 2 and 3 (modulo 4) none; the payload is ignored
+        let schedule = config.decode(&[1, 3, 2, 7, 4, 0, b'A', b'B']);
+        assert_eq!(
+            schedule.faults,
+            vec![
+                QemuFault {
+                    syscall: 2,
+                    nth: 3,
+                    errno: libc::ENOMEM
+                },
+                QemuFault {
+                    syscall: 1,
+                    nth: 0,
+                    errno: libc::EIO
+                },
+            ]
+        );
+
+        // The trailing odd byte of a short input is ignored
+        assert_eq!(
+            config.decode(&[0, 5, 1]).faults,
+            vec![QemuFault {
+                syscall: 1,
+                nth: 5,
+                errno: libc::EIO
+            }]
+        );
+
+        let metadata = config(QemuFaultSource::Metadata);
+        assert_eq!(metadata.input_offset(), 0);
+        assert_eq!(metadata.decode(&[0, 5]), QemuFaultSchedule::default());
+
+        let empty = QemuFaultConfig::new(vec![], QemuFaultSource::InputPrefix { len: 2 });
+        assert_eq!(empty.decode(&[0, 5]), QemuFaultSchedule::default());
+    }
+
+    #[test]
+    fn test_fault_schedule_mutate() {
+        let stage: QemuFaultScheduleStage<(), (), ()> =
+            QemuFaultScheduleStage::new(config(QemuFaultSource::Metadata)).with_max_nth(4);
+        let mut rand = StdRand::with_seed(1337);
+        let mut schedule = QemuFaultSchedule::default();
+        for _ in 0..100 {
+            let mutated = stage.mutate(&mut rand, &schedule);
+            assert!(mutated.faults.len().abs_diff(schedule.faults.len()) <= 1);
+            for fault in &mutated.faults {
+                assert!(fault.nth < 4);
+                assert!(stage
+                    .config
+                    .kinds()
+                    .iter()
+                    .any(|kind| kind.syscall == fault.syscall && kind.errno == fault.errno));
+            }
+            schedule = mutated;
+        }
+    }
+
+    #[test]
+    fn test_metadata_schedule() {
+        let fault = |nth| QemuFaultSchedule {
+            faults: vec![QemuFault {
+                syscall: 1,
+                nth,
+                errno: libc::EIO,
+            }],
+        };
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let mut testcase = Testcase::new(BytesInput::new(b"faulty".to_vec()));
+        testcase.add_metadata(fault(3));
+        let faulty = state.corpus_mut().add(testcase).unwrap();
+        let clean = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"clean".to_vec())))
+            .unwrap();
+
+        // No current testcase, or one without a schedule: no faults
+        assert_eq!(metadata_schedule(&state), QemuFaultSchedule::default());
+        *state.corpus_mut().current_mut() = Some(clean);
+        assert_eq!(metadata_schedule(&state), QemuFaultSchedule::default());
+
+        // The inputs mutated from a testcase run with its schedule
+        *state.corpus_mut().current_mut() = Some(faulty);
+        assert_eq!(metadata_schedule(&state), fault(3));
+
+        // The schedule tried by the stage comes first
+        state.add_metadata(fault(7));
+        assert_eq!(metadata_schedule(&state), fault(7));
+    }
+}
@@ -0,0 +1,521 @@
+//! A declarative harness for QEMU in systemmode.
+//!
+//! The guest runs from an entry breakpoint to one of the exit breakpoints, with the input written
+//! at a known address, and the VM state is restored after each run.
+
+use libafl::{executors::ExitKind, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    elf::EasyElf,
+    emu::{Emulator, FastSnapshot},
+    GuestAddr, GuestPhysAddr, GuestUsize, Regs,
+};
+
+/// An address in the guest, either absolute or the address of a symbol of the kernel ELF
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum QemuHarnessAddr {
+    Addr(GuestAddr),
+    Symbol(String),
+}
+
+impl QemuHarnessAddr {
+    /// Resolves the address, looking up the symbols in `elf`
+    pub fn resolve(&self, elf: Option<&EasyElf>) -> Result<GuestAddr, Error> {
+        match self {
+            Self::Addr(addr) => Ok(*addr),
+            Self::Symbol(name) => elf
+                .and_then(|elf| elf.resolve_symbol(name, 0))
+                .ok_or_else(|| Error::key_not_found(format!("Symbol {name} not found"))),
+        }
+    }
+}
+
+impl From<GuestAddr> for QemuHarnessAddr {
+    fn from(addr: GuestAddr) -> Self {
+        Self::Addr(addr)
+    }
+}
+
+impl From<&str> for QemuHarnessAddr {
+    fn from(name: &str) -> Self {
+        Self::Symbol(name.to_string())
+    }
+}
+
+impl From<String> for QemuHarnessAddr {
+    fn from(name: String) -> Self {
+        Self::Symbol(name)
+    }
+}
+
+/// How the VM state is restored after each run
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum QemuSystemSnapshot {
+    /// A fast snapshot of the memory and of the devices
+    Fast,
+    /// A vanilla QEMU snapshot with the given name, slower but more complete
+    Qemu(String),
+}
+
+/// The description of a [`QemuSystemHarness`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QemuSystemHarnessConfig {
+    /// The guest runs to the entry when the harness is built, and the snapshot is taken there
+    pub entry: QemuHarnessAddr,
+    /// The run ends successfully when the guest reaches one of the exits
+    pub exits: Vec<QemuHarnessAddr>,
+    /// The run is a crash when the guest reaches one of these, e.g. panic handlers or exception vectors
+    pub crashes: Vec<QemuHarnessAddr>,
+    /// Where the input is written
+    pub input: QemuHarnessAddr,
+    /// Where the length of the input is written, if any
+    pub input_len: Option<QemuHarnessAddr>,
+    /// The inputs are truncated to this size
+    pub input_max_size: usize,
+    /// Write the input with its physical address, e.g. to write into ROM
+    pub input_physical: bool,
+    pub snapshot: QemuSystemSnapshot,
+}
+
+impl QemuSystemHarnessConfig {
+    #[must_use]
+    pub fn new<E, I>(entry: E, input: I, input_max_size: usize) -> Self
+    where
+        E: Into<QemuHarnessAddr>,
+        I: Into<QemuHarnessAddr>,
+    {
+        Self {
+            entry: entry.into(),
+            exits: vec![],
+            crashes: vec![],
+            input: input.into(),
+            input_len: None,
+            input_max_size,
+            input_physical: false,
+            snapshot: QemuSystemSnapshot::Fast,
+        }
+    }
+
+    #[must_use]
+    pub fn with_exit<A: Into<QemuHarnessAddr>>(mut self, addr: A) -> Self {
+        self.exits.push(addr.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_crash<A: Into<QemuHarnessAddr>>(mut self, addr: A) -> Self {
+        self.crashes.push(addr.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_input_len<A: Into<QemuHarnessAddr>>(mut self, addr: A) -> Self {
+        self.input_len = Some(addr.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_physical_input(mut self, input_physical: bool) -> Self {
+        self.input_physical = input_physical;
+        self
+    }
+
+    #[must_use]
+    pub fn with_snapshot(mut self, snapshot: QemuSystemSnapshot) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// Resolves the addresses, with the symbols of `elf`, and runs the guest to the entry
+    pub fn build(
+        &self,
+        emulator: &Emulator,
+        elf: Option<&EasyElf>,
+    ) -> Result<QemuSystemHarness, Error> {
+        QemuSystemHarness::new(emulator, self, elf)
+    }
+
+    /// Resolves the exits, at least one is needed
+    fn resolve_exits(&self, elf: Option<&EasyElf>) -> Result<Vec<GuestAddr>, Error> {
+        let exits = resolve_all(&self.exits, elf)?;
+        if exits.is_empty() {
+            return Err(Error::illegal_argument(
+                "The systemmode harness needs at least an exit",
+            ));
+        }
+        Ok(exits)
+    }
+}
+
+fn resolve_all(addrs: &[QemuHarnessAddr], elf: Option<&EasyElf>) -> Result<Vec<GuestAddr>, Error> {
+    addrs.iter().map(|addr| addr.resolve(elf)).collect()
+}
+
+#[derive(Debug)]
+enum VmSnapshot {
+    Fast(FastSnapshot),
+    Qemu(String),
+}
+
+/// A systemmode harness built from a [`QemuSystemHarnessConfig`]
+#[derive(Debug)]
+pub struct QemuSystemHarness {
+    emulator: Emulator,
+    entry: GuestAddr,
+    exits: Vec<GuestAddr>,
+    crashes: Vec<GuestAddr>,
+    input: GuestAddr,
+    input_len: Option<GuestAddr>,
+    input_max_size: usize,
+    input_physical: bool,
+    snapshot: VmSnapshot,
+}
+
+impl QemuSystemHarness {
+    /// Resolves the addresses of the `config`, runs the guest to the entry and takes the snapshot
+    pub fn new(
+        emulator: &Emulator,
+        config: &QemuSystemHarnessConfig,
+        elf: Option<&EasyElf>,
+    ) -> Result<Self, Error> {
+        let entry = config.entry.resolve(elf)?;
+        let exits = config.resolve_exits(elf)?;
+        let crashes = resolve_all(&config.crashes, elf)?;
+        let input = config.input.resolve(elf)?;
+        let input_len = config
+            .input_len
+            .as_ref()
+            .map(|addr| addr.resolve(elf))
+            .transpose()?;
+
+        emulator.set_breakpoint(entry);
+        unsafe { emulator.run() };
+        emulator.remove_breakpoint(entry);
+
+        for addr in exits.iter().chain(crashes.iter()) {
+            emulator.set_breakpoint(*addr);
+        }
+
+        let snapshot = match &config.snapshot {
+            QemuSystemSnapshot::Fast => VmSnapshot::Fast(emulator.create_fast_snapshot(true)),
+            QemuSystemSnapshot::Qemu(name) => {
+                emulator.save_snapshot(name, true);
+                VmSnapshot::Qemu(name.clone())
+            }
+        };
+
+        Ok(Self {
+            emulator: emulator.clone(),
+            entry,
+            exits,
+            crashes,
+            input,
+            input_len,
+            input_max_size: config.input_max_size,
+            input_physical: config.input_physical,
+            snapshot,
+        })
+    }
+
+    #[must_use]
+    pub fn entry(&self) -> GuestAddr {
+        self.entry
+    }
+
+    #[must_use]
+    pub fn exits(&self) -> &[GuestAddr] {
+        &self.exits
+    }
+
+    #[must_use]
+    pub fn crashes(&self) -> &[GuestAddr] {
+        &self.crashes
+    }
+
+    #[must_use]
+    pub fn input(&self) -> GuestAddr {
+        self.input
+    }
+
+    /// Writes the input, runs the guest until it stops and restores the snapshot
+    pub fn run(&self, input: &[u8]) -> ExitKind {
+        let input = &input[..input.len().min(self.input_max_size)];
+        unsafe {
+            self.write_input(input);
+            self.emulator.run();
+        }
+        let exit_kind = self.exit_kind();
+        self.restore();
+        exit_kind
+    }
+
+    /// Restores the snapshot taken at the entry
+    pub fn restore(&self) {
+        match &self.snapshot {
+            VmSnapshot::Fast(snapshot) => self.emulator.restore_fast_snapshot(*snapshot),
+            VmSnapshot::Qemu(name) => self.emulator.load_snapshot(name, true),
+        }
+    }
+
+    /// The [`ExitKind`] of the last run, from where the CPUs stopped.
+    /// If the guest stopped anywhere else than at an exit, the run is considered a crash.
+    #[must_use]
+    pub fn exit_kind(&self) -> ExitKind {
+        exit_kind_at(&stopped_pcs(&self.emulator), &self.exits, &self.crashes)
+    }
+
+    unsafe fn write_input(&self, input: &[u8]) {
+        if self.input_physical {
+            self.emulator
+                .write_phys_mem(self.input as GuestPhysAddr, input);
+        } else {
+            self.emulator.write_mem(self.input, input);
+        }
+        if let Some(len_addr) = self.input_len {
+            let len = input.len() as GuestUsize;
+            #[cfg(any(feature = "be", cpu_target = "ppc"))]
+            let len = len.to_be_bytes();
+            #[cfg(not(any(feature = "be", cpu_target = "ppc")))]
+            let len = len.to_le_bytes();
+            if self.input_physical {
+                self.emulator
+                    .write_phys_mem(len_addr as GuestPhysAddr, &len);
+            } else {
+                self.emulator.write_mem(len_addr, &len);
+            }
+        }
+    }
+}
+
+/// The PCs where the CPUs stopped
+fn stopped_pcs(emulator: &Emulator) -> Vec<GuestAddr> {
+    (0..emulator.num_cpus())
+        .filter_map(|i| emulator.cpu_from_index(i).read_reg(Regs::Pc).ok())
+        .collect()
+}
+
+/// If one of the CPUs stopped at one of the `addrs`
+pub(crate) fn stopped_at(emulator: &Emulator, addrs: &[GuestAddr]) -> bool {
+    at_any(&stopped_pcs(emulator), addrs)
+}
+
+fn at_any(pcs: &[GuestAddr], addrs: &[GuestAddr]) -> bool {
+    pcs.iter()
+        .any(|pc| addrs.iter().any(|addr| same_addr(*pc, *addr)))
+}
+
+/// A run is ok if no CPU stopped at a crash, and one stopped at an exit
+fn exit_kind_at(pcs: &[GuestAddr], exits: &[GuestAddr], crashes: &[GuestAddr]) -> ExitKind {
+    if !at_any(pcs, crashes) && at_any(pcs, exits) {
+        ExitKind::Ok
+    } else {
+        ExitKind::Crash
+    }
+}
+
+/// The Thumb bit is set in the ARM symbols, but not in the PC.
+/// The breakpoints stop the CPUs with the PC exactly at their address, so no slack is allowed
+/// around it: the `breakpoint..breakpoint + 5` range the hand-written harnesses used to accept was
+/// only hiding the Thumb bit, and it would take a crash just after an exit for a clean exit.
+fn same_addr(pc: GuestAddr, addr: GuestAddr) -> bool {
+    #[cfg(cpu_target = "arm")]
+    let addr = addr & !1;
+    pc == addr
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::executors::ExitKind;
+
+    use super::{exit_kind_at, resolve_all, same_addr, QemuHarnessAddr, QemuSystemHarnessConfig};
+    use crate::{elf::EasyElf, GuestAddr};
+
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn libafl_qemu_harness_test_exit() -> u32 {
+        0x1234
+    }
+
+    /// The offset of [`libafl_qemu_harness_test_exit`] in `elf`, this test binary
+    fn test_exit_offset(elf: &EasyElf) -> GuestAddr {
+        let offset = elf
+            .resolve_symbol("libafl_qemu_harness_test_exit", 0)
+            .unwrap();
+        // Also keeps the function in the binary, the binary is loaded at a page boundary
+        let function = libafl_qemu_harness_test_exit as usize;
+        assert_eq!((function - offset as usize) % 0x1000, 0);
+        offset
+    }
+
+    #[test]
+    fn test_resolve_addrs() {
+        let mut buffer = Vec::new();
+        let elf = EasyElf::from_file(std::env::current_exe().unwrap(), &mut buffer).unwrap();
+        let exit = test_exit_offset(&elf);
+
+        assert_eq!(QemuHarnessAddr::from(0x1000).resolve(None).unwrap(), 0x1000);
+        assert_eq!(
+            QemuHarnessAddr::from("libafl_qemu_harness_test_exit")
+                .resolve(Some(&elf))
+                .unwrap(),
+            exit
+        );
+        // The symbols need an ELF, and must be in it
+        assert!(QemuHarnessAddr::from("libafl_qemu_harness_test_exit")
+            .resolve(None)
+            .is_err());
+        assert!(QemuHarnessAddr::from("libafl_qemu_harness_no_such_symbol")
+            .resolve(Some(&elf))
+            .is_err());
+
+        let addrs = [
+            QemuHarnessAddr::from(0x1000),
+            QemuHarnessAddr::from("libafl_qemu_harness_test_exit"),
+        ];
+        assert_eq!(resolve_all(&addrs, Some(&elf)).unwrap(), [0x1000, exit]);
+        assert!(resolve_all(&addrs, None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_exits() {
+        let mut buffer = Vec::new();
+        let elf = EasyElf::from_file(std::env::current_exe().unwrap(), &mut buffer).unwrap();
+        let exit = test_exit_offset(&elf);
+
+        let config = QemuSystemHarnessConfig::new(0x1000, 0x2000, 16);
+        assert!(config.resolve_exits(Some(&elf)).is_err());
+        let config = config
+            .with_exit("libafl_qemu_harness_test_exit")
+            .with_exit(0x3000);
+        assert_eq!(config.resolve_exits(Some(&elf)).unwrap(), [exit, 0x3000]);
+        let config = config.with_exit("libafl_qemu_harness_no_such_symbol");
+        assert!(config.resolve_exits(Some(&elf)).is_err());
+    }
+
+    #[test]
+    fn test_same_addr() {
+        assert!(same_addr(0x1000, 0x1000));
+        // The breakpoints stop the CPUs exactly at their address
+        assert!(!same_addr(0x1002, 0x1000));
+        assert!(!same_addr(0x1000, 0x1002));
+        // The Thumb bit of the ARM symbols is not in the PC
+        #[cfg(cpu_target = "arm")]
+        assert!(same_addr(0x1000, 0x1001));
+        #[cfg(not(cpu_target = "arm"))]
+        assert!(!same_addr(0x1000, 0x1001));
+    }
+
+    #[test]
+    fn test_exit_kind_at() {
+        let exits: [GuestAddr; 2] = [0x1000, 0x2000];
+        let crashes: [GuestAddr; 1] = [0x3000];
+
+        assert_eq!(exit_kind_at(&[0x2000], &exits, &crashes), ExitKind::Ok);
+        assert_eq!(exit_kind_at(&[0x3000], &exits, &crashes), ExitKind::Crash);
+        // Stopped anywhere else, e.g. by a fault, just after an exit
+        assert_eq!(exit_kind_at(&[0x1004], &exits, &crashes), ExitKind::Crash);
+        assert_eq!(exit_kind_at(&[], &exits, &crashes), ExitKind::Crash);
+        // A CPU at a crash wins over another at an exit
+        assert_eq!(
+            exit_kind_at(&[0x1000, 0x3000], &exits, &crashes),
+            ExitKind::Crash
+        );
+        assert_eq!(
+            exit_kind_at(&[0x1000, 0x4000], &exits, &crashes),
+            ExitKind::Ok
+        );
+    }
+}
+
+#[cfg(feature = "python")]
+pub mod pybind {
+    use libafl::executors::pybind::PythonExitKind;
+    use pyo3::{exceptions::PyValueError, prelude::*};
+
+    use super::{QemuHarnessAddr, QemuSystemHarnessConfig, QemuSystemSnapshot};
+    use crate::{elf::EasyElf, emu::pybind::Emulator, GuestAddr};
+
+    fn extract_addr(obj: &PyAny) -> PyResult<QemuHarnessAddr> {
+        if let Ok(addr) = obj.extract::<GuestAddr>() {
+            Ok(QemuHarnessAddr::Addr(addr))
+        } else {
+            obj.extract::<String>().map(QemuHarnessAddr::Symbol)
+        }
+    }
+
+    /// Python class for the systemmode harness, the addresses are either ints or symbol names
+    #[pyclass(unsendable)]
+    pub struct SystemHarness {
+        pub harness: super::QemuSystemHarness,
+    }
+
+    #[pymethods]
+    impl SystemHarness {
+        #[allow(clippy::too_many_arguments, clippy::needless_pass_by_value)]
+        #[new]
+        #[args(
+            crashes = "Vec::new()",
+            input_len = "None",
+            input_physical = "false",
+            snapshot = "None",
+            elf = "None"
+        )]
+        fn new(
+            emu: &Emulator,
+            entry: &PyAny,
+            exits: Vec<&PyAny>,
+            input: &PyAny,
+            input_max_size: usize,
+            crashes: Vec<&PyAny>,
+            input_len: Option<&PyAny>,
+            input_physical: bool,
+            snapshot: Option<String>,
+            elf: Option<String>,
+        ) -> PyResult<Self> {
+            let mut config = QemuSystemHarnessConfig::new(
+                extract_addr(entry)?,
+                extract_addr(input)?,
+                input_max_size,
+            )
+            .with_physical_input(input_physical)
+            .with_snapshot(snapshot.map_or(QemuSystemSnapshot::Fast, QemuSystemSnapshot::Qemu));
+            for addr in exits {
+                config = config.with_exit(extract_addr(addr)?);
+            }
+            for addr in crashes {
+                config = config.with_crash(extract_addr(addr)?);
+            }
+            if let Some(addr) = input_len {
+                config = config.with_input_len(extract_addr(addr)?);
+            }
+
+            let mut elf_buffer = Vec::new();
+            let elf = elf
+                .map(|path| EasyElf::from_file(path, &mut elf_buffer))
+                .transpose()
+                .map_err(|e| PyValueError::new_err(format!("{e:?}")))?;
+            let harness = config
+                .build(&emu.emu, elf.as_ref())
+                .map_err(|e| PyValueError::new_err(format!("{e:?}")))?;
+            Ok(Self { harness })
+        }
+
+        /// Runs the input, and returns the `ExitKind` of the run
+        fn run(&self, input: &[u8]) -> PythonExitKind {
+            self.harness.run(input).into()
+        }
+
+        fn restore(&self) {
+            self.harness.restore();
+        }
+
+        fn entry(&self) -> GuestAddr {
+            self.harness.entry()
+        }
+
+        fn input(&self) -> GuestAddr {
+            self.harness.input()
+        }
+    }
+}
@@ -5,6 +5,8 @@ use pyo3::prelude::*;
 pub use strum_macros::EnumIter;
 pub use syscall_numbers::x86::*;
 
+use crate::{ArchExtras, GuestAddr, CPU};
+
 #[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, EnumIter)]
 #[repr(i32)]
 pub enum Regs {
@@ -27,6 +29,35 @@ impl Regs {
     pub const Pc: Regs = Regs::Eip;
 }
 
+// cdecl, the arguments are on the stack after the return address
+impl ArchExtras for CPU {
+    fn read_return_address(&self) -> Result<GuestAddr, String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Esp)?;
+        let mut ret_addr = [0; 4];
+        unsafe { self.read_mem(stack_ptr, &mut ret_addr) };
+        Ok(GuestAddr::from_le_bytes(ret_addr))
+    }
+
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Esp)?;
+        unsafe { self.write_mem(stack_ptr, &val.to_le_bytes()) };
+        Ok(())
+    }
+
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Esp)?;
+        let mut arg = [0; 4];
+        unsafe { self.read_mem(stack_ptr + 4 + 4 * GuestAddr::from(idx), &mut arg) };
+        Ok(GuestAddr::from_le_bytes(arg))
+    }
+
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Esp)?;
+        unsafe { self.write_mem(stack_ptr + 4 + 4 * GuestAddr::from(idx), &val.to_le_bytes()) };
+        Ok(())
+    }
+}
+
 #[cfg(feature = "python")]
 impl IntoPy<PyObject> for Regs {
     fn into_py(self, py: Python) -> PyObject {
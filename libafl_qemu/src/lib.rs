@@ -3,7 +3,7 @@
 // This lint triggers too often on the current GuestAddr type when emulating 64-bit targets because
 // u64::from(GuestAddr) is a no-op, but the .into() call is needed when GuestAddr is u32.
 #![cfg_attr(
-    any(cpu_target = "x86_64", cpu_target = "aarch64"),
+    any(cpu_target = "x86_64", cpu_target = "aarch64", cpu_target = "riscv64"),
     allow(clippy::useless_conversion)
 )]
 #![allow(clippy::needless_pass_by_value)]
@@ -49,6 +49,16 @@ pub mod mips;
 #[cfg(cpu_target = "mips")]
 pub use mips::*;
 
+#[cfg(any(cpu_target = "riscv32", cpu_target = "riscv64"))]
+pub mod riscv;
+#[cfg(any(cpu_target = "riscv32", cpu_target = "riscv64"))]
+pub use riscv::*;
+
+#[cfg(cpu_target = "ppc")]
+pub mod ppc;
+#[cfg(cpu_target = "ppc")]
+pub use ppc::*;
+
 pub mod elf;
 
 pub mod helper;
@@ -57,32 +67,68 @@ pub mod hooks;
 pub use hooks::*;
 
 pub mod edges;
-pub use edges::QemuEdgeCoverageHelper;
+pub use edges::{QemuEdgeCoverageHelper, QemuEdgeCoverageMode};
 
 #[cfg(not(cpu_target = "mips"))]
 pub mod cmplog;
 #[cfg(not(cpu_target = "mips"))]
 pub use cmplog::QemuCmpLogHelper;
+#[cfg(all(not(cpu_target = "mips"), emulation_mode = "usermode"))]
+pub use cmplog::QemuCmpLogRoutinesHelper;
 
 #[cfg(emulation_mode = "usermode")]
 pub mod snapshot;
 #[cfg(emulation_mode = "usermode")]
 pub use snapshot::QemuSnapshotHelper;
 
+#[cfg(emulation_mode = "usermode")]
+pub mod persistent;
+#[cfg(emulation_mode = "usermode")]
+pub use persistent::{QemuPersistentHelper, QemuPersistentInput};
+
+#[cfg(emulation_mode = "usermode")]
+pub mod fault_injection;
+#[cfg(emulation_mode = "usermode")]
+pub use fault_injection::{
+    QemuFaultConfig, QemuFaultInjectionHelper, QemuFaultSchedule, QemuFaultScheduleFeedback,
+    QemuFaultScheduleStage, QemuFaultSource,
+};
+
+#[cfg(emulation_mode = "usermode")]
+pub mod streams;
+#[cfg(emulation_mode = "usermode")]
+pub use streams::{QemuStreamTarget, QemuStreamsHelper};
+
 #[cfg(emulation_mode = "usermode")]
 pub mod asan;
 #[cfg(emulation_mode = "usermode")]
 pub use asan::{init_with_asan, QemuAsanHelper};
 
+#[cfg(emulation_mode = "systemmode")]
+pub mod harness;
+#[cfg(emulation_mode = "systemmode")]
+pub use harness::{
+    QemuHarnessAddr, QemuSystemHarness, QemuSystemHarnessConfig, QemuSystemSnapshot,
+};
+
+#[cfg(emulation_mode = "systemmode")]
+pub mod snapshot_tree;
+#[cfg(emulation_mode = "systemmode")]
+pub use snapshot_tree::{HasQemuSnapshotTree, QemuSnapshotStage, QemuSnapshotTreeHelper};
+
 pub mod blocks;
 
 pub mod calls;
+pub use calls::{QemuCallTracerHelper, QemuCallstackObserver};
+
 pub mod drcov;
 
 pub mod executor;
 pub use executor::QemuExecutor;
 #[cfg(feature = "fork")]
 pub use executor::QemuForkExecutor;
+#[cfg(all(feature = "fork", emulation_mode = "usermode"))]
+pub use executor::{QemuForkserverExecutor, QemuInputLocation};
 
 pub mod emu;
 pub use emu::*;
@@ -127,9 +173,12 @@ pub fn python_module(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_submodule(mmapm)?;
 
     m.add_class::<emu::MapInfo>()?;
+    #[cfg(emulation_mode = "usermode")]
     m.add_class::<emu::GuestMaps>()?;
     m.add_class::<emu::SyscallHookResult>()?;
     m.add_class::<emu::pybind::Emulator>()?;
+    #[cfg(emulation_mode = "systemmode")]
+    m.add_class::<harness::pybind::SystemHarness>()?;
 
     Ok(())
 }
@@ -0,0 +1,470 @@
+//! Persistent mode for `QEMU` usermode, looping over a start routine of the target instead of
+//! running it from the entry point for each input, like the persistent mode of `afl-qemu-trace`.
+
+use core::fmt::Debug;
+
+use libafl::{
+    bolts::AsSlice,
+    inputs::{HasTargetBytes, UsesInput},
+    Error,
+};
+
+use crate::{
+    emu::{ArchExtras, CPUArchState, Emulator, MmapPerms, SyscallHookResult},
+    helper::{QemuHelper, QemuHelperTuple},
+    hooks::QemuHooks,
+    snapshot::QemuSnapshotHelper,
+    GuestAddr, GuestUsize, Regs, SYS_read,
+};
+
+/// The default number of iterations in persistent mode before a restart of the target
+pub const DEFAULT_PERSISTENT_ITERATIONS: usize = 1000;
+
+/// Where the [`QemuPersistentHelper`] places the input for each iteration
+#[derive(Debug, Clone)]
+pub enum QemuPersistentInput {
+    /// Map a buffer of `max_size` bytes, write the input there and pass the pointer to the buffer
+    /// in the `ptr` register, and the length of the input in the `len` register, if any
+    Registers {
+        ptr: Regs,
+        len: Option<Regs>,
+        max_size: usize,
+    },
+    /// Serve the input as the result of the `read` syscalls on `fd`, until it is consumed
+    ReadSyscall { fd: i32 },
+    /// Patch the target memory at `addr` with up to `max_size` bytes of input, and write the
+    /// length of the input at `len_addr`, if any
+    Memory {
+        addr: GuestAddr,
+        max_size: usize,
+        len_addr: Option<GuestAddr>,
+    },
+}
+
+/// A helper to run the target in persistent mode, looping between a start address and the
+/// address to which the start routine returns.
+///
+/// The target is run to the start address when the helper is created, and the CPU state there is
+/// saved with [`crate::CPU::save_state`]. Before each iteration, the CPU state is restored and the
+/// input is placed following the [`QemuPersistentInput`]. The harness then only has to call
+/// [`Emulator::run`], that returns once the start routine returns.
+///
+/// Put a [`QemuSnapshotHelper`] in the same helpers tuple to also restore the memory after each
+/// iteration, otherwise only the registers are restored. Every `max_iterations` iterations, the
+/// target is restarted from the start address: all the memory snapshot, taken there by the
+/// [`QemuSnapshotHelper`], is restored with the saved CPU state, and not only the pages written in
+/// the last iteration. Without a [`QemuSnapshotHelper`], the target can not be restarted and
+/// `max_iterations` must be `0`, or the first execution panics.
+pub struct QemuPersistentHelper {
+    start: GuestAddr,
+    ret: GuestAddr,
+    input: QemuPersistentInput,
+    buffer: GuestAddr,
+    saved: Box<CPUArchState>,
+    bytes: Vec<u8>,
+    read_offset: usize,
+    iterations: usize,
+    max_iterations: usize,
+    full_reset: bool,
+    placed: bool,
+}
+
+impl Debug for QemuPersistentHelper {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("QemuPersistentHelper")
+            .field("start", &self.start)
+            .field("ret", &self.ret)
+            .field("input", &self.input)
+            .field("buffer", &self.buffer)
+            .field("iterations", &self.iterations)
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+impl QemuPersistentHelper {
+    /// Runs the target to `start`, and saves the CPU state there.
+    /// If `ret` is `None`, the return address of the start routine is used as end address.
+    pub fn new(
+        emulator: &Emulator,
+        start: GuestAddr,
+        ret: Option<GuestAddr>,
+        input: QemuPersistentInput,
+    ) -> Result<Self, Error> {
+        emulator.set_breakpoint(start);
+        unsafe { emulator.run() };
+        emulator.remove_breakpoint(start);
+
+        let cpu = emulator.current_cpu().ok_or_else(|| {
+            Error::illegal_state("No current cpu after running to the persistent start address")
+        })?;
+        let ret = match ret {
+            Some(ret) => ret,
+            None => cpu.read_return_address().map_err(|err| {
+                Error::illegal_state(format!(
+                    "Failed to read the return address of the persistent start routine: {err}"
+                ))
+            })?,
+        };
+
+        let buffer = match &input {
+            QemuPersistentInput::Registers { max_size, .. } => emulator
+                .map_private(0, *max_size, MmapPerms::ReadWrite)
+                .map_err(|err| {
+                    Error::unknown(format!("Failed to map the persistent input buffer: {err}"))
+                })?,
+            QemuPersistentInput::Memory { addr, .. } => *addr,
+            QemuPersistentInput::ReadSyscall { .. } => 0,
+        };
+
+        emulator.set_breakpoint(ret);
+        let saved = Box::new(cpu.save_state());
+
+        Ok(Self::with_saved_state(start, ret, input, buffer, saved))
+    }
+
+    fn with_saved_state(
+        start: GuestAddr,
+        ret: GuestAddr,
+        input: QemuPersistentInput,
+        buffer: GuestAddr,
+        saved: Box<CPUArchState>,
+    ) -> Self {
+        Self {
+            start,
+            ret,
+            input,
+            buffer,
+            saved,
+            bytes: vec![],
+            read_offset: 0,
+            iterations: 0,
+            max_iterations: DEFAULT_PERSISTENT_ITERATIONS,
+            full_reset: false,
+            placed: false,
+        }
+    }
+
+    /// Sets the number of iterations before a restart of the target, `0` to never restart it,
+    /// which is required without a [`QemuSnapshotHelper`]
+    #[must_use]
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    #[must_use]
+    pub fn start(&self) -> GuestAddr {
+        self.start
+    }
+
+    #[must_use]
+    pub fn ret(&self) -> GuestAddr {
+        self.ret
+    }
+
+    /// The address of the input buffer, if the input is placed in memory
+    #[must_use]
+    pub fn buffer(&self) -> Option<GuestAddr> {
+        match self.input {
+            QemuPersistentInput::ReadSyscall { .. } => None,
+            _ => Some(self.buffer),
+        }
+    }
+
+    #[must_use]
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Restores the CPU state saved at the start address
+    pub fn restore(&self, emulator: &Emulator) {
+        emulator.current_cpu().unwrap().restore_state(&self.saved);
+    }
+
+    /// Takes the input of the next iteration, truncated to the room of the placement
+    fn set_input(&mut self, input: &[u8]) {
+        let len = input.len().min(self.max_size());
+        self.bytes.clear();
+        self.bytes.extend_from_slice(&input[..len]);
+        self.read_offset = 0;
+        self.placed = false;
+    }
+
+    /// The addresses of the input and of its length, if they are written in memory
+    fn input_addrs(&self) -> Option<(GuestAddr, Option<GuestAddr>)> {
+        match &self.input {
+            QemuPersistentInput::Registers { .. } => Some((self.buffer, None)),
+            QemuPersistentInput::Memory { len_addr, .. } => Some((self.buffer, *len_addr)),
+            QemuPersistentInput::ReadSyscall { .. } => None,
+        }
+    }
+
+    /// The length of the input, in the byte order of the guest
+    fn len_bytes(&self) -> [u8; core::mem::size_of::<GuestUsize>()] {
+        let len = self.bytes.len() as GuestUsize;
+        if cfg!(any(feature = "be", cpu_target = "ppc")) {
+            len.to_be_bytes()
+        } else {
+            len.to_le_bytes()
+        }
+    }
+
+    /// The registers set to the address and to the length of the input, if any
+    fn input_regs(&self) -> [Option<(Regs, GuestUsize)>; 2] {
+        match &self.input {
+            QemuPersistentInput::Registers { ptr, len, .. } => [
+                Some((*ptr, self.buffer)),
+                len.map(|len| (len, self.bytes.len() as GuestUsize)),
+            ],
+            _ => [None, None],
+        }
+    }
+
+    fn write_input_mem(&self, emulator: &Emulator) {
+        if let Some((addr, len_addr)) = self.input_addrs() {
+            unsafe {
+                emulator.write_mem(addr, &self.bytes);
+                if let Some(len_addr) = len_addr {
+                    emulator.write_mem(len_addr, &self.len_bytes());
+                }
+            }
+        }
+    }
+
+    fn write_input_regs(&self, emulator: &Emulator) {
+        for (reg, val) in self.input_regs().into_iter().flatten() {
+            emulator.write_reg(reg, val).unwrap();
+        }
+    }
+
+    fn max_size(&self) -> usize {
+        match &self.input {
+            QemuPersistentInput::Registers { max_size, .. }
+            | QemuPersistentInput::Memory { max_size, .. } => *max_size,
+            QemuPersistentInput::ReadSyscall { .. } => usize::MAX,
+        }
+    }
+
+    /// Takes the next chunk of input for a `read` of up to `size` bytes, empty once consumed
+    fn next_read(&mut self, size: usize) -> &[u8] {
+        let start = self.read_offset;
+        let len = (self.bytes.len() - start).min(size);
+        self.read_offset += len;
+        &self.bytes[start..start + len]
+    }
+
+    /// Serves the next chunk of input to a `read` of up to `size` bytes in `buf`
+    fn read(&mut self, emulator: &Emulator, buf: GuestAddr, size: usize) -> usize {
+        let chunk = self.next_read(size);
+        unsafe { emulator.write_mem(buf, chunk) };
+        chunk.len()
+    }
+}
+
+impl<S> QemuHelper<S> for QemuPersistentHelper
+where
+    S: UsesInput,
+    S::Input: HasTargetBytes,
+{
+    fn first_exec<QT>(&self, hooks: &QemuHooks<'_, QT, S>)
+    where
+        QT: QemuHelperTuple<S>,
+    {
+        assert!(
+            self.max_iterations == 0 || hooks.match_helper::<QemuSnapshotHelper>().is_some(),
+            "The QemuPersistentHelper restarts the target from the memory snapshot of a QemuSnapshotHelper, add one to the helpers or disable the restarts with with_max_iterations(0)"
+        );
+        hooks.instruction(self.start, persistent_start_hook::<QT, S>, true);
+        if let QemuPersistentInput::ReadSyscall { .. } = self.input {
+            hooks.syscalls(persistent_read_syscall::<QT, S>);
+        }
+    }
+
+    fn pre_exec(&mut self, emulator: &Emulator, input: &S::Input) {
+        self.set_input(input.target_bytes().as_slice());
+
+        self.iterations += 1;
+        if self.max_iterations != 0 && self.iterations >= self.max_iterations {
+            self.iterations = 0;
+            self.full_reset = true;
+        }
+
+        self.restore(emulator);
+        self.write_input_regs(emulator);
+    }
+}
+
+/// Places the input in memory when the start routine is entered, after restarting the target
+/// from the memory snapshot if a restart is due
+pub fn persistent_start_hook<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _pc: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let emulator = hooks.emulator().clone();
+    let h = hooks.match_helper_mut::<QemuPersistentHelper>().unwrap();
+    // The start routine may be recursive
+    if h.placed {
+        return;
+    }
+    h.placed = true;
+    let full_reset = h.full_reset;
+    h.full_reset = false;
+
+    // The CPU state is restored before each iteration, the restart restores all the memory
+    if full_reset {
+        hooks
+            .match_helper_mut::<QemuSnapshotHelper>()
+            .unwrap()
+            .reset_all(&emulator);
+    }
+
+    // The snapshot does not track the writes of the host, so the input is written after the restore
+    let h = hooks.match_helper::<QemuPersistentHelper>().unwrap();
+    h.write_input_mem(&emulator);
+}
+
+/// Replaces the result of the `read` syscalls on the input file descriptor with the input
+#[allow(clippy::too_many_arguments)]
+pub fn persistent_read_syscall<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    sys_num: i32,
+    a0: u64,
+    a1: u64,
+    a2: u64,
+    _a3: u64,
+    _a4: u64,
+    _a5: u64,
+    _a6: u64,
+    _a7: u64,
+) -> SyscallHookResult
+where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    if i64::from(sys_num) != SYS_read {
+        return SyscallHookResult::new(None);
+    }
+    let emulator = hooks.emulator().clone();
+    let h = hooks.match_helper_mut::<QemuPersistentHelper>().unwrap();
+    match h.input {
+        QemuPersistentInput::ReadSyscall { fd } if a0 as i32 == fd => {
+            let len = h.read(&emulator, a1 as GuestAddr, a2 as usize);
+            if len > 0 {
+                if let Some(snapshot) = hooks.match_helper_mut::<QemuSnapshotHelper>() {
+                    snapshot.access(a1 as GuestAddr, len);
+                }
+            }
+            SyscallHookResult::new(Some(len as u64))
+        }
+        _ => SyscallHookResult::new(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QemuPersistentHelper, QemuPersistentInput};
+    use crate::{GuestAddr, GuestUsize, Regs};
+
+    // Any two registers of all the targets
+    const PTR_REG: Regs = Regs::Sp;
+    const LEN_REG: Regs = Regs::Pc;
+
+    fn helper(input: QemuPersistentInput, buffer: GuestAddr) -> QemuPersistentHelper {
+        QemuPersistentHelper::with_saved_state(
+            0x1000,
+            0x2000,
+            input,
+            buffer,
+            Box::new(unsafe { core::mem::zeroed() }),
+        )
+    }
+
+    #[test]
+    fn test_registers_input() {
+        let mut h = helper(
+            QemuPersistentInput::Registers {
+                ptr: PTR_REG,
+                len: Some(LEN_REG),
+                max_size: 4,
+            },
+            0x8000,
+        );
+        h.set_input(b"abcdef");
+
+        // The input is truncated to the buffer, whose address and length are in the registers
+        assert_eq!(h.bytes, b"abcd");
+        assert_eq!(h.buffer(), Some(0x8000));
+        assert_eq!(h.input_addrs(), Some((0x8000, None)));
+        let regs: Vec<(i32, GuestUsize)> = h
+            .input_regs()
+            .into_iter()
+            .flatten()
+            .map(|(reg, val)| (i32::from(reg), val))
+            .collect();
+        assert_eq!(
+            regs,
+            [(i32::from(PTR_REG), 0x8000), (i32::from(LEN_REG), 4)]
+        );
+
+        let h = helper(
+            QemuPersistentInput::Registers {
+                ptr: PTR_REG,
+                len: None,
+                max_size: 4,
+            },
+            0x8000,
+        );
+        assert_eq!(h.input_regs().into_iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn test_memory_input() {
+        let mut h = helper(
+            QemuPersistentInput::Memory {
+                addr: 0x4000,
+                max_size: 8,
+                len_addr: Some(0x5000),
+            },
+            0x4000,
+        );
+        h.set_input(b"abc");
+
+        assert_eq!(h.bytes, b"abc");
+        assert_eq!(h.input_addrs(), Some((0x4000, Some(0x5000))));
+        assert!(h.input_regs().into_iter().flatten().next().is_none());
+        let len = if cfg!(any(feature = "be", cpu_target = "ppc")) {
+            GuestUsize::from_be_bytes(h.len_bytes())
+        } else {
+            GuestUsize::from_le_bytes(h.len_bytes())
+        };
+        assert_eq!(len, 3);
+
+        h.set_input(b"0123456789");
+        assert_eq!(h.bytes, b"01234567");
+    }
+
+    #[test]
+    fn test_read_syscall_input() {
+        let mut h = helper(QemuPersistentInput::ReadSyscall { fd: 0 }, 0);
+        h.set_input(b"abcdefg");
+
+        // The input is served in chunks, then the reads hit the end of the file
+        assert_eq!(h.buffer(), None);
+        assert_eq!(h.input_addrs(), None);
+        assert!(h.input_regs().into_iter().flatten().next().is_none());
+        assert_eq!(h.next_read(3), b"abc");
+        assert_eq!(h.next_read(3), b"def");
+        assert_eq!(h.next_read(3), b"g");
+        assert_eq!(h.next_read(3), b"");
+
+        // The next input is served from its start
+        h.set_input(b"xyz");
+        assert_eq!(h.next_read(16), b"xyz");
+    }
+}
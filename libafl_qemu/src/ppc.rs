@@ -0,0 +1,113 @@
+use capstone::arch::{BuildsCapstone, BuildsCapstoneEndian};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+pub use strum_macros::EnumIter;
+pub use syscall_numbers::powerpc::*;
+
+use crate::{ArchExtras, GuestAddr, CPU};
+
+/// Registers for the PowerPC instruction set.
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, EnumIter)]
+#[repr(i32)]
+pub enum Regs {
+    R0 = 0,
+    R1 = 1,
+    R2 = 2,
+    R3 = 3,
+    R4 = 4,
+    R5 = 5,
+    R6 = 6,
+    R7 = 7,
+    R8 = 8,
+    R9 = 9,
+    R10 = 10,
+    R11 = 11,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+    R15 = 15,
+    R16 = 16,
+    R17 = 17,
+    R18 = 18,
+    R19 = 19,
+    R20 = 20,
+    R21 = 21,
+    R22 = 22,
+    R23 = 23,
+    R24 = 24,
+    R25 = 25,
+    R26 = 26,
+    R27 = 27,
+    R28 = 28,
+    R29 = 29,
+    R30 = 30,
+    R31 = 31,
+    Pc = 64,
+    Msr = 65,
+    Cr = 66,
+    Lr = 67,
+    Ctr = 68,
+    Xer = 69,
+}
+
+/// alias registers
+#[allow(non_upper_case_globals)]
+impl Regs {
+    pub const Sp: Regs = Regs::R1;
+    pub const Toc: Regs = Regs::R2;
+    pub const Nip: Regs = Regs::Pc;
+}
+
+/// The registers of the integer arguments of a function, in the calling convention of the platform
+pub const ARGUMENT_REGS: [Regs; 8] = [
+    Regs::R3,
+    Regs::R4,
+    Regs::R5,
+    Regs::R6,
+    Regs::R7,
+    Regs::R8,
+    Regs::R9,
+    Regs::R10,
+];
+
+impl ArchExtras for CPU {
+    fn read_return_address(&self) -> Result<GuestAddr, String> {
+        self.read_reg(Regs::Lr)
+    }
+
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String> {
+        self.write_reg(Regs::Lr, val)
+    }
+
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.read_reg(*reg)
+    }
+
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.write_reg(*reg, val)
+    }
+}
+
+#[cfg(feature = "python")]
+impl IntoPy<PyObject> for Regs {
+    fn into_py(self, py: Python) -> PyObject {
+        let n: i32 = self.into();
+        n.into_py(py)
+    }
+}
+
+/// Return a PowerPC `ArchCapstoneBuilder`
+#[must_use]
+pub fn capstone() -> capstone::arch::ppc::ArchCapstoneBuilder {
+    capstone::Capstone::new()
+        .ppc()
+        .mode(capstone::arch::ppc::ArchMode::Mode32)
+        .endian(capstone::Endian::Big)
+}
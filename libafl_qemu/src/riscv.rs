@@ -0,0 +1,137 @@
+use capstone::arch::{BuildsCapstone, BuildsCapstoneExtraMode};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+pub use strum_macros::EnumIter;
+#[cfg(cpu_target = "riscv32")]
+pub use syscall_numbers::riscv32::*;
+#[cfg(cpu_target = "riscv64")]
+pub use syscall_numbers::riscv64::*;
+
+use crate::{ArchExtras, GuestAddr, CPU};
+
+// riscv32 only has the 64-bit time and offset variants of these syscalls
+#[cfg(cpu_target = "riscv32")]
+pub const SYS_mmap2: i64 = 222;
+#[cfg(cpu_target = "riscv32")]
+pub const SYS_statfs: i64 = 43;
+#[cfg(cpu_target = "riscv32")]
+pub const SYS_fstatfs: i64 = 44;
+#[cfg(cpu_target = "riscv32")]
+pub const SYS_futex: i64 = 422;
+
+/// Registers for the RISC-V instruction set.
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, EnumIter)]
+#[repr(i32)]
+pub enum Regs {
+    X0 = 0,
+    X1 = 1,
+    X2 = 2,
+    X3 = 3,
+    X4 = 4,
+    X5 = 5,
+    X6 = 6,
+    X7 = 7,
+    X8 = 8,
+    X9 = 9,
+    X10 = 10,
+    X11 = 11,
+    X12 = 12,
+    X13 = 13,
+    X14 = 14,
+    X15 = 15,
+    X16 = 16,
+    X17 = 17,
+    X18 = 18,
+    X19 = 19,
+    X20 = 20,
+    X21 = 21,
+    X22 = 22,
+    X23 = 23,
+    X24 = 24,
+    X25 = 25,
+    X26 = 26,
+    X27 = 27,
+    X28 = 28,
+    X29 = 29,
+    X30 = 30,
+    X31 = 31,
+    Pc = 32,
+}
+
+/// alias registers
+#[allow(non_upper_case_globals)]
+impl Regs {
+    pub const Zero: Regs = Regs::X0;
+    pub const Ra: Regs = Regs::X1;
+    pub const Sp: Regs = Regs::X2;
+    pub const Gp: Regs = Regs::X3;
+    pub const Tp: Regs = Regs::X4;
+    pub const Fp: Regs = Regs::X8;
+    pub const A0: Regs = Regs::X10;
+    pub const A1: Regs = Regs::X11;
+    pub const A2: Regs = Regs::X12;
+    pub const A3: Regs = Regs::X13;
+    pub const A4: Regs = Regs::X14;
+    pub const A5: Regs = Regs::X15;
+    pub const A6: Regs = Regs::X16;
+    pub const A7: Regs = Regs::X17;
+}
+
+/// The registers of the integer arguments of a function, in the calling convention of the platform
+pub const ARGUMENT_REGS: [Regs; 8] = [
+    Regs::A0,
+    Regs::A1,
+    Regs::A2,
+    Regs::A3,
+    Regs::A4,
+    Regs::A5,
+    Regs::A6,
+    Regs::A7,
+];
+
+impl ArchExtras for CPU {
+    fn read_return_address(&self) -> Result<GuestAddr, String> {
+        self.read_reg(Regs::Ra)
+    }
+
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String> {
+        self.write_reg(Regs::Ra, val)
+    }
+
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.read_reg(*reg)
+    }
+
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.write_reg(*reg, val)
+    }
+}
+
+#[cfg(feature = "python")]
+impl IntoPy<PyObject> for Regs {
+    fn into_py(self, py: Python) -> PyObject {
+        let n: i32 = self.into();
+        n.into_py(py)
+    }
+}
+
+/// Return a RISC-V `ArchCapstoneBuilder`, with the compressed instructions
+#[must_use]
+pub fn capstone() -> capstone::arch::riscv::ArchCapstoneBuilder {
+    #[cfg(cpu_target = "riscv32")]
+    let mode = capstone::arch::riscv::ArchMode::RiscV32;
+    #[cfg(cpu_target = "riscv64")]
+    let mode = capstone::arch::riscv::ArchMode::RiscV64;
+    capstone::Capstone::new().riscv().mode(mode).extra_mode(
+        [capstone::arch::riscv::ArchExtraMode::RiscVC]
+            .iter()
+            .copied(),
+    )
+}
@@ -8,20 +8,38 @@ use libafl::{inputs::UsesInput, state::HasMetadata};
 use meminterval::{Interval, IntervalTree};
 use thread_local::ThreadLocal;
 
-#[cfg(any(cpu_target = "arm", cpu_target = "i386", cpu_target = "mips"))]
+#[cfg(not(cpu_target = "riscv32"))]
+use crate::SYS_fstat;
+#[cfg(any(
+    cpu_target = "arm",
+    cpu_target = "i386",
+    cpu_target = "mips",
+    cpu_target = "ppc"
+))]
 use crate::SYS_fstatat64;
-#[cfg(not(cpu_target = "arm"))]
+#[cfg(not(any(cpu_target = "arm", cpu_target = "riscv32")))]
 use crate::SYS_mmap;
-#[cfg(any(cpu_target = "arm", cpu_target = "mips"))]
+#[cfg(any(
+    cpu_target = "arm",
+    cpu_target = "mips",
+    cpu_target = "ppc",
+    cpu_target = "riscv32"
+))]
 use crate::SYS_mmap2;
-#[cfg(not(any(cpu_target = "arm", cpu_target = "mips", cpu_target = "i386")))]
+#[cfg(not(any(
+    cpu_target = "arm",
+    cpu_target = "mips",
+    cpu_target = "i386",
+    cpu_target = "ppc",
+    cpu_target = "riscv32"
+)))]
 use crate::SYS_newfstatat;
 use crate::{
     emu::{Emulator, MmapPerms, SyscallHookResult},
     helper::{QemuHelper, QemuHelperTuple},
     hooks::QemuHooks,
-    GuestAddr, SYS_fstat, SYS_fstatfs, SYS_futex, SYS_getrandom, SYS_mprotect, SYS_mremap,
-    SYS_munmap, SYS_pread64, SYS_read, SYS_readlinkat, SYS_statfs,
+    GuestAddr, SYS_fstatfs, SYS_futex, SYS_getrandom, SYS_mprotect, SYS_mremap, SYS_munmap,
+    SYS_pread64, SYS_read, SYS_readlinkat, SYS_statfs,
 };
 
 // TODO use the functions provided by Emulator
@@ -291,6 +309,20 @@ impl QemuSnapshotHelper {
         emulator.set_mmap_start(self.mmap_start);
     }
 
+    /// Restores all the saved pages, and not only the ones written since the last reset
+    pub fn reset_all(&mut self, emulator: &Emulator) {
+        let pages: Vec<GuestAddr> = self
+            .pages
+            .values()
+            .filter(|info| info.data.is_some())
+            .map(|info| info.addr)
+            .collect();
+        for page in pages {
+            self.page_access_no_cache(page);
+        }
+        self.reset(emulator);
+    }
+
     pub fn is_unmap_allowed(&mut self, start: GuestAddr, mut size: usize) -> bool {
         if size % SNAPSHOT_PAGE_SIZE != 0 {
             size = size + (SNAPSHOT_PAGE_SIZE - size % SNAPSHOT_PAGE_SIZE);
@@ -638,21 +670,37 @@ where
             let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
             h.access(a0 as GuestAddr, a3 as usize);
         }
-        #[cfg(not(any(cpu_target = "arm", cpu_target = "i386", cpu_target = "mips")))]
+        #[cfg(not(any(
+            cpu_target = "arm",
+            cpu_target = "i386",
+            cpu_target = "mips",
+            cpu_target = "ppc",
+            cpu_target = "riscv32"
+        )))]
         SYS_newfstatat => {
             if a2 != 0 {
                 let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
                 h.access(a2 as GuestAddr, 4096); // stat is not greater than a page
             }
         }
-        #[cfg(any(cpu_target = "arm", cpu_target = "mips", cpu_target = "i386"))]
+        #[cfg(any(
+            cpu_target = "arm",
+            cpu_target = "mips",
+            cpu_target = "i386",
+            cpu_target = "ppc"
+        ))]
         SYS_fstatat64 => {
             if a2 != 0 {
                 let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
                 h.access(a2 as GuestAddr, 4096); // stat is not greater than a page
             }
         }
-        SYS_statfs | SYS_fstatfs | SYS_fstat => {
+        SYS_statfs | SYS_fstatfs => {
+            let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
+            h.access(a1 as GuestAddr, 4096); // stat is not greater than a page
+        }
+        #[cfg(not(cpu_target = "riscv32"))]
+        SYS_fstat => {
             let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
             h.access(a1 as GuestAddr, 4096); // stat is not greater than a page
         }
@@ -670,7 +718,12 @@ where
 
             // TODO handle huge pages
 
-            #[cfg(any(cpu_target = "arm", cpu_target = "mips"))]
+            #[cfg(any(
+                cpu_target = "arm",
+                cpu_target = "mips",
+                cpu_target = "ppc",
+                cpu_target = "riscv32"
+            ))]
             if i64::from(sys_num) == SYS_mmap2 {
                 if let Ok(prot) = MmapPerms::try_from(a2 as i32) {
                     let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
@@ -678,7 +731,7 @@ where
                 }
             }
 
-            #[cfg(not(cpu_target = "arm"))]
+            #[cfg(not(any(cpu_target = "arm", cpu_target = "riscv32")))]
             if i64::from(sys_num) == SYS_mmap {
                 if let Ok(prot) = MmapPerms::try_from(a2 as i32) {
                     let h = hooks.match_helper_mut::<QemuSnapshotHelper>().unwrap();
@@ -0,0 +1,512 @@
+//! A tree of fast snapshots for QEMU in systemmode.
+//!
+//! The root of the tree is the VM state when the fuzzer starts, and each other snapshot point is
+//! taken when the guest, restored from the parent point, reaches the point address, e.g. after the
+//! boot or after a protocol handshake. Each execution starts from the selected point.
+
+use core::{fmt::Debug, marker::PhantomData};
+
+use hashbrown::HashSet;
+use libafl::{
+    events::{Event, EventFirer},
+    executors::{ExitKind, TimeoutExecutor},
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::ObserversTuple,
+    stages::Stage,
+    state::UsesState,
+    Error,
+};
+
+use crate::{
+    emu::{Emulator, FastSnapshot},
+    executor::QemuExecutor,
+    harness::stopped_at,
+    helper::{QemuHelper, QemuHelperTuple},
+    hooks::QemuHooks,
+    GuestAddr,
+};
+
+/// The name of the root snapshot point
+pub const ROOT_SNAPSHOT_POINT: &str = "root";
+
+/// A snapshot point in the tree
+#[derive(Debug)]
+pub struct QemuSnapshotPoint {
+    name: String,
+    /// The address at which the snapshot is taken, `None` for the root
+    addr: Option<GuestAddr>,
+    parent: Option<usize>,
+    snapshot: Option<FastSnapshot>,
+    unreachable: bool,
+}
+
+impl QemuSnapshotPoint {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn addr(&self) -> Option<GuestAddr> {
+        self.addr
+    }
+
+    /// The index of the parent point, `None` for the root
+    #[must_use]
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    /// If the snapshot was already taken
+    #[must_use]
+    pub fn is_taken(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// If the guest, run from the parent point, stopped elsewhere than at the point address
+    #[must_use]
+    pub fn is_unreachable(&self) -> bool {
+        self.unreachable
+    }
+}
+
+/// A helper restoring the selected fast snapshot of a tree before each execution, and optionally
+/// tracking the pages written by the guest during the executions, see
+/// [`QemuSnapshotTreeHelper::with_dirty_pages_stats`]
+#[derive(Debug)]
+pub struct QemuSnapshotTreeHelper {
+    points: Vec<QemuSnapshotPoint>,
+    current: usize,
+    dirty_pages_stats: bool,
+    dirty: HashSet<GuestAddr>,
+    last_dirty: usize,
+    max_dirty: usize,
+    total_dirty: u64,
+    executions: u64,
+}
+
+impl QemuSnapshotTreeHelper {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            points: vec![QemuSnapshotPoint {
+                name: ROOT_SNAPSHOT_POINT.to_string(),
+                addr: None,
+                parent: None,
+                snapshot: None,
+                unreachable: false,
+            }],
+            current: 0,
+            dirty_pages_stats: false,
+            dirty: HashSet::new(),
+            last_dirty: 0,
+            max_dirty: 0,
+            total_dirty: 0,
+            executions: 0,
+        }
+    }
+
+    /// Adds a snapshot point `name`, taken when the guest reaches `addr` from the `parent` point
+    pub fn add_point(&mut self, name: &str, parent: &str, addr: GuestAddr) -> Result<(), Error> {
+        if self.index_of(name).is_some() {
+            return Err(Error::illegal_argument(format!(
+                "Snapshot point {name} already exists"
+            )));
+        }
+        let parent = self
+            .index_of(parent)
+            .ok_or_else(|| Error::key_not_found(format!("Snapshot point {parent} not found")))?;
+        self.points.push(QemuSnapshotPoint {
+            name: name.to_string(),
+            addr: Some(addr),
+            parent: Some(parent),
+            snapshot: None,
+            unreachable: false,
+        });
+        Ok(())
+    }
+
+    /// Adds a snapshot point, see [`Self::add_point`]
+    pub fn with_point(mut self, name: &str, parent: &str, addr: GuestAddr) -> Result<Self, Error> {
+        self.add_point(name, parent, addr)?;
+        Ok(self)
+    }
+
+    /// Tracks the pages written by the guest during each execution, for the statistics only.
+    /// All the memory writes are hooked then, so it is disabled by default.
+    #[must_use]
+    pub fn with_dirty_pages_stats(mut self, dirty_pages_stats: bool) -> Self {
+        self.dirty_pages_stats = dirty_pages_stats;
+        self
+    }
+
+    /// If the pages written by the guest are tracked
+    #[must_use]
+    pub fn tracks_dirty_pages(&self) -> bool {
+        self.dirty_pages_stats
+    }
+
+    #[must_use]
+    pub fn points(&self) -> &[QemuSnapshotPoint] {
+        &self.points
+    }
+
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.points.iter().position(|p| p.name == name)
+    }
+
+    /// The point from which the next executions start
+    #[must_use]
+    pub fn current(&self) -> &QemuSnapshotPoint {
+        &self.points[self.current]
+    }
+
+    /// Selects the point from which the next executions start
+    pub fn select(&mut self, name: &str) -> Result<(), Error> {
+        self.current = self
+            .index_of(name)
+            .ok_or_else(|| Error::key_not_found(format!("Snapshot point {name} not found")))?;
+        Ok(())
+    }
+
+    /// The number of pages written during the last execution
+    #[must_use]
+    pub fn dirty_pages(&self) -> usize {
+        self.last_dirty
+    }
+
+    /// The maximum number of pages written during an execution
+    #[must_use]
+    pub fn max_dirty_pages(&self) -> usize {
+        self.max_dirty
+    }
+
+    /// The average number of pages written during an execution
+    #[must_use]
+    pub fn avg_dirty_pages(&self) -> u64 {
+        if self.executions == 0 {
+            0
+        } else {
+            self.total_dirty / self.executions
+        }
+    }
+
+    /// Marks the pages of a write of `size` bytes at `addr` as dirty
+    pub fn access(&mut self, addr: GuestAddr, size: usize) {
+        let first = Emulator::page_from_addr(addr);
+        let last = Emulator::page_from_addr(addr + size as GuestAddr - 1);
+        self.dirty.insert(first);
+        if last != first {
+            self.dirty.insert(last);
+        }
+    }
+
+    /// Takes the snapshots of the root and of the selected point, if not taken yet.
+    /// The guest runs to the point with the hooks of all the helpers, so call it before the
+    /// observers are reset for the next execution, as the [`QemuSnapshotStage`] does, so that the
+    /// run to the point does not end up in their maps.
+    pub fn take_selected(&mut self, emulator: &Emulator) -> Result<(), Error> {
+        // The root is the state of the VM at the first execution
+        self.take(emulator, 0)?;
+        self.take(emulator, self.current)
+    }
+
+    /// Takes the snapshot of the point `idx` if needed, running the guest from its parent.
+    /// If the guest stops elsewhere, the point is marked unreachable and never run to again.
+    fn take(&mut self, emulator: &Emulator, idx: usize) -> Result<(), Error> {
+        let point = &self.points[idx];
+        if point.snapshot.is_some() {
+            return Ok(());
+        }
+        if point.unreachable {
+            return Err(Error::illegal_state(format!(
+                "Snapshot point {} is unreachable",
+                point.name
+            )));
+        }
+        if let (Some(parent), Some(addr)) = (point.parent, point.addr) {
+            self.take(emulator, parent)?;
+            emulator.restore_fast_snapshot(self.points[parent].snapshot.unwrap());
+            emulator.set_breakpoint(addr);
+            unsafe { emulator.run() };
+            emulator.remove_breakpoint(addr);
+            if !stopped_at(emulator, &[addr]) {
+                let point = &mut self.points[idx];
+                point.unreachable = true;
+                return Err(Error::illegal_state(format!(
+                    "The guest stopped before reaching the snapshot point {} at {addr:#x}",
+                    point.name
+                )));
+            }
+        }
+        self.points[idx].snapshot = Some(emulator.create_fast_snapshot(true));
+        Ok(())
+    }
+}
+
+impl Default for QemuSnapshotTreeHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> QemuHelper<S> for QemuSnapshotTreeHelper
+where
+    S: UsesInput,
+{
+    fn first_exec<QT>(&self, hooks: &QemuHooks<'_, QT, S>)
+    where
+        QT: QemuHelperTuple<S>,
+    {
+        if !self.dirty_pages_stats {
+            return;
+        }
+        hooks.writes(
+            None,
+            Some(trace_write1_snapshot_tree::<QT, S>),
+            Some(trace_write2_snapshot_tree::<QT, S>),
+            Some(trace_write4_snapshot_tree::<QT, S>),
+            Some(trace_write8_snapshot_tree::<QT, S>),
+            Some(trace_write_n_snapshot_tree::<QT, S>),
+        );
+    }
+
+    fn pre_exec(&mut self, emulator: &Emulator, _input: &S::Input) {
+        // Only the root is usually taken here, the stage takes the other points
+        self.take_selected(emulator)
+            .expect("Failed to take the snapshot of the selected point");
+        emulator.restore_fast_snapshot(self.points[self.current].snapshot.unwrap());
+        self.dirty.clear();
+    }
+
+    fn post_exec(&mut self, _emulator: &Emulator, _input: &S::Input) {
+        self.last_dirty = self.dirty.len();
+        self.max_dirty = self.max_dirty.max(self.last_dirty);
+        self.total_dirty += self.last_dirty as u64;
+        self.executions += 1;
+    }
+}
+
+pub fn trace_write1_snapshot_tree<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+    addr: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let h = hooks.match_helper_mut::<QemuSnapshotTreeHelper>().unwrap();
+    h.access(addr, 1);
+}
+
+pub fn trace_write2_snapshot_tree<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+    addr: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let h = hooks.match_helper_mut::<QemuSnapshotTreeHelper>().unwrap();
+    h.access(addr, 2);
+}
+
+pub fn trace_write4_snapshot_tree<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+    addr: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let h = hooks.match_helper_mut::<QemuSnapshotTreeHelper>().unwrap();
+    h.access(addr, 4);
+}
+
+pub fn trace_write8_snapshot_tree<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+    addr: GuestAddr,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let h = hooks.match_helper_mut::<QemuSnapshotTreeHelper>().unwrap();
+    h.access(addr, 8);
+}
+
+pub fn trace_write_n_snapshot_tree<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    _id: u64,
+    addr: GuestAddr,
+    size: usize,
+) where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let h = hooks.match_helper_mut::<QemuSnapshotTreeHelper>().unwrap();
+    h.access(addr, size);
+}
+
+/// An executor giving access to its [`QemuSnapshotTreeHelper`], if any
+pub trait HasQemuSnapshotTree {
+    fn snapshot_tree_mut(&mut self) -> Option<&mut QemuSnapshotTreeHelper>;
+}
+
+impl<'a, H, OT, QT, S> HasQemuSnapshotTree for QemuExecutor<'a, H, OT, QT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind,
+    S: UsesInput,
+    OT: ObserversTuple<S>,
+    QT: QemuHelperTuple<S>,
+{
+    fn snapshot_tree_mut(&mut self) -> Option<&mut QemuSnapshotTreeHelper> {
+        self.hooks_mut()
+            .match_helper_mut::<QemuSnapshotTreeHelper>()
+    }
+}
+
+impl<E> HasQemuSnapshotTree for TimeoutExecutor<E>
+where
+    E: HasQemuSnapshotTree,
+{
+    fn snapshot_tree_mut(&mut self) -> Option<&mut QemuSnapshotTreeHelper> {
+        self.inner().snapshot_tree_mut()
+    }
+}
+
+/// A stage running the wrapped stage from the given snapshot point, and reporting the dirty pages
+/// statistics of the [`QemuSnapshotTreeHelper`] as user stats, if it tracks them.
+/// The snapshot of the point is taken before the wrapped stage runs, if needed.
+#[derive(Debug)]
+pub struct QemuSnapshotStage<E, EM, ST, Z> {
+    point: String,
+    wrapped_stage: ST,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, ST, Z> QemuSnapshotStage<E, EM, ST, Z>
+where
+    ST: Stage<E, EM, Z>,
+    E: UsesState<State = ST::State>,
+    EM: UsesState<State = ST::State>,
+    Z: UsesState<State = ST::State>,
+{
+    /// Create a new [`QemuSnapshotStage`] running `wrapped_stage` from the snapshot `point`
+    pub fn new(point: &str, wrapped_stage: ST) -> Self {
+        Self {
+            point: point.to_string(),
+            wrapped_stage,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, ST, Z> UsesState for QemuSnapshotStage<E, EM, ST, Z>
+where
+    ST: Stage<E, EM, Z>,
+    E: UsesState<State = ST::State>,
+    EM: UsesState<State = ST::State>,
+    Z: UsesState<State = ST::State>,
+{
+    type State = ST::State;
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for QemuSnapshotStage<E, EM, ST, Z>
+where
+    ST: Stage<E, EM, Z>,
+    E: HasQemuSnapshotTree + UsesState<State = ST::State>,
+    EM: EventFirer<State = ST::State>,
+    Z: UsesState<State = ST::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut ST::State,
+        manager: &mut EM,
+        corpus_idx: usize,
+    ) -> Result<(), Error> {
+        let tree = executor
+            .snapshot_tree_mut()
+            .ok_or_else(|| Error::key_not_found("No QemuSnapshotTreeHelper in the executor"))?;
+        let previous = tree.current().name().to_string();
+        tree.select(&self.point)?;
+        if let Err(err) = tree.take_selected(&Emulator::new_empty()) {
+            tree.select(&previous)?;
+            return Err(err);
+        }
+
+        let res = self
+            .wrapped_stage
+            .perform(fuzzer, executor, state, manager, corpus_idx);
+
+        let tree = executor.snapshot_tree_mut().unwrap();
+        tree.select(&previous)?;
+        if !tree.tracks_dirty_pages() {
+            return res;
+        }
+        let (avg, max) = (tree.avg_dirty_pages(), tree.max_dirty_pages() as u64);
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "dirty_pages_avg".to_string(),
+                value: UserStats::Number(avg),
+                phantom: PhantomData,
+            },
+        )?;
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "dirty_pages_max".to_string(),
+                value: UserStats::Number(max),
+                phantom: PhantomData,
+            },
+        )?;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QemuSnapshotTreeHelper, ROOT_SNAPSHOT_POINT};
+
+    #[test]
+    fn test_snapshot_points() {
+        let mut tree = QemuSnapshotTreeHelper::new()
+            .with_point("boot", ROOT_SNAPSHOT_POINT, 0x1000)
+            .unwrap()
+            .with_point("handshake", "boot", 0x2000)
+            .unwrap();
+        tree.add_point("login", "boot", 0x3000).unwrap();
+
+        assert_eq!(tree.index_of(ROOT_SNAPSHOT_POINT), Some(0));
+        assert_eq!(tree.index_of("handshake"), Some(2));
+        assert_eq!(tree.index_of("missing"), None);
+
+        let handshake = &tree.points()[2];
+        assert_eq!(handshake.addr(), Some(0x2000));
+        assert_eq!(handshake.parent(), Some(1));
+        assert!(!handshake.is_taken() && !handshake.is_unreachable());
+        assert_eq!(tree.points()[0].addr(), None);
+        assert_eq!(tree.points()[0].parent(), None);
+
+        // The names are unique and the parent must exist
+        assert!(tree.add_point("boot", ROOT_SNAPSHOT_POINT, 0x4000).is_err());
+        assert!(tree.add_point("orphan", "missing", 0x4000).is_err());
+        assert_eq!(tree.points().len(), 4);
+
+        assert_eq!(tree.current().name(), ROOT_SNAPSHOT_POINT);
+        tree.select("login").unwrap();
+        assert_eq!(tree.current().name(), "login");
+        assert!(tree.select("missing").is_err());
+        assert_eq!(tree.current().name(), "login");
+    }
+}
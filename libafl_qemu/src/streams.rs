@@ -0,0 +1,754 @@
+//! Virtualization of the files and of the network connections of the target in QEMU usermode.
+//!
+//! The chosen files, file descriptors and connections are served from the streams of a
+//! [`MultiStreamInput`], so that the host is not touched.
+//!
+//! Each virtual file or socket is a real file descriptor reserved in the process, so that it
+//! never clashes with the ones the target opens and stays below `FD_SETSIZE` for `select`.
+//! A file is a memfd holding its stream, so the kernel serves its reads, seeks, `fstat` and
+//! `mmap`. A socket duplicates a local socket with pending data, so `poll`, `select` and `epoll`
+//! always report it ready, while its reads are served from its stream and its writes discarded.
+//! Once its connection is accepted, a listening socket is swapped for an idle one, never ready.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs::File,
+    io::{self, Seek, SeekFrom, Write},
+    net::{Ipv4Addr, Ipv6Addr},
+    os::unix::io::{FromRawFd, IntoRawFd},
+};
+
+use libafl::inputs::{MultiStreamInput, UsesInput};
+
+#[cfg(not(cpu_target = "i386"))]
+use crate::SYS_accept;
+#[cfg(not(cpu_target = "riscv32"))]
+use crate::SYS_lseek;
+#[cfg(not(any(cpu_target = "aarch64", cpu_target = "riscv32", cpu_target = "riscv64")))]
+use crate::SYS_open;
+use crate::{
+    emu::{Emulator, SyscallHookResult},
+    helper::{QemuHelper, QemuHelperTuple},
+    hooks::QemuHooks,
+    snapshot::QemuSnapshotHelper,
+    GuestAddr, SYS_accept4, SYS_bind, SYS_close, SYS_connect, SYS_listen, SYS_openat, SYS_read,
+    SYS_recvfrom, SYS_sendto, SYS_setsockopt, SYS_shutdown, SYS_socket, SYS_write,
+};
+
+/// The port the accepted connections come from, on the loopback
+pub const PEER_PORT: u16 = 40000;
+
+/// What a stream of the input is served as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QemuStreamTarget {
+    /// A file opened with `open` or `openat` at this path
+    File(String),
+    /// A file descriptor open when the target starts, e.g. stdin
+    Fd(i32),
+    /// A TCP or UDP connection to, or accepted on, this port
+    Port(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VirtualFd {
+    /// A memfd holding its stream, served by the kernel
+    File,
+    /// Serves the stream `idx` from `offset`
+    Stream {
+        idx: usize,
+        offset: usize,
+        seekable: bool,
+    },
+    /// A socket of `domain` not connected yet, bound to `port` if any
+    Socket {
+        domain: i32,
+        port: Option<u16>,
+        accepted: bool,
+    },
+}
+
+/// The local sockets the virtual sockets duplicate
+#[derive(Debug)]
+struct SocketTemplates {
+    /// A socket pair with a byte pending on its first end, always ready
+    ready: [i32; 2],
+    /// A socket pair with nothing pending, never ready to read
+    idle: [i32; 2],
+}
+
+impl SocketTemplates {
+    fn new() -> io::Result<Self> {
+        let mut ready = [-1; 2];
+        let mut idle = [-1; 2];
+        let templates = unsafe {
+            if libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                ready.as_mut_ptr(),
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                idle.as_mut_ptr(),
+            ) != 0
+            {
+                let err = io::Error::last_os_error();
+                libc::close(ready[0]);
+                libc::close(ready[1]);
+                return Err(err);
+            }
+            Self { ready, idle }
+        };
+        if unsafe { libc::write(templates.ready[1], b"\0".as_ptr().cast(), 1) } != 1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(templates)
+    }
+}
+
+impl Drop for SocketTemplates {
+    fn drop(&mut self) {
+        for fd in self.ready.iter().chain(self.idle.iter()) {
+            unsafe { libc::close(*fd) };
+        }
+    }
+}
+
+/// Checks that a reserved fd fits in an `fd_set`
+fn checked_fd(fd: i32) -> io::Result<i32> {
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else if fd >= libc::FD_SETSIZE as i32 {
+        unsafe { libc::close(fd) };
+        Err(io::Error::from_raw_os_error(libc::EMFILE))
+    } else {
+        Ok(fd)
+    }
+}
+
+/// Creates a memfd holding `data`, positioned at its start
+fn memfd_with(data: &[u8]) -> io::Result<i32> {
+    let fd = checked_fd(unsafe { libc::memfd_create(b"libafl_stream\0".as_ptr().cast(), 0) })?;
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(data)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file.into_raw_fd())
+}
+
+/// The `sockaddr` of the peer of the connections accepted on a socket of `domain`, as the guest
+/// lays it out: the family in the guest byte order, then the port and the address in network order
+fn peer_sockaddr(domain: i32) -> Vec<u8> {
+    let family = domain as u16;
+    let mut addr = vec![];
+    #[cfg(any(feature = "be", cpu_target = "ppc"))]
+    addr.extend_from_slice(&family.to_be_bytes());
+    #[cfg(not(any(feature = "be", cpu_target = "ppc")))]
+    addr.extend_from_slice(&family.to_le_bytes());
+    addr.extend_from_slice(&PEER_PORT.to_be_bytes());
+    if domain == libc::AF_INET6 {
+        // sockaddr_in6: flow info, address, scope id
+        addr.extend_from_slice(&[0; 4]);
+        addr.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        addr.extend_from_slice(&[0; 4]);
+    } else {
+        // sockaddr_in: address, padding
+        addr.extend_from_slice(&Ipv4Addr::LOCALHOST.octets());
+        addr.extend_from_slice(&[0; 8]);
+    }
+    addr
+}
+
+/// A helper serving the streams of a [`MultiStreamInput`] as files, file descriptors and sockets.
+///
+/// Once the stream of a listening port was accepted, the listening socket is never ready again
+/// and the next `accept` calls fail with `ECONNABORTED`; stop the harness at that point if the
+/// daemon loops on `accept`, or let it time out waiting on `poll`.
+#[derive(Debug)]
+pub struct QemuStreamsHelper {
+    targets: Vec<(QemuStreamTarget, String)>,
+    data: Vec<Vec<u8>>,
+    fds: HashMap<i32, VirtualFd>,
+    sockets: Option<SocketTemplates>,
+}
+
+impl QemuStreamsHelper {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            targets: vec![],
+            data: vec![],
+            fds: HashMap::new(),
+            sockets: None,
+        }
+    }
+
+    /// Serves the stream `name` of the input as `target`
+    #[must_use]
+    pub fn with_stream(mut self, target: QemuStreamTarget, name: &str) -> Self {
+        self.targets.push((target, name.to_string()));
+        self
+    }
+
+    #[must_use]
+    pub fn targets(&self) -> &[(QemuStreamTarget, String)] {
+        &self.targets
+    }
+
+    /// If `fd` is a virtual file or socket
+    #[must_use]
+    pub fn is_virtual(&self, fd: i32) -> bool {
+        self.fds.contains_key(&fd)
+    }
+
+    fn has_ports(&self) -> bool {
+        self.targets
+            .iter()
+            .any(|(target, _)| matches!(target, QemuStreamTarget::Port(_)))
+    }
+
+    fn stream_of(&self, target: &QemuStreamTarget) -> Option<usize> {
+        self.targets.iter().position(|(t, _)| t == target)
+    }
+
+    fn is_target_fd(&self, fd: i32) -> bool {
+        self.stream_of(&QemuStreamTarget::Fd(fd)).is_some()
+    }
+
+    fn templates(&mut self) -> io::Result<&SocketTemplates> {
+        if self.sockets.is_none() {
+            self.sockets = Some(SocketTemplates::new()?);
+        }
+        Ok(self.sockets.as_ref().unwrap())
+    }
+
+    /// Loads the streams of `input`, closing the fds left open by the last execution
+    fn load(&mut self, input: &MultiStreamInput) {
+        self.data = self
+            .targets
+            .iter()
+            .map(|(_, name)| input.stream(name).unwrap_or_default().to_vec())
+            .collect();
+        let fds: Vec<i32> = self.fds.drain().map(|(fd, _)| fd).collect();
+        for fd in fds {
+            if !self.is_target_fd(fd) {
+                unsafe { libc::close(fd) };
+            }
+        }
+        for (idx, (target, _)) in self.targets.iter().enumerate() {
+            if let QemuStreamTarget::Fd(fd) = target {
+                self.fds.insert(
+                    *fd,
+                    VirtualFd::Stream {
+                        idx,
+                        offset: 0,
+                        seekable: true,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Opens the stream of the file at `path` as a memfd, if any
+    fn open(&mut self, path: &str) -> io::Result<Option<i32>> {
+        if let Some(idx) = self.stream_of(&QemuStreamTarget::File(path.to_string())) {
+            let fd = memfd_with(&self.data[idx])?;
+            self.fds.insert(fd, VirtualFd::File);
+            Ok(Some(fd))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reserves a new socket of `domain`
+    fn socket(&mut self, domain: i32) -> io::Result<i32> {
+        let template = self.templates()?.ready[0];
+        let fd = checked_fd(unsafe { libc::dup(template) })?;
+        self.fds.insert(
+            fd,
+            VirtualFd::Socket {
+                domain,
+                port: None,
+                accepted: false,
+            },
+        );
+        Ok(fd)
+    }
+
+    /// The next chunk of up to `size` bytes of the stream of `fd`
+    fn read(&mut self, fd: i32, size: usize) -> Option<&[u8]> {
+        if let Some(VirtualFd::Stream { idx, offset, .. }) = self.fds.get_mut(&fd) {
+            let data = &self.data[*idx];
+            let start = (*offset).min(data.len());
+            let len = (data.len() - start).min(size);
+            *offset = start + len;
+            Some(&data[start..start + len])
+        } else {
+            None
+        }
+    }
+
+    /// Moves the offset in the stream of `fd` as `lseek` does, returning it or the error number
+    fn seek(&mut self, fd: i32, offset: i64, whence: i32) -> Option<Result<usize, i32>> {
+        if let Some(VirtualFd::Stream {
+            idx,
+            offset: current,
+            seekable,
+        }) = self.fds.get_mut(&fd)
+        {
+            if !*seekable {
+                return Some(Err(libc::ESPIPE));
+            }
+            let base = match whence {
+                libc::SEEK_SET => 0,
+                libc::SEEK_CUR => *current as i64,
+                libc::SEEK_END => self.data[*idx].len() as i64,
+                _ => return Some(Err(libc::EINVAL)),
+            };
+            match base.checked_add(offset) {
+                Some(new) if new >= 0 => {
+                    *current = new as usize;
+                    Some(Ok(*current))
+                }
+                _ => Some(Err(libc::EINVAL)),
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Binds the socket `fd` to `port`
+    fn bind(&mut self, fd: i32, port: u16) {
+        if let Some(VirtualFd::Socket { port: bound, .. }) = self.fds.get_mut(&fd) {
+            *bound = Some(port);
+        }
+    }
+
+    /// Connects the socket `fd` to the stream of `port`, if any
+    fn connect(&mut self, fd: i32, port: u16) {
+        if let Some(idx) = self.stream_of(&QemuStreamTarget::Port(port)) {
+            self.fds.insert(
+                fd,
+                VirtualFd::Stream {
+                    idx,
+                    offset: 0,
+                    seekable: false,
+                },
+            );
+        }
+    }
+
+    /// Accepts a connection on the listening `fd` from the stream of its port, once, returning
+    /// the new fd and the domain of the listener, or the error number
+    fn accept(&mut self, fd: i32) -> Result<(i32, i32), i32> {
+        let (domain, port) = match self.fds.get(&fd) {
+            Some(VirtualFd::Socket {
+                domain,
+                port: Some(port),
+                accepted: false,
+            }) => (*domain, *port),
+            Some(VirtualFd::Socket { port: None, .. }) => return Err(libc::EINVAL),
+            _ => return Err(libc::ECONNABORTED),
+        };
+        let idx = self
+            .stream_of(&QemuStreamTarget::Port(port))
+            .ok_or(libc::ECONNABORTED)?;
+        let errno = |err: io::Error| err.raw_os_error().unwrap_or(libc::EIO);
+        let templates = self.templates().map_err(errno)?;
+        let (ready, idle) = (templates.ready[0], templates.idle[0]);
+        let conn = checked_fd(unsafe { libc::dup(ready) }).map_err(errno)?;
+        // The listener is not ready anymore, so that the target waits instead of spinning
+        unsafe { libc::dup2(idle, fd) };
+        self.fds.insert(
+            fd,
+            VirtualFd::Socket {
+                domain,
+                port: Some(port),
+                accepted: true,
+            },
+        );
+        self.fds.insert(
+            conn,
+            VirtualFd::Stream {
+                idx,
+                offset: 0,
+                seekable: false,
+            },
+        );
+        Ok((conn, domain))
+    }
+
+    /// Forgets `fd`, returning if its real fd is to be closed, i.e. it is not a target fd
+    fn close(&mut self, fd: i32) -> bool {
+        self.fds.remove(&fd);
+        !self.is_target_fd(fd)
+    }
+}
+
+impl Default for QemuStreamsHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> QemuHelper<S> for QemuStreamsHelper
+where
+    S: UsesInput<Input = MultiStreamInput>,
+{
+    fn first_exec<QT>(&self, hooks: &QemuHooks<'_, QT, S>)
+    where
+        QT: QemuHelperTuple<S>,
+    {
+        hooks.syscalls(streams_syscall::<QT, S>);
+    }
+
+    fn pre_exec(&mut self, _emulator: &Emulator, input: &S::Input) {
+        self.load(input);
+    }
+}
+
+/// Reads the port of the `sockaddr_in` or `sockaddr_in6` at `addr`
+fn sockaddr_port(emulator: &Emulator, addr: GuestAddr) -> u16 {
+    let mut port = [0; 2];
+    // The port is in network order right after the family, for both IPv4 and IPv6
+    unsafe { emulator.read_mem(addr + 2, &mut port) };
+    u16::from_be_bytes(port)
+}
+
+/// Writes the peer address of a connection accepted on a socket of `domain` to the guest
+/// `addr`, truncated to the guest `*addrlen`, and sets `*addrlen` to its full size
+fn write_peer_sockaddr(emulator: &Emulator, domain: i32, addr: GuestAddr, addrlen: GuestAddr) {
+    let peer = peer_sockaddr(domain);
+    let mut len = [0; 4];
+    unsafe { emulator.read_mem(addrlen, &mut len) };
+    #[cfg(any(feature = "be", cpu_target = "ppc"))]
+    let (max, full) = (u32::from_be_bytes(len), (peer.len() as u32).to_be_bytes());
+    #[cfg(not(any(feature = "be", cpu_target = "ppc")))]
+    let (max, full) = (u32::from_le_bytes(len), (peer.len() as u32).to_le_bytes());
+    let len = peer.len().min(max as usize);
+    unsafe {
+        emulator.write_mem(addr, &peer[..len]);
+        emulator.write_mem(addrlen, &full);
+    }
+}
+
+fn ret(val: i64) -> SyscallHookResult {
+    SyscallHookResult::new(Some(val as u64))
+}
+
+fn ret_err(err: &io::Error) -> SyscallHookResult {
+    ret(-i64::from(err.raw_os_error().unwrap_or(libc::EIO)))
+}
+
+fn ret_open(opened: io::Result<Option<i32>>) -> SyscallHookResult {
+    match opened {
+        Ok(Some(fd)) => ret(fd.into()),
+        Ok(None) => SyscallHookResult::new(None),
+        Err(err) => ret_err(&err),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn streams_syscall<QT, S>(
+    hooks: &mut QemuHooks<'_, QT, S>,
+    _state: Option<&mut S>,
+    sys_num: i32,
+    a0: u64,
+    a1: u64,
+    a2: u64,
+    _a3: u64,
+    _a4: u64,
+    _a5: u64,
+    _a6: u64,
+    _a7: u64,
+) -> SyscallHookResult
+where
+    S: UsesInput,
+    QT: QemuHelperTuple<S>,
+{
+    let emulator = hooks.emulator().clone();
+    let h = hooks.match_helper_mut::<QemuStreamsHelper>().unwrap();
+    let sys_num = i64::from(sys_num);
+    let fd = a0 as i32;
+
+    #[cfg(not(any(cpu_target = "aarch64", cpu_target = "riscv32", cpu_target = "riscv64")))]
+    if sys_num == SYS_open {
+        let path = unsafe { CStr::from_ptr(emulator.g2h(a0 as GuestAddr)) };
+        return path.to_str().map_or_else(
+            |_| SyscallHookResult::new(None),
+            |path| ret_open(h.open(path)),
+        );
+    }
+
+    if sys_num == SYS_openat {
+        let path = unsafe { CStr::from_ptr(emulator.g2h(a1 as GuestAddr)) };
+        return path.to_str().map_or_else(
+            |_| SyscallHookResult::new(None),
+            |path| ret_open(h.open(path)),
+        );
+    }
+
+    if sys_num == SYS_socket {
+        let domain = a0 as i32;
+        if h.has_ports() && (domain == libc::AF_INET || domain == libc::AF_INET6) {
+            return h
+                .socket(domain)
+                .map_or_else(|err| ret_err(&err), |fd| ret(fd.into()));
+        }
+        return SyscallHookResult::new(None);
+    }
+
+    #[cfg(not(cpu_target = "i386"))]
+    let is_accept = sys_num == SYS_accept || sys_num == SYS_accept4;
+    #[cfg(cpu_target = "i386")]
+    let is_accept = sys_num == SYS_accept4;
+
+    // The other syscalls, e.g. fstat, mmap, poll and epoll, act on the reserved fd itself
+    let vfd = match h.fds.get(&fd) {
+        Some(vfd) => *vfd,
+        None => return SyscallHookResult::new(None),
+    };
+
+    if sys_num == SYS_read || sys_num == SYS_recvfrom {
+        match vfd {
+            VirtualFd::File => SyscallHookResult::new(None),
+            VirtualFd::Socket { .. } => ret(-i64::from(libc::ENOTCONN)),
+            VirtualFd::Stream { .. } => {
+                let buf = a1 as GuestAddr;
+                let len = {
+                    let chunk = h.read(fd, a2 as usize).unwrap_or_default();
+                    unsafe { emulator.write_mem(buf, chunk) };
+                    chunk.len()
+                };
+                if len > 0 {
+                    if let Some(snapshot) = hooks.match_helper_mut::<QemuSnapshotHelper>() {
+                        snapshot.access(buf, len);
+                    }
+                }
+                ret(len as i64)
+            }
+        }
+    } else if sys_num == SYS_write || sys_num == SYS_sendto {
+        if vfd == VirtualFd::File {
+            SyscallHookResult::new(None)
+        } else {
+            // Discard the writes to the connections
+            ret(a2 as i64)
+        }
+    } else if sys_num == SYS_close {
+        if h.close(fd) {
+            SyscallHookResult::new(None)
+        } else {
+            ret(0)
+        }
+    } else if sys_num == SYS_connect {
+        if let VirtualFd::Socket { .. } = vfd {
+            let port = sockaddr_port(&emulator, a1 as GuestAddr);
+            h.connect(fd, port);
+            ret(0)
+        } else {
+            ret(-i64::from(libc::EISCONN))
+        }
+    } else if sys_num == SYS_bind {
+        let port = sockaddr_port(&emulator, a1 as GuestAddr);
+        h.bind(fd, port);
+        ret(0)
+    } else if is_accept {
+        match h.accept(fd) {
+            Ok((conn, domain)) => {
+                if a1 != 0 {
+                    write_peer_sockaddr(&emulator, domain, a1 as GuestAddr, a2 as GuestAddr);
+                }
+                ret(conn.into())
+            }
+            Err(errno) => ret(-i64::from(errno)),
+        }
+    } else if sys_num == SYS_listen || sys_num == SYS_setsockopt || sys_num == SYS_shutdown {
+        // The socket is shared by the virtual sockets, leave it as is
+        ret(0)
+    } else {
+        #[cfg(not(cpu_target = "riscv32"))]
+        if sys_num == SYS_lseek {
+            return h.seek(fd, a1 as i64, a2 as i32).map_or_else(
+                || SyscallHookResult::new(None),
+                |res| res.map_or_else(|errno| ret(-i64::from(errno)), |off| ret(off as i64)),
+            );
+        }
+        SyscallHookResult::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Seek, SeekFrom},
+        mem::ManuallyDrop,
+        os::unix::io::FromRawFd,
+    };
+
+    use libafl::inputs::MultiStreamInput;
+
+    use super::{peer_sockaddr, QemuStreamTarget, QemuStreamsHelper, PEER_PORT};
+
+    fn loaded_helper() -> QemuStreamsHelper {
+        let mut helper = QemuStreamsHelper::new()
+            .with_stream(
+                QemuStreamTarget::File("/etc/app.conf".to_string()),
+                "config",
+            )
+            .with_stream(QemuStreamTarget::Fd(0), "stdin")
+            .with_stream(QemuStreamTarget::Port(8080), "http");
+        helper.load(
+            &MultiStreamInput::new()
+                .with_stream("config", b"a=b\n".to_vec())
+                .with_stream("stdin", b"hello".to_vec())
+                .with_stream("http", b"GET / HTTP/1.0\r\n\r\n".to_vec()),
+        );
+        helper
+    }
+
+    fn poll_in(fd: i32) -> bool {
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pfd, 1, 0) == 1 && pfd.revents & libc::POLLIN != 0 }
+    }
+
+    #[test]
+    fn test_streams_file() {
+        let mut helper = loaded_helper();
+        assert_eq!(helper.open("/etc/other.conf").unwrap(), None);
+
+        let fd = helper.open("/etc/app.conf").unwrap().unwrap();
+        assert!(fd < libc::FD_SETSIZE as i32);
+        assert!(helper.is_virtual(fd));
+
+        // The kernel serves the memfd
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+        assert_eq!(file.metadata().unwrap().len(), 4);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "a=b\n");
+        file.seek(SeekFrom::Start(2)).unwrap();
+        contents.clear();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "b\n");
+
+        assert!(helper.close(fd));
+        assert!(!helper.is_virtual(fd));
+        unsafe { libc::close(fd) };
+    }
+
+    #[test]
+    fn test_streams_fd() {
+        let mut helper = loaded_helper();
+        assert!(helper.is_virtual(0));
+        assert_eq!(helper.read(0, 3).unwrap(), b"hel");
+        assert_eq!(helper.read(0, 16).unwrap(), b"lo");
+        assert_eq!(helper.read(0, 16).unwrap(), b"");
+
+        assert_eq!(helper.seek(0, 1, libc::SEEK_SET), Some(Ok(1)));
+        assert_eq!(helper.read(0, 16).unwrap(), b"ello");
+        assert_eq!(helper.seek(0, -2, libc::SEEK_END), Some(Ok(3)));
+        assert_eq!(helper.seek(0, -4, libc::SEEK_CUR), Some(Err(libc::EINVAL)));
+        assert_eq!(helper.read(0, 16).unwrap(), b"lo");
+
+        // The real stdin is left open
+        assert!(!helper.close(0));
+        assert!(!helper.is_virtual(0));
+        assert_eq!(helper.read(0, 16), None);
+    }
+
+    #[test]
+    fn test_streams_accept() {
+        let mut helper = loaded_helper();
+        let listener = helper.socket(libc::AF_INET).unwrap();
+        assert!(listener < libc::FD_SETSIZE as i32);
+        assert_eq!(helper.accept(listener), Err(libc::EINVAL));
+        assert_eq!(helper.read(listener, 16), None);
+
+        helper.bind(listener, 8080);
+        assert!(poll_in(listener));
+        let (conn, domain) = helper.accept(listener).unwrap();
+        assert_eq!(domain, libc::AF_INET);
+        assert!(conn < libc::FD_SETSIZE as i32);
+
+        // The connection is ready, the listener is not anymore
+        assert!(poll_in(conn));
+        assert!(!poll_in(listener));
+        assert_eq!(helper.accept(listener), Err(libc::ECONNABORTED));
+
+        unsafe {
+            let epoll = libc::epoll_create1(libc::EPOLL_CLOEXEC);
+            let mut event = libc::epoll_event {
+                events: libc::EPOLLIN as u32,
+                u64: conn as u64,
+            };
+            assert_eq!(
+                libc::epoll_ctl(epoll, libc::EPOLL_CTL_ADD, conn, &mut event),
+                0
+            );
+            let mut events = [libc::epoll_event { events: 0, u64: 0 }; 2];
+            assert_eq!(libc::epoll_wait(epoll, events.as_mut_ptr(), 2, 0), 1);
+            libc::close(epoll);
+        }
+
+        assert_eq!(helper.read(conn, 4).unwrap(), b"GET ");
+        assert_eq!(
+            helper.seek(conn, 0, libc::SEEK_SET),
+            Some(Err(libc::ESPIPE))
+        );
+
+        // The next execution closes the sockets
+        helper.load(&MultiStreamInput::new());
+        assert!(!helper.is_virtual(listener));
+        assert!(!helper.is_virtual(conn));
+        assert!(helper.is_virtual(0));
+    }
+
+    #[test]
+    fn test_streams_connect() {
+        let mut helper = loaded_helper();
+        let other = helper.socket(libc::AF_INET6).unwrap();
+        helper.connect(other, 443);
+        assert_eq!(helper.read(other, 16), None);
+
+        let client = helper.socket(libc::AF_INET6).unwrap();
+        helper.connect(client, 8080);
+        assert_eq!(helper.read(client, 64).unwrap(), b"GET / HTTP/1.0\r\n\r\n");
+        assert!(helper.close(client));
+        assert!(helper.close(other));
+        unsafe {
+            libc::close(client);
+            libc::close(other);
+        }
+    }
+
+    #[test]
+    fn test_peer_sockaddr() {
+        let addr = peer_sockaddr(libc::AF_INET);
+        assert_eq!(addr.len(), 16);
+        assert_eq!(addr[2..4], PEER_PORT.to_be_bytes());
+        assert_eq!(addr[4..8], [127, 0, 0, 1]);
+
+        let addr = peer_sockaddr(libc::AF_INET6);
+        assert_eq!(addr.len(), 28);
+        assert_eq!(addr[2..4], PEER_PORT.to_be_bytes());
+        let mut localhost = [0; 16];
+        localhost[15] = 1;
+        assert_eq!(addr[8..24], localhost);
+        #[cfg(not(any(feature = "be", cpu_target = "ppc")))]
+        assert_eq!(
+            u16::from_le_bytes([addr[0], addr[1]]),
+            libc::AF_INET6 as u16
+        );
+    }
+}
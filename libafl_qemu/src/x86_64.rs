@@ -5,6 +5,8 @@ use pyo3::prelude::*;
 pub use strum_macros::EnumIter;
 pub use syscall_numbers::x86_64::*;
 
+use crate::{ArchExtras, GuestAddr, CPU};
+
 #[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, EnumIter)]
 #[repr(i32)]
 pub enum Regs {
@@ -35,6 +37,45 @@ impl Regs {
     pub const Pc: Regs = Regs::Rip;
 }
 
+/// The registers of the integer arguments of a function, in the calling convention of the platform
+pub const ARGUMENT_REGS: [Regs; 6] = [
+    Regs::Rdi,
+    Regs::Rsi,
+    Regs::Rdx,
+    Regs::Rcx,
+    Regs::R8,
+    Regs::R9,
+];
+
+impl ArchExtras for CPU {
+    fn read_return_address(&self) -> Result<GuestAddr, String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Rsp)?;
+        let mut ret_addr = [0; 8];
+        unsafe { self.read_mem(stack_ptr, &mut ret_addr) };
+        Ok(GuestAddr::from_le_bytes(ret_addr))
+    }
+
+    fn write_return_address(&self, val: GuestAddr) -> Result<(), String> {
+        let stack_ptr: GuestAddr = self.read_reg(Regs::Rsp)?;
+        unsafe { self.write_mem(stack_ptr, &val.to_le_bytes()) };
+        Ok(())
+    }
+
+    fn read_function_argument(&self, idx: u8) -> Result<GuestAddr, String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.read_reg(*reg)
+    }
+
+    fn write_function_argument(&self, idx: u8, val: GuestAddr) -> Result<(), String> {
+        let reg = ARGUMENT_REGS
+            .get(idx as usize)
+            .ok_or_else(|| format!("Argument {idx} is not passed in a register"))?;
+        self.write_reg(*reg, val)
+    }
+}
+
 #[cfg(feature = "python")]
 impl IntoPy<PyObject> for Regs {
     fn into_py(self, py: Python) -> PyObject {
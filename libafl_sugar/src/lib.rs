@@ -71,6 +71,11 @@ pub mod forkserver;
 #[cfg(target_family = "unix")]
 pub use forkserver::ForkserverBytesCoverageSugar;
 
+#[cfg(all(target_family = "unix", feature = "libfuzzer"))]
+pub mod libfuzzer;
+#[cfg(all(target_family = "unix", feature = "libfuzzer"))]
+pub use libfuzzer::{libfuzzer_main, LibfuzzerSugar};
+
 /// Default timeout for a run
 pub const DEFAULT_TIMEOUT_SECS: u64 = 1200;
 /// Default cache size for the corpus in memory.
@@ -0,0 +1,796 @@
+//! A drop-in replacement of the `libFuzzer` driver.
+//! Use this sugar to run `libfuzzer`-style harnesses with the `libFuzzer` command line,
+//! so that existing build scripts and CI jobs keep working.
+
+use core::{
+    fmt::{self, Debug, Formatter},
+    slice,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    bolts::{
+        cli::LibfuzzerOptions,
+        core_affinity::{get_core_ids, Cores},
+        current_nanos, current_time,
+        launcher::Launcher,
+        rands::StdRand,
+        shmem::{ShMemProvider, StdShMemProvider},
+        tuples::{tuple_list, Merge, Named},
+        AsSlice,
+    },
+    corpus::{CachedOnDiskCorpus, Corpus, InMemoryCorpus, OnDiskCorpus, Testcase},
+    events::{
+        EventConfig, EventFirer, LlmpRestartingEventManager, ProgressReporter, SimpleEventManager,
+    },
+    executors::{
+        inprocess::{InProcessExecutor, InProcessForkExecutor},
+        Executor, ExitKind, TimeoutExecutor,
+    },
+    feedback_or, feedback_or_fast,
+    feedbacks::{
+        map::CoverageTrackingMetadata, CrashFeedback, Feedback, MaxMapFeedback, TimeFeedback,
+        TimeoutFeedback,
+    },
+    fuzzer::{Fuzzer, StdFuzzer},
+    generators::RandBytesGenerator,
+    inputs::{BytesInput, HasBytesVec, HasTargetBytes, Input, UsesInput},
+    monitors::{MultiMonitor, SimpleMonitor},
+    mutators::{
+        scheduled::{havoc_mutations, tokens_mutations, StdScheduledMutator},
+        token_mutations::Tokens,
+        Mutator,
+    },
+    observers::{HitcountsMapObserver, ObserversTuple, StdMapObserver, TimeObserver},
+    schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler},
+    stages::StdMutationalStage,
+    state::{
+        HasClientPerfMonitor, HasCorpus, HasExecutions, HasMaxSize, HasMetadata, HasNamedMetadata,
+        StdState,
+    },
+    Error,
+};
+use libafl_targets::{EDGES_MAP, MAX_EDGES_NUM};
+use typed_builder::TypedBuilder;
+
+use crate::CORPUS_CACHE_SIZE;
+
+/// The number of attempts to minimize a crash, if `-runs` is not given
+pub const DEFAULT_MINIMIZE_CRASH_RUNS: usize = 1 << 16;
+
+/// How often the fuzzing clients report their stats
+const STATS_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A [`Feedback`] wrapper naming the solutions like `libFuzzer` artifacts,
+/// `{artifact_prefix}crash-{sha1}` or `{artifact_prefix}timeout-{sha1}`
+#[derive(Debug)]
+pub struct ArtifactNameFeedback<F> {
+    inner: F,
+    prefix: String,
+    kind: &'static str,
+    name: String,
+}
+
+impl<F, S> Feedback<S> for ArtifactNameFeedback<F>
+where
+    F: Feedback<S>,
+    S: UsesInput + HasClientPerfMonitor,
+    S::Input: HasTargetBytes,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.kind = match exit_kind {
+            ExitKind::Timeout => "timeout",
+            ExitKind::Oom => "oom",
+            _ => "crash",
+        };
+        self.inner
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        self.inner.append_metadata(state, testcase)?;
+        if let Some(input) = testcase.input() {
+            let sha1 = sha1_smol::Sha1::from(input.target_bytes().as_slice()).digest();
+            let filename = format!("{}{}-{sha1}", self.prefix, self.kind);
+            testcase.set_filename(filename);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+impl<F> Named for ArtifactNameFeedback<F> {
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<F> ArtifactNameFeedback<F>
+where
+    F: Named,
+{
+    /// Creates a new [`ArtifactNameFeedback`] wrapping the given objective, with the
+    /// `-artifact_prefix` of the given options
+    pub fn new(inner: F, options: &LibfuzzerOptions) -> Self {
+        let name = format!("ArtifactName({})", inner.name());
+        Self {
+            inner,
+            prefix: options.artifact_prefix.clone(),
+            kind: "crash",
+            name,
+        }
+    }
+}
+
+/// A drop-in replacement of the `libFuzzer` driver.
+///
+/// Depending on the [`LibfuzzerOptions`], it fuzzes the corpus directories, runs the input files,
+/// merges the corpus directories (`-merge=1`), or minimizes a crash (`-minimize_crash=1`).
+#[derive(TypedBuilder)]
+pub struct LibfuzzerSugar<'a, H>
+where
+    H: FnMut(&[u8]),
+{
+    /// The parsed `libFuzzer` flags
+    options: &'a LibfuzzerOptions,
+    /// The port used for communication between the fuzzing jobs
+    #[builder(default = 1337_u16)]
+    broker_port: u16,
+    /// Bytes harness
+    #[builder(setter(strip_option))]
+    harness: Option<H>,
+}
+
+impl<H> Debug for LibfuzzerSugar<'_, H>
+where
+    H: FnMut(&[u8]),
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LibfuzzerSugar")
+            .field("options", &self.options)
+            .field("broker_port", &self.broker_port)
+            .field(
+                "harness",
+                if self.harness.is_some() {
+                    &"<harness_fn>"
+                } else {
+                    &"None"
+                },
+            )
+            .finish()
+    }
+}
+
+#[allow(clippy::similar_names)]
+impl<H> LibfuzzerSugar<'_, H>
+where
+    H: FnMut(&[u8]),
+{
+    /// Run the mode selected by the flags
+    pub fn run(&mut self) -> Result<(), Error> {
+        if self.options.minimize_crash {
+            self.minimize_crash()
+        } else if self.options.merge {
+            self.merge()
+        } else if !self.options.inputs.is_empty()
+            && self.options.inputs.iter().all(|input| input.is_file())
+        {
+            self.run_inputs()
+        } else {
+            self.fuzz()
+        }
+    }
+
+    /// Run each input file, `-runs` times, like `libFuzzer` does if no directory is given
+    fn run_inputs(&mut self) -> Result<(), Error> {
+        let harness = self.harness.as_mut().unwrap();
+        let runs = self.options.runs.unwrap_or(1);
+        println!(
+            "INFO: Running {} inputs {runs} time(s) each.",
+            self.options.inputs.len()
+        );
+        for path in &self.options.inputs {
+            let bytes = fs::read(path)?;
+            println!("Running: {}", path.display());
+            for _ in 0..runs {
+                let start = Instant::now();
+                harness(&bytes);
+                println!(
+                    "Executed {} in {} ms",
+                    path.display(),
+                    start.elapsed().as_millis()
+                );
+            }
+        }
+        println!("***\n*** NOTE: fuzzing was not performed, you have only\n***       executed the target code on a fixed set of inputs.\n***");
+        Ok(())
+    }
+
+    /// Merge the interesting inputs of the corpus directories after the first one into the first one.
+    /// A crashing input aborts the merge, and is stored in the artifact directory.
+    fn merge(&mut self) -> Result<(), Error> {
+        let (out_dir, in_dirs) = match self.options.inputs.split_first() {
+            Some((out_dir, in_dirs)) if !in_dirs.is_empty() => (out_dir, in_dirs),
+            _ => {
+                return Err(Error::illegal_argument(
+                    "-merge=1 needs at least two corpus directories".to_string(),
+                ))
+            }
+        };
+        fs::create_dir_all(out_dir)?;
+
+        let mut harness_bytes = self.harness.take().unwrap();
+        let mut harness = |input: &BytesInput| {
+            let target = input.target_bytes();
+            harness_bytes(target.as_slice());
+            ExitKind::Ok
+        };
+
+        let edges = unsafe { &mut EDGES_MAP[0..MAX_EDGES_NUM] };
+        let edges_observer = HitcountsMapObserver::new(StdMapObserver::new("edges", edges));
+
+        // Only new coverage matters when merging
+        let mut feedback = MaxMapFeedback::new(&edges_observer);
+        let mut objective = ArtifactNameFeedback::new(
+            feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new()),
+            self.options,
+        );
+
+        let mut state = StdState::new(
+            StdRand::with_seed(current_nanos()),
+            InMemoryCorpus::new(),
+            OnDiskCorpus::new(self.options.artifact_dir())?,
+            &mut feedback,
+            &mut objective,
+        )?;
+        track_coverage(&mut state, self.options)?;
+
+        let mut mgr = SimpleEventManager::new(SimpleMonitor::new(|s| println!("{s}")));
+        let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+
+        let mut executor = TimeoutExecutor::new(
+            InProcessExecutor::new(
+                &mut harness,
+                tuple_list!(edges_observer),
+                &mut fuzzer,
+                &mut state,
+                &mut mgr,
+            )?,
+            self.options.timeout,
+        );
+
+        // The inputs already in the output corpus are kept anyway
+        state.load_initial_inputs_forced(
+            &mut fuzzer,
+            &mut executor,
+            &mut mgr,
+            slice::from_ref(out_dir),
+        )?;
+        let initial = state.corpus().count();
+        state.load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, in_dirs)?;
+
+        for idx in initial..state.corpus().count() {
+            let input = state.corpus().get(idx)?.borrow_mut().load_input()?.clone();
+            input.to_file(out_dir.join(input.generate_name(idx)))?;
+        }
+        println!(
+            "MERGE-OUTER: {} new files added to {}",
+            state.corpus().count() - initial,
+            out_dir.display()
+        );
+        Ok(())
+    }
+
+    /// Minimize the crashing input, running each candidate in a forked child.
+    /// The smallest input that still crashes is written to `{artifact_prefix}minimized-from-{name}`.
+    fn minimize_crash(&mut self) -> Result<(), Error> {
+        let path = match self.options.inputs.as_slice() {
+            [path] if path.is_file() => path,
+            _ => {
+                return Err(Error::illegal_argument(
+                    "-minimize_crash=1 needs exactly one crashing input file".to_string(),
+                ))
+            }
+        };
+        let runs = self.options.runs.unwrap_or(DEFAULT_MINIMIZE_CRASH_RUNS);
+        let start = Instant::now();
+
+        let mut harness_bytes = self.harness.take().unwrap();
+        let mut harness = |input: &BytesInput| {
+            let target = input.target_bytes();
+            harness_bytes(target.as_slice());
+            ExitKind::Ok
+        };
+
+        let mut feedback = ();
+        let mut objective = CrashFeedback::new();
+        let mut state = StdState::new(
+            StdRand::with_seed(self.seed()),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )?;
+
+        let mut mgr = SimpleEventManager::new(SimpleMonitor::new(|s| println!("{s}")));
+        // No observers, the exit status of the child is all we need
+        let mut fuzzer: StdFuzzer<_, _, _, ()> =
+            StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+
+        let mut executor = InProcessForkExecutor::new(
+            &mut harness,
+            (),
+            &mut fuzzer,
+            &mut state,
+            &mut mgr,
+            StdShMemProvider::new()?,
+        )?;
+
+        let mut best = BytesInput::from_file(path)?;
+        if executor.run_target(&mut fuzzer, &mut state, &mut mgr, &best)? != ExitKind::Crash {
+            return Err(Error::illegal_argument(format!(
+                "{} does not crash",
+                path.display()
+            )));
+        }
+        let original_len = best.bytes().len();
+
+        let mut mutator = StdScheduledMutator::new(havoc_mutations());
+        for _ in 0..runs {
+            if best.bytes().len() <= 1 || self.time_is_up(start) {
+                break;
+            }
+            let mut candidate = best.clone();
+            mutator.mutate(&mut state, &mut candidate, 0)?;
+            if candidate.bytes().len() >= best.bytes().len() {
+                continue;
+            }
+            *state.executions_mut() += 1;
+            if executor.run_target(&mut fuzzer, &mut state, &mut mgr, &candidate)?
+                == ExitKind::Crash
+            {
+                println!(
+                    "CRASH_MIN: {} bytes still crash (from {original_len})",
+                    candidate.bytes().len()
+                );
+                best = candidate;
+            }
+        }
+
+        let name = path.file_name().map_or_else(
+            || best.generate_name(0),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let out = self
+            .options
+            .artifact_path(&format!("minimized-from-{name}"));
+        best.to_file(&out)?;
+        println!(
+            "CRASH_MIN: minimized {original_len} bytes to {} bytes, written to {}",
+            best.bytes().len(),
+            out.display()
+        );
+        Ok(())
+    }
+
+    /// If the `-max_total_time` has passed since `start`
+    fn time_is_up(&self, start: Instant) -> bool {
+        self.options
+            .max_total_time
+            .map_or(false, |max_total_time| start.elapsed() >= max_total_time)
+    }
+
+    /// The seed of the `-seed` flag, or a random one
+    fn seed(&self) -> u64 {
+        if self.options.seed == 0 {
+            current_nanos()
+        } else {
+            self.options.seed
+        }
+    }
+
+    /// The output corpus directory: the first corpus directory, like in `libFuzzer`
+    fn corpus_dir(&self) -> Result<PathBuf, Error> {
+        let corpus_dir = self.options.inputs.first().cloned().unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("libafl-corpus-{}", std::process::id()))
+        });
+        fs::create_dir_all(&corpus_dir)?;
+        Ok(corpus_dir)
+    }
+
+    /// Fuzz in this process until `-runs` executions are done or the `-max_total_time` has passed,
+    /// then return. The whole run happens in a single process, as a broker would keep running
+    /// after the clients.
+    fn fuzz_limited(&mut self) -> Result<(), Error> {
+        let start = Instant::now();
+        let options = self.options;
+        let runs = options.runs.unwrap_or(usize::MAX);
+        let corpus_dir = self.corpus_dir()?;
+        let artifact_dir = options.artifact_dir();
+
+        let mut harness_bytes = self.harness.take().unwrap();
+        let mut harness = |input: &BytesInput| {
+            let target = input.target_bytes();
+            harness_bytes(target.as_slice());
+            ExitKind::Ok
+        };
+
+        let edges = unsafe { &mut EDGES_MAP[0..MAX_EDGES_NUM] };
+        let edges_observer = HitcountsMapObserver::new(StdMapObserver::new("edges", edges));
+        let time_observer = TimeObserver::new("time");
+
+        let mut feedback = feedback_or!(
+            MaxMapFeedback::new_tracking(&edges_observer, true, false),
+            TimeFeedback::new_with_observer(&time_observer)
+        );
+        let mut objective = ArtifactNameFeedback::new(
+            feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new()),
+            options,
+        );
+
+        let mut state = StdState::new(
+            StdRand::with_seed(self.seed()),
+            CachedOnDiskCorpus::new(corpus_dir, CORPUS_CACHE_SIZE)?,
+            OnDiskCorpus::new(artifact_dir)?,
+            &mut feedback,
+            &mut objective,
+        )?;
+        track_coverage(&mut state, options)?;
+        if let Some(max_len) = options.max_len {
+            state.set_max_size(max_len);
+        }
+        if let Some(dict) = &options.dict {
+            state.add_metadata(Tokens::from_file(dict)?);
+        }
+
+        let mut mgr = SimpleEventManager::new(SimpleMonitor::new(|s| println!("{s}")));
+        let scheduler = IndexesLenTimeMinimizerScheduler::new(QueueScheduler::new());
+        let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+        let mut executor = TimeoutExecutor::new(
+            InProcessExecutor::new(
+                &mut harness,
+                tuple_list!(edges_observer, time_observer),
+                &mut fuzzer,
+                &mut state,
+                &mut mgr,
+            )?,
+            options.timeout,
+        );
+
+        state.load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &options.inputs)?;
+        if state.corpus().count() < 1 {
+            // libFuzzer starts from an empty input, start from a few random ones
+            let mut generator = RandBytesGenerator::new(32);
+            state.generate_initial_inputs_forced(
+                &mut fuzzer,
+                &mut executor,
+                &mut generator,
+                &mut mgr,
+                8,
+            )?;
+        }
+        println!("INFO: seed corpus: files: {}", state.corpus().count());
+
+        let mutator = StdScheduledMutator::new(havoc_mutations().merge(tokens_mutations()));
+        let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+
+        // -runs counts executions, not fuzzing iterations
+        let mut last = current_time();
+        while *state.executions() < runs && !self.time_is_up(start) {
+            fuzzer.fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr)?;
+            last = mgr.maybe_report_progress(&mut state, last, STATS_TIMEOUT)?;
+        }
+        println!(
+            "Done {} runs in {} second(s)",
+            state.executions(),
+            start.elapsed().as_secs()
+        );
+        Ok(())
+    }
+
+    /// Fuzz, with one client for each `-workers`, or in this process if the `-runs` or the
+    /// `-max_total_time` are limited
+    #[allow(clippy::too_many_lines)]
+    fn fuzz(&mut self) -> Result<(), Error> {
+        if self.options.runs.is_some() || self.options.max_total_time.is_some() {
+            return self.fuzz_limited();
+        }
+        let options = self.options;
+
+        let corpus_dir = self.corpus_dir()?;
+        let artifact_dir = options.artifact_dir();
+        fs::create_dir_all(&artifact_dir)?;
+
+        let workers = options.worker_count(get_core_ids()?.len());
+        let cores = Cores::from((0..workers).collect::<Vec<_>>());
+
+        let mut harness_bytes = self.harness.take().unwrap();
+
+        let shmem_provider = StdShMemProvider::new()?;
+
+        let monitor = MultiMonitor::new(|s| println!("{s}"));
+
+        let mut run_client = |state: Option<_>,
+                              mut mgr: LlmpRestartingEventManager<_, _>,
+                              core_id: usize| {
+            // Create an observation channel using the coverage map
+            let edges = unsafe { &mut EDGES_MAP[0..MAX_EDGES_NUM] };
+            let edges_observer = HitcountsMapObserver::new(StdMapObserver::new("edges", edges));
+
+            // Create an observation channel to keep track of the execution time
+            let time_observer = TimeObserver::new("time");
+
+            // Feedback to rate the interestingness of an input
+            let mut feedback = feedback_or!(
+                MaxMapFeedback::new_tracking(&edges_observer, true, false),
+                TimeFeedback::new_with_observer(&time_observer)
+            );
+
+            // A feedback to choose if an input is a solution or not
+            let mut objective = ArtifactNameFeedback::new(
+                feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new()),
+                options,
+            );
+
+            // If not restarting, create a State from scratch
+            let mut state = state.unwrap_or_else(|| {
+                let seed = if options.seed == 0 {
+                    current_nanos()
+                } else {
+                    options.seed + core_id as u64
+                };
+                let mut state = StdState::new(
+                    StdRand::with_seed(seed),
+                    CachedOnDiskCorpus::new(corpus_dir.clone(), CORPUS_CACHE_SIZE).unwrap(),
+                    OnDiskCorpus::new(artifact_dir.clone()).unwrap(),
+                    &mut feedback,
+                    &mut objective,
+                )
+                .unwrap();
+                track_coverage(&mut state, options).unwrap();
+                state
+            });
+
+            if let Some(max_len) = options.max_len {
+                state.set_max_size(max_len);
+            }
+
+            // Create a dictionary if not existing
+            if let Some(dict) = &options.dict {
+                if state.metadata().get::<Tokens>().is_none() {
+                    state.add_metadata(Tokens::from_file(dict)?);
+                }
+            }
+
+            // A minimization+queue policy to get testcasess from the corpus
+            let scheduler = IndexesLenTimeMinimizerScheduler::new(QueueScheduler::new());
+
+            // A fuzzer with feedbacks and a corpus scheduler
+            let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+            // The wrapped harness function, calling out to the LLVM-style harness
+            let mut harness = |input: &BytesInput| {
+                let target = input.target_bytes();
+                harness_bytes(target.as_slice());
+                ExitKind::Ok
+            };
+
+            let mut executor = TimeoutExecutor::new(
+                InProcessExecutor::new(
+                    &mut harness,
+                    tuple_list!(edges_observer, time_observer),
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                )?,
+                options.timeout,
+            );
+
+            // In case the corpus is empty (on first run), reset
+            if state.corpus().count() < 1 {
+                state.load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &options.inputs)?;
+                if state.corpus().count() < 1 {
+                    // libFuzzer starts from an empty input, start from a few random ones
+                    let mut generator = RandBytesGenerator::new(32);
+                    state.generate_initial_inputs_forced(
+                        &mut fuzzer,
+                        &mut executor,
+                        &mut generator,
+                        &mut mgr,
+                        8,
+                    )?;
+                }
+                println!("INFO: seed corpus: files: {}", state.corpus().count());
+            }
+
+            let mutator = StdScheduledMutator::new(havoc_mutations().merge(tokens_mutations()));
+            let mut stages = tuple_list!(StdMutationalStage::new(mutator));
+
+            fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
+            Ok(())
+        };
+
+        let launcher = Launcher::builder()
+            .shmem_provider(shmem_provider)
+            .configuration(EventConfig::from_name("libfuzzer"))
+            .monitor(monitor)
+            .run_client(&mut run_client)
+            .cores(&cores)
+            .broker_port(self.broker_port);
+        match launcher.build().launch() {
+            Ok(()) | Err(Error::ShuttingDown) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The [`MaxMapFeedback`] logs the coverage progress, to `{artifact_prefix}coverage.csv`
+fn track_coverage<S>(state: &mut S, options: &LibfuzzerOptions) -> Result<(), Error>
+where
+    S: HasNamedMetadata,
+{
+    let path = options.artifact_path("coverage.csv");
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    state.add_named_metadata(
+        CoverageTrackingMetadata {
+            coverage_file_path: path.to_string_lossy().into_owned(),
+            num_execs: 0,
+        },
+        "coverage_file_path",
+    );
+    Ok(())
+}
+
+/// The `main` of a `libFuzzer`-compatible fuzzer for the `LLVMFuzzerTestOneInput` harness.
+///
+/// It parses the `libFuzzer` flags, calls `LLVMFuzzerInitialize` with all the arguments, and runs
+/// the [`LibfuzzerSugar`].
+pub fn libfuzzer_main() {
+    use libafl::bolts::cli::{parse_libfuzzer_args, LIBFUZZER_IGNORED_FLAGS};
+    use libafl_targets::{libfuzzer_initialize, libfuzzer_test_one_input};
+
+    let options = parse_libfuzzer_args();
+    for flag in &options.ignored {
+        let name = flag.trim_start_matches('-');
+        let name = name.split_once('=').map_or(name, |(name, _)| name);
+        if LIBFUZZER_IGNORED_FLAGS.contains(&name) {
+            println!("INFO: {flag} has no effect with LibAFL, ignored");
+        } else {
+            println!("WARNING: unrecognized flag '{flag}', ignored; use -help=1 to list all flags");
+        }
+    }
+    let args: Vec<String> = std::env::args().collect();
+    if libfuzzer_initialize(&args) == -1 {
+        println!("Warning: LLVMFuzzerInitialize failed with -1");
+    }
+
+    let result = LibfuzzerSugar::builder()
+        .options(&options)
+        .harness(|buf: &[u8]| {
+            libfuzzer_test_one_input(buf);
+        })
+        .build()
+        .run();
+    if let Err(err) = result {
+        println!("ERROR: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use libafl::{
+        bolts::{cli::LibfuzzerOptions, rands::StdRand},
+        corpus::{InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{ConstFeedback, CrashFeedback, Feedback},
+        inputs::BytesInput,
+        state::StdState,
+    };
+    use libafl_targets::{EDGES_MAP, MAX_EDGES_NUM};
+
+    use super::{ArtifactNameFeedback, LibfuzzerSugar};
+
+    /// name a timeout, expect the `libFuzzer` artifact name
+    #[test]
+    fn libfuzzer_artifact_name() {
+        let options =
+            LibfuzzerOptions::parse_from(["fuzz-target", "-artifact_prefix=out/"]).unwrap();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let mut feedback = ArtifactNameFeedback::new(CrashFeedback::new(), &options);
+        let input = BytesInput::new(b"abc".to_vec());
+
+        feedback
+            .is_interesting(
+                &mut state,
+                &mut NopEventManager::new(),
+                &input,
+                &(),
+                &ExitKind::Timeout,
+            )
+            .unwrap();
+        let mut testcase = Testcase::new(input);
+        feedback.append_metadata(&mut state, &mut testcase).unwrap();
+        assert_eq!(
+            testcase.filename().as_deref(),
+            Some("out/timeout-a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+    }
+
+    /// fuzz with `-runs`, expect the sugar to return once the runs are done
+    #[test]
+    fn libfuzzer_runs_terminate() {
+        let dir = std::env::temp_dir().join(format!("libafl-sugar-runs-{}", std::process::id()));
+        let corpus = dir.join("corpus");
+        let options = LibfuzzerOptions::parse_from([
+            "fuzz-target".to_string(),
+            "-runs=1000".to_string(),
+            format!("-artifact_prefix={}/", dir.display()),
+            corpus.display().to_string(),
+        ])
+        .unwrap();
+
+        // Fake the coverage of an instrumented target, one edge for each input length
+        unsafe {
+            MAX_EDGES_NUM = 64;
+        }
+        let mut runs = 0;
+        LibfuzzerSugar::builder()
+            .options(&options)
+            .harness(|buf: &[u8]| {
+                runs += 1;
+                unsafe {
+                    EDGES_MAP[buf.len() % 64] = 1;
+                }
+            })
+            .build()
+            .run()
+            .unwrap();
+        assert!(runs >= 1000);
+
+        let _res = fs::remove_dir_all(dir);
+    }
+}
@@ -97,16 +97,18 @@ static long area_is_valid(void *ptr, size_t len) {
 
 }
 
-void __libafl_targets_cmplog_routines(uintptr_t k, uint8_t *ptr1, uint8_t *ptr2) {
+void __libafl_targets_cmplog_routines_len(uintptr_t k, uint8_t *ptr1,
+                                          uint8_t *ptr2, size_t len) {
 
-  if (!libafl_cmplog_enabled) { return; }
+  if (!libafl_cmplog_enabled || len == 0) { return; }
+  if (len > CMPLOG_RTN_LEN) { len = CMPLOG_RTN_LEN; }
 
   int l1, l2;
-  if ((l1 = area_is_valid(ptr1, CMPLOG_RTN_LEN)) <= 0 ||
-      (l2 = area_is_valid(ptr2, CMPLOG_RTN_LEN)) <= 0) {
+  if ((l1 = area_is_valid(ptr1, len)) <= 0 ||
+      (l2 = area_is_valid(ptr2, len)) <= 0) {
     return;
   }
-  int len = MIN(l1, l2);
+  len = MIN(l1, l2);
 
   uint32_t hits;
 
@@ -128,6 +130,12 @@ void __libafl_targets_cmplog_routines(uintptr_t k, uint8_t *ptr1, uint8_t *ptr2)
 
 }
 
+void __libafl_targets_cmplog_routines(uintptr_t k, uint8_t *ptr1, uint8_t *ptr2) {
+
+  __libafl_targets_cmplog_routines_len(k, ptr1, ptr2, CMPLOG_RTN_LEN);
+
+}
+
 void __cmplog_rtn_hook(uint8_t *ptr1, uint8_t *ptr2) {
 
   uintptr_t k = RETADDR;
@@ -3,6 +3,8 @@
 
 #include "common.h"
 
+#include <stddef.h>
+
 #ifndef CMPLOG_MAP_W
   #define CMPLOG_MAP_W 65536
 #endif
@@ -53,6 +55,9 @@ void __libafl_targets_cmplog_instructions(uintptr_t k, uint8_t shape,
 void __libafl_targets_cmplog_routines(uintptr_t k, uint8_t *ptr1,
                                       uint8_t *ptr2);
 
+void __libafl_targets_cmplog_routines_len(uintptr_t k, uint8_t *ptr1,
+                                          uint8_t *ptr2, size_t len);
+
 static inline void __libafl_targets_cmplog(uintptr_t k, uint8_t shape,
                                            uint64_t arg1, uint64_t arg2) {
   if (!libafl_cmplog_enabled) { return; }
@@ -36,6 +36,17 @@ extern "C" {
     /// Logs an instruction for feedback during fuzzing
     pub fn __libafl_targets_cmplog_instructions(k: usize, shape: u8, arg1: u64, arg2: u64);
 
+    /// Logs the first [`CMPLOG_RTN_LEN`] bytes of the arguments of a routine for feedback during fuzzing
+    pub fn __libafl_targets_cmplog_routines(k: usize, ptr1: *const u8, ptr2: *const u8);
+
+    /// Logs the first `len` bytes, up to [`CMPLOG_RTN_LEN`], of the arguments of a routine for feedback during fuzzing
+    pub fn __libafl_targets_cmplog_routines_len(
+        k: usize,
+        ptr1: *const u8,
+        ptr2: *const u8,
+        len: usize,
+    );
+
     /// Pointer to the `CmpLog` map
     pub static mut libafl_cmplog_map_ptr: *mut CmpLogMap;
 }
@@ -89,6 +100,29 @@ impl Default for CmpLogMap {
     }
 }
 
+impl CmpLogMap {
+    /// Copies the headers of `other` and the values of its entries with hits, the only values read
+    /// through the headers. Much cheaper than copying the whole map, mostly made of unused values.
+    pub fn copy_hits_from(&mut self, other: &Self) {
+        self.headers.copy_from_slice(&other.headers);
+        for idx in 0..CMPLOG_MAP_W {
+            let hits = other.usable_executions_for(idx);
+            if hits == 0 {
+                continue;
+            }
+            unsafe {
+                if other.headers[idx].kind == CMPLOG_KIND_INS {
+                    self.vals.operands[idx][..hits]
+                        .copy_from_slice(&other.vals.operands[idx][..hits]);
+                } else {
+                    self.vals.routines[idx][..hits]
+                        .copy_from_slice(&other.vals.routines[idx][..hits]);
+                }
+            }
+        }
+    }
+}
+
 impl CmpMap for CmpLogMap {
     fn len(&self) -> usize {
         CMPLOG_MAP_W
@@ -2,7 +2,20 @@
 //! This makes `LibAFL` interoperable with harnesses written for other fuzzers like `Libfuzzer` and [`AFLplusplus`](aflplus.plus).
 //! We will interact with a C++ target, so use external c functionality
 
-use alloc::{string::String, vec::Vec};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cmp::max, marker::PhantomData, slice};
+
+use libafl::{
+    bolts::{rands::Rand, tuples::Named},
+    corpus::Corpus,
+    inputs::HasBytesVec,
+    mutators::{MutationResult, Mutator, MutatorsTuple, StdScheduledMutator},
+    state::{HasCorpus, HasMaxSize, HasRand, State},
+    Error,
+};
 
 extern "C" {
     /// int LLVMFuzzerTestOneInput(const uint8_t *Data, size_t Size)
@@ -10,8 +23,37 @@ extern "C" {
 
     // libafl_targets_libfuzzer_init calls LLVMFUzzerInitialize()
     fn libafl_targets_libfuzzer_init(argc: *const i32, argv: *const *const *const u8) -> i32;
+
+    fn libafl_targets_has_libfuzzer_custom_mutator() -> i32;
+
+    // libafl_targets_libfuzzer_custom_mutator calls LLVMFuzzerCustomMutator()
+    fn libafl_targets_libfuzzer_custom_mutator(
+        data: *mut u8,
+        size: usize,
+        max_size: usize,
+        seed: u32,
+    ) -> usize;
+
+    fn libafl_targets_has_libfuzzer_custom_crossover() -> i32;
+
+    // libafl_targets_libfuzzer_custom_crossover calls LLVMFuzzerCustomCrossOver()
+    fn libafl_targets_libfuzzer_custom_crossover(
+        data1: *const u8,
+        size1: usize,
+        data2: *const u8,
+        size2: usize,
+        out: *mut u8,
+        max_out_size: usize,
+        seed: u32,
+    ) -> usize;
 }
 
+/// A mutation function, taking the buffer and the size of the data in it, returning the new size
+type LLVMFuzzerMutateFn = dyn FnMut(&mut [u8], usize) -> usize;
+
+/// The mutation function called back by `LLVMFuzzerMutate` while a custom mutator runs
+static mut LLVM_FUZZER_MUTATE: Option<*mut LLVMFuzzerMutateFn> = None;
+
 /// Calls the (native) libfuzzer initialize function.
 /// Returns the value returned by the init function.
 /// # Note
@@ -37,3 +79,318 @@ pub fn libfuzzer_initialize(args: &[String]) -> i32 {
 pub fn libfuzzer_test_one_input(buf: &[u8]) -> i32 {
     unsafe { LLVMFuzzerTestOneInput(buf.as_ptr(), buf.len()) }
 }
+
+/// Returns `true` if the target defines `LLVMFuzzerCustomMutator`
+#[must_use]
+pub fn libfuzzer_has_custom_mutator() -> bool {
+    unsafe { libafl_targets_has_libfuzzer_custom_mutator() != 0 }
+}
+
+/// Returns `true` if the target defines `LLVMFuzzerCustomCrossOver`
+#[must_use]
+pub fn libfuzzer_has_custom_crossover() -> bool {
+    unsafe { libafl_targets_has_libfuzzer_custom_crossover() != 0 }
+}
+
+/// `size_t LLVMFuzzerMutate(uint8_t *Data, size_t Size, size_t MaxSize)`
+///
+/// The default mutator of `libFuzzer`, called by custom mutators of the target.
+/// It mutates the data with the havoc mutator of the [`LLVMCustomMutator`] running the custom
+/// mutator, and returns the new size. Outside of a custom mutator, it leaves the data untouched.
+///
+/// # Safety
+/// `data` must be valid for writes of `max_size` bytes.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn LLVMFuzzerMutate(data: *mut u8, size: usize, max_size: usize) -> usize {
+    match LLVM_FUZZER_MUTATE {
+        Some(mutate) if !data.is_null() && size <= max_size => {
+            (*mutate)(slice::from_raw_parts_mut(data, max_size), size)
+        }
+        _ => size,
+    }
+}
+
+/// A [`Mutator`] calling the `LLVMFuzzerCustomMutator` of the target or, if `CROSSOVER` is set,
+/// its `LLVMFuzzerCustomCrossOver` with another testcase of the corpus.
+/// While the target runs, `LLVMFuzzerMutate` mutates with the given havoc mutator.
+#[derive(Debug)]
+pub struct LLVMCustomMutator<MT, S, const CROSSOVER: bool>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand,
+{
+    havoc: StdScheduledMutator<MT, S>,
+    phantom: PhantomData<S>,
+}
+
+impl<MT, S> LLVMCustomMutator<MT, S, false>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand,
+{
+    /// Creates a new [`LLVMCustomMutator`] calling `LLVMFuzzerCustomMutator`,
+    /// fails if the target does not define it
+    pub fn mutate(havoc: StdScheduledMutator<MT, S>) -> Result<Self, Error> {
+        if libfuzzer_has_custom_mutator() {
+            Ok(Self {
+                havoc,
+                phantom: PhantomData,
+            })
+        } else {
+            Err(Error::illegal_state(
+                "LLVMFuzzerCustomMutator is not defined by the target".to_string(),
+            ))
+        }
+    }
+}
+
+impl<MT, S> LLVMCustomMutator<MT, S, true>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand,
+{
+    /// Creates a new [`LLVMCustomMutator`] calling `LLVMFuzzerCustomCrossOver`,
+    /// fails if the target does not define it
+    pub fn crossover(havoc: StdScheduledMutator<MT, S>) -> Result<Self, Error> {
+        if libfuzzer_has_custom_crossover() {
+            Ok(Self {
+                havoc,
+                phantom: PhantomData,
+            })
+        } else {
+            Err(Error::illegal_state(
+                "LLVMFuzzerCustomCrossOver is not defined by the target".to_string(),
+            ))
+        }
+    }
+}
+
+impl<MT, S, const CROSSOVER: bool> LLVMCustomMutator<MT, S, CROSSOVER>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand + HasMaxSize + HasCorpus,
+    S::Input: HasBytesVec,
+{
+    /// Runs `target` with `LLVMFuzzerMutate` calling the havoc mutator on the given state
+    #[allow(clippy::transmute_ptr_to_ptr)]
+    fn with_llvm_fuzzer_mutate<R>(
+        &mut self,
+        state: &mut S,
+        template: &S::Input,
+        target: impl FnOnce() -> R,
+    ) -> R {
+        let havoc = &mut self.havoc;
+        let mut mutate = |data: &mut [u8], size: usize| {
+            let mut input = template.clone();
+            *input.bytes_mut() = data[..size].to_vec();
+
+            let orig_max_size = state.max_size();
+            state.set_max_size(data.len());
+            let res = havoc.mutate(state, &mut input, 0);
+            state.set_max_size(orig_max_size);
+            if !matches!(res, Ok(MutationResult::Mutated)) {
+                return size;
+            }
+
+            let new_size = input.bytes().len().min(data.len());
+            data[..new_size].copy_from_slice(&input.bytes()[..new_size]);
+            new_size
+        };
+
+        unsafe {
+            let mutate: *mut (dyn FnMut(&mut [u8], usize) -> usize + '_) = &mut mutate;
+            // The pointer is only used while `target` runs, and is cleared right after
+            LLVM_FUZZER_MUTATE = Some(core::mem::transmute::<
+                *mut (dyn FnMut(&mut [u8], usize) -> usize + '_),
+                *mut LLVMFuzzerMutateFn,
+            >(mutate));
+            let ret = target();
+            LLVM_FUZZER_MUTATE = None;
+            ret
+        }
+    }
+}
+
+impl<MT, S, const CROSSOVER: bool> Mutator<S> for LLVMCustomMutator<MT, S, CROSSOVER>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand + HasMaxSize + HasCorpus,
+    S::Input: HasBytesVec,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut S::Input,
+        _stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        let seed = state.rand_mut().next() as u32;
+        let size = input.bytes().len();
+        let max_size = max(state.max_size(), size);
+
+        let other = if CROSSOVER {
+            // We don't want to use the testcase we're already using for the crossover
+            let count = state.corpus().count();
+            let idx = state.rand_mut().below(count as u64) as usize;
+            if let Some(cur) = state.corpus().current() {
+                if idx == *cur {
+                    return Ok(MutationResult::Skipped);
+                }
+            }
+            let mut other_testcase = state.corpus().get(idx)?.borrow_mut();
+            other_testcase.load_input()?.bytes().to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let mut out = vec![0; max_size];
+        let template = input.clone();
+        let new_size = if CROSSOVER {
+            self.with_llvm_fuzzer_mutate(state, &template, || unsafe {
+                libafl_targets_libfuzzer_custom_crossover(
+                    template.bytes().as_ptr(),
+                    size,
+                    other.as_ptr(),
+                    other.len(),
+                    out.as_mut_ptr(),
+                    max_size,
+                    seed,
+                )
+            })
+        } else {
+            out[..size].copy_from_slice(template.bytes());
+            self.with_llvm_fuzzer_mutate(state, &template, || unsafe {
+                libafl_targets_libfuzzer_custom_mutator(out.as_mut_ptr(), size, max_size, seed)
+            })
+        };
+
+        // libFuzzer discards empty results as well
+        if new_size == 0 || new_size > max_size {
+            return Ok(MutationResult::Skipped);
+        }
+        out.truncate(new_size);
+        if out == template.bytes() {
+            return Ok(MutationResult::Skipped);
+        }
+        *input.bytes_mut() = out;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<MT, S, const CROSSOVER: bool> Named for LLVMCustomMutator<MT, S, CROSSOVER>
+where
+    MT: MutatorsTuple<S>,
+    S: State + HasRand,
+{
+    fn name(&self) -> &str {
+        if CROSSOVER {
+            "LLVMCustomCrossOver"
+        } else {
+            "LLVMCustomMutator"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        bolts::{
+            rands::StdRand,
+            tuples::{tuple_list, Named},
+        },
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasBytesVec, UsesInput},
+        mutators::{MutationResult, Mutator, StdScheduledMutator},
+        state::{HasMaxSize, StdState},
+        Error,
+    };
+
+    use super::{LLVMCustomMutator, LLVMFuzzerMutate};
+
+    /// Mutates with `LLVMFuzzerMutate`, as the custom mutators of the targets do
+    #[no_mangle]
+    extern "C" fn LLVMFuzzerCustomMutator(
+        data: *mut u8,
+        size: usize,
+        max_size: usize,
+        _seed: u32,
+    ) -> usize {
+        unsafe { LLVMFuzzerMutate(data, size, max_size) }
+    }
+
+    /// Appends bytes, ignoring the max size
+    #[derive(Debug)]
+    struct GrowMutator;
+
+    impl<S> Mutator<S> for GrowMutator
+    where
+        S: UsesInput,
+        S::Input: HasBytesVec,
+    {
+        fn mutate(
+            &mut self,
+            _state: &mut S,
+            input: &mut S::Input,
+            _stage_idx: i32,
+        ) -> Result<MutationResult, Error> {
+            input.bytes_mut().extend_from_slice(&[b'A'; 16]);
+            Ok(MutationResult::Mutated)
+        }
+    }
+
+    impl Named for GrowMutator {
+        fn name(&self) -> &str {
+            "GrowMutator"
+        }
+    }
+
+    type TestState =
+        StdState<BytesInput, InMemoryCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>>;
+
+    #[test]
+    fn test_custom_mutator_llvm_fuzzer_mutate() {
+        let mut state: TestState = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut ConstFeedback::new(false),
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        state.set_max_size(8);
+
+        // Outside of a custom mutator, the data is left untouched
+        let mut data = *b"abc\0\0\0\0\0";
+        assert_eq!(
+            unsafe { LLVMFuzzerMutate(data.as_mut_ptr(), 3, data.len()) },
+            3
+        );
+        assert_eq!(&data, b"abc\0\0\0\0\0");
+
+        let mut mutator =
+            LLVMCustomMutator::mutate(StdScheduledMutator::new(tuple_list!(GrowMutator))).unwrap();
+        assert!(
+            LLVMCustomMutator::<_, TestState, true>::crossover(StdScheduledMutator::new(
+                tuple_list!(GrowMutator)
+            ))
+            .is_err()
+        );
+
+        // The havoc mutation grows past the max size, LLVMFuzzerMutate truncates it
+        let mut input = BytesInput::new(b"abc".to_vec());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input, 0).unwrap(),
+            MutationResult::Mutated
+        );
+        assert_eq!(input.bytes(), b"abcAAAAA");
+        assert_eq!(state.max_size(), 8);
+
+        // Already at the max size, the truncated mutation changes nothing
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input, 0).unwrap(),
+            MutationResult::Skipped
+        );
+        assert_eq!(input.bytes(), b"abcAAAAA");
+    }
+}